@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: u64,
+    level: String,
+    message: String,
+    tags: Vec<String>,
+}
+
+fn corpus(count: u64) -> Vec<u8> {
+    let entries = (0..count).map(|id| Entry {
+        id,
+        level: ["debug", "info", "warn"][id as usize % 3].to_owned(),
+        message: format!("request {} handled in {} ms", id, id % 977),
+        tags: (0..id % 5).map(|tag| format!("tag{}", tag)).collect(),
+    });
+    let mut buf = Vec::new();
+    tnetstring::to_writer_seq(&mut buf, entries).unwrap();
+    buf
+}
+
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) -> Duration {
+    let iterations = 5;
+    let mut count = 0;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        count = f();
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!(
+        "{:<16} {:>10} frames {:>12.3} ms/iter",
+        name,
+        count,
+        per_iter.as_secs_f64() * 1000.0
+    );
+    per_iter
+}
+
+fn main() {
+    let buf = corpus(500_000);
+    let sequential = time("sequential", || {
+        tnetstring::Deserializer::from_slice(&buf)
+            .into_iter::<Entry>()
+            .collect::<tnetstring::Result<Vec<_>>>()
+            .unwrap()
+            .len()
+    });
+    for threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let parallel = time(&format!("{} threads", threads), || {
+            pool.install(|| tnetstring::from_frames_par::<Entry>(&buf).unwrap().len())
+        });
+        println!(
+            "{:<16} {:>10.2}x",
+            "",
+            sequential.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Serialize, Serializer};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[derive(Serialize)]
+struct Node {
+    data: Vec<u64>,
+    children: Vec<Node>,
+}
+
+fn nested(depth: usize, leaf: Vec<u64>) -> Node {
+    if depth == 0 {
+        Node {
+            data: leaf,
+            children: vec![],
+        }
+    } else {
+        Node {
+            data: vec![],
+            children: vec![nested(depth - 1, leaf)],
+        }
+    }
+}
+
+// serialized through `collect_str`
+struct Addr(Ipv4Addr);
+
+impl Serialize for Addr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+fn bench<T: Serialize>(name: &str, value: &T) {
+    let iterations = 20;
+    let mut len = 0;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        len = tnetstring::to_vec(value).unwrap().len();
+    }
+    let per_iter = start.elapsed() / iterations;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / iterations as usize;
+    println!(
+        "{:<16} {:>10} bytes {:>12.3} ms/iter {:>10} allocs/iter",
+        name,
+        len,
+        per_iter.as_secs_f64() * 1000.0,
+        allocations
+    );
+}
+
+fn main() {
+    bench("nested depth 64", &nested(64, vec![7; 100_000]));
+    bench("nested depth 256", &nested(256, vec![7; 10_000]));
+    bench("wide 3 levels", &vec![vec![vec![1u8; 100]; 100]; 100]);
+    bench("vec u64 50k", &(0..50_000u64).collect::<Vec<_>>());
+    bench(
+        "display 50k",
+        &(0..50_000u32)
+            .map(|i| Addr(Ipv4Addr::from(i)))
+            .collect::<Vec<_>>(),
+    );
+}
@@ -0,0 +1,206 @@
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::de::from_slice;
+use crate::error::{Error, Result};
+use crate::parse::MAX_LEN_DIGITS;
+use crate::ser::to_vec;
+
+/// Read one tnetstring from `reader` and deserialize it.
+///
+/// The length prefix is read a byte at a time, then exactly the rest of the
+/// frame, so nothing past it is consumed. Dropping the future part way
+/// through, e.g. on a timeout, leaves the stream in the middle of a frame
+/// with no way to find the start of the next one.
+pub async fn read_frame<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    read_frame_with_max(reader, 0).await
+}
+
+/// `read_frame`, failing with `Error::InputLimitExceeded` as soon as the
+/// length prefix says the frame is longer than `max_len` bytes. `0` means no
+/// limit.
+pub async fn read_frame_with_max<R, T>(reader: &mut R, max_len: usize) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut frame = Vec::new();
+    let mut byte = [0];
+    loop {
+        reader.read_exact(&mut byte).await.map_err(eof)?;
+        match byte[0] {
+            b':' if !frame.is_empty() => break,
+            b'0'..=b'9' if frame.len() < MAX_LEN_DIGITS => frame.push(byte[0]),
+            b'0'..=b'9' => return Err(Error::ParsingLength),
+            _ => return Err(Error::LengthNotFound),
+        }
+    }
+    let len: usize = std::str::from_utf8(&frame)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(Error::ParsingLength)?;
+    let total = frame.len() + len + 2;
+    if max_len != 0 && total > max_len {
+        return Err(Error::InputLimitExceeded {
+            limit: max_len,
+            size: total,
+        });
+    }
+    frame.push(b':');
+    // grows as the bytes arrive rather than trusting the prefix up front
+    let rest = len as u64 + 1;
+    if reader.take(rest).read_to_end(&mut frame).await? as u64 != rest {
+        return Err(Error::Eof);
+    }
+    from_slice(&frame)
+}
+
+/// Serialize `value` and write it to `writer` as a single frame, then flush.
+///
+/// Like `read_frame`, cancelling this part way through can leave a partial
+/// frame on the stream.
+pub async fn write_frame<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    write_frame_with_max(writer, value, 0).await
+}
+
+/// `write_frame`, failing with `Error::OutputLimitExceeded` before anything
+/// is written if the frame would be longer than `max_len` bytes. `0` means
+/// no limit.
+pub async fn write_frame_with_max<W, T>(writer: &mut W, value: &T, max_len: usize) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let frame = to_vec(value)?;
+    if max_len != 0 && frame.len() > max_len {
+        return Err(Error::OutputLimitExceeded {
+            limit: max_len,
+            attempted: frame.len(),
+        });
+    }
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn eof(error: std::io::Error) -> Error {
+    if error.kind() == std::io::ErrorKind::UnexpectedEof {
+        Error::Eof
+    } else {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_frame, read_frame_with_max, write_frame, write_frame_with_max};
+    use crate::error::Error;
+    use futures::executor::block_on;
+    use futures::io::{AsyncRead, Cursor};
+    use serde::{Deserialize, Serialize};
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        name: String,
+    }
+
+    // hands out one byte per read, like a slow socket
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let len = buf.len().min(1);
+            Pin::new(&mut self.0).poll_read(cx, &mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        block_on(async {
+            let mut pipe = Vec::new();
+            for id in 0..3 {
+                let record = Record {
+                    id,
+                    name: "x".repeat(id as usize),
+                };
+                write_frame(&mut pipe, &record).await.unwrap();
+            }
+            write_frame(&mut pipe, &"tail").await.unwrap();
+
+            let mut reader = Trickle(Cursor::new(pipe));
+            for id in 0..3 {
+                let record: Record = read_frame(&mut reader).await.unwrap();
+                assert_eq!(record.id, id);
+                assert_eq!(record.name.len(), id as usize);
+            }
+            let tail: String = read_frame(&mut reader).await.unwrap();
+            assert_eq!(tail, "tail");
+            assert_eq!(read_frame::<_, String>(&mut reader).await, Err(Error::Eof));
+        });
+    }
+
+    #[test]
+    fn test_errors() {
+        block_on(async {
+            let mut reader = Cursor::new(b"5:hel".to_vec());
+            assert_eq!(read_frame::<_, String>(&mut reader).await, Err(Error::Eof));
+
+            let mut reader = Cursor::new(b"x:".to_vec());
+            assert_eq!(
+                read_frame::<_, String>(&mut reader).await,
+                Err(Error::LengthNotFound)
+            );
+
+            let mut reader = Cursor::new(b"999999999:".to_vec());
+            assert_eq!(
+                read_frame_with_max::<_, String>(&mut reader, 64).await,
+                Err(Error::InputLimitExceeded {
+                    limit: 64,
+                    size: 999_999_999 + 11,
+                })
+            );
+
+            let mut reader = Cursor::new(b"1234567890:".to_vec());
+            assert_eq!(
+                read_frame::<_, String>(&mut reader).await,
+                Err(Error::ParsingLength)
+            );
+
+            let mut reader = Cursor::new(b"5:hello,0:~".to_vec());
+            assert_eq!(
+                read_frame_with_max::<_, String>(&mut reader, 8)
+                    .await
+                    .as_deref(),
+                Ok("hello")
+            );
+            assert_eq!(reader.position(), 8);
+
+            let mut pipe = Vec::new();
+            assert_eq!(
+                write_frame_with_max(&mut pipe, &"hello!", 8).await,
+                Err(Error::OutputLimitExceeded {
+                    limit: 8,
+                    attempted: 9,
+                })
+            );
+            assert!(pipe.is_empty());
+        });
+    }
+}
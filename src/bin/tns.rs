@@ -0,0 +1,175 @@
+//! Convert between tnetstrings and JSON.
+//!
+//! Exits with 1 on malformed input or IO errors and 2 on bad arguments.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use tnetstring::{frames, validate, Error, Value};
+
+const USAGE: &str = "\
+usage: tns <decode|encode> [--stream] [FILE]
+
+  decode    read a tnetstring and print it as JSON
+  encode    read JSON and print it as a tnetstring
+
+  --stream  decode concatenated tnetstrings, one JSON line each, or encode
+            newline-delimited JSON into concatenated tnetstrings
+
+Reads FILE, or stdin when it's missing or `-`.";
+
+struct Args {
+    encode: bool,
+    stream: bool,
+    path: Option<String>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(msg) => {
+            eprintln!("tns: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(msg) = run(&args) {
+        eprintln!("tns: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut command = None;
+    let mut stream = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--stream" => stream = true,
+            "decode" | "encode" if command.is_none() => command = Some(arg),
+            _ if arg.starts_with("--") => return Err(format!("unknown flag {}", arg)),
+            _ if command.is_some() && path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let command = command.ok_or("missing command")?;
+    Ok(Some(Args {
+        encode: command == "encode",
+        stream,
+        path: path.filter(|path| path != "-"),
+    }))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let mut input = Vec::new();
+    match args.path {
+        Some(ref path) => {
+            input = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        }
+        None => {
+            io::stdin()
+                .read_to_end(&mut input)
+                .map_err(|error| error.to_string())?;
+        }
+    }
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = match (args.encode, args.stream) {
+        (false, false) => decode(&input, &mut out),
+        (false, true) => decode_stream(&input, &mut out),
+        (true, false) => encode(&input, &mut out),
+        (true, true) => encode_stream(&input, &mut out),
+    };
+    // write out whatever was converted before any error
+    let flushed = out.flush().map_err(|error| error.to_string());
+    result.and(flushed)
+}
+
+fn decode(input: &[u8], out: &mut impl Write) -> Result<(), String> {
+    let value = decode_frame(input, 0)?;
+    write_json(&value, out)
+}
+
+fn decode_stream(input: &[u8], out: &mut impl Write) -> Result<(), String> {
+    let mut iter = frames(input);
+    loop {
+        let start = iter.offset();
+        match iter.next() {
+            Some(frame) => {
+                let value = decode_frame(frame.map_err(|error| error.to_string())?, start)?;
+                write_json(&value, out)?;
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+// `offset` is where `frame` starts in the whole input, for error messages
+fn decode_frame(frame: &[u8], offset: usize) -> Result<Value, String> {
+    let shift = |error| match error {
+        Error::At {
+            offset: inner,
+            error,
+        } => Error::At {
+            offset: offset + inner,
+            error,
+        },
+        error => Error::At {
+            offset,
+            error: Box::new(error),
+        },
+    };
+    validate(frame).map_err(|error| shift(error).to_string())?;
+    tnetstring::from_slice(frame).map_err(|error| shift(error).to_string())
+}
+
+fn write_json(value: &Value, out: &mut impl Write) -> Result<(), String> {
+    serde_json::to_writer(&mut *out, value).map_err(|error| error.to_string())?;
+    out.write_all(b"\n").map_err(|error| error.to_string())
+}
+
+fn encode(input: &[u8], out: &mut impl Write) -> Result<(), String> {
+    let value = serde_json::from_slice(input).map_err(|error| json_error(input, 0, &error))?;
+    write_tnetstring(&value, out)
+}
+
+fn encode_stream(input: &[u8], out: &mut impl Write) -> Result<(), String> {
+    let mut start = 0;
+    for line in input.split(|&byte| byte == b'\n') {
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            let value =
+                serde_json::from_slice(line).map_err(|error| json_error(line, start, &error))?;
+            write_tnetstring(&value, out)?;
+        }
+        start += line.len() + 1;
+    }
+    Ok(())
+}
+
+fn write_tnetstring(value: &Value, out: &mut impl Write) -> Result<(), String> {
+    tnetstring::to_writer(&mut *out, value).map_err(|error| error.to_string())
+}
+
+// serde_json reports a line and column, turn those into a byte offset in
+// the whole input like the tnetstring errors have
+fn json_error(input: &[u8], offset: usize, error: &serde_json::Error) -> String {
+    let line_start: usize = input
+        .split(|&byte| byte == b'\n')
+        .take(error.line().saturating_sub(1))
+        .map(|line| line.len() + 1)
+        .sum();
+    let at = offset + line_start + error.column().saturating_sub(1);
+    let msg = error.to_string();
+    // drop serde_json's own " at line L column C"
+    let msg = match msg.rfind(" at line ") {
+        Some(end) => &msg[..end],
+        None => &msg,
+    };
+    format!("at byte {}: {}", at, msg)
+}
@@ -0,0 +1,369 @@
+use bytes::{Buf, Bytes, BytesMut};
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::IoSlice;
+use std::ops::Deref;
+
+use crate::de::from_slice;
+use crate::error::Result;
+use crate::frames::frame_error;
+use crate::parse::{frame_len, FrameStatus};
+use crate::ser::to_vec;
+
+// 20 digits are enough for any `u64` length, plus the `:`
+const MAX_PREFIX: usize = 21;
+
+/// Serialize `value` and append it to `buf`.
+pub fn encode_to<T>(value: &T, buf: &mut BytesMut) -> Result<()>
+where
+    T: Serialize,
+{
+    buf.extend_from_slice(&to_vec(value)?);
+    Ok(())
+}
+
+/// Deserialize the frame at the front of `buf`, or return `None` without
+/// consuming anything if it isn't all there yet.
+///
+/// On success `buf` is advanced past exactly one frame. If the frame is
+/// complete but doesn't deserialize it's consumed all the same, so the next
+/// call can carry on with the frame after it. A frame that's split over
+/// several chunks is copied out first; the length prefix is read through
+/// `chunks_vectored`, so a `Buf` that splits its data over chunks has to
+/// expose them there, as the `bytes` types do.
+pub fn decode_from<T, B>(buf: &mut B) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+    B: Buf,
+{
+    let mut prefix = [0; MAX_PREFIX];
+    let peeked = peek(buf, &mut prefix);
+    let len = match frame_len(&prefix[..peeked]).map_err(frame_error)? {
+        FrameStatus::Complete(len) => len,
+        FrameStatus::Incomplete(Some(needed)) => peeked + needed,
+        FrameStatus::Incomplete(None) => return Ok(None),
+    };
+    if buf.remaining() < len {
+        return Ok(None);
+    }
+    let value = if buf.chunk().len() >= len {
+        let value = from_slice(&buf.chunk()[..len]);
+        buf.advance(len);
+        value
+    } else {
+        from_slice(&buf.copy_to_bytes(len))
+    };
+    value.map(Some)
+}
+
+thread_local! {
+    // the buffer `from_bytes` is reading, for `SharedBytes` to slice
+    static SOURCE: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+// puts back whatever `from_bytes` was reading before, even on a panic
+struct RestoreSource(Option<Bytes>);
+
+impl Drop for RestoreSource {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        SOURCE.with(|source| *source.borrow_mut() = previous);
+    }
+}
+
+/// Deserialize a `T` from `input`, with string payloads read into
+/// `SharedBytes`, or `Bytes` fields using `shared_bytes`, taken as slices of
+/// `input` rather than copied.
+///
+/// The slices keep `input`'s allocation alive and don't borrow from it, so
+/// the result can be held across an `.await`.
+pub fn from_bytes<T>(input: Bytes) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let previous = SOURCE.with(|source| source.replace(Some(input.clone())));
+    let _restore = RestoreSource(previous);
+    from_slice(&input)
+}
+
+// `bytes` as a slice of the buffer `from_bytes` is reading, if it's in there
+fn shared_slice(bytes: &[u8]) -> Option<Bytes> {
+    SOURCE.with(|source| {
+        let source = source.borrow();
+        let source = source.as_ref()?;
+        let start = source.as_ptr() as usize;
+        let offset = (bytes.as_ptr() as usize).checked_sub(start)?;
+        if offset + bytes.len() > source.len() {
+            return None;
+        }
+        Some(source.slice(offset..offset + bytes.len()))
+    })
+}
+
+/// A string payload that, read with `from_bytes`, shares the input's
+/// buffer instead of being copied.
+///
+/// Read any other way it holds a copy, like a `Vec<u8>` would. It's
+/// serialized as a string, whatever it holds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SharedBytes(Bytes);
+
+impl SharedBytes {
+    pub fn into_inner(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for SharedBytes {
+    type Target = Bytes;
+
+    fn deref(&self) -> &Bytes {
+        &self.0
+    }
+}
+
+impl From<Bytes> for SharedBytes {
+    fn from(bytes: Bytes) -> SharedBytes {
+        SharedBytes(bytes)
+    }
+}
+
+impl From<SharedBytes> for Bytes {
+    fn from(bytes: SharedBytes) -> Bytes {
+        bytes.0
+    }
+}
+
+impl Serialize for SharedBytes {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedBytes {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<SharedBytes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SharedBytesVisitor;
+
+        impl<'de> Visitor<'de> for SharedBytesVisitor {
+            type Value = SharedBytes;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tnetstring string")
+            }
+
+            fn visit_borrowed_bytes<E: de::Error>(
+                self,
+                v: &'de [u8],
+            ) -> std::result::Result<SharedBytes, E> {
+                Ok(SharedBytes(
+                    shared_slice(v).unwrap_or_else(|| Bytes::copy_from_slice(v)),
+                ))
+            }
+
+            fn visit_borrowed_str<E: de::Error>(
+                self,
+                v: &'de str,
+            ) -> std::result::Result<SharedBytes, E> {
+                self.visit_borrowed_bytes(v.as_bytes())
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> std::result::Result<SharedBytes, E> {
+                Ok(SharedBytes(Bytes::copy_from_slice(v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<SharedBytes, E> {
+                self.visit_bytes(v.as_bytes())
+            }
+
+            fn visit_byte_buf<E: de::Error>(
+                self,
+                v: Vec<u8>,
+            ) -> std::result::Result<SharedBytes, E> {
+                Ok(SharedBytes(v.into()))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> std::result::Result<SharedBytes, E> {
+                Ok(SharedBytes(v.into()))
+            }
+        }
+
+        deserializer.deserialize_bytes(SharedBytesVisitor)
+    }
+}
+
+/// For `Bytes` fields, with `#[serde(with = "tnetstring::shared_bytes")]`,
+/// read the way `SharedBytes` is.
+pub mod shared_bytes {
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::SharedBytes;
+
+    pub fn serialize<S>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(value)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Bytes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        SharedBytes::deserialize(deserializer).map(SharedBytes::into_inner)
+    }
+}
+
+// copies the first bytes of `buf` into `prefix` without consuming them
+fn peek<B: Buf>(buf: &B, prefix: &mut [u8; MAX_PREFIX]) -> usize {
+    let mut chunks = [IoSlice::new(&[]); MAX_PREFIX];
+    let count = buf.chunks_vectored(&mut chunks);
+    let mut filled = 0;
+    for chunk in &chunks[..count] {
+        let n = chunk.len().min(MAX_PREFIX - filled);
+        prefix[filled..filled + n].copy_from_slice(&chunk[..n]);
+        filled += n;
+        if filled == MAX_PREFIX {
+            break;
+        }
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_from, encode_to};
+    use crate::error::{Error, Result};
+    use bytes::{Buf, Bytes, BytesMut};
+    use maplit::btreemap;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let mut buf = BytesMut::new();
+        encode_to(&btreemap! {"a".to_string() => vec![1, 2]}, &mut buf)?;
+        encode_to(&"hello", &mut buf)?;
+        assert_eq!(&buf[..], b"15:1:a,8:1:1#1:2#]}5:hello,");
+
+        let map: BTreeMap<String, Vec<i32>> = decode_from(&mut buf)?.unwrap();
+        assert_eq!(map["a"], [1, 2]);
+        assert_eq!(
+            decode_from::<String, _>(&mut buf)?.as_deref(),
+            Some("hello")
+        );
+        assert_eq!(decode_from::<String, _>(&mut buf)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partially_filled() -> Result<()> {
+        let encoded = b"15:1:a,8:1:1#1:2#]}";
+        let mut buf = BytesMut::new();
+        for (i, &byte) in encoded.iter().enumerate() {
+            assert_eq!(
+                decode_from::<BTreeMap<String, Vec<i32>>, _>(&mut buf)?,
+                None
+            );
+            assert_eq!(buf.len(), i);
+            buf.extend_from_slice(&[byte]);
+        }
+        buf.extend_from_slice(b"1:");
+        assert!(decode_from::<BTreeMap<String, Vec<i32>>, _>(&mut buf)?.is_some());
+        assert_eq!(&buf[..], b"1:");
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked() -> Result<()> {
+        let encoded = b"12:3:foo,3:bar,]2:42#";
+        for split in 0..encoded.len() {
+            let mut buf =
+                Bytes::from_static(&encoded[..split]).chain(Bytes::from_static(&encoded[split..]));
+            assert_eq!(
+                decode_from::<Vec<String>, _>(&mut buf)?,
+                Some(vec!["foo".to_string(), "bar".to_string()])
+            );
+            assert_eq!(buf.remaining(), 5);
+            assert_eq!(decode_from::<i32, _>(&mut buf)?, Some(42));
+            assert_eq!(buf.remaining(), 0);
+        }
+
+        // a long prefix split over many chunks
+        let mut buf = Bytes::from_static(b"1")
+            .chain(Bytes::from_static(b"0"))
+            .chain(Bytes::from_static(b":0123456789,"));
+        assert_eq!(
+            decode_from::<String, _>(&mut buf)?.as_deref(),
+            Some("0123456789")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        let mut buf = BytesMut::from(&b"2:42#0:~"[..]);
+        assert!(decode_from::<String, _>(&mut buf).is_err());
+        assert_eq!(&buf[..], b"0:~");
+
+        let mut buf = BytesMut::from(&b"x:"[..]);
+        assert_eq!(
+            decode_from::<String, _>(&mut buf),
+            Err(Error::LengthNotFound)
+        );
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[test]
+    fn test_from_bytes_shares() {
+        use super::{from_bytes, SharedBytes};
+        use crate::de::from_slice;
+        use crate::ser::to_vec;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Message {
+            id: u32,
+            #[serde(with = "super::shared_bytes")]
+            body: Bytes,
+            parts: Vec<SharedBytes>,
+        }
+
+        fn within(outer: &[u8], inner: &[u8]) -> bool {
+            let start = outer.as_ptr() as usize;
+            let at = inner.as_ptr() as usize;
+            at >= start && at + inner.len() <= start + outer.len()
+        }
+
+        let message = Message {
+            id: 7,
+            body: Bytes::from(vec![b'x'; 1 << 20]),
+            parts: vec![
+                Bytes::from_static(b"head").into(),
+                Bytes::from(vec![0xff, 0]).into(),
+            ],
+        };
+        let input = Bytes::from(to_vec(&message).unwrap());
+        let decoded: Message = from_bytes(input.clone()).unwrap();
+        assert_eq!(decoded, message);
+        assert!(within(&input, &decoded.body));
+        assert!(decoded.parts.iter().all(|part| within(&input, part)));
+
+        // outliving the input's handle
+        drop(input);
+        assert_eq!(decoded.body.len(), 1 << 20);
+
+        // anything else copies
+        let input = to_vec(&message).unwrap();
+        let copied: Message = from_slice(&input).unwrap();
+        assert_eq!(copied, message);
+        assert!(!within(&input, &copied.body));
+    }
+}
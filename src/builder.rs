@@ -0,0 +1,342 @@
+use serde::ser::{Serialize, Serializer as _};
+
+use crate::error::{Error, Result};
+use crate::ser::Serializer;
+use crate::validate::validate;
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    is_dict: bool,
+    // whether a dict has a key waiting for its value
+    has_key: bool,
+}
+
+/// Writes a tnetstring piece by piece, for when there's no `Serialize` type
+/// that fits the data.
+///
+/// Every call returns the builder so calls can be chained with `?`. The
+/// output is the same as the serializer's for the same data, and using the
+/// builder out of order, like a value in a dict without a key, fails with
+/// `Error::Nesting`.
+///
+/// ```
+/// use tnetstring::Builder;
+///
+/// let mut builder = Builder::new();
+/// builder.begin_dict()?.key("id")?.push_int(7)?;
+/// builder.key("body")?.push_raw(b"5:hello,")?.end_dict()?;
+/// assert_eq!(builder.finish()?, b"24:2:id,1:7#4:body,5:hello,}");
+/// # Ok::<(), tnetstring::Error>(())
+/// ```
+pub struct Builder {
+    ser: Serializer,
+    stack: Vec<Level>,
+    done: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder {
+            ser: Serializer::new(Vec::new()),
+            stack: Vec::new(),
+            done: false,
+        }
+    }
+
+    // called before anything that takes up a value's slot
+    fn value(&mut self) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(Level {
+                is_dict: true,
+                has_key,
+            }) => {
+                if !*has_key {
+                    return Err(Error::Nesting("dict value without a key"));
+                }
+                *has_key = false;
+            }
+            Some(_) => {}
+            None if self.done => return Err(Error::Nesting("more than one top-level value")),
+            None => {}
+        }
+        if self.stack.is_empty() {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    pub fn push_str(&mut self, v: &str) -> Result<&mut Self> {
+        self.push_bytes(v.as_bytes())
+    }
+
+    /// Push a string payload that doesn't have to be UTF-8.
+    pub fn push_bytes(&mut self, v: &[u8]) -> Result<&mut Self> {
+        self.value()?;
+        (&mut self.ser).serialize_bytes(v)?;
+        Ok(self)
+    }
+
+    pub fn push_int(&mut self, v: i64) -> Result<&mut Self> {
+        self.value()?;
+        (&mut self.ser).serialize_i64(v)?;
+        Ok(self)
+    }
+
+    pub fn push_float(&mut self, v: f64) -> Result<&mut Self> {
+        self.value()?;
+        (&mut self.ser).serialize_f64(v)?;
+        Ok(self)
+    }
+
+    pub fn push_bool(&mut self, v: bool) -> Result<&mut Self> {
+        self.value()?;
+        (&mut self.ser).serialize_bool(v)?;
+        Ok(self)
+    }
+
+    pub fn push_null(&mut self) -> Result<&mut Self> {
+        self.value()?;
+        (&mut self.ser).serialize_unit()?;
+        Ok(self)
+    }
+
+    /// Push anything `Serialize` as a single value.
+    pub fn push<T>(&mut self, value: &T) -> Result<&mut Self>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.value()?;
+        value.serialize(&mut self.ser)?;
+        Ok(self)
+    }
+
+    /// Splice in an already encoded tnetstring, which has to be a single well
+    /// formed value.
+    pub fn push_raw(&mut self, encoded: &[u8]) -> Result<&mut Self> {
+        validate(encoded)?;
+        self.value()?;
+        self.ser.add_to_output(encoded)?;
+        Ok(self)
+    }
+
+    pub fn begin_list(&mut self) -> Result<&mut Self> {
+        self.begin(false)
+    }
+
+    pub fn end_list(&mut self) -> Result<&mut Self> {
+        self.end(false)
+    }
+
+    pub fn begin_dict(&mut self) -> Result<&mut Self> {
+        self.begin(true)
+    }
+
+    pub fn end_dict(&mut self) -> Result<&mut Self> {
+        self.end(true)
+    }
+
+    /// Push the key for the next value in the current dict.
+    pub fn key(&mut self, key: &str) -> Result<&mut Self> {
+        match self.stack.last_mut() {
+            Some(Level {
+                is_dict: true,
+                has_key,
+            }) if !*has_key => *has_key = true,
+            Some(Level { is_dict: true, .. }) => {
+                return Err(Error::Nesting("dict key without a value"))
+            }
+            _ => return Err(Error::Nesting("key outside of a dict")),
+        }
+        (&mut self.ser).serialize_str(key)?;
+        Ok(self)
+    }
+
+    fn begin(&mut self, is_dict: bool) -> Result<&mut Self> {
+        self.value()?;
+        self.ser.start_container();
+        self.stack.push(Level {
+            is_dict,
+            has_key: false,
+        });
+        Ok(self)
+    }
+
+    fn end(&mut self, is_dict: bool) -> Result<&mut Self> {
+        match self.stack.last() {
+            Some(level) if level.is_dict != is_dict => {
+                return Err(Error::Nesting(if is_dict {
+                    "end_dict inside a list"
+                } else {
+                    "end_list inside a dict"
+                }))
+            }
+            Some(level) if level.has_key => return Err(Error::Nesting("dict key without a value")),
+            Some(_) => {}
+            None if is_dict => return Err(Error::Nesting("end_dict without begin_dict")),
+            None => return Err(Error::Nesting("end_list without begin_list")),
+        }
+        self.stack.pop();
+        self.ser.end_container(if is_dict { b'}' } else { b']' })?;
+        Ok(self)
+    }
+
+    /// Return the encoded value, checking that every list and dict was ended.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        if !self.stack.is_empty() {
+            return Err(Error::Nesting("unclosed list or dict"));
+        }
+        if !self.done {
+            return Err(Error::Nesting("nothing was pushed"));
+        }
+        self.ser.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Builder;
+    use crate::error::{Error, Result};
+    use crate::ser::to_vec;
+    use maplit::btreemap;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Message {
+        id: i64,
+        tags: Vec<&'static str>,
+        score: f64,
+        meta: Meta,
+    }
+
+    #[derive(Serialize)]
+    struct Meta {
+        ok: bool,
+        next: Option<i64>,
+    }
+
+    #[test]
+    fn test_matches_serializer() -> Result<()> {
+        let message = Message {
+            id: -3,
+            tags: vec!["a", "bc"],
+            score: 1.5,
+            meta: Meta {
+                ok: true,
+                next: None,
+            },
+        };
+        let mut builder = Builder::new();
+        builder.begin_dict()?;
+        builder.key("id")?.push_int(-3)?;
+        builder.key("tags")?.begin_list()?;
+        builder.push_str("a")?.push_str("bc")?.end_list()?;
+        builder.key("score")?.push_float(1.5)?;
+        builder.key("meta")?.begin_dict()?;
+        builder.key("ok")?.push_bool(true)?;
+        builder.key("next")?.push_null()?;
+        builder.end_dict()?.end_dict()?;
+        assert_eq!(builder.finish()?, to_vec(&message)?);
+
+        let mut builder = Builder::new();
+        builder
+            .begin_list()?
+            .begin_list()?
+            .end_list()?
+            .begin_dict()?;
+        builder
+            .end_dict()?
+            .push(&btreemap! {"k" => 1})?
+            .end_list()?;
+        let empty: (Vec<()>, BTreeMap<String, ()>) = Default::default();
+        assert_eq!(
+            builder.finish()?,
+            to_vec(&(empty.0, empty.1, btreemap! {"k" => 1}))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_and_scalars() -> Result<()> {
+        let mut builder = Builder::new();
+        builder.push_raw(b"12:3:foo,3:bar,]")?;
+        assert_eq!(builder.finish()?, b"12:3:foo,3:bar,]");
+
+        let scalar = |push: &dyn Fn(&mut Builder) -> Result<&mut Builder>| {
+            let mut builder = Builder::new();
+            push(&mut builder)?;
+            builder.finish()
+        };
+        assert_eq!(scalar(&|b| b.push_str("hello"))?, to_vec(&"hello")?);
+        assert_eq!(scalar(&|b| b.push_bytes(b"\xff"))?, b"1:\xff,");
+        assert_eq!(scalar(&|b| b.push_int(i64::MIN))?, to_vec(&i64::MIN)?);
+        assert_eq!(scalar(&|b| b.push_float(0.1))?, to_vec(&0.1)?);
+        assert_eq!(scalar(&|b| b.push_float(f64::NAN))?, to_vec(&f64::NAN)?);
+        assert_eq!(scalar(&|b| b.push_bool(false))?, to_vec(&false)?);
+        assert_eq!(scalar(&|b| b.push_null())?, to_vec(&())?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nesting_errors() -> Result<()> {
+        let nesting = |msg| Err(Error::Nesting(msg));
+
+        assert_eq!(
+            Builder::new().end_list().map(|_| ()),
+            nesting("end_list without begin_list")
+        );
+        assert_eq!(
+            Builder::new().key("a").map(|_| ()),
+            nesting("key outside of a dict")
+        );
+        let mut builder = Builder::new();
+        builder.begin_list()?;
+        assert_eq!(
+            builder.key("a").map(|_| ()),
+            nesting("key outside of a dict")
+        );
+        assert_eq!(
+            builder.end_dict().map(|_| ()),
+            nesting("end_dict inside a list")
+        );
+        assert_eq!(
+            builder.finish().map(|_| ()),
+            nesting("unclosed list or dict")
+        );
+
+        let mut builder = Builder::new();
+        builder.begin_dict()?;
+        assert_eq!(
+            builder.push_int(1).map(|_| ()),
+            nesting("dict value without a key")
+        );
+        builder.key("a")?;
+        assert_eq!(
+            builder.key("b").map(|_| ()),
+            nesting("dict key without a value")
+        );
+        assert_eq!(
+            builder.end_dict().map(|_| ()),
+            nesting("dict key without a value")
+        );
+        builder.push_null()?.end_dict()?;
+        assert_eq!(
+            builder.push_null().map(|_| ()),
+            nesting("more than one top-level value")
+        );
+        assert_eq!(builder.finish(), Ok(b"7:1:a,0:~}".to_vec()));
+
+        assert_eq!(
+            Builder::new().finish().map(|_| ()),
+            nesting("nothing was pushed")
+        );
+        assert!(Builder::new().push_raw(b"5:hello").is_err());
+        Ok(())
+    }
+}
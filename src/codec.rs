@@ -0,0 +1,232 @@
+use bytes::{Buf, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::de::from_slice;
+use crate::error::{Error, Result};
+use crate::frames::{frame_error, incomplete};
+use crate::parse::{frame_len, FrameStatus, MAX_LEN_DIGITS};
+use crate::ser::to_vec;
+
+const MAX_RESERVE: usize = 64 * 1024;
+
+/// A `tokio_util` codec that reads and writes one tnetstring per frame.
+///
+/// Decoding waits until a whole frame is buffered, then deserializes it as a
+/// `T`. A frame that fails to deserialize is still consumed, so the next one
+/// can be read; only a malformed length prefix or type tag leaves the stream
+/// somewhere it can't recover from.
+pub struct TNetStringCodec<T> {
+    max_frame_len: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TNetStringCodec<T> {
+    pub fn new() -> Self {
+        TNetStringCodec {
+            max_frame_len: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Fail with `Error::InputLimitExceeded` on frames longer than this many
+    /// bytes, both ways. Incoming frames are rejected as soon as their length
+    /// prefix is read. `0`, the default, means no limit.
+    pub fn max_frame_len(mut self, limit: usize) -> Self {
+        self.max_frame_len = limit;
+        self
+    }
+
+    fn check_len(&self, len: usize) -> Result<()> {
+        if self.max_frame_len != 0 && len > self.max_frame_len {
+            return Err(Error::InputLimitExceeded {
+                limit: self.max_frame_len,
+                size: len,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for TNetStringCodec<T> {
+    fn default() -> Self {
+        TNetStringCodec::new()
+    }
+}
+
+impl<T> Clone for TNetStringCodec<T> {
+    fn clone(&self) -> Self {
+        TNetStringCodec {
+            max_frame_len: self.max_frame_len,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Decoder for TNetStringCodec<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        if src.iter().take_while(|b| b.is_ascii_digit()).count() > MAX_LEN_DIGITS {
+            return Err(Error::ParsingLength);
+        }
+        match frame_len(src).map_err(frame_error)? {
+            FrameStatus::Complete(len) => {
+                self.check_len(len)?;
+                let value = from_slice(&src[..len]);
+                src.advance(len);
+                value.map(Some)
+            }
+            FrameStatus::Incomplete(Some(needed)) => {
+                self.check_len(src.len() + needed)?;
+                // the rest grows as it arrives, not all at once on the prefix's word
+                src.reserve(needed.min(MAX_RESERVE));
+                Ok(None)
+            }
+            FrameStatus::Incomplete(None) => {
+                self.check_len(src.len())?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// A frame cut short by the end of the stream fails with
+    /// `Error::Incomplete`.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        match self.decode(src)? {
+            Some(value) => Ok(Some(value)),
+            None if src.is_empty() => Ok(None),
+            None => Err(incomplete(src).unwrap_or(Error::Eof)),
+        }
+    }
+}
+
+impl<T> Encoder<T> for TNetStringCodec<T>
+where
+    T: Serialize,
+{
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<()> {
+        let encoded = to_vec(&item)?;
+        self.check_len(encoded.len())?;
+        dst.extend_from_slice(&encoded);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TNetStringCodec;
+    use crate::error::Error;
+    use bytes::BytesMut;
+    use futures::{SinkExt, StreamExt};
+    use serde::{Deserialize, Serialize};
+    use tokio_util::codec::{Decoder, FramedRead, FramedWrite};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Message {
+        Ping(u32),
+        Data { key: String, values: Vec<i64> },
+        Bye,
+    }
+
+    #[tokio::test]
+    async fn test_framed_duplex() {
+        let (client, server) = tokio::io::duplex(16);
+        let messages = vec![
+            Message::Ping(1),
+            Message::Data {
+                key: "k".into(),
+                values: vec![1, -2, 3],
+            },
+            Message::Bye,
+        ];
+
+        let mut writer = FramedWrite::new(client, TNetStringCodec::<Message>::new());
+        let mut reader = FramedRead::new(server, TNetStringCodec::<Message>::new());
+        let expected = messages.len();
+        let send = async move {
+            for message in messages {
+                writer.send(message).await.unwrap();
+            }
+        };
+        let receive = async move {
+            let mut received = Vec::new();
+            while received.len() < expected {
+                received.push(reader.next().await.unwrap().unwrap());
+            }
+            received
+        };
+        let ((), received) = tokio::join!(send, receive);
+        assert_eq!(
+            received,
+            [
+                Message::Ping(1),
+                Message::Data {
+                    key: "k".into(),
+                    values: vec![1, -2, 3],
+                },
+                Message::Bye,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_partial_and_bad_frames() {
+        let mut codec = TNetStringCodec::<String>::new();
+        let mut buf = BytesMut::from(&b"5:he"[..]);
+        assert_eq!(codec.decode(&mut buf), Ok(None));
+        buf.extend_from_slice(b"llo,2:42#1:a,");
+        assert_eq!(codec.decode(&mut buf), Ok(Some("hello".to_string())));
+        // the integer isn't a string, but it's consumed all the same
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf), Ok(Some("a".to_string())));
+        assert_eq!(codec.decode(&mut buf), Ok(None));
+        assert!(buf.is_empty());
+
+        let mut codec = TNetStringCodec::<String>::new().max_frame_len(8);
+        let mut buf = BytesMut::from(&b"100:"[..]);
+        assert_eq!(
+            codec.decode(&mut buf),
+            Err(Error::InputLimitExceeded {
+                limit: 8,
+                size: 105,
+            })
+        );
+        let mut codec = TNetStringCodec::<String>::new();
+        let mut buf = BytesMut::from(&b"999999999:abc"[..]);
+        assert_eq!(codec.decode(&mut buf), Ok(None));
+        assert!(buf.capacity() < 1 << 20, "{}", buf.capacity());
+        let mut buf = BytesMut::from(&b"1234567890"[..]);
+        assert_eq!(codec.decode(&mut buf), Err(Error::ParsingLength));
+
+        let mut codec = TNetStringCodec::<String>::new().max_frame_len(8);
+        let mut out = BytesMut::new();
+        assert!(tokio_util::codec::Encoder::encode(&mut codec, "hello!".into(), &mut out).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_decode_eof() {
+        let mut codec = TNetStringCodec::<String>::new();
+        let mut buf = BytesMut::from(&b"2:ok,5:he"[..]);
+        assert_eq!(codec.decode_eof(&mut buf), Ok(Some("ok".to_string())));
+        assert_eq!(
+            codec.decode_eof(&mut buf),
+            Err(Error::Incomplete { needed: Some(4) })
+        );
+        let mut buf = BytesMut::from(&b"12"[..]);
+        assert_eq!(
+            codec.decode_eof(&mut buf),
+            Err(Error::Incomplete { needed: None })
+        );
+        assert_eq!(codec.decode_eof(&mut BytesMut::new()), Ok(None));
+    }
+}
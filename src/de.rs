@@ -25,25 +25,151 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::error::{Error, Result};
-use crate::parse::{parse_type, TNetStringType};
+use crate::frames::incomplete;
+use crate::intern::{self, Interner};
+use crate::parse::{parse, parse_type, Parsed, TNetStringError, TNetStringType};
+use crate::raw;
+use crate::spanned;
 use serde::{
     de::{
-        self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
-        Visitor,
+        self, value::BorrowedStrDeserializer, value::UsizeDeserializer, DeserializeSeed,
+        EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
     },
-    forward_to_deserialize_any, Deserialize,
+    Deserialize,
 };
-use std::ops::{AddAssign, MulAssign, Neg, SubAssign};
-use std::str;
-
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::{self, FromStr};
+
+// how many bytes either side of an error `Error::Expected` shows
+const SNIPPET_LEN: usize = 16;
+
+/// Reads tnetstrings into any `Deserialize` type.
+///
+/// The type tags make the format self-describing, so `deserialize_any`
+/// always knows what it's looking at. That's what lets
+/// `serde_transcode::transcode` feed a tnetstring straight into another
+/// format's serializer, such as `serde_json`'s; call `end` afterwards to
+/// check nothing was left over.
 pub struct Deserializer<'de> {
-    input: &'de str,
+    input: &'de [u8],
+    // the whole input, for error offsets
+    start: &'de [u8],
+    lenient_bool: bool,
+    lossy_utf8: bool,
+    case_insensitive_fields: bool,
+    human_readable: bool,
+    interner: Option<Interner>,
+    // how many more lists, dicts and enums can be nested
+    remaining_depth: usize,
+    #[cfg(feature = "unbounded_depth")]
+    unbounded_depth: bool,
 }
 
+// the same limit as `ValidateConfig`'s default and `serde_json`'s
+const RECURSION_LIMIT: usize = 128;
+
 impl<'de> Deserializer<'de> {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(input: &'de str) -> Self {
-        Deserializer { input }
+        Deserializer::from_slice(input.as_bytes())
+    }
+
+    pub fn from_slice(input: &'de [u8]) -> Self {
+        Deserializer {
+            input,
+            start: input,
+            lenient_bool: false,
+            lossy_utf8: false,
+            case_insensitive_fields: false,
+            human_readable: true,
+            interner: None,
+            remaining_depth: RECURSION_LIMIT,
+            #[cfg(feature = "unbounded_depth")]
+            unbounded_depth: false,
+        }
+    }
+
+    /// Accept the integers `1:0#` and `1:1#` wherever a bool is expected.
+    ///
+    /// Some producers encode booleans as integers instead of `4:true!` and
+    /// `5:false!`. Only `0` and `1` are accepted, anything else is still an
+    /// error, and `deserialize_any` is unaffected.
+    pub fn lenient_bool(mut self, enabled: bool) -> Self {
+        self.lenient_bool = enabled;
+        self
+    }
+
+    /// Replace invalid UTF-8 in strings with `U+FFFD` instead of failing.
+    ///
+    /// By default a string that isn't UTF-8 fails with `Error::InvalidUtf8`,
+    /// giving the offset of the first invalid byte in the input. Lossy
+    /// strings are copied, so they can't be borrowed as `&str`.
+    pub fn lossy_utf8(mut self, enabled: bool) -> Self {
+        self.lossy_utf8 = enabled;
+        self
+    }
+
+    /// Match dict keys to struct fields regardless of case.
+    ///
+    /// This only applies to struct fields, keys of maps like
+    /// `HashMap<String, _>` are left as they are.
+    pub fn case_insensitive_fields(mut self, enabled: bool) -> Self {
+        self.case_insensitive_fields = enabled;
+        self
+    }
+
+    /// Set what `is_human_readable` reports to `Deserialize` impls, `true` by
+    /// default. This has to match the serializer's setting.
+    pub fn human_readable(mut self, enabled: bool) -> Self {
+        self.human_readable = enabled;
+        self
+    }
+
+    /// Read strings into `InternedStr` through `interner`, so that repeated
+    /// ones share an allocation.
+    pub fn interner(mut self, interner: &Interner) -> Self {
+        self.interner = Some(interner.clone());
+        self
+    }
+
+    /// Allow nesting deeper than the default limit of 128 lists, dicts and
+    /// enums, which otherwise fails with `Error::DepthLimitExceeded`.
+    ///
+    /// Only do this for trusted input: each level of nesting takes stack
+    /// space, so deep enough input overflows the stack. Wrapping the
+    /// deserializer in `serde_stacker::Deserializer` grows the stack on the
+    /// heap as needed instead.
+    #[cfg(feature = "unbounded_depth")]
+    pub fn disable_recursion_limit(&mut self) {
+        self.unbounded_depth = true;
+    }
+
+    /// Turn the deserializer into an iterator over the concatenated values in
+    /// the input.
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<T>(self) -> StreamDeserializer<'de, T>
+    where
+        T: Deserialize<'de>,
+    {
+        StreamDeserializer {
+            de: self,
+            failed: false,
+            resync: false,
+            skipped: 0,
+            separators: Vec::new(),
+            output: PhantomData,
+        }
+    }
+
+    /// Check that all of the input was consumed.
+    pub fn end(&self) -> Result<()> {
+        if self.input.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnusedParseData)
+        }
     }
 }
 
@@ -51,138 +177,551 @@ pub fn from_str<'a, T>(s: &'a str) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_str(s);
-    let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.is_empty() {
-        Ok(t)
+    from_slice(s.as_bytes())
+}
+
+pub fn from_slice<'a, T>(v: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_slice_seed(PhantomData, v)
+}
+
+/// Like `from_str`, but driven by a `DeserializeSeed`, for types that need
+/// some context to be built, like an arena or an interner.
+pub fn from_str_seed<'a, S>(seed: S, s: &'a str) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    from_slice_seed(seed, s.as_bytes())
+}
+
+/// Like `from_slice`, but driven by a `DeserializeSeed`.
+pub fn from_slice_seed<'a, S>(seed: S, v: &'a [u8]) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    let mut deserializer = Deserializer::from_slice(v);
+    let result = seed
+        .deserialize(&mut deserializer)
+        .and_then(|t| deserializer.end().map(|()| t))
+        .map_err(|error| incomplete(v).unwrap_or(error));
+    if let Err(ref error) = result {
+        deserializer.trace_error(error);
+    }
+    result
+}
+
+/// Iterator over a buffer of concatenated tnetstrings, created with
+/// `Deserializer::into_iter`.
+pub struct StreamDeserializer<'de, T> {
+    de: Deserializer<'de>,
+    failed: bool,
+    resync: bool,
+    skipped: usize,
+    separators: Vec<u8>,
+    output: PhantomData<T>,
+}
+
+impl<'de, T> StreamDeserializer<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    /// Skip corrupt frames instead of stopping at the first error.
+    ///
+    /// When a frame's length prefix or type tag is malformed, the input is
+    /// scanned forward to the next position that looks like the start of a
+    /// well formed frame and reading resumes from there. The number of bytes
+    /// passed over is available from `skipped`. A well formed frame that
+    /// doesn't deserialize into `T` still yields its error, and reading
+    /// carries on after it.
+    pub fn resync(mut self, enabled: bool) -> Self {
+        self.resync = enabled;
+        self
+    }
+
+    /// Skip any of these bytes between frames, such as `b"\r\n"` for one
+    /// frame per line. None are skipped by default, and they're never
+    /// skipped inside a frame.
+    pub fn separators(mut self, separators: &[u8]) -> Self {
+        self.separators = separators.to_vec();
+        self
+    }
+
+    /// Total number of bytes skipped while resynchronizing.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    fn next_frame(&mut self, frame: &'de [u8]) -> Result<T> {
+        self.de.input = frame;
+        let result = T::deserialize(&mut self.de).and_then(|value| self.de.end().map(|()| value));
+        if let Err(ref error) = result {
+            self.de.trace_error(error);
+        }
+        result
+    }
+}
+
+impl<'de, T> Iterator for StreamDeserializer<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        loop {
+            let input = self.de.input;
+            let separators = input
+                .iter()
+                .take_while(|byte| self.separators.contains(byte))
+                .count();
+            self.de.input = &input[separators..];
+            if self.failed || self.de.input.is_empty() {
+                return None;
+            }
+            let input = self.de.input;
+            let error = match split_frame(input) {
+                Ok((frame, rest)) => {
+                    let result = self.next_frame(frame);
+                    self.de.input = rest;
+                    match result {
+                        Ok(value) => return Some(Ok(value)),
+                        // the frame itself is well formed, so the next one
+                        // can still be found
+                        Err(e) if self.resync => return Some(Err(e)),
+                        Err(e) => e,
+                    }
+                }
+                Err(_) if self.resync => {
+                    let start = (1..input.len())
+                        .find(|&index| split_frame(&input[index..]).is_ok())
+                        .unwrap_or(input.len());
+                    self.skipped += start;
+                    self.de.input = &input[start..];
+                    continue;
+                }
+                Err(e) => incomplete(input).unwrap_or(e),
+            };
+            self.failed = true;
+            return Some(Err(error));
+        }
+    }
+}
+
+// Splits the frame at the front of `input` from whatever follows it, checking
+// that its length prefix lines up with a valid type tag. That's one `parse`
+// of the prefix, which checks the tag as well.
+fn split_frame(input: &[u8]) -> Result<(&[u8], &[u8])> {
+    let (_, rest) = split_payload(input)?;
+    Ok((&input[..input.len() - rest.len()], rest))
+}
+
+/// Deserialize the value stored under `key` in a top-level dict.
+///
+/// The values of other keys are skipped over using their length prefixes
+/// rather than being parsed.
+pub fn get<'a, T>(input: &'a str, key: &str) -> Result<Option<T>>
+where
+    T: Deserialize<'a>,
+{
+    let input = input.as_bytes();
+    match parse_type(input) {
+        Ok(TNetStringType::Dict) => {}
+        Ok(found) => {
+            return Err(Error::UnexpectedType {
+                expected: TNetStringType::Dict,
+                found,
+            })
+        }
+        Err(_) => return Err(Error::ParsingMap),
+    }
+    let (mut payload, rest) = split_payload(input)?;
+    if !rest.is_empty() {
+        return Err(Error::UnusedParseData);
+    }
+    while !payload.is_empty() {
+        match parse_type(payload) {
+            Ok(TNetStringType::Str) => {}
+            Ok(found) => {
+                return Err(Error::UnexpectedType {
+                    expected: TNetStringType::Str,
+                    found,
+                })
+            }
+            Err(_) => return Err(Error::ParsingString),
+        }
+        let (current_key, value) = split_payload(payload)?;
+        let (_, remain) = split_payload(value)?;
+        if current_key == key.as_bytes() {
+            return from_slice(&value[..value.len() - remain.len()]).map(Some);
+        }
+        payload = remain;
+    }
+    Ok(None)
+}
+
+// Splits the tnetstring at the front of `input` into its payload and whatever
+// follows its type tag.
+fn split_payload(input: &[u8]) -> Result<(&[u8], &[u8])> {
+    match parse(input) {
+        Ok(Parsed { payload, rest, .. }) => Ok((payload, rest)),
+        Err(TNetStringError::EmptyInput) => Err(Error::LengthNotFound),
+        Err(TNetStringError::MissingLengthPrefix) if !input.contains(&b':') => {
+            Err(Error::LengthNotFound)
+        }
+        Err(TNetStringError::MissingLengthPrefix) | Err(TNetStringError::UnableToParseInt) => {
+            Err(Error::ParsingLength)
+        }
+        Err(TNetStringError::UnknownSegmentType) => Err(Error::UnknownSegmentType),
+        Err(_) => Err(Error::Eof),
+    }
+}
+
+// Whether `digits` is an optionally negative run of decimal digits, however
+// many there are.
+fn is_integer(digits: &str) -> bool {
+    let magnitude = digits.strip_prefix('-').unwrap_or(digits);
+    !magnitude.is_empty() && magnitude.bytes().all(|b| b.is_ascii_digit())
+}
+
+// The error for an integer that doesn't fit in the type `name`, worded like
+// the one serde's own visitors give.
+fn out_of_range(digits: &str, name: &'static str) -> Error {
+    let unexpected = if let Ok(v) = digits.parse() {
+        de::Unexpected::Signed(v)
+    } else if let Ok(v) = digits.parse() {
+        de::Unexpected::Unsigned(v)
     } else {
-        Err(Error::UnusedParseData)
+        de::Unexpected::Other("a wide integer")
+    };
+    de::Error::invalid_value(unexpected, &name)
+}
+
+// Checks that the tnetstring at the front of `input` is well formed, without
+// decoding its scalars, and returns whatever follows it.
+pub(crate) fn skip_value(input: &[u8]) -> Result<&[u8]> {
+    let (mut payload, rest) = split_payload(input)?;
+    match parse_type(input).map_err(|_| Error::UnknownSegmentType)? {
+        TNetStringType::Null if !payload.is_empty() => return Err(Error::ParsingUnit),
+        TNetStringType::Bool if payload != b"true" && payload != b"false" => {
+            return Err(Error::ParsingBool)
+        }
+        TNetStringType::List => {
+            while !payload.is_empty() {
+                payload = skip_value(payload)?;
+            }
+        }
+        TNetStringType::Dict => {
+            while !payload.is_empty() {
+                match parse_type(payload) {
+                    Ok(TNetStringType::Str) => {}
+                    Ok(found) => {
+                        return Err(Error::UnexpectedType {
+                            expected: TNetStringType::Str,
+                            found,
+                        })
+                    }
+                    Err(_) => return Err(Error::UnknownSegmentType),
+                }
+                payload = skip_value(payload)?;
+                if payload.is_empty() {
+                    return Err(Error::ParsingMap);
+                }
+                payload = skip_value(payload)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(rest)
+}
+
+fn count_elements(mut payload: &[u8]) -> Result<usize> {
+    let mut count = 0;
+    while !payload.is_empty() {
+        payload = split_payload(payload)?.1;
+        count += 1;
     }
+    Ok(count)
 }
 
 impl<'de> Deserializer<'de> {
+    // Checks the value at the front of the input is tagged `expected`. A bad
+    // prefix or truncated value is left for `split_payload` to report.
+    fn expect(&self, expected: TNetStringType) -> Result<()> {
+        match parse_type(self.input) {
+            Ok(found) if found != expected => Err(self.unexpected(expected)),
+            Err(TNetStringError::EmptyInput) => Err(self.unexpected(expected)),
+            _ => Ok(()),
+        }
+    }
+
+    // `expect`, naming the Rust type being read in the error.
+    fn expect_for(&self, expected: TNetStringType, target: &dyn Display) -> Result<()> {
+        self.expect(expected)
+            .map_err(|_| self.unexpected_for(expected, target))
+    }
+
+    fn unexpected_for(&self, expected: TNetStringType, target: &dyn Display) -> Error {
+        match self.unexpected(expected) {
+            Error::Expected {
+                expected,
+                found,
+                offset,
+                snippet,
+                ..
+            } => Error::Expected {
+                expected,
+                target: Some(target.to_string()),
+                found,
+                offset,
+                snippet,
+            },
+            error => error,
+        }
+    }
+
+    // The error for a value at the front of the input that isn't `expected`,
+    // with the bytes around it.
+    fn unexpected(&self, expected: TNetStringType) -> Error {
+        let offset = self.offset();
+        let found = parse(self.input)
+            .ok()
+            .map(|parsed| self.input[self.input.len() - parsed.rest.len() - 1]);
+        let from = offset.saturating_sub(SNIPPET_LEN);
+        let to = self.start.len().min(offset + SNIPPET_LEN);
+        Error::Expected {
+            expected,
+            target: None,
+            found,
+            offset,
+            snippet: format!(
+                "{}{}{}",
+                if from > 0 { "..." } else { "" },
+                self.start[from..to].escape_ascii(),
+                if to < self.start.len() { "..." } else { "" },
+            ),
+        }
+    }
+
     fn parse_bool(&mut self) -> Result<bool> {
-        if self.input.starts_with("4:true!") {
-            self.input = &self.input["4:true!".len()..];
+        if self.input.starts_with(b"4:true!") {
+            self.trace_value();
+            self.input = &self.input[b"4:true!".len()..];
             Ok(true)
-        } else if self.input.starts_with("5:false!") {
-            self.input = &self.input["5:false!".len()..];
+        } else if self.input.starts_with(b"5:false!") {
+            self.trace_value();
+            self.input = &self.input[b"5:false!".len()..];
             Ok(false)
+        } else if self.lenient_bool && parse_type(self.input) == Ok(TNetStringType::Int) {
+            match self.parse_integer::<i64>("i64")? {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(Error::ParsingBool),
+            }
         } else {
+            self.expect(TNetStringType::Bool)?;
             Err(Error::ParsingBool)
         }
     }
 
-    fn last_char(&self) -> Result<char> {
-        self.input.chars().last().ok_or(Error::Eof)
-    }
-
-    fn parse_unsigned<T>(&mut self) -> Result<T>
+    // Runs `f` with the input narrowed to the payload of the container at the
+    // front of the input, then moves past the container.
+    fn visit_container<F, T>(&mut self, f: F) -> Result<T>
     where
-        T: AddAssign<T> + MulAssign<T> + From<u8>,
+        F: FnOnce(&mut Self) -> Result<T>,
     {
-        let start_pos = match self.input.find(':') {
-            Some(len) => len + 1,
-            _ => return Err(Error::ParsingUnsigned),
-        };
-
-        let val_len: usize = match self.input[..start_pos - 1].parse() {
-            Ok(v) => v,
-            _ => return Err(Error::ParsingUnsigned),
-        };
+        self.trace_value();
+        let start = self.offset();
+        let (payload, rest) = split_payload(self.input)?;
+        self.enter()?;
+        self.input = payload;
+        let value = f(self).and_then(|value| self.end().map(|()| value));
+        // restored even on errors, which a `StreamDeserializer` can skip past
+        self.remaining_depth = self.remaining_depth.wrapping_add(1);
+        let value = value?;
+        self.input = rest;
+        self.trace_close(start, payload);
+        Ok(value)
+    }
 
-        let data = &self.input[start_pos..start_pos + val_len];
+    // With `trace-parse`, logs the value at the front of the input before
+    // it's read. These compile to nothing without it.
+    #[cfg(feature = "trace-parse")]
+    fn trace_value(&self) {
+        if let Ok(parsed) = parse(self.input) {
+            let start = self.offset();
+            let end = start + self.input.len() - parsed.rest.len();
+            log::trace!(
+                "{}..{} {}, length {}",
+                start,
+                end,
+                parsed.kind,
+                parsed.payload.len()
+            );
+        }
+    }
 
-        let mut int = T::from(0);
-        for c in data.chars() {
-            int *= T::from(10);
-            int += T::from(c as u8 - b'0')
+    // Logs the end of the list or dict whose `payload` was just read.
+    #[cfg(feature = "trace-parse")]
+    fn trace_close(&self, start: usize, payload: &[u8]) {
+        let end = self.offset();
+        let count = count_elements(payload).unwrap_or(0);
+        match self.start[end - 1] {
+            b'}' => log::trace!("{}..{} dict closed, {} entries", start, end, count / 2),
+            _ => log::trace!("{}..{} list closed, {} elements", start, end, count),
         }
-        self.input = &self.input[start_pos + val_len + 1..];
-        Ok(int)
     }
 
-    fn parse_signed<T>(&mut self) -> Result<T>
-    where
-        T: Neg<Output = T> + AddAssign<T> + SubAssign<T> + MulAssign<T> + From<i8>,
-    {
-        let start_pos = match self.input.find(':') {
-            Some(len) => len + 1,
-            _ => return Err(Error::ParsingString),
-        };
+    #[cfg(feature = "trace-parse")]
+    fn trace_error(&self, error: &Error) {
+        log::warn!("failed at byte {}: {}", self.offset(), error);
+    }
 
-        let val_len: usize = match self.input[..start_pos - 1].parse() {
-            Ok(v) => v,
-            _ => return Err(Error::ParsingUnsigned),
-        };
+    #[cfg(not(feature = "trace-parse"))]
+    fn trace_value(&self) {}
 
-        let data = &self.input[start_pos..start_pos + val_len];
+    #[cfg(not(feature = "trace-parse"))]
+    fn trace_close(&self, _start: usize, _payload: &[u8]) {}
 
-        let is_negated = match data.chars().nth(0) {
-            Some('-') => true,
-            _ => false,
-        };
+    #[cfg(not(feature = "trace-parse"))]
+    fn trace_error(&self, _error: &Error) {}
 
-        let mut num = T::from(0);
-        let skip = if is_negated { 1 } else { 0 };
+    fn offset(&self) -> usize {
+        self.input.as_ptr() as usize - self.start.as_ptr() as usize
+    }
 
-        for c in data.chars().skip(skip) {
-            num *= T::from(10);
-            let adder = T::from(c as i8 - b'0' as i8);
-            if is_negated {
-                num -= adder;
-            } else {
-                num += adder;
+    fn enter(&mut self) -> Result<()> {
+        #[cfg(feature = "unbounded_depth")]
+        {
+            if self.unbounded_depth {
+                // still counted, wrapping, so leaving is the same either way
+                self.remaining_depth = self.remaining_depth.wrapping_sub(1);
+                return Ok(());
             }
         }
+        if self.remaining_depth == 0 {
+            return Err(Error::At {
+                offset: self.offset(),
+                error: Box::new(Error::DepthLimitExceeded(RECURSION_LIMIT)),
+            });
+        }
+        self.remaining_depth -= 1;
+        Ok(())
+    }
 
-        self.input = &self.input[start_pos + val_len + 1..];
+    // Parsed rather than accumulated digit by digit, so integers too wide
+    // for `T` fail instead of overflowing. Those are an invalid value for
+    // the type `name`, while payloads that aren't an integer at all are
+    // `Error::ParsingInt`.
+    fn parse_integer<T>(&mut self, name: &'static str) -> Result<T>
+    where
+        T: FromStr,
+    {
+        self.expect_for(TNetStringType::Int, &format_args!("`{}`", name))?;
+        self.trace_value();
+        let (data, rest) = split_payload(self.input)?;
+        let digits = str::from_utf8(data).map_err(|_| Error::ParsingInt)?;
+        let num = match digits.parse() {
+            Ok(num) => num,
+            Err(_) if is_integer(digits) => return Err(out_of_range(digits, name)),
+            Err(_) => return Err(Error::ParsingInt),
+        };
+        self.input = rest;
         Ok(num)
     }
 
-    fn parse_string(&mut self) -> Result<&'de str> {
-        if let Ok(TNetStringType::Str) = parse_type(self.input.as_bytes()) {
-            let start_pos = match self.input.find(':') {
-                Some(len) => len + 1,
-                _ => {
-                    return Err(Error::ParsingString);
-                }
-            };
-            let val_len: usize = match self.input[..start_pos - 1].parse() {
-                Ok(v) => v,
-                _ => return Err(Error::ParsingUnsigned),
-            };
-            let end_pos = val_len + start_pos;
-            let val = &self.input[start_pos..end_pos];
-            self.input = &self.input[end_pos + 1..];
-            Ok(val)
-        } else {
-            Err(Error::ParsingString)
+    fn parse_float<T>(&mut self, name: &'static str) -> Result<T>
+    where
+        T: FromStr,
+    {
+        self.expect_for(TNetStringType::Float, &format_args!("`{}`", name))?;
+        self.trace_value();
+        let (data, rest) = split_payload(self.input)?;
+        let num = str::from_utf8(data)
+            .ok()
+            .and_then(|data| data.parse().ok())
+            .ok_or(Error::ParsingFloat)?;
+        self.input = rest;
+        Ok(num)
+    }
+
+    fn parse_bytes(&mut self) -> Result<&'de [u8]> {
+        self.expect(TNetStringType::Str)?;
+        self.trace_value();
+        let (val, rest) = split_payload(self.input)?;
+        self.input = rest;
+        Ok(val)
+    }
+
+    fn parse_string(&mut self) -> Result<Cow<'de, str>> {
+        let bytes = self.parse_bytes()?;
+        match str::from_utf8(bytes) {
+            Ok(s) => Ok(Cow::Borrowed(s)),
+            Err(_) if self.lossy_utf8 => Ok(String::from_utf8_lossy(bytes)),
+            Err(error) => Err(Error::InvalidUtf8 {
+                offset: bytes.as_ptr() as usize - self.start.as_ptr() as usize
+                    + error.valid_up_to(),
+                error,
+            }),
         }
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        match self.last_char()? {
-            '~' => self.deserialize_unit(visitor),
-            '!' => self.deserialize_bool(visitor),
-            ',' => self.deserialize_str(visitor),
-            '^' => self.deserialize_f64(visitor),
-            '#' => self.deserialize_i64(visitor),
-            ']' => self.deserialize_seq(visitor),
-            '}' => self.deserialize_map(visitor),
-            _ => Err(Error::UnknownSegmentType),
+        if self.input.is_empty() {
+            return Err(Error::Eof);
+        }
+        match parse_type(self.input) {
+            Ok(TNetStringType::Null) => self.deserialize_unit(visitor),
+            Ok(TNetStringType::Bool) => self.deserialize_bool(visitor),
+            Ok(TNetStringType::Str) => {
+                let v = self.parse_bytes()?;
+                match str::from_utf8(v) {
+                    Ok(v) => visitor.visit_borrowed_str(v),
+                    Err(_) => visitor.visit_borrowed_bytes(v),
+                }
+            }
+            Ok(TNetStringType::Float) => self.deserialize_f64(visitor),
+            Ok(TNetStringType::Int) => {
+                // only integers past `i64::MAX` are read as unsigned
+                let (data, _) = split_payload(self.input)?;
+                let fits_i64 = str::from_utf8(data).map_or(true, |s| s.parse::<i64>().is_ok());
+                #[cfg(feature = "arbitrary_precision")]
+                {
+                    let fits_u64 = str::from_utf8(data).map_or(true, |s| s.parse::<u64>().is_ok());
+                    if !fits_i64 && !fits_u64 {
+                        if let Some(n) = str::from_utf8(data)
+                            .ok()
+                            .and_then(crate::number::Number::from_digits)
+                        {
+                            self.trace_value();
+                            let (_, rest) = split_payload(self.input)?;
+                            self.input = rest;
+                            return n.deserialize_any(visitor);
+                        }
+                    }
+                }
+                if !fits_i64 && !data.starts_with(b"-") {
+                    self.deserialize_u64(visitor)
+                } else {
+                    self.deserialize_i64(visitor)
+                }
+            }
+            Ok(TNetStringType::List) => self.deserialize_seq(visitor),
+            Ok(TNetStringType::Dict) => self.deserialize_map(visitor),
+            Err(_) => Err(Error::UnknownSegmentType),
         }
-    }
-
-    forward_to_deserialize_any! {
-        i8 i16 i32 u8 u16 char unit_struct tuple struct bytes byte_buf
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -192,46 +731,111 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_bool(self.parse_bool()?)
     }
 
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(self.parse_integer("i8")?)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(self.parse_integer("i16")?)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(self.parse_integer("i32")?)
+    }
+
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i64(self.parse_signed()?)
+        visitor.visit_i64(self.parse_integer("i64")?)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.parse_integer("u8")?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(self.parse_integer("u16")?)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u32(self.parse_unsigned()?)
+        visitor.visit_u32(self.parse_integer("u32")?)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u64(self.parse_unsigned()?)
+        visitor.visit_u64(self.parse_integer("u64")?)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(self.parse_integer("i128")?)
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(self.parse_integer("u128")?)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f32(self.parse_float("f32")?)
     }
 
-    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::UnsupportedType)
+        visitor.visit_f64(self.parse_float("f64")?)
     }
 
-    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::UnsupportedType)
+        self.expect_for(TNetStringType::Str, &"`char`")?;
+        let s = self.parse_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::ParsingChar(s.to_string(), s.chars().count())),
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_borrowed_str(self.parse_string()?)
+        match self.parse_string()? {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -241,12 +845,27 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_str(visitor)
     }
 
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.parse_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if self.input.starts_with("0:~") {
-            self.input = &self.input["0:~".len()..];
+        if self.input.starts_with(b"0:~") {
+            self.trace_value();
+            self.input = &self.input[b"0:~".len()..];
             visitor.visit_none()
         } else {
             visitor.visit_some(self)
@@ -257,64 +876,138 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if self.input.starts_with("0:~") {
-            self.input = &self.input["0:~".len()..];
+        if self.input.starts_with(b"0:~") {
+            self.trace_value();
+            self.input = &self.input[b"0:~".len()..];
             visitor.visit_unit()
         } else {
             Err(Error::ParsingUnit)
         }
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == raw::TOKEN {
+            self.trace_value();
+            let rest = skip_value(self.input)?;
+            let frame = &self.input[..self.input.len() - rest.len()];
+            self.input = rest;
+            return visitor.visit_borrowed_bytes(frame);
+        }
+        if name == intern::TOKEN {
+            // a cheap handle clone, as parsing needs `self` mutably
+            if let Some(interner) = self.interner.clone() {
+                let s = self.parse_string()?;
+                return intern::visit_interned(interner.intern(&s), |s| visitor.visit_str(s));
+            }
+        }
         visitor.visit_newtype_struct(self)
     }
 
-    fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if let Ok(TNetStringType::List) = parse_type(self.input.as_bytes()) {
-            let start_pos = match self.input.find(':') {
-                Some(len) => len + 1,
-                _ => return Err(Error::ParsingString),
-            };
-            self.input = &self.input[start_pos..self.input.len() - 1];
-            let value = visitor.visit_seq(TNetStringAccess::new(&mut self))?;
-            Ok(value)
-        } else {
-            Err(Error::ParsingSeq)
+        match parse_type(self.input) {
+            Ok(TNetStringType::List) => {
+                self.visit_container(|de| visitor.visit_seq(TNetStringAccess::new(de)))
+            }
+            Ok(_) => Err(self.unexpected(TNetStringType::List)),
+            Err(_) => Err(Error::ParsingSeq),
         }
     }
 
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.expect_for(
+            TNetStringType::Null,
+            &format_args!("unit struct `{}`", name),
+        )?;
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.expect_for(TNetStringType::List, &format_args!("a tuple of {}", len))?;
+        self.deserialize_seq(visitor)
+    }
+
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.expect_for(
+            TNetStringType::List,
+            &format_args!("tuple struct `{}`", name),
+        )?;
         self.deserialize_seq(visitor)
     }
 
-    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if let Ok(TNetStringType::Dict) = parse_type(self.input.as_bytes()) {
-            let start_pos = match self.input.find(':') {
-                Some(len) => len + 1,
-                _ => return Err(Error::ParsingString),
-            };
-            self.input = &self.input[start_pos..self.input.len() - 1];
-            let value = visitor.visit_map(TNetStringAccess::new(&mut self))?;
-            Ok(value)
-        } else {
-            Err(Error::ParsingMap)
+        match parse_type(self.input) {
+            Ok(TNetStringType::Dict) => {
+                self.visit_container(|de| visitor.visit_map(TNetStringAccess::new(de)))
+            }
+            Ok(_) => Err(self.unexpected(TNetStringType::Dict)),
+            Err(_) => Err(Error::ParsingMap),
+        }
+    }
+
+    // Structs are read from a dict by field name, or from a list by field
+    // position when they were written with `to_string_compact`.
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == spanned::NAME && fields == spanned::FIELDS {
+            let (_, rest) = split_payload(self.input)?;
+            let start = self.offset();
+            let end = start + self.input.len() - rest.len();
+            return visitor.visit_map(SpannedAccess {
+                de: self,
+                start,
+                end,
+                field: 0,
+            });
+        }
+        match parse_type(self.input) {
+            Ok(TNetStringType::Dict) if self.case_insensitive_fields => self
+                .visit_container(|de| visitor.visit_map(TNetStringAccess::with_fields(de, fields))),
+            Ok(TNetStringType::Dict) => self.deserialize_map(visitor),
+            Ok(TNetStringType::List) => {
+                let found = count_elements(split_payload(self.input)?.0)?;
+                if found != fields.len() {
+                    return Err(Error::StructLength {
+                        name,
+                        expected: fields.len(),
+                        found,
+                    });
+                }
+                self.deserialize_seq(visitor)
+            }
+            Ok(_) | Err(TNetStringError::EmptyInput) => {
+                Err(self.unexpected_for(TNetStringType::Dict, &format_args!("struct `{}`", name)))
+            }
+            Err(_) => Err(Error::ParsingMap),
         }
     }
 
@@ -327,27 +1020,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let Ok(TNetStringType::Str) = parse_type(self.input.as_bytes()) {
-            let start_pos = match self.input.find(':') {
-                Some(len) => len + 1,
-                _ => return Err(Error::ParsingLength),
-            };
-
-            let val = &self.input[start_pos..self.input.len() - 1];
-
-            self.input = &self.input[self.input.len()..];
-
-            visitor.visit_enum(val.into_deserializer())
-        } else if let Ok(TNetStringType::Dict) = parse_type(self.input.as_bytes()) {
-            let start_pos = match self.input.find(':') {
-                Some(len) => len + 1,
-                _ => return Err(Error::ParsingUnsigned),
-            };
-
-            self.input = &self.input[start_pos..self.input.len() - 1];
-
-            let value = visitor.visit_enum(Enum::new(self))?;
-            Ok(value)
+        if let Ok(TNetStringType::Str) = parse_type(self.input) {
+            visitor.visit_enum(self.parse_string()?.into_deserializer())
+        } else if let Ok(TNetStringType::Dict) = parse_type(self.input) {
+            self.visit_container(|de| visitor.visit_enum(Enum::new(de)))
         } else {
             Err(Error::ParsingEnum)
         }
@@ -370,15 +1046,24 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
 struct TNetStringAccess<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
+    // struct fields to match keys against ignoring case
+    fields: Option<&'static [&'static str]>,
 }
 
 impl<'a, 'de> TNetStringAccess<'a, 'de> {
     fn new(de: &'a mut Deserializer<'de>) -> Self {
-        TNetStringAccess { de }
+        TNetStringAccess { de, fields: None }
+    }
+
+    fn with_fields(de: &'a mut Deserializer<'de>, fields: &'static [&'static str]) -> Self {
+        TNetStringAccess {
+            de,
+            fields: Some(fields),
+        }
     }
 }
 
-impl<'de, 'a> SeqAccess<'de> for TNetStringAccess<'a, 'de> {
+impl<'de> SeqAccess<'de> for TNetStringAccess<'_, 'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -392,7 +1077,7 @@ impl<'de, 'a> SeqAccess<'de> for TNetStringAccess<'a, 'de> {
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for TNetStringAccess<'a, 'de> {
+impl<'de> MapAccess<'de> for TNetStringAccess<'_, 'de> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -402,6 +1087,18 @@ impl<'de, 'a> MapAccess<'de> for TNetStringAccess<'a, 'de> {
         if self.de.input.is_empty() {
             return Ok(None);
         }
+        if let Some(fields) = self.fields {
+            let key = self.de.parse_string()?;
+            let lowercase = || key.chars().flat_map(char::to_lowercase);
+            return match fields
+                .iter()
+                .find(|field| field.chars().flat_map(char::to_lowercase).eq(lowercase()))
+            {
+                Some(field) => seed.deserialize(BorrowedStrDeserializer::new(field)),
+                None => seed.deserialize(key.into_deserializer()),
+            }
+            .map(Some);
+        }
         seed.deserialize(&mut *self.de).map(Some)
     }
 
@@ -413,17 +1110,55 @@ impl<'de, 'a> MapAccess<'de> for TNetStringAccess<'a, 'de> {
     }
 }
 
-struct Enum<'a, 'de: 'a> {
+// Hands a `Spanned` the span of the value at the front of the input, then
+// the value itself.
+struct SpannedAccess<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
+    start: usize,
+    end: usize,
+    // which of `spanned::FIELDS` is next
+    field: usize,
 }
 
-impl<'a, 'de> Enum<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
-        Enum { de }
-    }
+impl<'de> MapAccess<'de> for SpannedAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match spanned::FIELDS.get(self.field) {
+            Some(&field) => seed
+                .deserialize(BorrowedStrDeserializer::new(field))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.field += 1;
+        match self.field {
+            1 => seed.deserialize(UsizeDeserializer::new(self.start)),
+            2 => seed.deserialize(UsizeDeserializer::new(self.end)),
+            _ => seed.deserialize(&mut *self.de),
+        }
+    }
+}
+
+struct Enum<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'a, 'de> Enum<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>) -> Self {
+        Enum { de }
+    }
 }
 
-impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
+impl<'de> EnumAccess<'de> for Enum<'_, 'de> {
     type Error = Error;
     type Variant = Self;
 
@@ -436,12 +1171,13 @@ impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
     }
 }
 
-impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
+impl<'de> VariantAccess<'de> for Enum<'_, 'de> {
     type Error = Error;
 
-    // handled in `deserialize_enum`.
+    // a bare string is handled in `deserialize_enum`, this is the
+    // `{name: null}` form
     fn unit_variant(self) -> Result<()> {
-        Err(Error::ParsingUnitVariant)
+        Deserialize::deserialize(self.de)
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -458,22 +1194,30 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
         de::Deserializer::deserialize_seq(self.de, visitor)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if self.de.case_insensitive_fields
+            && matches!(parse_type(self.de.input), Ok(TNetStringType::Dict))
+        {
+            return self.de.visit_container(|de| {
+                visitor.visit_map(TNetStringAccess::with_fields(de, fields))
+            });
+        }
         de::Deserializer::deserialize_map(self.de, visitor)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::from_str;
-    use super::{Error, Result};
-    use crate::error::Error::Message;
-    use serde::Deserialize;
-    use std::f32;
-    use std::f64;
+    use super::{from_slice, from_str, get};
+    use super::{Deserializer, Error, Result};
+    use crate::parse::TNetStringType;
+    use crate::ser::{to_string, to_string_compact, to_vec};
+    use crate::testing::{assert_decodes_from, assert_encodes_to, assert_roundtrip};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_struct() {
@@ -488,7 +1232,209 @@ mod tests {
             int: 1,
             seq: vec!["a".to_owned(), "b".to_owned()],
         };
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
+    }
+
+    #[test]
+    fn test_struct_compact() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Inner {
+            a: u32,
+            b: Option<String>,
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Outer {
+            inner: Inner,
+            seq: Vec<Inner>,
+            flag: bool,
+        }
+
+        let test = Outer {
+            inner: Inner {
+                a: 1,
+                b: Some("x".into()),
+            },
+            seq: vec![Inner { a: 2, b: None }, Inner { a: 3, b: None }],
+            flag: true,
+        };
+
+        let compact = to_string_compact(&test).unwrap();
+        assert_eq!(Ok(&test), from_str(&compact).as_ref());
+
+        let named = to_string(&test).unwrap();
+        assert_eq!(Ok(&test), from_str(&named).as_ref());
+
+        let t = "7:1:1#0:~]";
+        assert_eq!(Ok(Inner { a: 1, b: None }), from_str(t));
+
+        let t = "4:1:1#]";
+        let actual: Result<Inner> = from_str(t);
+        assert_eq!(
+            Err(Error::StructLength {
+                name: "Inner",
+                expected: 2,
+                found: 1,
+            }),
+            actual
+        );
+
+        let t = "10:1:1#0:~0:~]";
+        let actual: Result<Inner> = from_str(t);
+        assert_eq!(
+            "expected struct Inner with 2 fields, found a list of 3",
+            actual.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_fields() {
+        use std::collections::HashMap;
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            #[serde(rename = "content-type")]
+            content_type: String,
+            headers: HashMap<String, String>,
+        }
+
+        let t = "55:12:Content-Type,4:text,7:HEADERS,18:5:X-Foo,7:Bar-Baz,}}";
+
+        let mut de = Deserializer::from_str(t).case_insensitive_fields(true);
+        let actual = Test::deserialize(&mut de).and_then(|t| de.end().map(|_| t));
+        let expected = Test {
+            content_type: "text".into(),
+            headers: vec![("X-Foo".into(), "Bar-Baz".into())]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(Ok(expected), actual);
+
+        let actual: Result<Test> = from_str(t);
+        assert_eq!(Err(Error::MissingField("content-type")), actual);
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        enum Message {
+            Reply {
+                #[serde(rename = "content-type")]
+                content_type: String,
+                status: u32,
+            },
+        }
+
+        let t = "50:5:Reply,38:12:Content-Type,4:text,6:STATUS,3:200#}}";
+        let mut de = Deserializer::from_str(t).case_insensitive_fields(true);
+        let actual = Message::deserialize(&mut de).and_then(|t| de.end().map(|_| t));
+        let expected = Message::Reply {
+            content_type: "text".into(),
+            status: 200,
+        };
+        assert_eq!(Ok(expected), actual);
+        assert!(from_str::<Message>(t).is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        #[derive(Serialize)]
+        struct Big {
+            seq: Vec<Vec<String>>,
+            nested: Vec<(u32, String)>,
+        }
+
+        #[derive(Serialize)]
+        struct Message {
+            route: String,
+            body: Big,
+            id: u32,
+        }
+
+        let message = Message {
+            route: "users.create".into(),
+            body: Big {
+                seq: vec![vec!["x".repeat(100); 10]; 10],
+                nested: (0..100).map(|i| (i, i.to_string())).collect(),
+            },
+            id: 42,
+        };
+        let t = to_string(&message).unwrap();
+
+        assert_eq!(Ok(Some("users.create")), get(&t, "route"));
+        assert_eq!(Ok(Some(42)), get::<u32>(&t, "id"));
+        assert_eq!(Ok(None), get::<u32>(&t, "missing"));
+
+        #[derive(Deserialize)]
+        struct Body {
+            nested: Vec<(u32, String)>,
+        }
+
+        let body: Option<Body> = get(&t, "body").unwrap();
+        let nested = body.unwrap().nested;
+        assert_eq!(100, nested.len());
+        assert_eq!((99, "99".to_string()), nested[99]);
+
+        assert_eq!(
+            Err(Error::UnexpectedType {
+                expected: TNetStringType::Dict,
+                found: TNetStringType::List,
+            }),
+            get::<u32>("4:1:1#]", "id")
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let t = "3:one,3:two,5:three,";
+        let values: Result<Vec<String>> = Deserializer::from_str(t).into_iter().collect();
+        assert_eq!(Ok(vec!["one".into(), "two".into(), "three".into()]), values);
+
+        let t = "3:one,5:tw5:three,";
+        let mut stream = Deserializer::from_str(t).into_iter::<String>();
+        assert_eq!(Some(Ok("one".into())), stream.next());
+        assert!(stream.next().unwrap().is_err());
+        assert_eq!(None, stream.next());
+
+        let mut stream = Deserializer::from_str(t).into_iter::<String>().resync(true);
+        assert_eq!(Some(Ok("one".into())), stream.next());
+        assert_eq!(Some(Ok("three".into())), stream.next());
+        assert_eq!(None, stream.next());
+        assert_eq!(4, stream.skipped());
+
+        // a frame of the wrong type isn't skipped
+        let t = "3:one,1:2#5:three,";
+        let mut stream = Deserializer::from_str(t).into_iter::<String>().resync(true);
+        assert_eq!(Some(Ok("one".into())), stream.next());
+        assert!(stream.next().unwrap().unwrap_err().is_data());
+        assert_eq!(Some(Ok("three".into())), stream.next());
+        assert_eq!(None, stream.next());
+        assert_eq!(0, stream.skipped());
+    }
+
+    #[test]
+    fn test_separators() {
+        let expected = vec!["one".to_owned(), "two".into(), "a\nb".into()];
+        for (input, separators) in &[
+            ("3:one,\n3:two,\n3:a\nb,\n", &b"\n"[..]),
+            ("3:one,\r\n3:two,\r\n3:a\nb,\r\n", b"\r\n"),
+            ("\n\n3:one,3:two,\n\r\n3:a\nb,", b"\r\n"),
+            ("3:one,3:two,3:a\nb,", b""),
+        ] {
+            let values: Result<Vec<String>> = Deserializer::from_str(input)
+                .into_iter()
+                .separators(separators)
+                .collect();
+            assert_eq!(values.as_ref(), Ok(&expected), "{:?}", input);
+        }
+
+        // inside a frame the separator is part of the payload
+        let values: Result<Vec<String>> = Deserializer::from_str("4:\none,\n")
+            .into_iter()
+            .separators(b"\n")
+            .collect();
+        assert_eq!(values, Ok(vec!["\none".to_owned()]));
+        // and without the option it's an error
+        let mut stream = Deserializer::from_str("3:one,\n3:two,").into_iter::<String>();
+        assert_eq!(stream.next(), Some(Ok("one".to_owned())));
+        assert!(stream.next().unwrap().is_err());
     }
 
     #[test]
@@ -498,7 +1444,7 @@ mod tests {
 
         let t = "2:-1#";
         let expected = Test(-1);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -510,11 +1456,11 @@ mod tests {
 
         let input = "13:6:option,1:1#}";
         let expected = Test { option: Some(1) };
-        assert_eq!(Ok(expected), from_str(input));
+        assert_decodes_from(input, &expected);
 
         let input = "12:6:option,0:~}";
         let expected = Test { option: None };
-        assert_eq!(Ok(expected), from_str(input));
+        assert_decodes_from(input, &expected);
     }
 
     #[test]
@@ -531,27 +1477,186 @@ mod tests {
 
         let j = "4:Unit,";
         let expected = E::Unit;
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
 
         let j = "3:Foo,";
         let expected = E::Foo;
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
 
         let j = "14:7:Newtype,1:1#}";
         let expected = E::Newtype(1);
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
 
         let j = "9:1:N,2:20#}";
         let expected = E::N(20);
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
 
         let j = "19:5:Tuple,8:1:1#1:2#]}";
         let expected = E::Tuple(1, 2);
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
 
         let j = "20:6:Struct,8:1:a,1:1#}}";
         let expected = E::Struct { a: 1 };
-        assert_eq!(Ok(expected), from_str(j));
+        assert_decodes_from(j, &expected);
+    }
+
+    #[test]
+    fn test_newtype_variant_nested() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        enum E {
+            T(u32),
+            S(String),
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            first: u32,
+            e: E,
+            map: BTreeMap<String, E>,
+            list: Vec<E>,
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), E::T(1));
+        map.insert("b".to_string(), E::S("foo".into()));
+        let test = Test {
+            first: 1,
+            e: E::T(2),
+            map,
+            list: vec![E::S("bar".into()), E::T(3), E::T(4)],
+        };
+        let t = to_string(&test).unwrap();
+        assert_eq!(Ok(test), from_str(&t));
+    }
+
+    #[test]
+    fn test_tuple_variant_nested() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        enum E {
+            T(u32, u32),
+            U(String, Vec<u32>, bool),
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            first: u32,
+            e: E,
+            last: String,
+        }
+
+        let list = vec![E::T(1, 2), E::T(3, 4)];
+        let t = to_string(&list).unwrap();
+        assert_eq!("38:15:1:T,8:1:1#1:2#]}15:1:T,8:1:3#1:4#]}]", t);
+        assert_eq!(Ok(list), from_str(&t));
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), E::T(1, 2));
+        map.insert("b".to_string(), E::U("foo".into(), vec![5], true));
+        let t = to_string(&map).unwrap();
+        assert_eq!(Ok(map), from_str(&t));
+
+        let test = Test {
+            first: 1,
+            e: E::U("bar".into(), vec![1, 2, 3], false),
+            last: "end".into(),
+        };
+        let t = to_string(&test).unwrap();
+        assert_eq!(Ok(test), from_str(&t));
+    }
+
+    #[test]
+    fn test_unit_variants_as_dicts() {
+        use crate::ser::{to_string_with, SerializerConfig};
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        enum E {
+            Unit,
+            Newtype(u32),
+            Tuple(u32, u32),
+            Struct { a: u32 },
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            list: Vec<E>,
+            map: BTreeMap<String, E>,
+        }
+
+        let config = SerializerConfig::new().unit_variants_as_dicts(true);
+        assert_eq!(to_string(&E::Unit), Ok("4:Unit,".into()));
+        assert_eq!(
+            to_string_with(&E::Unit, &config),
+            Ok("10:4:Unit,0:~}".into())
+        );
+        assert_eq!(Ok(E::Unit), from_str("4:Unit,"));
+        assert_eq!(Ok(E::Unit), from_str("10:4:Unit,0:~}"));
+        assert_eq!(Err(Error::ParsingUnit), from_str::<E>("11:4:Unit,1:1#}"));
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), E::Unit);
+        map.insert("b".to_string(), E::Newtype(1));
+        map.insert("c".to_string(), E::Unit);
+        let test = Test {
+            list: vec![
+                E::Unit,
+                E::Newtype(2),
+                E::Tuple(3, 4),
+                E::Struct { a: 5 },
+                E::Unit,
+            ],
+            map,
+        };
+        let t = to_string(&test).unwrap();
+        assert_eq!(Ok(&test), from_str(&t).as_ref());
+        let t = to_string_with(&test, &config).unwrap();
+        assert!(!t.contains("4:Unit,1:"));
+        assert_eq!(Ok(&test), from_str(&t).as_ref());
+    }
+
+    #[test]
+    fn test_struct_variant() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        enum E {
+            Two { host: String, port: u16 },
+            Three { a: u32, b: String, c: Vec<u32> },
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            first: E,
+            rest: Vec<E>,
+            last: u32,
+        }
+
+        let two = E::Two {
+            host: "localhost".into(),
+            port: 80,
+        };
+        let t = to_string(&two).unwrap();
+        assert_eq!("41:3:Two,31:4:host,9:localhost,4:port,2:80#}}", t);
+        assert_eq!(Ok(&two), from_str(&t).as_ref());
+
+        let three = E::Three {
+            a: 1,
+            b: "b".into(),
+            c: vec![1, 2],
+        };
+        let t = to_string(&three).unwrap();
+        assert_eq!(Ok(&three), from_str(&t).as_ref());
+
+        let test = Test {
+            first: three,
+            rest: vec![
+                two,
+                E::Two {
+                    host: "example.com".into(),
+                    port: 443,
+                },
+            ],
+            last: 7,
+        };
+        let t = to_string(&test).unwrap();
+        assert_eq!(Ok(test), from_str(&t));
     }
 
     #[test]
@@ -571,11 +1676,42 @@ mod tests {
 
         let t = "4:true!";
         let expected = Test(true);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
 
         let t = "5:false!";
         let expected = Test(false);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
+    }
+
+    #[test]
+    fn test_lenient_bool() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            flag: bool,
+        }
+
+        let from_str_lenient = |input| -> Result<Test> {
+            let mut de = Deserializer::from_str(input).lenient_bool(true);
+            let t = Test::deserialize(&mut de)?;
+            de.end()?;
+            Ok(t)
+        };
+
+        let t = "11:4:flag,1:1#}";
+        assert_eq!(Ok(Test { flag: true }), from_str_lenient(t));
+        let actual: Result<Test> = from_str(t);
+        assert!(matches!(actual, Err(Error::Expected { offset: 10, .. })));
+
+        let t = "11:4:flag,1:0#}";
+        assert_eq!(Ok(Test { flag: false }), from_str_lenient(t));
+        let actual: Result<Test> = from_str(t);
+        assert!(matches!(actual, Err(Error::Expected { offset: 10, .. })));
+
+        let t = "11:4:flag,1:2#}";
+        assert_eq!(Err(Error::ParsingBool), from_str_lenient(t));
+
+        let t = "14:4:flag,4:true!}";
+        assert_eq!(Ok(Test { flag: true }), from_str_lenient(t));
     }
 
     #[test]
@@ -585,16 +1721,40 @@ mod tests {
 
         let t = "1:a,";
         let expected = Test('a');
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
+
+        let t = "4:🦀,";
+        let expected = Test('🦀');
+        assert_decodes_from(t, &expected);
+
+        let t = "0:,";
+        let actual: Result<Test> = from_str(t);
+        assert_eq!(Err(Error::ParsingChar("".into(), 0)), actual);
 
         let t = "2:ab,";
         let actual: Result<Test> = from_str(t);
+        assert_eq!(Err(Error::ParsingChar("ab".into(), 2)), actual);
+        assert_eq!(
+            "error parsing char: expected a single character, found \"ab\" with 2 characters",
+            actual.unwrap_err().to_string()
+        );
+
+        let t = "1:1#";
+        let actual: Result<Test> = from_str(t);
         assert_eq!(
-            Err(Message(
-                "invalid value: string \"ab\", expected a character".into()
-            )),
+            Err(Error::Expected {
+                expected: TNetStringType::Str,
+                target: Some("`char`".into()),
+                found: Some(b'#'),
+                offset: 0,
+                snippet: "1:1#".into(),
+            }),
             actual
         );
+        assert_eq!(
+            "expected `char` (string), found integer `#` at byte 0 in `1:1#`",
+            actual.unwrap_err().to_string()
+        );
     }
 
     #[test]
@@ -604,7 +1764,7 @@ mod tests {
 
         let t = "1:a,";
         let expected = Test(String::from("a"));
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -623,8 +1783,8 @@ mod tests {
         struct Test(u8);
 
         let t = "3:255#";
-        let expected = Test(u8::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(u8::MAX);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -633,8 +1793,8 @@ mod tests {
         struct Test(u16);
 
         let t = "5:65535#";
-        let expected = Test(u16::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(u16::MAX);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -643,8 +1803,8 @@ mod tests {
         struct Test(u32);
 
         let t = "10:4294967295#";
-        let expected = Test(u32::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(u32::MAX);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -653,8 +1813,8 @@ mod tests {
         struct Test(u64);
 
         let t = "20:18446744073709551615#";
-        let expected = Test(u64::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(u64::MAX);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -663,12 +1823,12 @@ mod tests {
         struct Test(i8);
 
         let t = "3:127#";
-        let expected = Test(i8::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i8::MAX);
+        assert_decodes_from(t, &expected);
 
         let t = "4:-128#";
-        let expected = Test(i8::min_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i8::MIN);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -677,12 +1837,12 @@ mod tests {
         struct Test(i16);
 
         let t = "5:32767#";
-        let expected = Test(i16::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i16::MAX);
+        assert_decodes_from(t, &expected);
 
         let t = "6:-32768#";
-        let expected = Test(i16::min_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i16::MIN);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -691,12 +1851,12 @@ mod tests {
         struct Test(i32);
 
         let t = "10:2147483647#";
-        let expected = Test(i32::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i32::MAX);
+        assert_decodes_from(t, &expected);
 
         let t = "11:-2147483648#";
-        let expected = Test(i32::min_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i32::MIN);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
@@ -705,100 +1865,260 @@ mod tests {
         struct Test(i64);
 
         let t = "19:9223372036854775807#";
-        let expected = Test(i64::max_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i64::MAX);
+        assert_decodes_from(t, &expected);
 
         let t = "20:-9223372036854775808#";
-        let expected = Test(i64::min_value());
-        assert_eq!(Ok(expected), from_str(t));
+        let expected = Test(i64::MIN);
+        assert_decodes_from(t, &expected);
     }
 
     #[test]
-    fn test_unimplemented_f32() {
-        #[derive(Deserialize, PartialEq, Debug)]
-        struct Test(f32);
+    fn test_128_bit_ints() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            small: i64,
+            signed: i128,
+            unsigned: u128,
+            count: u64,
+        }
 
-        let actual: Result<Test> = from_str("4:1.00^");
-        assert_eq!(Err(Error::UnsupportedType), actual);
-    }
+        let t = "39:340282366920938463463374607431768211455#";
+        assert_eq!(Ok(u128::MAX), from_str(t));
+        assert_eq!(Ok(u128::MIN), from_str("1:0#"));
 
-    #[test]
-    fn test_unimplemented_f64() {
-        #[derive(Deserialize, PartialEq, Debug)]
-        struct Test(f64);
+        let t = "40:-170141183460469231731687303715884105728#";
+        assert_eq!(Ok(i128::MIN), from_str(t));
+        let t = "39:170141183460469231731687303715884105727#";
+        assert_eq!(Ok(i128::MAX), from_str(t));
+
+        for value in &[u128::MIN, 1, u128::from(u64::MAX) + 1, u128::MAX] {
+            assert_roundtrip(value);
+        }
+        for value in &[i128::MIN, -1, 0, i128::MAX] {
+            assert_roundtrip(value);
+        }
 
-        let actual: Result<Test> = from_str("4:1.00^");
-        assert_eq!(Err(Error::UnsupportedType), actual);
+        let test = Test {
+            small: i64::MIN,
+            signed: i128::MIN,
+            unsigned: u128::MAX,
+            count: u64::MAX,
+        };
+        assert_roundtrip(&test);
     }
 
     #[test]
-    #[ignore]
     fn test_f32() {
         #[derive(Deserialize, PartialEq, Debug)]
         struct Test(f32);
 
         let t = "4:1.00^";
         let expected = Test(1.00);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
 
-        let t = "4:1.00^";
-        let expected = Test(f32::MAX);
-        assert_eq!(Ok(expected), from_str(t));
-
-        let t = "4:1.00^";
-        let expected = Test(f32::NAN);
-        assert_eq!(Ok(expected), from_str(t));
+        let t = "3:1.1^";
+        let expected = Test(1.1);
+        assert_decodes_from(t, &expected);
 
-        let t = "4:1.00^";
+        let t = "3:inf^";
         let expected = Test(f32::INFINITY);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
 
-        let t = "4:1.00^";
-        let expected = Test(f32::NEG_INFINITY);
-        assert_eq!(Ok(expected), from_str(t));
+        let actual: Result<Test> = from_str("3:abc^");
+        assert_eq!(Err(Error::ParsingFloat), actual);
 
-        let t = "4:1.00^";
-        let expected = Test(f32::MIN);
-        assert_eq!(Ok(expected), from_str(t));
+        let actual: Result<Test> = from_str("1:1#");
+        assert_eq!(
+            Err(Error::Expected {
+                expected: TNetStringType::Float,
+                target: Some("`f32`".into()),
+                found: Some(b'#'),
+                offset: 0,
+                snippet: "1:1#".into(),
+            }),
+            actual
+        );
 
-        let t = "4:1.00^";
-        let expected = Test(f32::MIN_POSITIVE);
-        assert_eq!(Ok(expected), from_str(t));
+        let actual: f32 = from_str(&to_string(&f32::NAN).unwrap()).unwrap();
+        assert!(actual.is_nan());
+
+        let values = [
+            1.1,
+            -1.1,
+            0.0,
+            -0.0,
+            f32::MAX,
+            f32::MIN,
+            f32::MIN_POSITIVE,
+            f32::EPSILON,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::from_bits(1),
+            f32::from_bits(0x007f_ffff),
+        ];
+        let sweep = (0..u32::MAX)
+            .step_by(999_983)
+            .map(f32::from_bits)
+            .filter(|f| !f.is_nan());
+        for value in values.iter().cloned().chain(sweep) {
+            let actual: f32 = from_str(&to_string(&value).unwrap()).unwrap();
+            assert_eq!(value.to_bits(), actual.to_bits());
+        }
     }
 
     #[test]
-    #[ignore]
     fn test_f64() {
         #[derive(Deserialize, PartialEq, Debug)]
         struct Test(f64);
 
         let t = "4:1.00^";
         let expected = Test(1.00);
-        assert_eq!(Ok(expected), from_str(t));
+        assert_decodes_from(t, &expected);
+
+        let t = "5:123.4^";
+        let expected = Test(123.4);
+        assert_decodes_from(t, &expected);
+
+        let values = [
+            0.1,
+            -1.0,
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        let sweep = (0..u64::MAX)
+            .step_by(4_294_967_311_000_003)
+            .map(f64::from_bits)
+            .filter(|f| !f.is_nan());
+        for value in values.iter().cloned().chain(sweep) {
+            let actual: f64 = from_str(&to_string(&value).unwrap()).unwrap();
+            assert_eq!(value.to_bits(), actual.to_bits());
+        }
 
-        let t = "4:1.00^";
-        let expected = Test(f64::MAX);
-        assert_eq!(Ok(expected), from_str(t));
+        let nan: f64 = from_str(&to_string(&f64::NAN).unwrap()).unwrap();
+        assert!(nan.is_nan());
+        let nan: f32 = from_str(&to_string(&f32::NAN).unwrap()).unwrap();
+        assert!(nan.is_nan());
+    }
 
-        let t = "4:1.00^";
-        let expected = Test(f64::NAN);
-        assert_eq!(Ok(expected), from_str(t));
+    #[test]
+    fn test_python_fixtures() {
+        use crate::ser::{to_vec_with, SerializerConfig};
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Sensor {
+            name: String,
+            ratio: f64,
+            count: u32,
+            ok: bool,
+            missing: Option<u32>,
+            readings: Vec<f64>,
+        }
 
-        let t = "4:1.00^";
-        let expected = Test(f64::INFINITY);
-        assert_eq!(Ok(expected), from_str(t));
+        let config = SerializerConfig::new().python_compat(true);
+
+        let input = include_bytes!("../tests/fixtures/python_floats.tnet");
+        let floats: Vec<f64> = from_slice(input).unwrap();
+        assert_eq!(floats.len(), 17);
+        assert_eq!(floats[4], 1e16);
+        assert_eq!(floats[6], 1e-5);
+        assert_eq!(floats[14], 5e-324);
+        assert!(floats[10].is_sign_negative());
+        assert_eq!(to_vec_with(&floats, &config).unwrap(), &input[..]);
+
+        let input = include_bytes!("../tests/fixtures/python_dict.tnet");
+        let sensor: Sensor = from_slice(input).unwrap();
+        let expected = Sensor {
+            name: "sensor".into(),
+            ratio: 0.5,
+            count: 3,
+            ok: true,
+            missing: None,
+            readings: vec![1.0, 2.25, -40.0, 1e-7],
+        };
+        assert_eq!(sensor, expected);
+        assert_eq!(to_vec_with(&sensor, &config).unwrap(), &input[..]);
+    }
 
-        let t = "4:1.00^";
-        let expected = Test(f64::NEG_INFINITY);
-        assert_eq!(Ok(expected), from_str(t));
+    #[test]
+    fn test_skip_none_fields() {
+        use crate::ser::{to_string_with, SerializerConfig};
 
-        let t = "4:1.00^";
-        let expected = Test(f64::MIN);
-        assert_eq!(Ok(expected), from_str(t));
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Default)]
+        #[serde(default)]
+        struct Test {
+            id: u32,
+            name: Option<String>,
+            parent: Option<u32>,
+            tags: Vec<Option<String>>,
+        }
 
-        let t = "4:1.00^";
-        let expected = Test(f64::MIN_POSITIVE);
-        assert_eq!(Ok(expected), from_str(t));
+        let config = SerializerConfig::new().skip_none_fields(true);
+        let tests = vec![
+            Test {
+                id: 1,
+                name: Some("a".into()),
+                parent: None,
+                tags: vec![None, Some("t".into())],
+            },
+            Test {
+                id: 2,
+                name: None,
+                parent: None,
+                tags: vec![],
+            },
+            Test {
+                id: 3,
+                name: None,
+                parent: Some(1),
+                tags: vec![None],
+            },
+        ];
+        for test in tests {
+            let full = to_string(&test).unwrap();
+            let skipped = to_string_with(&test, &config).unwrap();
+            assert!(skipped.len() <= full.len());
+            assert_eq!(Ok(&test), from_str(&full).as_ref());
+            assert_eq!(Ok(&test), from_str(&skipped).as_ref());
+        }
+    }
+
+    #[test]
+    fn test_human_readable() {
+        use crate::ser::{to_string_with, SerializerConfig};
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            v4: IpAddr,
+            v6: IpAddr,
+        }
+
+        let test = Test {
+            v4: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            v6: IpAddr::V6(Ipv6Addr::LOCALHOST),
+        };
+
+        let t = to_string(&Ipv4Addr::new(127, 0, 0, 1)).unwrap();
+        assert_eq!("9:127.0.0.1,", t);
+        let t = to_string(&test).unwrap();
+        assert_eq!(Ok(&test), from_str(&t).as_ref());
+
+        let config = SerializerConfig::new().human_readable(false);
+        let t = to_string_with(&Ipv4Addr::new(127, 0, 0, 1), &config).unwrap();
+        assert_eq!("18:3:127#1:0#1:0#1:1#]", t);
+
+        let t = to_string_with(&test, &config).unwrap();
+        let mut de = Deserializer::from_str(&t).human_readable(false);
+        assert_eq!(Ok(&test), Test::deserialize(&mut de).as_ref());
+        assert_eq!(Ok(()), de.end());
+
+        // both sides have to agree
+        assert!(from_str::<Test>(&t).is_err());
     }
 
     #[test]
@@ -807,6 +2127,486 @@ mod tests {
         struct Test<'a>(&'a [u8]);
 
         assert_eq!(Ok(Test(&[48, 49, 50, 51, 52, 53])), from_str("6:012345,"));
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Binary {
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            name: String,
+        }
+
+        let test = Binary {
+            data: vec![0xff, 0x00],
+            name: "bin".into(),
+        };
+        let t = to_vec(&test).unwrap();
+        assert_eq!(b"2:\xff\x00,", &t[10..15]);
+        assert_eq!(Ok(test), from_slice(&t));
+
+        let actual: Result<String> = from_slice(b"2:\xff\x00,");
+        assert!(matches!(actual, Err(Error::InvalidUtf8 { offset: 2, .. })));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Line {
+            host: String,
+            message: String,
+        }
+
+        let input = b"37:4:host,3:web,7:message,10:bad \xff byte,}";
+        let error = from_slice::<Line>(input).unwrap_err();
+        match error {
+            Error::InvalidUtf8 { offset, error } => {
+                assert_eq!(offset, 33);
+                assert_eq!(input[offset], 0xff);
+                assert_eq!(error.valid_up_to(), 4);
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert_eq!(
+            from_slice::<String>(b"4:ab\xffc,").unwrap_err().to_string(),
+            "invalid utf8 at byte 4: invalid utf-8 sequence of 1 bytes from index 2"
+        );
+
+        let mut de = Deserializer::from_slice(input).lossy_utf8(true);
+        assert_eq!(
+            Line::deserialize(&mut de).unwrap(),
+            Line {
+                host: "web".to_owned(),
+                message: "bad \u{fffd} byte".to_owned(),
+            }
+        );
+        let mut de = Deserializer::from_slice(b"4:ab\xffc,").lossy_utf8(true);
+        assert!(<&str>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Reading {
+            name: String,
+            count: i64,
+            unit: String,
+        }
+
+        let t = "67:4:name,23:sensor-with-a-long-name,5:count,5:hello,4:unit,7:celsius,}";
+        let error = from_str::<Reading>(t).unwrap_err();
+        assert!(matches!(error, Error::Expected { offset: 45, .. }));
+        assert_eq!(
+            error.to_string(),
+            "expected `i64` (integer), found string `,` at byte 45 in \
+             `...ng-name,5:count,5:hello,4:unit,7...`"
+        );
+
+        let error = from_str::<Reading>("7:4:name,}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected string, found end of input at byte 9 in `7:4:name,}`"
+        );
+        assert!(error.is_eof());
+
+        let error = from_slice::<Vec<String>>(b"6:3:\x00\n\xff#]").unwrap_err();
+        assert!(
+            error.to_string().ends_with("in `6:3:\\x00\\n\\xff#]`"),
+            "{}",
+            error
+        );
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    enum Tagged {
+        Ping { seq: u32 },
+        Data { key: String, values: Vec<i64> },
+        Unit,
+    }
+
+    #[test]
+    fn test_internally_tagged() {
+        let data = || Tagged::Data {
+            key: "k".into(),
+            values: vec![1, -2],
+        };
+        assert_eq!(
+            crate::ser::to_string(&data()).unwrap(),
+            "45:4:type,4:Data,3:key,1:k,6:values,9:1:1#2:-2#]}"
+        );
+        for input in &[
+            "45:4:type,4:Data,3:key,1:k,6:values,9:1:1#2:-2#]}",
+            "45:3:key,1:k,6:values,9:1:1#2:-2#]4:type,4:Data,}",
+            "45:3:key,1:k,4:type,4:Data,6:values,9:1:1#2:-2#]}",
+        ] {
+            assert_eq!(from_str::<Tagged>(input), Ok(data()), "{}", input);
+        }
+
+        // buffering one item mustn't eat into the next
+        assert_eq!(
+            from_str::<Vec<Tagged>>(
+                "77:24:4:type,4:Ping,3:seq,1:1#}45:3:key,1:k,6:values,9:1:1#2:-2#]4:type,4:Data,}]"
+            ),
+            Ok(vec![Tagged::Ping { seq: 1 }, data()])
+        );
+        assert_eq!(
+            crate::ser::to_string(&Tagged::Unit).unwrap(),
+            "14:4:type,4:Unit,}"
+        );
+        assert_eq!(from_str("14:4:type,4:Unit,}"), Ok(Tagged::Unit));
+        assert_eq!(
+            from_str::<Tagged>("14:4:type,4:Nope,}"),
+            Err(Error::UnknownVariant {
+                got: "Nope".into(),
+                expected: &["Ping", "Data", "Unit"],
+            })
+        );
+        assert_eq!(
+            from_str::<Tagged>("8:1:n,1:5#}"),
+            Err(Error::MissingField("type"))
+        );
     }
 
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Unit,
+        Newtype(u32),
+        List(Vec<i64>),
+        Tuple(u32, String),
+        Struct { name: String, tags: Vec<String> },
+    }
+
+    #[test]
+    fn test_adjacently_tagged() {
+        for value in [
+            Adjacent::Unit,
+            Adjacent::Newtype(5),
+            Adjacent::List(vec![1, -2]),
+            Adjacent::Tuple(1, "a".into()),
+            Adjacent::Struct {
+                name: "n".into(),
+                tags: vec!["x".into()],
+            },
+        ] {
+            assert_roundtrip(&value);
+        }
+
+        let value = Adjacent::Struct {
+            name: "n".into(),
+            tags: vec!["x".into()],
+        };
+        assert_encodes_to(&value, "46:1:t,6:Struct,1:c,25:4:name,1:n,4:tags,4:1:x,]}}");
+        // the content can come before the tag
+        assert_decodes_from("46:1:c,25:4:name,1:n,4:tags,4:1:x,]}1:t,6:Struct,}", &value);
+        assert_decodes_from(
+            "27:1:c,9:1:1#2:-2#]1:t,4:List,}",
+            &Adjacent::List(vec![1, -2]),
+        );
+        assert_decodes_from("18:1:c,0:~1:t,4:Unit,}", &Adjacent::Unit);
+        assert_decodes_from("11:1:t,4:Unit,}", &Adjacent::Unit);
+    }
+
+    #[test]
+    fn test_type_aware_errors() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Config {
+            port: u16,
+        }
+        #[derive(Deserialize, Debug)]
+        struct Pair(#[allow(dead_code)] u8, #[allow(dead_code)] u8);
+        #[derive(Deserialize, Debug)]
+        struct Marker;
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Outer {
+            config: Config,
+        }
+
+        let error = from_str::<Outer>("13:6:config,1:5#}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected struct `Config` (dict), found integer `#` at byte 12 in \
+             `13:6:config,1:5#}`"
+        );
+        assert_eq!(
+            from_str::<Pair>("1:1#").unwrap_err().to_string(),
+            "expected tuple struct `Pair` (list), found integer `#` at byte 0 in `1:1#`"
+        );
+        assert_eq!(
+            from_str::<(u8, u8)>("0:}").unwrap_err().to_string(),
+            "expected a tuple of 2 (list), found dict `}` at byte 0 in `0:}`"
+        );
+        assert_eq!(
+            from_str::<Marker>("0:]").unwrap_err().to_string(),
+            "expected unit struct `Marker` (null), found list `]` at byte 0 in `0:]`"
+        );
+        assert_eq!(
+            from_str::<Config>("11:4:port,1:x,}")
+                .unwrap_err()
+                .to_string(),
+            "expected `u16` (integer), found string `,` at byte 10 in `11:4:port,1:x,}`"
+        );
+
+        // out of range is an invalid value, the same for every width
+        assert_eq!(
+            from_str::<u8>("3:256#").unwrap_err().to_string(),
+            "invalid value: integer `256`, expected u8"
+        );
+        assert_eq!(
+            from_str::<u32>("2:-1#").unwrap_err().to_string(),
+            "invalid value: integer `-1`, expected u32"
+        );
+        assert_eq!(
+            from_str::<u64>("20:18446744073709551616#")
+                .unwrap_err()
+                .to_string(),
+            "invalid value: a wide integer, expected u64"
+        );
+        assert_eq!(
+            from_str::<i64>("19:9223372036854775808#")
+                .unwrap_err()
+                .to_string(),
+            "invalid value: integer `9223372036854775808`, expected i64"
+        );
+        let wide = format!("{}0", u128::MAX);
+        let error = from_str::<u128>(&format!("{}:{}#", wide.len(), wide)).unwrap_err();
+        assert!(error.is_data(), "{}", error);
+        assert_eq!(from_str::<u64>("2:1x#"), Err(Error::ParsingInt));
+        assert_eq!(
+            from_str::<i128>("1:x,").unwrap_err().to_string(),
+            "expected `i128` (integer), found string `,` at byte 0 in `1:x,`"
+        );
+        assert_eq!(from_str::<i8>("4:-128#"), Ok(i8::MIN));
+        assert_eq!(from_str::<u16>("5:65535#"), Ok(u16::MAX));
+        assert_decodes_from("0:~", &());
+        assert_decodes_from("8:1:1#1:a,]", &(1u8, "a".to_owned()));
+    }
+
+    #[cfg(feature = "trace-parse")]
+    #[test]
+    fn test_trace_parse() {
+        use std::cell::RefCell;
+
+        thread_local! {
+            static EVENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                let event = format!("{} {}", record.level(), record.args());
+                EVENTS.with(|events| events.borrow_mut().push(event));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture;
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Pump {
+            name: String,
+            tags: Vec<u32>,
+            ok: bool,
+        }
+
+        let events = |input: &str| {
+            EVENTS.with(|events| events.borrow_mut().clear());
+            let result = from_str::<Pump>(input);
+            (result.is_ok(), EVENTS.with(|events| events.take()))
+        };
+        assert_eq!(
+            events("45:4:name,4:pump,4:tags,9:1:1#2:22#]2:ok,4:true!}"),
+            (
+                true,
+                vec![
+                    "TRACE 0..49 dict, length 45".to_owned(),
+                    "TRACE 3..10 string, length 4".into(),
+                    "TRACE 10..17 string, length 4".into(),
+                    "TRACE 17..24 string, length 4".into(),
+                    "TRACE 24..36 list, length 9".into(),
+                    "TRACE 26..30 integer, length 1".into(),
+                    "TRACE 30..35 integer, length 2".into(),
+                    "TRACE 24..36 list closed, 2 elements".into(),
+                    "TRACE 36..41 string, length 2".into(),
+                    "TRACE 41..48 boolean, length 4".into(),
+                    "TRACE 0..49 dict closed, 3 entries".into(),
+                ]
+            )
+        );
+        let (ok, events) = events("45:4:name,4:pump,4:tags,9:1:1#2:2x#]2:ok,4:true!}");
+        assert!(!ok);
+        assert_eq!(
+            events[5..],
+            [
+                "TRACE 26..30 integer, length 1",
+                "TRACE 30..35 integer, length 2",
+                "WARN failed at byte 30: error parsing integer",
+            ]
+        );
+    }
+
+    // `depth` lists, each holding the next, written without recursing
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut frames = vec![3];
+        for _ in 1..depth {
+            let inner = *frames.last().unwrap();
+            frames.push(inner + inner.to_string().len() + 2);
+        }
+        let mut output = Vec::new();
+        for inner in frames[..depth - 1].iter().rev() {
+            output.extend_from_slice(format!("{}:", inner).as_bytes());
+        }
+        output.extend_from_slice(b"0:]");
+        output.resize(output.len() + depth - 1, b']');
+        output
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        use crate::value::Value;
+
+        assert!(from_slice::<Value>(&nested_lists(128)).is_ok());
+        let input = nested_lists(129);
+        let offset = input.len() - 128 - 3;
+        assert_eq!(
+            from_slice::<Value>(&input),
+            Err(Error::At {
+                offset,
+                error: Box::new(Error::DepthLimitExceeded(128)),
+            })
+        );
+
+        // failing doesn't use up the limit for the values after it
+        let mut stream = input.clone();
+        stream.extend_from_slice(&nested_lists(128));
+        let mut values = Deserializer::from_slice(&stream)
+            .into_iter::<Value>()
+            .resync(true);
+        assert!(matches!(
+            values.next(),
+            Some(Err(Error::At { error, .. })) if *error == Error::DepthLimitExceeded(128)
+        ));
+        assert!(values.next().unwrap().is_ok());
+        assert_eq!(values.skipped(), 0);
+    }
+
+    #[cfg(feature = "unbounded_depth")]
+    #[test]
+    fn test_disable_recursion_limit() {
+        use crate::value::Value;
+
+        let input = nested_lists(100_000);
+        let mut de = Deserializer::from_slice(&input);
+        de.disable_recursion_limit();
+        let de = serde_stacker::Deserializer::new(&mut de);
+        let mut value = Value::deserialize(de).unwrap();
+
+        // dropped a level at a time, so dropping doesn't overflow the stack
+        let mut depth = 1;
+        while let Value::List(mut list) = value {
+            match list.pop() {
+                Some(inner) => value = inner,
+                None => break,
+            }
+            depth += 1;
+        }
+        assert_eq!(depth, 100_000);
+    }
+
+    #[test]
+    fn test_seed() {
+        use super::{from_slice_seed, from_str_seed};
+        use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+        use std::fmt;
+
+        // reads a list of strings as their indices in a shared table
+        struct Interned<'a>(&'a mut Vec<String>);
+
+        impl<'de> DeserializeSeed<'de> for Interned<'_> {
+            type Value = Vec<usize>;
+
+            fn deserialize<D>(self, deserializer: D) -> std::result::Result<Vec<usize>, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_seq(self)
+            }
+        }
+
+        impl<'de> Visitor<'de> for Interned<'_> {
+            type Value = Vec<usize>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of strings")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Vec<usize>, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut indices = Vec::new();
+                while let Some(s) = seq.next_element::<&str>()? {
+                    let index = match self.0.iter().position(|t| t == s) {
+                        Some(index) => index,
+                        None => {
+                            self.0.push(s.to_owned());
+                            self.0.len() - 1
+                        }
+                    };
+                    indices.push(index);
+                }
+                Ok(indices)
+            }
+        }
+
+        let mut table = Vec::new();
+        assert_eq!(
+            from_str_seed(Interned(&mut table), "12:1:a,1:b,1:a,]"),
+            Ok(vec![0, 1, 0])
+        );
+        assert_eq!(
+            from_slice_seed(Interned(&mut table), b"8:1:c,1:a,]"),
+            Ok(vec![2, 0])
+        );
+        assert_eq!(table, ["a", "b", "c"]);
+        assert_eq!(
+            from_str_seed(Interned(&mut table), "8:1:c,1:a,]0:~"),
+            Err(Error::UnusedParseData)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_in_place() {
+        // serde reuses the buffers of strings and vecs given `visit_str`
+        // and a sequence to fill
+        let input = b"35:12:first record,15:5:alpha,4:beta,]]\
+            25:6:second,12:5:gamma,1:d,]]\
+            15:5:third,4:1:e,]]";
+        let mut de = Deserializer::from_slice(input);
+        let mut record = (String::new(), Vec::<String>::new());
+        Deserialize::deserialize_in_place(&mut de, &mut record).unwrap();
+        let pointers = |record: &(String, Vec<String>)| {
+            (record.0.as_ptr(), record.1.as_ptr(), record.1[0].as_ptr())
+        };
+        let first = pointers(&record);
+        Deserialize::deserialize_in_place(&mut de, &mut record).unwrap();
+        assert_eq!(record, ("second".into(), vec!["gamma".into(), "d".into()]));
+        assert_eq!(pointers(&record), first);
+        Deserialize::deserialize_in_place(&mut de, &mut record).unwrap();
+        assert_eq!(record, ("third".into(), vec!["e".into()]));
+        assert_eq!(pointers(&record), first);
+        assert_eq!(record.0.capacity(), 12);
+        assert!(de.end().is_ok());
+    }
 }
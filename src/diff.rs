@@ -0,0 +1,257 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+
+use crate::de::from_str;
+use crate::error::Result;
+use crate::value::Value;
+
+/// One difference between two values, found by `diff` or `Value::diff`.
+///
+/// Paths are JSON Pointers (RFC 6901), the same as `Value::pointer` takes,
+/// so `""` is the root and `"/a/0"` is the first item of the `a` list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// A dict key that's only on the right.
+    Added { path: String, value: Value },
+    /// A dict key that's only on the left.
+    Removed { path: String, value: Value },
+    /// Two scalars of the same type with different values.
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+    /// Two lists of different lengths. Items they have in common are still
+    /// compared.
+    Length {
+        path: String,
+        old: usize,
+        new: usize,
+    },
+    /// Two values of different types, e.g. an integer and a float.
+    Type {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Difference {
+    pub fn path(&self) -> &str {
+        match *self {
+            Difference::Added { ref path, .. }
+            | Difference::Removed { ref path, .. }
+            | Difference::Changed { ref path, .. }
+            | Difference::Length { ref path, .. }
+            | Difference::Type { ref path, .. } => path,
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let path = match self.path() {
+            "" => "<root>",
+            path => path,
+        };
+        match *self {
+            Difference::Added { ref value, .. } => write!(formatter, "{}: added {}", path, value),
+            Difference::Removed { ref value, .. } => {
+                write!(formatter, "{}: removed {}", path, value)
+            }
+            Difference::Changed {
+                ref old, ref new, ..
+            } => {
+                write!(formatter, "{}: changed from {} to {}", path, old, new)
+            }
+            Difference::Length { old, new, .. } => write!(
+                formatter,
+                "{}: list length changed from {} to {}",
+                path, old, new
+            ),
+            Difference::Type {
+                ref old, ref new, ..
+            } => write!(
+                formatter,
+                "{}: changed from {} {} to {} {}",
+                path,
+                old.kind(),
+                old,
+                new.kind(),
+                new
+            ),
+        }
+    }
+}
+
+/// Parse two tnetstrings and list how they differ, see `Value::diff`.
+pub fn diff(a: &str, b: &str) -> Result<Vec<Difference>> {
+    let a: Value = from_str(a)?;
+    let b: Value = from_str(b)?;
+    Ok(a.diff(&b))
+}
+
+pub(crate) fn walk(path: &mut String, old: &Value, new: &Value, out: &mut Vec<Difference>) {
+    match (old, new) {
+        (Value::List(old), Value::List(new)) => {
+            if old.len() != new.len() {
+                out.push(Difference::Length {
+                    path: path.clone(),
+                    old: old.len(),
+                    new: new.len(),
+                });
+            }
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&i.to_string());
+                walk(path, old, new, out);
+                path.truncate(len);
+            }
+        }
+        (Value::Dict(old), Value::Dict(new)) => {
+            // sorted, so the report doesn't depend on the `Map`'s order
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                let len = path.len();
+                push_key(path, key);
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => walk(path, old, new, out),
+                    (Some(old), None) => out.push(Difference::Removed {
+                        path: path.clone(),
+                        value: old.clone(),
+                    }),
+                    (None, Some(new)) => out.push(Difference::Added {
+                        path: path.clone(),
+                        value: new.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.truncate(len);
+            }
+        }
+        _ if old.kind() != new.kind() => out.push(Difference::Type {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        // floats that are NaN on both sides count as the same
+        _ if old == new || both_nan(old, new) => {}
+        _ => out.push(Difference::Changed {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+fn both_nan(old: &Value, new: &Value) -> bool {
+    let is_nan = |v: &Value| v.as_f64().is_some_and(f64::is_nan);
+    is_nan(old) && is_nan(new)
+}
+
+fn push_key(path: &mut String, key: &str) {
+    path.push('/');
+    path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Difference};
+    use crate::value::Value;
+
+    #[test]
+    fn test_nested_key() {
+        let a = "55:4:user,26:3:age,2:30#4:name,5:alice,}4:tags,8:1:a,1:b,]}";
+        let b = "61:4:user,27:5:email,3:b@x,4:name,3:bob,}4:tags,12:1:b,1:a,1:c,]}";
+        let differences = diff(a, b).unwrap();
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Length {
+                    path: "/tags".into(),
+                    old: 2,
+                    new: 3,
+                },
+                Difference::Changed {
+                    path: "/tags/0".into(),
+                    old: Value::from("a"),
+                    new: Value::from("b"),
+                },
+                Difference::Changed {
+                    path: "/tags/1".into(),
+                    old: Value::from("b"),
+                    new: Value::from("a"),
+                },
+                Difference::Removed {
+                    path: "/user/age".into(),
+                    value: Value::from(30),
+                },
+                Difference::Added {
+                    path: "/user/email".into(),
+                    value: Value::from("b@x"),
+                },
+                Difference::Changed {
+                    path: "/user/name".into(),
+                    old: Value::from("alice"),
+                    new: Value::from("bob"),
+                },
+            ]
+        );
+        let report: Vec<String> = differences.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            report,
+            [
+                "/tags: list length changed from 2 to 3",
+                "/tags/0: changed from 1:a, to 1:b,",
+                "/tags/1: changed from 1:b, to 1:a,",
+                "/user/age: removed 2:30#",
+                "/user/email: added 3:b@x,",
+                "/user/name: changed from 5:alice, to 3:bob,",
+            ]
+        );
+        assert_eq!(diff(a, a), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_numeric_type() {
+        let a = "23:1:n,1:1#3:a/b,6:3:1.5^]}";
+        let b = "25:1:n,3:1.0^3:a/b,6:3:2.5^]}";
+        let differences = diff(a, b).unwrap();
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Changed {
+                    path: "/a~1b/0".into(),
+                    old: Value::from(1.5),
+                    new: Value::from(2.5),
+                },
+                Difference::Type {
+                    path: "/n".into(),
+                    old: Value::from(1),
+                    new: Value::from(1.0),
+                },
+            ]
+        );
+        assert_eq!(
+            differences[1].to_string(),
+            "/n: changed from integer 1:1# to float 1:1^"
+        );
+        let original: Value = a.parse().unwrap();
+        assert_eq!(
+            original.pointer(differences[0].path()),
+            Some(&Value::from(1.5))
+        );
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(
+            diff("1:1#", "0:]").map(|d| d[0].to_string()),
+            Ok("<root>: changed from integer 1:1# to list 0:]".to_string())
+        );
+        assert_eq!(diff("3:nan^", "3:nan^"), Ok(vec![]));
+        assert_eq!(diff("0:~", "0:~"), Ok(vec![]));
+        assert!(diff("1:1#", "1:1").is_err());
+    }
+}
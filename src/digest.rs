@@ -0,0 +1,165 @@
+use digest::{Digest, Output};
+use serde::Serialize;
+use std::io;
+
+use crate::de::from_slice;
+use crate::error::Result;
+use crate::len::to_writer_canonical;
+use crate::value::Value;
+
+/// Hash the canonical encoding of `value` with `D`, for signing messages.
+///
+/// The canonical encoding is what `SerializerConfig::canonical` writes, but
+/// with struct fields sorted bytewise by their encoded keys too, as every
+/// dict is. That's the canonical encoding of `value` as a `Value`, so
+/// `digest` of a value and `digest_encoded` of any encoding of it agree.
+///
+/// The encoding is written into the hasher as it's produced, the way
+/// `to_writer_streaming` writes, so the value is serialized twice. Only the
+/// entries of the dict being sorted are held in memory.
+pub fn digest<T, D>(value: &T) -> Result<Output<D>>
+where
+    T: ?Sized + Serialize,
+    D: Digest,
+{
+    let mut hasher = Hasher(D::new());
+    to_writer_canonical(&mut hasher, value)?;
+    Ok(hasher.0.finalize())
+}
+
+/// Hash the canonical encoding of an already encoded tnetstring, as `digest`
+/// does, for checking the signature of a message as it was received.
+pub fn digest_encoded<D>(input: &[u8]) -> Result<Output<D>>
+where
+    D: Digest,
+{
+    digest::<_, D>(&from_slice::<Value>(input)?)
+}
+
+struct Hasher<D>(D);
+
+impl<D: Digest> io::Write for Hasher<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{digest, digest_encoded};
+    use crate::ser::{to_vec, to_vec_with, SerializerConfig};
+    use crate::value::Value;
+    use serde::Serialize;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Message {
+        to: String,
+        headers: HashMap<String, Value>,
+    }
+
+    fn message(order: &[&str]) -> Message {
+        let mut headers = HashMap::new();
+        for &key in order {
+            headers.insert(key.to_owned(), Value::Str(format!("{} value", key)));
+        }
+        headers.insert(
+            "nested".to_owned(),
+            Value::List(vec![Value::from(1.5), Value::from(-2i64), Value::Null]),
+        );
+        Message {
+            to: "inbox".to_owned(),
+            headers,
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let expected = digest::<_, Sha256>(&message(&["b", "ab", "c", "long key"])).unwrap();
+        for order in &[["ab", "b", "long key", "c"], ["long key", "c", "b", "ab"]] {
+            let message = message(order);
+            assert_eq!(digest::<_, Sha256>(&message).unwrap(), expected);
+            assert_eq!(
+                digest_encoded::<Sha256>(&to_vec(&message).unwrap()).unwrap(),
+                expected
+            );
+        }
+
+        // the same as hashing the canonical encoding
+        let value = crate::value::to_value(&message(&["b", "ab"])).unwrap();
+        let canonical = to_vec_with(&value, &SerializerConfig::new().canonical(true)).unwrap();
+        assert_eq!(
+            digest::<_, Sha256>(&message(&["ab", "b"])).unwrap(),
+            Sha256::digest(&canonical)
+        );
+
+        let mut changed = message(&["b", "ab", "c", "long key"]);
+        changed.headers.insert(
+            "nested".to_owned(),
+            Value::List(vec![Value::from(1.5), Value::from(-3i64), Value::Null]),
+        );
+        assert_ne!(digest::<_, Sha256>(&changed).unwrap(), expected);
+
+        assert!(digest_encoded::<Sha256>(b"3:abc").is_err());
+    }
+
+    #[test]
+    fn test_struct_fields_sorted() {
+        #[derive(Serialize)]
+        enum Body {
+            Text { zz: u8, a: String },
+        }
+
+        #[derive(Serialize)]
+        struct Unsorted {
+            long_name: u8,
+            b: Body,
+            a: Option<u8>,
+        }
+
+        let value = Unsorted {
+            long_name: 1,
+            b: Body::Text {
+                zz: 2,
+                a: "x".to_owned(),
+            },
+            a: None,
+        };
+        let canonical = to_vec_with(
+            &crate::value::to_value(&value).unwrap(),
+            &SerializerConfig::new().canonical(true),
+        )
+        .unwrap();
+        assert_eq!(
+            canonical,
+            &b"59:1:a,0:~1:b,28:4:Text,17:1:a,1:x,2:zz,1:2#}}9:long_name,1:1#}"[..]
+        );
+        assert_eq!(
+            digest::<_, Sha256>(&value).unwrap(),
+            Sha256::digest(&canonical)
+        );
+        assert_eq!(
+            digest_encoded::<Sha256>(&to_vec(&value).unwrap()).unwrap(),
+            Sha256::digest(&canonical)
+        );
+
+        // lists around and after dicts are streamed, with the dicts' own
+        // lists and dicts sorted inside them
+        let list = (vec![value], message(&["b", "ab", "c"]), Vec::<u8>::new(), 1);
+        let canonical = to_vec_with(
+            &crate::value::to_value(&list).unwrap(),
+            &SerializerConfig::new().canonical(true),
+        )
+        .unwrap();
+        assert_eq!(
+            digest::<_, Sha256>(&list).unwrap(),
+            Sha256::digest(&canonical)
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Serializing values behind `dyn erased_serde::Serialize`, such as the
+//! trait objects a plugin system hands out.
+//!
+//! Errors from the tnetstring serializer, like `Error::NonFiniteFloat`, come
+//! back as they are. Errors the value raises itself become
+//! `Error::Message`.
+
+use std::io;
+
+use crate::error::Result;
+use crate::ser::{Serializer, SerializerConfig};
+
+pub fn to_vec_erased(value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+    to_vec_erased_with(value, &SerializerConfig::default())
+}
+
+pub fn to_vec_erased_with(
+    value: &dyn erased_serde::Serialize,
+    config: &SerializerConfig,
+) -> Result<Vec<u8>> {
+    let mut serializer = Serializer::with_config(Vec::new(), config.clone());
+    erased_serde::serialize(value, &mut serializer)?;
+    serializer.into_inner()
+}
+
+pub fn to_string_erased(value: &dyn erased_serde::Serialize) -> Result<String> {
+    Ok(String::from_utf8(to_vec_erased(value)?)?)
+}
+
+pub fn to_writer_erased<W>(mut writer: W, value: &dyn erased_serde::Serialize) -> Result<()>
+where
+    W: io::Write,
+{
+    writer.write_all(&to_vec_erased(value)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_string_erased, to_vec_erased, to_vec_erased_with, to_writer_erased};
+    use crate::error::{Error, Result};
+    use crate::ser::{to_string, to_vec, NonFiniteFloats, Serializer, SerializerConfig};
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Resize {
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(Serialize)]
+    enum Filter {
+        Blur(f64),
+        Tags { names: Vec<String> },
+    }
+
+    fn plugins() -> Vec<Box<dyn erased_serde::Serialize>> {
+        let mut options = BTreeMap::new();
+        options.insert("quality", 90);
+        vec![
+            Box::new(Resize {
+                width: 640,
+                height: 480,
+            }),
+            Box::new(Filter::Blur(1.5)),
+            Box::new(Filter::Tags {
+                names: vec!["a".to_owned(), "b".to_owned()],
+            }),
+            Box::new(options),
+            Box::new("plain"),
+        ]
+    }
+
+    #[test]
+    fn test_erased() {
+        let expected = [
+            to_string(&Resize {
+                width: 640,
+                height: 480,
+            })
+            .unwrap(),
+            to_string(&Filter::Blur(1.5)).unwrap(),
+            to_string(&Filter::Tags {
+                names: vec!["a".to_owned(), "b".to_owned()],
+            })
+            .unwrap(),
+            "15:7:quality,2:90#}".to_owned(),
+            "5:plain,".to_owned(),
+        ];
+        let mut written = Vec::new();
+        for (plugin, expected) in plugins().iter().zip(&expected) {
+            assert_eq!(&to_string_erased(plugin.as_ref()).unwrap(), expected);
+            assert_eq!(to_vec_erased(plugin.as_ref()).unwrap(), expected.as_bytes());
+            to_writer_erased(&mut written, plugin.as_ref()).unwrap();
+        }
+        assert_eq!(written, expected.concat().into_bytes());
+
+        // the list as a whole, since boxed trait objects are `Serialize` too
+        assert_eq!(
+            to_vec_erased(&plugins()).unwrap(),
+            to_vec(&plugins()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let nan: Box<dyn erased_serde::Serialize> = Box::new(vec![f64::NAN]);
+        let config = SerializerConfig::new().non_finite_floats(NonFiniteFloats::Error);
+        assert_eq!(
+            to_vec_erased_with(nan.as_ref(), &config),
+            Err(Error::NonFiniteFloat)
+        );
+
+        // serializing through `erase` by hand, errors convert with `?`
+        fn erase_by_hand(value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+            let mut serializer = Serializer::with_config(
+                Vec::new(),
+                SerializerConfig::new().non_finite_floats(NonFiniteFloats::Error),
+            );
+            value.erased_serialize(&mut <dyn erased_serde::Serializer>::erase(&mut serializer))?;
+            serializer.into_inner()
+        }
+        assert_eq!(erase_by_hand(&1.5).unwrap(), b"3:1.5^");
+        assert!(matches!(
+            erase_by_hand(nan.as_ref()),
+            Err(Error::Message(_))
+        ));
+    }
+}
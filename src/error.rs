@@ -1,20 +1,38 @@
-use std;
 use std::convert::From;
 use std::fmt::{self, Display};
+use std::io;
+use std::mem;
 
 use serde::{de, ser};
 
+use crate::parse::{tag_type, TNetStringType};
+
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Clone, Debug, PartialEq)]
+/// Everything that can go wrong reading or writing tnetstrings.
+///
+/// `PartialEq` compares `Io` errors by their `io::ErrorKind` alone, since
+/// `io::Error` can't be compared.
+#[derive(Debug)]
 pub enum Error {
     Message(String),
     UnknownSegmentType,
     LengthNotFound,
     StackProblem,
     NonUtf8Str,
+    /// A string that wasn't UTF-8, with the offset of its first invalid byte.
+    InvalidUtf8 {
+        offset: usize,
+        error: std::str::Utf8Error,
+    },
     UnsupportedType,
     Eof,
+    /// The input is a valid start of a tnetstring, but stops before its end.
+    /// Once the length prefix has been read, `needed` is how many more bytes
+    /// the frame takes.
+    Incomplete {
+        needed: Option<usize>,
+    },
     ParsingLength,
     UnusedParseData,
     ParsingUnit,
@@ -25,6 +43,175 @@ pub enum Error {
     ParsingString,
     ParsingSeq,
     ParsingUnitVariant,
+    ParsingChar(String, usize),
+    ParsingFloat,
+    ParsingInt,
+    UnexpectedType {
+        expected: TNetStringType,
+        found: TNetStringType,
+    },
+    /// A value had the wrong type tag, with where it starts in the input
+    /// and the bytes around it, escaped.
+    Expected {
+        expected: TNetStringType,
+        /// The Rust type being read, like ``struct `Config` ``, when known.
+        target: Option<String>,
+        /// The type tag, or `None` at the end of the input.
+        found: Option<u8>,
+        offset: usize,
+        snippet: String,
+    },
+    StructLength {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    Io(io::Error),
+    NonStringKey(TNetStringType),
+    NonFiniteFloat,
+    Item {
+        index: u64,
+        error: Box<Error>,
+    },
+    Fmt,
+    OutputLimitExceeded {
+        limit: usize,
+        attempted: usize,
+    },
+    InputLimitExceeded {
+        limit: usize,
+        size: usize,
+    },
+    BufferTooSmall {
+        needed: usize,
+        available: usize,
+    },
+    DepthLimitExceeded(usize),
+    Nesting(&'static str),
+    Mongrel2(&'static str),
+    MissingComma,
+    At {
+        offset: usize,
+        error: Box<Error>,
+    },
+    MissingField(&'static str),
+    /// A dict on a `patch` path without the next key.
+    MissingKey(String),
+    UnknownField {
+        got: String,
+        expected: &'static [&'static str],
+    },
+    UnknownVariant {
+        got: String,
+        expected: &'static [&'static str],
+    },
+    /// What serde's `Unexpected` and `Expected` describe, as text.
+    InvalidType {
+        unexpected: String,
+        expected: String,
+    },
+    InvalidValue {
+        unexpected: String,
+        expected: String,
+    },
+    InvalidLength {
+        len: usize,
+        expected: String,
+    },
+}
+
+/// The broad kind of an `Error`, see `Error::classify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// Reading or writing failed.
+    Io,
+    /// The input isn't a well formed tnetstring, or is over a limit.
+    Syntax,
+    /// The input is well formed but doesn't fit the type it's being read
+    /// into, or a value can't be written as a tnetstring.
+    Data,
+    /// The input ended part way through a value.
+    Eof,
+}
+
+impl Error {
+    /// Sort this error into a `Category`, looking through `At` and `Item`
+    /// to the error they wrap.
+    pub fn classify(&self) -> Category {
+        match *self {
+            Error::Io(..) | Error::Fmt => Category::Io,
+            Error::Eof | Error::Incomplete { .. } | Error::Expected { found: None, .. } => {
+                Category::Eof
+            }
+            Error::UnknownSegmentType
+            | Error::LengthNotFound
+            | Error::ParsingLength
+            | Error::UnusedParseData
+            | Error::ParsingUnit
+            | Error::ParsingBool
+            | Error::ParsingMap
+            | Error::ParsingFloat
+            | Error::ParsingInt
+            | Error::ParsingUnsigned
+            | Error::ParsingString
+            | Error::ParsingSeq
+            | Error::InputLimitExceeded { .. }
+            | Error::DepthLimitExceeded(_)
+            | Error::MissingComma
+            | Error::Mongrel2(_) => Category::Syntax,
+            Error::Message(_)
+            | Error::MissingField(_)
+            | Error::MissingKey(_)
+            | Error::UnknownField { .. }
+            | Error::UnknownVariant { .. }
+            | Error::InvalidType { .. }
+            | Error::InvalidValue { .. }
+            | Error::InvalidLength { .. }
+            | Error::StackProblem
+            | Error::NonUtf8Str
+            | Error::InvalidUtf8 { .. }
+            | Error::UnsupportedType
+            | Error::ParsingEnum
+            | Error::ParsingUnitVariant
+            | Error::ParsingChar(..)
+            | Error::UnexpectedType { .. }
+            | Error::Expected { found: Some(_), .. }
+            | Error::StructLength { .. }
+            | Error::NonStringKey(_)
+            | Error::NonFiniteFloat
+            | Error::OutputLimitExceeded { .. }
+            | Error::BufferTooSmall { .. }
+            | Error::Nesting(_) => Category::Data,
+            Error::At { ref error, .. } | Error::Item { ref error, .. } => error.classify(),
+        }
+    }
+
+    pub fn is_io(&self) -> bool {
+        self.classify() == Category::Io
+    }
+
+    pub fn is_syntax(&self) -> bool {
+        self.classify() == Category::Syntax
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.classify() == Category::Data
+    }
+
+    /// Whether the input was cut short, so more of it might parse.
+    pub fn is_eof(&self) -> bool {
+        self.classify() == Category::Eof
+    }
+
+    /// Whether this is an `Incomplete` error, looking through `At` and
+    /// `Item`: the input so far is fine and more of it is needed.
+    pub fn is_incomplete(&self) -> bool {
+        match *self {
+            Error::Incomplete { .. } => true,
+            Error::At { ref error, .. } | Error::Item { ref error, .. } => error.is_incomplete(),
+            _ => false,
+        }
+    }
 }
 
 impl ser::Error for Error {
@@ -37,40 +224,577 @@ impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
     }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error::MissingField(field)
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        Error::UnknownField {
+            got: field.to_string(),
+            expected,
+        }
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        Error::UnknownVariant {
+            got: variant.to_string(),
+            expected,
+        }
+    }
+
+    fn invalid_type(unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        Error::InvalidType {
+            unexpected: unexpected.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    fn invalid_value(unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        Error::InvalidValue {
+            unexpected: unexpected.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    fn invalid_length(len: usize, expected: &dyn de::Expected) -> Self {
+        Error::InvalidLength {
+            len,
+            expected: expected.to_string(),
+        }
+    }
+}
+
+// the same wording serde uses for the names a field or variant could have had
+struct OneOf(&'static [&'static str]);
+
+impl Display for OneOf {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            [] => formatter.write_str("there are none"),
+            [name] => write!(formatter, "expected `{}`", name),
+            names => {
+                formatter.write_str("expected one of ")?;
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        formatter.write_str(", ")?;
+                    }
+                    write!(formatter, "`{}`", name)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 impl From<std::string::FromUtf8Error> for Error {
-    fn from(_error: std::string::FromUtf8Error) -> Self {
-        Error::NonUtf8Str
+    fn from(error: std::string::FromUtf8Error) -> Self {
+        let error = error.utf8_error();
+        Error::InvalidUtf8 {
+            offset: error.valid_up_to(),
+            error,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// IO errors come back out as they went in. Unexpected ends of input
+/// become `UnexpectedEof` and anything else `InvalidData`.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            Error::Eof | Error::Incomplete { .. } => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, error)
+            }
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// For serializing through `erased_serde::Serializer::erase` by hand, where
+/// errors come back as text. `erased::to_vec_erased` keeps them as they were.
+#[cfg(feature = "erased")]
+impl From<erased_serde::Error> for Error {
+    fn from(error: erased_serde::Error) -> Self {
+        Error::Message(error.to_string())
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_error: fmt::Error) -> Self {
+        Error::Fmt
     }
 }
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+        match *self {
+            Error::Message(ref msg) => formatter.write_str(msg),
+            Error::UnknownSegmentType => formatter.write_str("unknown segment type"),
+            Error::LengthNotFound => formatter.write_str("length not found but required"),
+            Error::StackProblem => formatter.write_str("stack problem"),
+            Error::UnusedParseData => formatter.write_str("unused parse data"),
+            Error::ParsingUnit => formatter.write_str("error parsing unit"),
+            Error::ParsingBool => formatter.write_str("error parsing bool"),
+            Error::ParsingMap => formatter.write_str("error parsing map"),
+            Error::ParsingEnum => formatter.write_str("error parsing enum"),
+            Error::ParsingUnsigned => formatter.write_str("error parsing unsigned"),
+            Error::ParsingString => formatter.write_str("error parsing string"),
+            Error::ParsingSeq => formatter.write_str("error parsing sequence"),
+            Error::ParsingUnitVariant => formatter.write_str("error parsing unit variant"),
+            Error::Eof => formatter.write_str("error eof"),
+            Error::Incomplete {
+                needed: Some(needed),
+            } => {
+                write!(formatter, "incomplete input, {} more bytes needed", needed)
+            }
+            Error::Incomplete { needed: None } => formatter.write_str("incomplete input"),
+            Error::UnsupportedType => formatter.write_str("unsupported type"),
+            Error::ParsingLength => formatter.write_str("error parsing data length"),
+            Error::NonUtf8Str => formatter.write_str("error parsing string that wasn't utf8"),
+            Error::InvalidUtf8 { offset, error } => {
+                write!(formatter, "invalid utf8 at byte {}: {}", offset, error)
+            }
+            Error::ParsingFloat => formatter.write_str("error parsing float"),
+            Error::ParsingInt => formatter.write_str("error parsing integer"),
+            Error::NonFiniteFloat => formatter.write_str("can't serialize NaN or infinite float"),
+            Error::ParsingChar(ref payload, len) => write!(
+                formatter,
+                "error parsing char: expected a single character, found {:?} with {} characters",
+                payload, len
+            ),
+            Error::UnexpectedType { expected, found } => {
+                write!(formatter, "expected {}, found {}", expected, found)
+            }
+            Error::Expected {
+                expected,
+                ref target,
+                found,
+                offset,
+                ref snippet,
+            } => {
+                match *target {
+                    Some(ref target) => {
+                        write!(formatter, "expected {} ({}), found ", target, expected)?
+                    }
+                    None => write!(formatter, "expected {}, found ", expected)?,
+                }
+                match found.map(|tag| (tag, tag_type(tag))) {
+                    Some((tag, Some(kind))) => write!(formatter, "{} `{}`", kind, tag as char)?,
+                    Some((tag, None)) => write!(formatter, "`{}`", tag.escape_ascii())?,
+                    None => formatter.write_str("end of input")?,
+                }
+                write!(formatter, " at byte {} in `{}`", offset, snippet)
+            }
+            Error::StructLength {
+                name,
+                expected,
+                found,
+            } => write!(
+                formatter,
+                "expected struct {} with {} fields, found a list of {}",
+                name, expected, found
+            ),
+            Error::Io(ref error) => write!(formatter, "io error: {}", error),
+            Error::Fmt => formatter.write_str("error writing to formatter"),
+            Error::OutputLimitExceeded { limit, attempted } => write!(
+                formatter,
+                "output of {} bytes exceeds the limit of {} bytes",
+                attempted, limit
+            ),
+            Error::InputLimitExceeded { limit, size } => write!(
+                formatter,
+                "input of {} bytes exceeds the limit of {} bytes",
+                size, limit
+            ),
+            Error::BufferTooSmall { needed, available } => write!(
+                formatter,
+                "output of {} bytes doesn't fit in a buffer of {} bytes",
+                needed, available
+            ),
+            Error::DepthLimitExceeded(limit) => {
+                write!(formatter, "nesting exceeds the depth limit of {}", limit)
+            }
+            Error::MissingField(field) => write!(formatter, "missing field `{}`", field),
+            Error::MissingKey(ref key) => write!(formatter, "missing key `{}`", key),
+            Error::UnknownField { ref got, expected } => {
+                write!(formatter, "unknown field `{}`, {}", got, OneOf(expected))
+            }
+            Error::UnknownVariant { ref got, expected } => {
+                write!(formatter, "unknown variant `{}`, {}", got, OneOf(expected))
+            }
+            Error::InvalidType {
+                ref unexpected,
+                ref expected,
+            } => write!(
+                formatter,
+                "invalid type: {}, expected {}",
+                unexpected, expected
+            ),
+            Error::InvalidValue {
+                ref unexpected,
+                ref expected,
+            } => write!(
+                formatter,
+                "invalid value: {}, expected {}",
+                unexpected, expected
+            ),
+            Error::InvalidLength { len, ref expected } => {
+                write!(formatter, "invalid length {}, expected {}", len, expected)
+            }
+            Error::MissingComma => formatter.write_str("netstring missing its trailing comma"),
+            Error::Mongrel2(msg) => write!(formatter, "mongrel2 error: {}", msg),
+            Error::Nesting(msg) => write!(formatter, "nesting error: {}", msg),
+            Error::At { offset, ref error } => write!(formatter, "at byte {}: {}", offset, error),
+            Error::Item { index, ref error } => write!(formatter, "item {}: {}", index, error),
+            Error::NonStringKey(found) => {
+                write!(formatter, "dict keys must be strings, found {}", found)
+            }
+        }
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
-            Error::Message(ref msg) => msg,
-            Error::UnknownSegmentType => "unknown segment type",
-            Error::LengthNotFound => "length not found but required",
-            Error::StackProblem => "stack problem",
-            Error::UnusedParseData => "unused parse data",
-            Error::ParsingUnit => "error parsing unit",
-            Error::ParsingBool => "error parsing bool",
-            Error::ParsingMap => "error parsing map",
-            Error::ParsingEnum => "error parsing enum",
-            Error::ParsingUnsigned => "error parsing unsigned",
-            Error::ParsingString => "error parsing string",
-            Error::ParsingSeq => "error parsing sequence",
-            Error::ParsingUnitVariant => "error parsing unit variant",
-            Error::Eof => "error eof",
-            Error::UnsupportedType => "unsupported type",
-            Error::ParsingLength => "error parsing data length",
-            Error::NonUtf8Str => "error parsing string that wasn't utf8",
+            Error::Io(ref error) => Some(error),
+            Error::At { ref error, .. } | Error::Item { ref error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        match (self, other) {
+            (Error::Io(a), Error::Io(b)) => a.kind() == b.kind(),
+            (Error::Message(a), Error::Message(b)) => a == b,
+            (Error::ParsingChar(a, a_len), Error::ParsingChar(b, b_len)) => {
+                a == b && a_len == b_len
+            }
+            (
+                Error::UnexpectedType { expected, found },
+                Error::UnexpectedType {
+                    expected: b_expected,
+                    found: b_found,
+                },
+            ) => expected == b_expected && found == b_found,
+            (
+                Error::Expected {
+                    expected,
+                    target,
+                    found,
+                    offset,
+                    snippet,
+                },
+                Error::Expected {
+                    expected: b_expected,
+                    target: b_target,
+                    found: b_found,
+                    offset: b_offset,
+                    snippet: b_snippet,
+                },
+            ) => {
+                expected == b_expected
+                    && target == b_target
+                    && found == b_found
+                    && offset == b_offset
+                    && snippet == b_snippet
+            }
+            (
+                Error::StructLength {
+                    name,
+                    expected,
+                    found,
+                },
+                Error::StructLength {
+                    name: b_name,
+                    expected: b_expected,
+                    found: b_found,
+                },
+            ) => name == b_name && expected == b_expected && found == b_found,
+            (Error::NonStringKey(a), Error::NonStringKey(b)) => a == b,
+            (
+                Error::Item { index, error },
+                Error::Item {
+                    index: b_index,
+                    error: b_error,
+                },
+            ) => index == b_index && error == b_error,
+            (
+                Error::OutputLimitExceeded { limit, attempted },
+                Error::OutputLimitExceeded {
+                    limit: b_limit,
+                    attempted: b_attempted,
+                },
+            ) => limit == b_limit && attempted == b_attempted,
+            (
+                Error::InputLimitExceeded { limit, size },
+                Error::InputLimitExceeded {
+                    limit: b_limit,
+                    size: b_size,
+                },
+            ) => limit == b_limit && size == b_size,
+            (Error::Incomplete { needed }, Error::Incomplete { needed: b_needed }) => {
+                needed == b_needed
+            }
+            (
+                Error::BufferTooSmall { needed, available },
+                Error::BufferTooSmall {
+                    needed: b_needed,
+                    available: b_available,
+                },
+            ) => needed == b_needed && available == b_available,
+            (
+                Error::InvalidUtf8 { offset, error },
+                Error::InvalidUtf8 {
+                    offset: b_offset,
+                    error: b_error,
+                },
+            ) => offset == b_offset && error == b_error,
+            (Error::DepthLimitExceeded(a), Error::DepthLimitExceeded(b)) => a == b,
+            (Error::Nesting(a), Error::Nesting(b)) => a == b,
+            (Error::Mongrel2(a), Error::Mongrel2(b)) => a == b,
+            (
+                Error::At { offset, error },
+                Error::At {
+                    offset: b_offset,
+                    error: b_error,
+                },
+            ) => offset == b_offset && error == b_error,
+            (Error::MissingField(a), Error::MissingField(b)) => a == b,
+            (Error::MissingKey(a), Error::MissingKey(b)) => a == b,
+            (
+                Error::UnknownField { got, expected },
+                Error::UnknownField {
+                    got: b_got,
+                    expected: b_expected,
+                },
+            )
+            | (
+                Error::UnknownVariant { got, expected },
+                Error::UnknownVariant {
+                    got: b_got,
+                    expected: b_expected,
+                },
+            ) => got == b_got && expected == b_expected,
+            (
+                Error::InvalidType {
+                    unexpected,
+                    expected,
+                },
+                Error::InvalidType {
+                    unexpected: b_unexpected,
+                    expected: b_expected,
+                },
+            )
+            | (
+                Error::InvalidValue {
+                    unexpected,
+                    expected,
+                },
+                Error::InvalidValue {
+                    unexpected: b_unexpected,
+                    expected: b_expected,
+                },
+            ) => unexpected == b_unexpected && expected == b_expected,
+            (
+                Error::InvalidLength { len, expected },
+                Error::InvalidLength {
+                    len: b_len,
+                    expected: b_expected,
+                },
+            ) => len == b_len && expected == b_expected,
+            // every variant with fields is compared above, which leaves the
+            // ones without
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Category, Error};
+    use crate::de::from_str;
+    use crate::ser::{to_string, to_string_with, NonFiniteFloats, SerializerConfig};
+    use crate::validate::validate;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::io;
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn test_classify() {
+        let eof = from_str::<String>("5:hel").unwrap_err();
+        assert_eq!(eof.classify(), Category::Eof);
+        assert!(eof.is_eof());
+
+        let syntax = from_str::<String>("5:hello?").unwrap_err();
+        assert!(syntax.is_syntax());
+        assert!(from_str::<f64>("3:1.x^").unwrap_err().is_syntax());
+        assert!(from_str::<String>("5:hello,0:~").unwrap_err().is_syntax());
+
+        let data = from_str::<Point>("8:1:x,1:1#}").unwrap_err();
+        assert_eq!(data.classify(), Category::Data);
+        assert!(from_str::<i32>("5:hello,").unwrap_err().is_data());
+
+        let config = SerializerConfig::new().non_finite_floats(NonFiniteFloats::Error);
+        assert!(to_string_with(&f64::NAN, &config).unwrap_err().is_data());
+        let mut keys = HashMap::new();
+        keys.insert(vec![1], 1);
+        assert!(to_string(&keys).unwrap_err().is_data());
+
+        let io = Error::from(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert!(io.is_io());
+        assert!(!io.is_data());
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    enum Shape {
+        Circle,
+        Square,
+    }
+
+    #[test]
+    fn test_structured_serde_errors() {
+        let error = from_str::<Point>("8:1:x,1:1#}").unwrap_err();
+        assert_eq!(error, Error::MissingField("y"));
+        assert_eq!(error.to_string(), "missing field `y`");
+
+        let error = from_str::<Shape>("8:triangle,").unwrap_err();
+        assert_eq!(
+            error,
+            Error::UnknownVariant {
+                got: "triangle".into(),
+                expected: &["Circle", "Square"],
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown variant `triangle`, expected one of `Circle`, `Square`"
+        );
+
+        let error = from_str::<(u8, u8)>("4:1:1#]").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid length 1, expected a tuple of size 2"
+        );
+        let error = from_str::<u8>("3:300#").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid value: integer `300`, expected u8"
+        );
+        assert!(error.is_data());
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Only {
+        x: i32,
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let error = from_str::<Only>("16:1:x,1:1#1:z,1:2#}").unwrap_err();
+        assert_eq!(
+            error,
+            Error::UnknownField {
+                got: "z".into(),
+                expected: &["x"],
+            }
+        );
+        assert_eq!(error.to_string(), "unknown field `z`, expected `x`");
+    }
+
+    #[test]
+    fn test_io_round_trip() {
+        let error = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+        assert_eq!(error.to_string(), "io error: closed");
+        assert_eq!(
+            std::error::Error::source(&error).map(ToString::to_string),
+            Some("closed".to_string())
+        );
+        assert_eq!(error, Error::Io(io::ErrorKind::BrokenPipe.into()));
+        assert_ne!(error, Error::Io(io::ErrorKind::Other.into()));
+
+        let back = io::Error::from(error);
+        assert_eq!(back.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(back.to_string(), "closed");
+
+        assert_eq!(
+            io::Error::from(Error::Eof).kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        let invalid = io::Error::from(Error::ParsingInt);
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(invalid.to_string(), "error parsing integer");
+    }
+
+    #[test]
+    fn test_classify_wrapped() {
+        let error = validate(b"8:1:a,0:~").unwrap_err();
+        assert!(matches!(error, Error::At { .. }));
+        assert!(error.is_eof());
+        assert!(validate(b"5:1:a,x]").unwrap_err().is_syntax());
+    }
+
+    #[test]
+    fn test_incomplete() {
+        use crate::parse::{frame_len, FrameStatus};
+        use crate::value::Value;
+
+        let frame = b"44:5:hello,2:42#4:true!3:1.5^0:~11:1:k,4:1:1#]}]";
+        for end in 0..frame.len() {
+            let prefix = &frame[..end];
+            let needed = match frame_len(prefix) {
+                Ok(FrameStatus::Incomplete(needed)) => needed,
+                status => panic!("{:?} at {}", status, end),
+            };
+            assert_eq!(
+                needed,
+                if end < 3 {
+                    None
+                } else {
+                    Some(frame.len() - end)
+                }
+            );
+            let expected = Error::Incomplete { needed };
+            assert_eq!(crate::de::from_slice::<Value>(prefix), Err(expected));
+            let error = validate(prefix).unwrap_err();
+            assert!(error.is_incomplete() && error.is_eof(), "{}", end);
         }
+
+        // a bad type tag anywhere is malformed, however much follows
+        for &tag in &[10, 15, 22, 28, 31, 38, 44, 45, 46, 47] {
+            let mut bad = frame.to_vec();
+            bad[tag] = b'x';
+            let error = crate::de::from_slice::<Value>(&bad).unwrap_err();
+            assert!(error.is_syntax() && !error.is_incomplete(), "{}", error);
+            let error = validate(&bad).unwrap_err();
+            assert!(error.is_syntax() && !error.is_incomplete(), "{}", error);
+        }
+
+        assert_eq!(
+            Error::Incomplete { needed: Some(3) }.to_string(),
+            "incomplete input, 3 more bytes needed"
+        );
+        assert_eq!(
+            io::Error::from(Error::Incomplete { needed: None }).kind(),
+            io::ErrorKind::UnexpectedEof
+        );
     }
 }
@@ -0,0 +1,307 @@
+use std::fmt::Write;
+
+use crate::parse::{tag_type, Parser, TNetStringError, Token};
+
+// longer strings are cut short in the dump
+const MAX_SHOWN: usize = 32;
+
+/// Describe the tnetstrings in `input` line by line, for debugging.
+///
+/// Each value gets a line with its byte range, indented by how deeply it's
+/// nested, like `7..30 dict (3 entries)` or `9..15 str "alice"`. Unlike
+/// everything else here this copes with malformed input: whatever parses is
+/// listed, then a last line like `!! error at byte 23: expected digit, found
+/// 'x'` says where and why it stopped. Containers that were never finished
+/// have their count so far.
+pub fn explain(input: &[u8]) -> String {
+    let mut lines = Vec::new();
+    let mut open: Vec<Open> = Vec::new();
+    let mut parser = Parser::new(input);
+    loop {
+        let depth = parser.depth();
+        let (start, limit) = parser.position();
+        let (token, range) = match parser.next() {
+            Some(Ok(next)) => next,
+            Some(Err(error)) => {
+                let (offset, reason) = diagnose(input, start, limit, error);
+                for container in open.drain(..) {
+                    container.finish(&mut lines, " so far");
+                }
+                lines.push(format!("!! error at byte {}: {}", offset, reason));
+                break;
+            }
+            None if input.is_empty() => {
+                lines.push("!! error at byte 0: expected digit, found end of input".to_string());
+                break;
+            }
+            None => break,
+        };
+        let describe = match token {
+            Token::EndList | Token::EndDict => {
+                if let Some(container) = open.pop() {
+                    container.finish(&mut lines, "");
+                }
+                continue;
+            }
+            Token::StartList | Token::StartDict => String::new(),
+            Token::Key(key) => format!("key {}", quoted(key.as_bytes())),
+            Token::Str(payload) => format!("str {}", quoted(payload)),
+            Token::Int(n) => format!("int {}", n),
+            Token::Float(n) => format!("float {}", n),
+            Token::Bool(b) => format!("bool {}", b),
+            Token::Null => "null".to_string(),
+        };
+        if let Some(container) = open.last_mut() {
+            container.count += 1;
+        }
+        if let Token::StartList | Token::StartDict = token {
+            open.push(Open {
+                line: lines.len(),
+                count: 0,
+                is_dict: token == Token::StartDict,
+            });
+        }
+        lines.push(format!(
+            "{:indent$}{}..{} {}",
+            "",
+            range.start,
+            range.end,
+            describe,
+            indent = depth * 2
+        ));
+    }
+    let mut output = String::new();
+    for line in lines {
+        // `String` never fails
+        let _ = writeln!(output, "{}", line);
+    }
+    output
+}
+
+// A list or dict whose line is waiting for its item count.
+struct Open {
+    line: usize,
+    count: usize,
+    is_dict: bool,
+}
+
+impl Open {
+    fn finish(&self, lines: &mut [String], suffix: &str) {
+        let summary = if self.is_dict {
+            let entries = self.count / 2;
+            format!(
+                "dict ({} {}{})",
+                entries,
+                plural(entries, "entry", "entries"),
+                suffix
+            )
+        } else {
+            format!(
+                "list ({} {}{})",
+                self.count,
+                plural(self.count, "item", "items"),
+                suffix
+            )
+        };
+        lines[self.line].push_str(&summary);
+    }
+}
+
+fn plural(n: usize, one: &'static str, many: &'static str) -> &'static str {
+    if n == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+fn quoted(bytes: &[u8]) -> String {
+    if bytes.len() > MAX_SHOWN {
+        format!(
+            "\"{}\"... ({} bytes)",
+            bytes[..MAX_SHOWN].escape_ascii(),
+            bytes.len()
+        )
+    } else {
+        format!("\"{}\"", bytes.escape_ascii())
+    }
+}
+
+fn found(byte: Option<&u8>) -> String {
+    match byte {
+        Some(byte) => format!("'{}'", byte.escape_ascii()),
+        None => "end of input".to_string(),
+    }
+}
+
+// Works out the offset and reason for a parser error, re-reading the frame
+// at `start` that has to end by `limit`.
+fn diagnose(input: &[u8], start: usize, limit: usize, error: TNetStringError) -> (usize, String) {
+    if error == TNetStringError::MissingDictValue {
+        return (start, error.to_string());
+    }
+    let data = &input[start..limit];
+    let digits = data.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return (
+            start,
+            format!("expected digit, found {}", found(data.first())),
+        );
+    }
+    if data.get(digits) != Some(&b':') {
+        return (
+            start + digits,
+            format!("expected digit or ':', found {}", found(data.get(digits))),
+        );
+    }
+    let len = match std::str::from_utf8(&data[..digits])
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+    {
+        Some(len) => len,
+        None => return (start, "length prefix is too large".to_string()),
+    };
+    let payload_start = start + digits + 1;
+    let tag_pos = match payload_start.checked_add(len) {
+        Some(tag_pos) if tag_pos < limit => tag_pos,
+        _ => {
+            let within = if limit == input.len() {
+                "the input"
+            } else {
+                "its container"
+            };
+            return (
+                start,
+                format!(
+                    "length prefix of {} needs {} more bytes, but {} has {} left",
+                    len,
+                    len.saturating_add(1),
+                    within,
+                    limit - payload_start.min(limit)
+                ),
+            );
+        }
+    };
+    if tag_type(input[tag_pos]).is_none() {
+        return (
+            tag_pos,
+            format!("unknown type tag {}", found(input.get(tag_pos))),
+        );
+    }
+    match error {
+        TNetStringError::FoundNonStringKey | TNetStringError::NonUtf8Key => {
+            (start, error.to_string())
+        }
+        _ => (payload_start, error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            explain(b"56:2:id,2:42#4:name,5:alice,4:tags,20:1:a,3:1.5^0:~4:true!]}0:]"),
+            concat!(
+                "0..60 dict (3 entries)\n",
+                "  3..8 key \"id\"\n",
+                "  8..13 int 42\n",
+                "  13..20 key \"name\"\n",
+                "  20..28 str \"alice\"\n",
+                "  28..35 key \"tags\"\n",
+                "  35..59 list (4 items)\n",
+                "    38..42 str \"a\"\n",
+                "    42..48 float 1.5\n",
+                "    48..51 null\n",
+                "    51..58 bool true\n",
+                "60..63 list (0 items)\n",
+            )
+        );
+        assert_eq!(
+            explain(b"40:abcdefghijklmnopqrstuvwxyz\x00bcdefghijklmn,"),
+            "0..44 str \"abcdefghijklmnopqrstuvwxyz\\x00bcdef\"... (40 bytes)\n"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            explain(b"56:2:id,2:4x#4:name,5:alice,4:tags,20:1:a,3:1.5^0:~4:true!]}"),
+            concat!(
+                "0..60 dict (0 entries so far)\n",
+                "  3..8 key \"id\"\n",
+                "!! error at byte 10: error parsing integer\n",
+            )
+        );
+        assert_eq!(
+            explain(b"23:1:a,15:1:k,8:1:1#1:2x]}]"),
+            concat!(
+                "0..27 list (2 items so far)\n",
+                "  3..7 str \"a\"\n",
+                "  7..26 dict (1 entry so far)\n",
+                "    10..14 key \"k\"\n",
+                "    14..25 list (1 item so far)\n",
+                "      16..20 int 1\n",
+                "!! error at byte 23: unknown type tag 'x'\n",
+            )
+        );
+        for (input, expected) in &[
+            (
+                &b"8:1:a,x:b,]"[..],
+                "0..11 list (1 item so far)\n  2..6 str \"a\"\n\
+                 !! error at byte 6: expected digit, found 'x'\n",
+            ),
+            (
+                b"4:1:a,}",
+                "0..7 dict (0 entries so far)\n  2..6 key \"a\"\n\
+                 !! error at byte 6: dict key without a value\n",
+            ),
+            (
+                b"8:1:1#1:a,}",
+                "0..11 dict (0 entries so far)\n\
+                 !! error at byte 2: dict key that isn't a string\n",
+            ),
+            (
+                b"56:2:id,2:42#4:na",
+                "!! error at byte 0: length prefix of 56 needs 57 more bytes, but the input has 14 left\n",
+            ),
+            (
+                b"6:4:abc,]",
+                "0..9 list (0 items so far)\n\
+                 !! error at byte 2: length prefix of 4 needs 5 more bytes, but its container has 4 left\n",
+            ),
+            (b"5:hello?", "!! error at byte 7: unknown type tag '?'\n"),
+            (
+                b"12x:",
+                "!! error at byte 2: expected digit or ':', found 'x'\n",
+            ),
+            (
+                b"",
+                "!! error at byte 0: expected digit, found end of input\n",
+            ),
+            (
+                b"5:hello,5:",
+                "0..8 str \"hello\"\n\
+                 !! error at byte 8: length prefix of 5 needs 6 more bytes, but the input has 0 left\n",
+            ),
+            (
+                b"99999999999999999999999:",
+                "!! error at byte 0: length prefix is too large\n",
+            ),
+        ] {
+            assert_eq!(explain(input), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_never_panics() {
+        let input = b"23:1:a,15:1:k,8:1:1#1:2#]}]";
+        for end in 0..input.len() {
+            for start in 0..end {
+                explain(&input[start..end]);
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use crate::de::from_slice;
+use crate::error::{Error, Result};
+use crate::frames::FrameAccumulator;
+use crate::ser::to_vec;
+
+const READ_SIZE: usize = 8 * 1024;
+
+/// A blocking stream of tnetstring frames, such as a `TcpStream`, for
+/// sending and receiving one typed message per frame.
+///
+/// Reads are buffered, so a frame split across several reads is put back
+/// together and bytes read past the end of one frame are kept for the next.
+pub struct Framed<S> {
+    stream: S,
+    frames: FrameAccumulator,
+    max_frame_len: usize,
+}
+
+impl<S> Framed<S>
+where
+    S: Read + Write,
+{
+    pub fn new(stream: S) -> Self {
+        Framed {
+            stream,
+            frames: FrameAccumulator::new(),
+            max_frame_len: 0,
+        }
+    }
+
+    /// Fail on frames longer than this many bytes, both ways: with
+    /// `Error::InputLimitExceeded` as soon as an incoming frame's length
+    /// prefix is read, and with `Error::OutputLimitExceeded` before anything
+    /// is written. `0`, the default, means no limit.
+    pub fn max_frame_len(mut self, limit: usize) -> Self {
+        self.frames = self.frames.max_frame_len(limit);
+        self.max_frame_len = limit;
+        self
+    }
+
+    /// Serialize `value` and write it as a single frame, then flush.
+    pub fn send<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let frame = to_vec(value)?;
+        if self.max_frame_len != 0 && frame.len() > self.max_frame_len {
+            return Err(Error::OutputLimitExceeded {
+                limit: self.max_frame_len,
+                attempted: frame.len(),
+            });
+        }
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Block until the next frame has arrived and deserialize it.
+    ///
+    /// Fails with `Error::Eof` if the stream ends first. A frame that fails
+    /// to deserialize is still consumed, so the next one can be read.
+    pub fn recv<T>(&mut self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return from_slice(&frame);
+            }
+            if self.fill()? == 0 {
+                return Err(Error::Eof);
+            }
+        }
+    }
+
+    /// How many bytes have been read but not yet received as a frame.
+    pub fn buffered(&self) -> usize {
+        self.frames.buffered()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Reading or writing the stream directly can leave it part way through
+    /// a frame.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// The stream back, dropping any buffered bytes.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    // read once into the accumulator, returning how many bytes were read
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; READ_SIZE];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(n) => {
+                    self.frames.push(&chunk[..n]);
+                    return Ok(n);
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl<S> Framed<S>
+where
+    S: Read + Write + ReadTimeout,
+{
+    /// `recv`, giving up with `Ok(None)` if no whole frame arrives within
+    /// `timeout`.
+    ///
+    /// Part of a frame that did arrive stays buffered for the next call. The
+    /// stream's own read timeout is put back afterwards.
+    pub fn recv_timeout<T>(&mut self, timeout: Duration) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let previous = self.stream.read_timeout()?;
+        let result = self.recv_before(Instant::now() + timeout);
+        self.stream.set_read_timeout(previous)?;
+        result
+    }
+
+    fn recv_before<T>(&mut self, deadline: Instant) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return from_slice(&frame).map(Some);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return Ok(None);
+            }
+            self.stream.set_read_timeout(Some(remaining))?;
+            match self.fill() {
+                Ok(0) => return Err(Error::Eof),
+                Ok(_) => {}
+                Err(ref error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+/// A stream with a read timeout, for `Framed::recv_timeout`.
+pub trait ReadTimeout {
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ReadTimeout for std::os::unix::net::UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        std::os::unix::net::UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Framed;
+    use crate::error::Error;
+    use std::io::{self, Cursor, Read, Write};
+
+    // hands out its input a byte per read and records what's written
+    struct Trickle {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Trickle {
+        fn new(input: &[u8]) -> Self {
+            Trickle {
+                input: Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_partial_reads() {
+        let mut framed = Framed::new(Trickle::new(b"5:hello,1:1#3:abc#2:ok,4:clo"));
+        assert_eq!(framed.recv::<String>().unwrap(), "hello");
+        assert_eq!(framed.recv::<u8>().unwrap(), 1);
+        // a bad frame is skipped
+        assert!(framed.recv::<u8>().is_err());
+        assert_eq!(framed.recv::<String>().unwrap(), "ok");
+        assert_eq!(framed.recv::<String>(), Err(Error::Eof));
+        assert_eq!(framed.buffered(), 5);
+
+        framed.send(&("a", 1)).unwrap();
+        framed.send(&"b").unwrap();
+        assert_eq!(framed.get_ref().output, b"8:1:a,1:1#]1:b,");
+    }
+
+    #[test]
+    fn test_max_frame_len() {
+        let mut framed = Framed::new(Trickle::new(b"5:hello,100:")).max_frame_len(8);
+        assert_eq!(framed.recv::<String>().unwrap(), "hello");
+        assert!(matches!(
+            framed.recv::<String>(),
+            Err(Error::InputLimitExceeded {
+                limit: 8,
+                size: 105
+            })
+        ));
+        assert!(matches!(
+            framed.send(&"hello!"),
+            Err(Error::OutputLimitExceeded {
+                limit: 8,
+                attempted: 9
+            })
+        ));
+        assert!(framed.into_inner().output.is_empty());
+    }
+}
@@ -0,0 +1,404 @@
+use crate::error::{Error, Result};
+use crate::parse::{frame_len, parse, FrameStatus, TNetStringError};
+
+/// Split a buffer of back-to-back tnetstrings into one slice per frame.
+///
+/// Only each frame's length prefix and type tag are checked. On malformed or
+/// truncated input the iterator yields a single `Error::At` with the offset
+/// of the bad frame, then stops. A frame cut short by the end of the buffer
+/// is `Error::Incomplete`.
+pub fn frames(buf: &[u8]) -> Frames<'_> {
+    Frames {
+        buf,
+        offset: 0,
+        failed: false,
+        separators: Vec::new(),
+    }
+}
+
+/// The iterator returned by `frames`.
+#[derive(Clone, Debug)]
+pub struct Frames<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    failed: bool,
+    separators: Vec<u8>,
+}
+
+impl<'a> Frames<'a> {
+    /// Where the next frame starts.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Skip any of these bytes between frames, such as `b"\r\n"` for one
+    /// frame per line. None are skipped by default.
+    pub fn separators(mut self, separators: &[u8]) -> Self {
+        self.separators = separators.to_vec();
+        self
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.offset += self.buf[self.offset..]
+            .iter()
+            .take_while(|byte| self.separators.contains(byte))
+            .count();
+        let input = &self.buf[self.offset..];
+        if self.failed || input.is_empty() {
+            return None;
+        }
+        match parse(input) {
+            Ok(parsed) => {
+                let frame = &input[..input.len() - parsed.rest.len()];
+                self.offset += frame.len();
+                Some(Ok(frame))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(Error::At {
+                    offset: self.offset,
+                    error: Box::new(incomplete(input).unwrap_or_else(|| frame_error(error))),
+                }))
+            }
+        }
+    }
+}
+
+/// Deserialize each of the back-to-back tnetstrings in `buf` into a `T`, in
+/// parallel on rayon's thread pool.
+///
+/// The buffer is split with `frames` first, which only reads each frame's
+/// prefix and tag, then the frames are decoded in parallel. The output is in
+/// input order. If any frame fails, the error for the first one that did is
+/// wrapped in `Error::Item` with its index.
+#[cfg(feature = "rayon")]
+pub fn from_frames_par<T>(buf: &[u8]) -> Result<Vec<T>>
+where
+    T: serde::de::DeserializeOwned + Send,
+{
+    use rayon::prelude::*;
+
+    let frames = frames(buf).collect::<Result<Vec<_>>>()?;
+    let values: Vec<Result<T>> = frames
+        .par_iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            crate::de::from_slice(frame).map_err(|error| Error::Item {
+                index: index as u64,
+                error: Box::new(error),
+            })
+        })
+        .collect();
+    values.into_iter().collect()
+}
+
+/// Reassembles frames from chunks of a byte stream, such as reads off a
+/// socket.
+///
+/// Push bytes in as they arrive and pop frames out with `next_frame`. Once
+/// a frame's length prefix has been read the accumulator remembers its size,
+/// so pushing more bytes doesn't rescan what's already buffered.
+#[derive(Clone, Debug, Default)]
+pub struct FrameAccumulator {
+    buf: Vec<u8>,
+    // where the next frame starts in `buf`
+    start: usize,
+    // the next frame's total length, once its prefix has been read
+    frame_len: Option<usize>,
+    // bytes popped so far, for error offsets
+    popped: usize,
+    max_frame_len: usize,
+}
+
+impl FrameAccumulator {
+    pub fn new() -> Self {
+        FrameAccumulator::default()
+    }
+
+    /// Fail with `Error::InputLimitExceeded` on any frame longer than this
+    /// many bytes, as soon as its length prefix has been read. `0`, the
+    /// default, means no limit.
+    pub fn max_frame_len(mut self, limit: usize) -> Self {
+        self.max_frame_len = limit;
+        self
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// How many bytes are buffered, but not yet popped as a frame.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// Pop the next complete frame, or `None` if more bytes are needed.
+    ///
+    /// Errors are wrapped in `Error::At` with the frame's offset in the
+    /// stream. After one the buffered bytes are left in place, since there's
+    /// no telling where the next frame would start.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let len = match self.frame_len {
+            Some(len) => len,
+            None => {
+                let len = match frame_len(&self.buf[self.start..]) {
+                    Ok(FrameStatus::Complete(len)) => len,
+                    Ok(FrameStatus::Incomplete(Some(needed))) => self.buffered() + needed,
+                    Ok(FrameStatus::Incomplete(None)) => {
+                        self.check_len(self.buffered())?;
+                        return Ok(None);
+                    }
+                    Err(error) => return Err(self.error(error)),
+                };
+                self.check_len(len)?;
+                self.frame_len = Some(len);
+                len
+            }
+        };
+        if self.buffered() < len {
+            return Ok(None);
+        }
+        let frame = self.buf[self.start..self.start + len].to_vec();
+        self.start += len;
+        self.popped += len;
+        self.frame_len = None;
+        // drop popped frames once they make up most of the buffer
+        if self.start > self.buf.len() / 2 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+        Ok(Some(frame))
+    }
+
+    fn check_len(&self, len: usize) -> Result<()> {
+        if self.max_frame_len != 0 && len > self.max_frame_len {
+            return Err(Error::InputLimitExceeded {
+                limit: self.max_frame_len,
+                size: len,
+            });
+        }
+        Ok(())
+    }
+
+    fn error(&self, error: TNetStringError) -> Error {
+        Error::At {
+            offset: self.popped,
+            error: Box::new(frame_error(error)),
+        }
+    }
+}
+
+// `Error::Incomplete` if `input` is the start of a frame that stops short,
+// so whatever went wrong reading it was only for want of more bytes
+pub(crate) fn incomplete(input: &[u8]) -> Option<Error> {
+    match frame_len(input) {
+        Ok(FrameStatus::Incomplete(needed)) => Some(Error::Incomplete { needed }),
+        _ => None,
+    }
+}
+
+// maps the errors `parse` and `frame_len` give for a bad length prefix or
+// type tag
+pub(crate) fn frame_error(error: TNetStringError) -> Error {
+    match error {
+        TNetStringError::MissingLengthPrefix => Error::LengthNotFound,
+        TNetStringError::UnableToParseInt => Error::ParsingLength,
+        TNetStringError::UnknownSegmentType => Error::UnknownSegmentType,
+        _ => Error::Eof,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frames, FrameAccumulator};
+    use crate::error::Error;
+
+    fn at(offset: usize, error: Error) -> Error {
+        Error::At {
+            offset,
+            error: Box::new(error),
+        }
+    }
+
+    #[test]
+    fn test_three_frames() {
+        let buf = b"5:hello,12:3:foo,3:bar,]0:~";
+        let split: Vec<_> = frames(buf).collect();
+        assert_eq!(
+            split,
+            vec![
+                Ok(&b"5:hello,"[..]),
+                Ok(&b"12:3:foo,3:bar,]"[..]),
+                Ok(&b"0:~"[..]),
+            ]
+        );
+        assert_eq!(frames(b"").next(), None);
+    }
+
+    #[test]
+    fn test_separators() {
+        let split: Vec<_> = frames(b"5:hello,\r\n4:a\r\nb,\r\n")
+            .separators(b"\r\n")
+            .collect();
+        assert_eq!(split, vec![Ok(&b"5:hello,"[..]), Ok(&b"4:a\r\nb,"[..])]);
+
+        let mut iter = frames(b"5:hello,\n0:~");
+        assert_eq!(iter.next(), Some(Ok(&b"5:hello,"[..])));
+        assert!(iter.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut iter = frames(b"5:hello,12:3:foo,");
+        assert_eq!(iter.next(), Some(Ok(&b"5:hello,"[..])));
+        assert_eq!(
+            iter.next(),
+            Some(Err(at(8, Error::Incomplete { needed: Some(7) })))
+        );
+        assert_eq!(iter.next(), None);
+
+        let split: Vec<_> = frames(b"0:~12").collect();
+        assert_eq!(
+            split,
+            vec![
+                Ok(&b"0:~"[..]),
+                Err(at(3, Error::Incomplete { needed: None }))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        // the declared length runs past the end of the buffer
+        let split: Vec<_> = frames(b"0:~999:abc,").collect();
+        assert_eq!(
+            split,
+            vec![
+                Ok(&b"0:~"[..]),
+                Err(at(3, Error::Incomplete { needed: Some(996) }))
+            ]
+        );
+
+        let split: Vec<_> = frames(b"5:hello?0:~").collect();
+        assert_eq!(split, vec![Err(at(0, Error::UnknownSegmentType))]);
+        let split: Vec<_> = frames(b"0:~\n0:~").collect();
+        assert_eq!(
+            split,
+            vec![Ok(&b"0:~"[..]), Err(at(3, Error::LengthNotFound))]
+        );
+    }
+
+    const STREAM: &[u8] = b"5:hello,12:3:foo,3:bar,]0:~22:1:a,1:1#1:b,7:1:c,0:~]}0:,2:-1#";
+
+    fn expected() -> Vec<Vec<u8>> {
+        frames(STREAM)
+            .map(|frame| frame.unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_accumulate_bytewise() {
+        let mut accumulator = FrameAccumulator::new();
+        let mut popped = Vec::new();
+        for &byte in STREAM {
+            accumulator.push(&[byte]);
+            while let Some(frame) = accumulator.next_frame().unwrap() {
+                popped.push(frame);
+            }
+        }
+        assert_eq!(popped, expected());
+        assert_eq!(accumulator.buffered(), 0);
+    }
+
+    #[test]
+    fn test_accumulate_random_chunks() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200 {
+            let mut accumulator = FrameAccumulator::new().max_frame_len(64);
+            let mut popped = Vec::new();
+            let mut rest = STREAM;
+            while !rest.is_empty() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let (chunk, tail) = rest.split_at(((state % 20) as usize + 1).min(rest.len()));
+                accumulator.push(chunk);
+                rest = tail;
+                while let Some(frame) = accumulator.next_frame().unwrap() {
+                    popped.push(frame);
+                }
+            }
+            assert_eq!(popped, expected());
+        }
+    }
+
+    #[test]
+    fn test_accumulate_errors() {
+        let mut accumulator = FrameAccumulator::new().max_frame_len(16);
+        accumulator.push(b"5:hello,1");
+        assert_eq!(accumulator.next_frame(), Ok(Some(b"5:hello,".to_vec())));
+        assert_eq!(accumulator.next_frame(), Ok(None));
+        accumulator.push(b"00:");
+        assert_eq!(
+            accumulator.next_frame(),
+            Err(Error::InputLimitExceeded {
+                limit: 16,
+                size: 105,
+            })
+        );
+
+        // a prefix that can't end up under the limit
+        let mut accumulator = FrameAccumulator::new().max_frame_len(16);
+        accumulator.push(b"00000000000000000");
+        assert!(accumulator.next_frame().is_err());
+
+        let mut accumulator = FrameAccumulator::new();
+        accumulator.push(b"0:~5:hello?");
+        assert_eq!(accumulator.next_frame(), Ok(Some(b"0:~".to_vec())));
+        assert_eq!(
+            accumulator.next_frame(),
+            Err(at(3, Error::UnknownSegmentType))
+        );
+        assert_eq!(accumulator.buffered(), 8);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_from_frames_par() {
+        use super::from_frames_par;
+        use crate::de::Deserializer;
+        use crate::ser::to_writer_seq;
+        use crate::value::Value;
+
+        let mut buf = Vec::new();
+        let values = (0..5000u64).map(|i| match i % 3 {
+            0 => Value::from(i),
+            1 => Value::Str(format!("value {}", i)),
+            _ => Value::List(vec![Value::from(i), Value::Null]),
+        });
+        to_writer_seq(&mut buf, values).unwrap();
+
+        let sequential = Deserializer::from_slice(&buf)
+            .into_iter::<Value>()
+            .collect::<crate::Result<Vec<_>>>();
+        assert_eq!(from_frames_par(&buf), sequential);
+        assert_eq!(from_frames_par::<Value>(b""), Ok(vec![]));
+
+        // the first frame that fails, whichever thread gets there first
+        assert_eq!(
+            from_frames_par::<u32>(b"1:1#1:x,1:2#2:-1#"),
+            Err(Error::Item {
+                index: 1,
+                error: Box::new(crate::de::from_slice::<u32>(b"1:x,").unwrap_err()),
+            })
+        );
+        assert_eq!(
+            from_frames_par::<u32>(b"1:1#1:2#3:x"),
+            Err(at(8, Error::Incomplete { needed: Some(3) }))
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::map::Map;
+use crate::value::Value;
+
+impl Value {
+    /// Generate a value from fuzzer input, with lists and dicts nested at
+    /// most `max_depth` deep and holding at most `max_len` entries each.
+    ///
+    /// Floats are never NaN, so generated values always equal themselves.
+    pub fn arbitrary_bounded(
+        u: &mut Unstructured,
+        max_depth: usize,
+        max_len: usize,
+    ) -> Result<Value> {
+        let choices = if max_depth == 0 { 7 } else { 9 };
+        Ok(match u.choose_index(choices)? {
+            0 => Value::Null,
+            1 => Value::Bool(bool::arbitrary(u)?),
+            2 => Value::from(u64::arbitrary(u)?),
+            3 => Value::from(i64::arbitrary(u)?),
+            4 => {
+                let v = f64::arbitrary(u)?;
+                Value::from(if v.is_nan() { 0.0 } else { v })
+            }
+            5 => Value::Str(String::arbitrary(u)?),
+            // only bytes that aren't UTF-8, the way they'd be parsed
+            6 => match String::from_utf8(Vec::arbitrary(u)?) {
+                Ok(v) => Value::Str(v),
+                Err(e) => Value::Bytes(e.into_bytes()),
+            },
+            7 => {
+                let len = u.int_in_range(0..=max_len)?;
+                let mut list = Vec::with_capacity(len);
+                for _ in 0..len {
+                    list.push(Value::arbitrary_bounded(u, max_depth - 1, max_len)?);
+                }
+                Value::List(list)
+            }
+            _ => {
+                let len = u.int_in_range(0..=max_len)?;
+                let mut dict = Map::new();
+                for _ in 0..len {
+                    let key = String::arbitrary(u)?;
+                    dict.insert(key, Value::arbitrary_bounded(u, max_depth - 1, max_len)?);
+                }
+                Value::Dict(dict)
+            }
+        })
+    }
+}
+
+// four levels of at most eight entries keeps generated values small
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Value> {
+        Value::arbitrary_bounded(u, 4, 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::de::from_slice;
+    use crate::ser::to_vec;
+    use crate::value::Value;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    // xorshift, so the test doesn't need a random number crate
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut nested = 0;
+        for seed in 0..2000 {
+            let bytes = random_bytes(seed, 4096);
+            let value = Value::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            if matches!(value, Value::List(_) | Value::Dict(_)) {
+                nested += 1;
+            }
+            let encoded = to_vec(&value).unwrap();
+            assert_eq!(
+                from_slice::<Value>(&encoded),
+                Ok(value),
+                "{}",
+                String::from_utf8_lossy(&encoded)
+            );
+        }
+        assert!(nested > 100);
+    }
+
+    #[test]
+    fn test_bounds() {
+        fn depth(value: &Value) -> usize {
+            match *value {
+                Value::List(ref list) => 1 + list.iter().map(depth).max().unwrap_or(0),
+                Value::Dict(ref dict) => 1 + dict.values().map(depth).max().unwrap_or(0),
+                _ => 0,
+            }
+        }
+
+        for seed in 0..500 {
+            let bytes = random_bytes(seed, 4096);
+            let mut u = Unstructured::new(&bytes);
+            let value = Value::arbitrary_bounded(&mut u, 2, 3).unwrap();
+            assert!(depth(&value) <= 2, "{:?}", value);
+        }
+        let value = Value::arbitrary_bounded(&mut Unstructured::new(&[]), 4, 8).unwrap();
+        assert_eq!(value, Value::Null);
+    }
+}
@@ -0,0 +1,386 @@
+//! Helpers for `Duration` and `SystemTime` fields, for use with
+//! `#[serde(with = "...")]`.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Job {
+//!     #[serde(with = "tnetstring::helpers::duration_millis")]
+//!     timeout: Duration,
+//!     #[serde(with = "tnetstring::helpers::systemtime_unix_secs")]
+//!     started: SystemTime,
+//! }
+//! ```
+//!
+//! Each is written as a single `#` integer, where serde's own impls write a
+//! dict of seconds and nanoseconds. Values that don't fit in a `u64`, and
+//! times before the Unix epoch, fail with an error rather than wrapping.
+//!
+//! With the `heapless` feature, `heapless_bytes` reads a byte string into a
+//! `heapless::Vec<u8, N>` without allocating. `heapless::String<N>` and
+//! `heapless::Vec<T, N>` need no helper. Input too long for any of them
+//! fails with an error.
+//!
+//! With the `uuid` feature, `Uuid`s are written as their hyphenated string
+//! by default, and `uuid_bytes` and `uuid_simple` write them more compactly.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+fn to_units(duration: Duration, nanos: u64) -> Option<u64> {
+    u64::try_from(duration.as_nanos() / u128::from(nanos)).ok()
+}
+
+fn from_units(n: u64, nanos: u64) -> Duration {
+    let per_sec = NANOS_PER_SEC / nanos;
+    Duration::new(n / per_sec, ((n % per_sec) * nanos) as u32)
+}
+
+macro_rules! duration_module {
+    ($name:ident, $unit:expr, $doc:expr) => {
+        #[doc = $doc]
+        pub mod $name {
+            use serde::de::{Deserialize, Deserializer};
+            use serde::ser::{self, Serializer};
+            use std::time::Duration;
+
+            pub fn serialize<S>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let n = super::to_units(*value, $unit)
+                    .ok_or_else(|| ser::Error::custom("duration out of range"))?;
+                serializer.serialize_u64(n)
+            }
+
+            pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                Ok(super::from_units(u64::deserialize(deserializer)?, $unit))
+            }
+        }
+    };
+}
+
+macro_rules! systemtime_module {
+    ($name:ident, $unit:expr, $doc:expr) => {
+        #[doc = $doc]
+        pub mod $name {
+            use serde::de::{self, Deserialize, Deserializer, Unexpected};
+            use serde::ser::{self, Serializer};
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            pub fn serialize<S>(value: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let since = value
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|_| ser::Error::custom("time before the Unix epoch"))?;
+                let n = super::to_units(since, $unit)
+                    .ok_or_else(|| ser::Error::custom("time out of range"))?;
+                serializer.serialize_u64(n)
+            }
+
+            pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let n = u64::deserialize(deserializer)?;
+                UNIX_EPOCH
+                    .checked_add(super::from_units(n, $unit))
+                    .ok_or_else(|| {
+                        de::Error::invalid_value(Unexpected::Unsigned(n), &"a time in range")
+                    })
+            }
+        }
+    };
+}
+
+duration_module!(
+    duration_millis,
+    1_000_000,
+    "Whole milliseconds, as an integer. Anything below a millisecond is \
+     dropped."
+);
+duration_module!(
+    duration_nanos,
+    1,
+    "Nanoseconds, as an integer, for durations of up to about 584 years."
+);
+systemtime_module!(
+    systemtime_unix_secs,
+    1_000_000_000,
+    "Whole seconds since the Unix epoch, as an integer. Anything below a \
+     second is dropped."
+);
+systemtime_module!(
+    systemtime_unix_nanos,
+    1,
+    "Nanoseconds since the Unix epoch, as an integer, for times up to the \
+     year 2554."
+);
+
+/// A `heapless::Vec<u8, N>` as a byte string.
+#[cfg(feature = "heapless")]
+pub mod heapless_bytes {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S, const N: usize>(
+        value: &heapless::Vec<u8, N>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(value)
+    }
+
+    pub fn deserialize<'de, D, const N: usize>(
+        deserializer: D,
+    ) -> Result<heapless::Vec<u8, N>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BytesVisitor<const N: usize>;
+
+        impl<const N: usize> Visitor<'_> for BytesVisitor<N> {
+            type Value = heapless::Vec<u8, N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "at most {} bytes", N)
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                heapless::Vec::from_slice(v).map_err(|_| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// The 16 bytes of a `Uuid`, as a byte string.
+#[cfg(feature = "uuid")]
+pub mod uuid_bytes {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+    use std::fmt;
+    use uuid::Uuid;
+
+    pub fn serialize<S>(value: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(value.as_bytes())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BytesVisitor;
+
+        impl Visitor<'_> for BytesVisitor {
+            type Value = Uuid;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("16 bytes")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Uuid, E>
+            where
+                E: de::Error,
+            {
+                Uuid::from_slice(v).map_err(|_| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// A `Uuid` as 32 lowercase hex digits, without hyphens.
+#[cfg(feature = "uuid")]
+pub mod uuid_simple {
+    use serde::de::{self, Deserialize, Deserializer, Unexpected};
+    use serde::ser::Serializer;
+    use std::borrow::Cow;
+    use uuid::Uuid;
+
+    pub fn serialize<S>(value: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(value.simple().encode_lower(&mut Uuid::encode_buffer()))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        match Uuid::try_parse(&s) {
+            Ok(uuid) if s.len() == 32 => Ok(uuid),
+            _ => Err(de::Error::invalid_value(
+                Unexpected::Str(&s),
+                &"32 hex digits",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::de::from_str;
+    use crate::ser::to_string;
+    use serde::{Deserialize, Serialize};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Job {
+        #[serde(with = "super::duration_millis")]
+        timeout: Duration,
+        #[serde(with = "super::duration_nanos")]
+        elapsed: Duration,
+        #[serde(with = "super::systemtime_unix_secs")]
+        started: SystemTime,
+        #[serde(with = "super::systemtime_unix_nanos")]
+        finished: SystemTime,
+    }
+
+    const ENCODED: &str = "99:7:timeout,4:1500#7:elapsed,10:2000000003#7:started,\
+                           10:1000000000#8:finished,19:1000000005000000006#}";
+
+    #[test]
+    fn test_round_trip() {
+        let job = Job {
+            timeout: Duration::from_millis(1500),
+            elapsed: Duration::new(2, 3),
+            started: UNIX_EPOCH + Duration::from_secs(1_000_000_000),
+            finished: UNIX_EPOCH + Duration::new(1_000_000_005, 6),
+        };
+        let encoded = to_string(&job).unwrap();
+        assert_eq!(encoded, ENCODED);
+        assert_eq!(from_str::<Job>(&encoded).unwrap(), job);
+
+        // finer than the unit is dropped
+        let rounded = Job {
+            timeout: Duration::new(1, 500_999_999),
+            started: UNIX_EPOCH + Duration::new(1_000_000_000, 999_999_999),
+            ..job
+        };
+        let decoded: Job = from_str(&to_string(&rounded).unwrap()).unwrap();
+        assert_eq!(decoded.timeout, Duration::from_millis(1500));
+        assert_eq!(
+            decoded.started,
+            UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        #[derive(Serialize)]
+        struct Plain {
+            timeout: Duration,
+        }
+        #[derive(Serialize)]
+        struct Helper {
+            #[serde(with = "super::duration_millis")]
+            timeout: Duration,
+        }
+
+        let timeout = Duration::from_millis(1500);
+        assert_eq!(
+            to_string(&Helper { timeout }).unwrap(),
+            "17:7:timeout,4:1500#}"
+        );
+        assert_eq!(
+            to_string(&Plain { timeout }).unwrap(),
+            "45:7:timeout,31:4:secs,1:1#5:nanos,9:500000000#}}"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let job = |elapsed, started| Job {
+            timeout: Duration::from_millis(1),
+            elapsed,
+            started,
+            finished: UNIX_EPOCH,
+        };
+        assert!(to_string(&job(Duration::MAX, UNIX_EPOCH)).is_err());
+        assert!(to_string(&job(Duration::ZERO, UNIX_EPOCH - Duration::from_secs(1))).is_err());
+
+        for input in &[
+            "58:7:timeout,2:-1#7:elapsed,1:0#7:started,1:0#8:finished,1:0#}",
+            "59:7:timeout,1:0#7:elapsed,1:0#7:started,3:1.5^8:finished,1:0#}",
+            "77:7:timeout,1:0#7:elapsed,1:0#7:started,20:18446744073709551615#8:finished,1:0#}",
+            "77:7:timeout,1:0#7:elapsed,1:0#7:started,20:18446744073709551616#8:finished,1:0#}",
+        ] {
+            assert!(from_str::<Job>(input).is_err(), "{}", input);
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid() {
+        use crate::de::from_slice;
+        use crate::ser::to_vec;
+        use uuid::Uuid;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Ids {
+            text: Uuid,
+            #[serde(with = "super::uuid_bytes")]
+            bytes: Uuid,
+            #[serde(with = "super::uuid_simple")]
+            simple: Uuid,
+        }
+
+        let id = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        let ids = Ids {
+            text: id,
+            bytes: id,
+            simple: id,
+        };
+        let encoded = to_vec(&ids).unwrap();
+        assert_eq!(from_slice::<Ids>(&encoded).unwrap(), ids);
+        let text = "36:67e55044-10b1-426f-9247-bb680e5fe0c8,";
+        assert!(encoded.windows(text.len()).any(|w| w == text.as_bytes()));
+        let simple = "32:67e5504410b1426f9247bb680e5fe0c8,";
+        assert!(encoded
+            .windows(simple.len())
+            .any(|w| w == simple.as_bytes()));
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Bytes(#[serde(with = "super::uuid_bytes")] Uuid);
+
+        let frame = to_vec(&Bytes(id)).unwrap();
+        // `16:`, the bytes and `,`
+        assert_eq!(frame.len(), 20);
+        assert_eq!(&frame[..3], b"16:");
+        assert_eq!(&frame[3..19], id.as_bytes());
+        assert_eq!(from_slice::<Bytes>(&frame).unwrap(), Bytes(id));
+
+        for input in &[
+            &b"15:0123456789abcde,"[..],
+            b"17:0123456789abcdefg,",
+            b"0:,",
+        ] {
+            assert!(from_slice::<Bytes>(input).unwrap_err().is_data());
+        }
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Simple(#[serde(with = "super::uuid_simple")] Uuid);
+        assert!(from_str::<Simple>("36:67e55044-10b1-426f-9247-bb680e5fe0c8,").is_err());
+        assert!(from_str::<Simple>("32:67e5504410b1426f9247bb680e5fe0cg,").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+// The newtype struct name that asks this crate's deserializer for an
+// interned string.
+pub(crate) const TOKEN: &str = "$tnetstring::private::InternedStr";
+
+thread_local! {
+    // the string the deserializer just interned, for `InternedStr` to take
+    static INTERNED: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// A cache of strings shared by every `InternedStr` read through it.
+///
+/// Give it to `Deserializer::interner` and each distinct string read into an
+/// `InternedStr` is allocated once, however many times it repeats. Clones
+/// share the same cache, so one interner can be handed to the deserializer
+/// of each frame in a stream. Strings are kept until `clear` is called.
+#[derive(Clone, Debug, Default)]
+pub struct Interner {
+    strings: Arc<Mutex<HashSet<Arc<str>>>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// The cached copy of `s`, adding it if it isn't there yet.
+    pub fn intern(&self, s: &str) -> InternedStr {
+        let mut strings = self.strings.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(string) = strings.get(s) {
+            return InternedStr(string.clone());
+        }
+        let string: Arc<str> = Arc::from(s);
+        strings.insert(string.clone());
+        InternedStr(string)
+    }
+
+    /// How many distinct strings are cached.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.strings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// A shared string, for the keys of maps like `HashMap<InternedStr, _>`
+/// that repeat across many records.
+///
+/// Read by a deserializer with an `Interner`, equal strings share one
+/// allocation; otherwise each is allocated on its own. `Value` dicts keep
+/// `String` keys, so they can't share them.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InternedStr(Arc<str>);
+
+impl InternedStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Arc<str> {
+        self.0
+    }
+}
+
+impl From<&str> for InternedStr {
+    fn from(s: &str) -> Self {
+        InternedStr(Arc::from(s))
+    }
+}
+
+impl Deref for InternedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for InternedStr {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for InternedStr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedStr {
+    fn deserialize<D>(deserializer: D) -> Result<InternedStr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct InternedVisitor;
+
+        impl<'de> Visitor<'de> for InternedVisitor {
+            type Value = InternedStr;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<InternedStr, E>
+            where
+                E: de::Error,
+            {
+                let interned = INTERNED.with(|interned| interned.borrow_mut().take());
+                Ok(match interned {
+                    Some(string) if string.as_ptr() == v.as_ptr() => InternedStr(string),
+                    _ => InternedStr::from(v),
+                })
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<InternedStr, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_str(self)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(TOKEN, InternedVisitor)
+    }
+}
+
+// Hands `string` to the `InternedStr` being deserialized by `visit`.
+pub(crate) fn visit_interned<T, F>(string: InternedStr, visit: F) -> T
+where
+    F: FnOnce(&str) -> T,
+{
+    let string = string.into_inner();
+    INTERNED.with(|interned| *interned.borrow_mut() = Some(string.clone()));
+    let result = visit(&string);
+    INTERNED.with(|interned| interned.borrow_mut().take());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InternedStr, Interner};
+    use crate::de::{from_str, Deserializer};
+    use crate::ser::to_writer_seq;
+    use crate::value::Value;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn records(count: u32) -> Vec<u8> {
+        let mut output = Vec::new();
+        let records = (0..count).map(|n| {
+            let mut record = HashMap::new();
+            record.insert("id", Value::from(n));
+            record.insert("level", Value::from("info"));
+            record.insert("message", Value::from(format!("request {}", n)));
+            record
+        });
+        to_writer_seq(&mut output, records).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_shared_keys() {
+        let input = records(1000);
+        let interner = Interner::new();
+        let interned: Vec<HashMap<InternedStr, Value>> = Deserializer::from_slice(&input)
+            .interner(&interner)
+            .into_iter()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(interner.len(), 3);
+        let level = interner.intern("level").into_inner();
+        // one for each record, the interner and `level` itself
+        assert_eq!(Arc::strong_count(&level), 1002);
+        let first = interned[0].get_key_value("id").unwrap().0;
+        let last = interned[999].get_key_value("id").unwrap().0;
+        assert!(Arc::ptr_eq(
+            &first.clone().into_inner(),
+            &last.clone().into_inner()
+        ));
+
+        // the same as without interning
+        let plain: Vec<HashMap<String, Value>> = Deserializer::from_slice(&input)
+            .into_iter()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(plain.len(), interned.len());
+        for (plain, interned) in plain.iter().zip(&interned) {
+            assert_eq!(plain.len(), interned.len());
+            for (key, value) in plain {
+                assert_eq!(interned.get(key.as_str()), Some(value));
+            }
+        }
+        let unshared: Vec<HashMap<InternedStr, Value>> = Deserializer::from_slice(&input)
+            .into_iter()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(unshared, interned);
+        assert_eq!(interner.len(), 3);
+    }
+
+    #[test]
+    fn test_across_frames() {
+        let interner = Interner::new();
+        for frame in &["11:2:id,3:one,}", "11:2:id,3:two,}", "9:2:ok,1:1#}"] {
+            let mut de = Deserializer::from_str(frame).interner(&interner);
+            HashMap::<InternedStr, Value>::deserialize(&mut de).unwrap();
+        }
+        assert_eq!(interner.len(), 2);
+        interner.clear();
+        assert!(interner.is_empty());
+
+        // values can be interned too, and other deserializers just allocate
+        let mut de = Deserializer::from_str("12:3:abc,3:abc,]").interner(&interner);
+        let list = Vec::<InternedStr>::deserialize(&mut de).unwrap();
+        assert_eq!(list, [InternedStr::from("abc"), InternedStr::from("abc")]);
+        assert_eq!(interner.len(), 1);
+        let value: Value = from_str("12:3:abc,3:abc,]").unwrap();
+        assert_eq!(
+            crate::value::from_value::<Vec<InternedStr>>(value).unwrap(),
+            list
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Conversions to and from `serde_json`.
+//!
+//! Both sides tell integers and floats apart and cover the same `i64` and
+//! `u64` range, so most values convert exactly. The edges are:
+//!
+//! - NaN and infinite floats have no JSON form, so converting them to JSON
+//!   fails with `Error::NonFiniteFloat`.
+//! - JSON text with an integer outside the `i64` and `u64` range is read by
+//!   `serde_json` as a float, so `from_json_str` gives a float `Number`.
+//! - JSON strings are always UTF-8, so converting `Value::Bytes` fails with
+//!   `Error::NonUtf8Str`.
+//! - Dict order follows each side's map, sorted by key unless the
+//!   `preserve_order` features are on.
+
+use std::convert::TryFrom;
+
+use crate::error::{Error, Result};
+use crate::number::Number;
+use crate::value::Value;
+
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => Value::Number(match (n.as_u64(), n.as_i64()) {
+                (Some(v), _) => Number::from(v),
+                (_, Some(v)) => Number::from(v),
+                _ => Number::from(n.as_f64().unwrap_or(f64::NAN)),
+            }),
+            serde_json::Value::String(s) => Value::Str(s),
+            serde_json::Value::Array(list) => {
+                Value::List(list.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(dict) => Value::Dict(
+                dict.into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl TryFrom<Value> for serde_json::Value {
+    type Error = Error;
+
+    /// Fails with `Error::NonFiniteFloat` on a NaN or infinite float, and
+    /// `Error::NonUtf8Str` on `Bytes`, anywhere in the tree.
+    fn try_from(value: Value) -> Result<Self> {
+        Ok(match value {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::Value::Bool(b),
+            Value::Number(n) => serde_json::Value::Number(match (n.as_u64(), n.as_i64()) {
+                (Some(v), _) if !n.is_f64() => v.into(),
+                (_, Some(v)) if !n.is_f64() => v.into(),
+                _ => n
+                    .as_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .ok_or(Error::NonFiniteFloat)?,
+            }),
+            Value::Str(s) => serde_json::Value::String(s),
+            Value::Bytes(_) => return Err(Error::NonUtf8Str),
+            Value::List(list) => serde_json::Value::Array(
+                list.into_iter()
+                    .map(serde_json::Value::try_from)
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Dict(dict) => serde_json::Value::Object(
+                dict.into_iter()
+                    .map(|(key, value)| Ok((key, serde_json::Value::try_from(value)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+/// Write `value` as JSON text, failing like `TryFrom` does on floats JSON
+/// can't hold instead of writing them as `null`.
+pub fn to_json_string(value: &Value) -> Result<String> {
+    let json = serde_json::Value::try_from(value.clone())?;
+    Ok(json.to_string())
+}
+
+/// Read JSON text into a `Value`.
+pub fn from_json_str(s: &str) -> Result<Value> {
+    serde_json::from_str(s).map_err(|error| Error::Message(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json_str, to_json_string};
+    use crate::error::Error;
+    use crate::value::Value;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_round_trip() {
+        let json = json!({
+            "id": 42,
+            "ratio": 0.5,
+            "whole": 1.0,
+            "big": u64::MAX,
+            "min": i64::MIN,
+            "ok": true,
+            "missing": null,
+            "tags": ["a", [], {}],
+        });
+        let value = Value::from(json.clone());
+        assert_eq!(value["id"], Value::from(42));
+        assert_eq!(value["whole"].as_number().map(|n| n.is_f64()), Some(true));
+        assert_eq!(value["big"].as_u64(), Some(u64::MAX));
+        assert_eq!(value["min"].as_i64(), Some(i64::MIN));
+        assert_eq!(value["missing"], Value::Null);
+        assert_eq!(serde_json::Value::try_from(value.clone()), Ok(json.clone()));
+
+        let text = to_json_string(&value).unwrap();
+        assert_eq!(from_json_str(&text), Ok(value));
+        assert_eq!(text, json.to_string());
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        for f in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let value = Value::List(vec![Value::from(1), Value::from(*f)]);
+            assert_eq!(
+                serde_json::Value::try_from(value.clone()),
+                Err(Error::NonFiniteFloat)
+            );
+            assert_eq!(to_json_string(&value), Err(Error::NonFiniteFloat));
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_ints() {
+        let value = from_json_str("[18446744073709551616, -9223372036854775809]").unwrap();
+        assert_eq!(value[0].as_f64(), Some(18446744073709551616.0));
+        assert_eq!(value[0].as_number().map(|n| n.is_f64()), Some(true));
+        assert_eq!(value[1].as_number().map(|n| n.is_f64()), Some(true));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(from_json_str("{\"a\" 1}"), Err(Error::Message(_))));
+    }
+}
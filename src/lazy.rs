@@ -0,0 +1,299 @@
+use std::str;
+
+use crate::error::{Error, Result};
+use crate::frames::frame_error;
+use crate::parse::{parse, TNetStringType};
+
+/// A borrowed tnetstring that's only decoded as far as it's looked at.
+///
+/// `new` checks the outer frame, and list items and dict entries are split
+/// off one at a time as the iterators advance, so reading a few fields of a
+/// large message doesn't touch the rest of it or allocate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TNetStr<'a> {
+    encoded: &'a [u8],
+    tag: TNetStringType,
+    payload: &'a [u8],
+}
+
+impl<'a> TNetStr<'a> {
+    /// Wrap `input`, which has to be exactly one frame.
+    pub fn new(input: &'a [u8]) -> Result<Self> {
+        let (value, rest) = TNetStr::split(input)?;
+        if !rest.is_empty() {
+            return Err(Error::UnusedParseData);
+        }
+        Ok(value)
+    }
+
+    fn split(input: &'a [u8]) -> Result<(Self, &'a [u8])> {
+        let parsed = parse(input).map_err(frame_error)?;
+        let value = TNetStr {
+            encoded: &input[..input.len() - parsed.rest.len()],
+            tag: parsed.kind,
+            payload: parsed.payload,
+        };
+        Ok((value, parsed.rest))
+    }
+
+    pub fn tag(&self) -> TNetStringType {
+        self.tag
+    }
+
+    /// Everything between the `:` and the type tag.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// The whole frame, including the length prefix and type tag.
+    pub fn encoded(&self) -> &'a [u8] {
+        self.encoded
+    }
+
+    fn expect(&self, expected: TNetStringType) -> Result<()> {
+        if self.tag != expected {
+            return Err(Error::UnexpectedType {
+                expected,
+                found: self.tag,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn as_str(&self) -> Result<&'a str> {
+        self.expect(TNetStringType::Str)?;
+        str::from_utf8(self.payload).map_err(|_| Error::NonUtf8Str)
+    }
+
+    pub fn as_i64(&self) -> Result<i64> {
+        self.expect(TNetStringType::Int)?;
+        str::from_utf8(self.payload)
+            .ok()
+            .and_then(|payload| payload.parse().ok())
+            .ok_or(Error::ParsingInt)
+    }
+
+    pub fn as_f64(&self) -> Result<f64> {
+        self.expect(TNetStringType::Float)?;
+        str::from_utf8(self.payload)
+            .ok()
+            .and_then(|payload| payload.parse().ok())
+            .ok_or(Error::ParsingFloat)
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        self.expect(TNetStringType::Bool)?;
+        match self.payload {
+            b"true" => Ok(true),
+            b"false" => Ok(false),
+            _ => Err(Error::ParsingBool),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.tag == TNetStringType::Null
+    }
+
+    /// Iterate over the items of a list. For anything else the iterator
+    /// yields a single `Error::UnexpectedType`.
+    pub fn iter_list(&self) -> ListIter<'a> {
+        ListIter {
+            payload: self.payload,
+            found: Some(self.tag).filter(|&tag| tag != TNetStringType::List),
+        }
+    }
+
+    /// Iterate over the entries of a dict. For anything else the iterator
+    /// yields a single `Error::UnexpectedType`.
+    pub fn iter_dict(&self) -> DictIter<'a> {
+        DictIter {
+            payload: self.payload,
+            found: Some(self.tag).filter(|&tag| tag != TNetStringType::Dict),
+        }
+    }
+
+    /// Look up `key` in a dict, scanning its entries until it's found.
+    pub fn get(&self, key: &str) -> Result<Option<TNetStr<'a>>> {
+        for entry in self.iter_dict() {
+            let (k, value) = entry?;
+            if k == key {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The iterator returned by `TNetStr::iter_list`. It stops after the first
+/// error.
+#[derive(Clone, Debug)]
+pub struct ListIter<'a> {
+    payload: &'a [u8],
+    // the tag when this isn't a list, reported by the first `next`
+    found: Option<TNetStringType>,
+}
+
+impl<'a> Iterator for ListIter<'a> {
+    type Item = Result<TNetStr<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(found) = self.found.take() {
+            self.payload = &[];
+            return Some(Err(Error::UnexpectedType {
+                expected: TNetStringType::List,
+                found,
+            }));
+        }
+        if self.payload.is_empty() {
+            return None;
+        }
+        match TNetStr::split(self.payload) {
+            Ok((item, rest)) => {
+                self.payload = rest;
+                Some(Ok(item))
+            }
+            Err(error) => {
+                self.payload = &[];
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// The iterator returned by `TNetStr::iter_dict`. It stops after the first
+/// error.
+#[derive(Clone, Debug)]
+pub struct DictIter<'a> {
+    payload: &'a [u8],
+    // the tag when this isn't a dict, reported by the first `next`
+    found: Option<TNetStringType>,
+}
+
+impl<'a> DictIter<'a> {
+    fn entry(&mut self) -> Result<(&'a str, TNetStr<'a>)> {
+        let (key, rest) = TNetStr::split(self.payload)?;
+        if key.tag != TNetStringType::Str {
+            return Err(Error::NonStringKey(key.tag));
+        }
+        let key = key.as_str()?;
+        if rest.is_empty() {
+            return Err(Error::ParsingMap);
+        }
+        let (value, rest) = TNetStr::split(rest)?;
+        self.payload = rest;
+        Ok((key, value))
+    }
+}
+
+impl<'a> Iterator for DictIter<'a> {
+    type Item = Result<(&'a str, TNetStr<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(found) = self.found.take() {
+            self.payload = &[];
+            return Some(Err(Error::UnexpectedType {
+                expected: TNetStringType::Dict,
+                found,
+            }));
+        }
+        if self.payload.is_empty() {
+            return None;
+        }
+        let entry = self.entry();
+        if entry.is_err() {
+            self.payload = &[];
+        }
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TNetStr;
+    use crate::error::{Error, Result};
+    use crate::parse::TNetStringType;
+
+    const INPUT: &[u8] =
+        b"76:2:id,2:42#4:user,31:4:name,5:alice,5:admin,5:false!}4:tags,13:1:a,3:1.5^0:~]}";
+
+    #[test]
+    fn test_walk() -> Result<()> {
+        let root = TNetStr::new(INPUT)?;
+        assert_eq!(root.tag(), TNetStringType::Dict);
+        assert_eq!(root.encoded(), INPUT);
+
+        let keys: Vec<_> = root.iter_dict().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(keys, ["id", "user", "tags"]);
+
+        assert_eq!(root.get("id")?.unwrap().as_i64(), Ok(42));
+        let user = root.get("user")?.unwrap();
+        assert_eq!(user.get("name")?.unwrap().as_str(), Ok("alice"));
+        assert_eq!(user.get("admin")?.unwrap().as_bool(), Ok(false));
+        assert_eq!(user.get("missing")?, None);
+
+        let tags: Vec<_> = root
+            .get("tags")?
+            .unwrap()
+            .iter_list()
+            .collect::<Result<_>>()?;
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[0].as_str(), Ok("a"));
+        assert_eq!(tags[0].payload(), b"a");
+        assert_eq!(tags[1].as_f64(), Ok(1.5));
+        assert!(tags[2].is_null());
+        assert_eq!(
+            tags[1].as_i64(),
+            Err(Error::UnexpectedType {
+                expected: TNetStringType::Int,
+                found: TNetStringType::Float,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_early_termination() -> Result<()> {
+        // everything after the first entry is garbage, but it's never reached
+        let input = b"19:2:id,1:7#junk junk!}";
+        let root = TNetStr::new(input)?;
+        let (key, value) = root.iter_dict().next().unwrap()?;
+        assert_eq!((key, value.as_i64()?), ("id", 7));
+        assert_eq!(root.get("id")?.unwrap().encoded(), b"1:7#");
+
+        let list = TNetStr::new(b"11:1:1#???????]")?;
+        assert_eq!(list.iter_list().next().unwrap()?.as_i64(), Ok(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let list = TNetStr::new(b"9:1:1#2:ab?]")?;
+        let items: Vec<_> = list.iter_list().collect();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert_eq!(items[1], Err(Error::UnknownSegmentType));
+
+        let list = TNetStr::new(b"8:6:hello,]")?;
+        assert_eq!(list.iter_list().collect::<Vec<_>>(), [Err(Error::Eof)]);
+
+        let dict = TNetStr::new(b"16:1:a,1:1#1:2#1:b,}")?;
+        assert_eq!(dict.get("b"), Err(Error::NonStringKey(TNetStringType::Int)));
+        assert_eq!(TNetStr::new(b"4:1:a,}")?.get("a"), Err(Error::ParsingMap));
+
+        let scalar = TNetStr::new(b"3:abc,")?;
+        assert_eq!(
+            scalar.iter_list().collect::<Vec<_>>(),
+            [Err(Error::UnexpectedType {
+                expected: TNetStringType::List,
+                found: TNetStringType::Str,
+            })]
+        );
+        assert_eq!(scalar.iter_dict().count(), 1);
+
+        assert_eq!(TNetStr::new(b"0:~0:~"), Err(Error::UnusedParseData));
+        assert_eq!(TNetStr::new(b"5:hello"), Err(Error::Eof));
+        assert_eq!(TNetStr::new(b"3:yes!")?.as_bool(), Err(Error::ParsingBool));
+        assert_eq!(TNetStr::new(b"1:x#")?.as_i64(), Err(Error::ParsingInt));
+        Ok(())
+    }
+}
@@ -0,0 +1,1025 @@
+use serde::ser::{self, Serialize};
+use std::fmt::{self, Display, Write as _};
+use std::io::{self, Write};
+use std::str;
+
+use crate::error::{Error, Result};
+use crate::raw;
+use crate::ser::{MapKeySerializer, Serializer, SerializerConfig};
+
+/// Compute the length of `to_vec(value)` without building the output.
+pub fn serialized_len<T>(value: &T) -> Result<usize>
+where
+    T: ?Sized + Serialize,
+{
+    let mut counter = LenSerializer::new(io::sink(), Pass::Count { record: false });
+    value.serialize(&mut counter)?;
+    counter.finish()?;
+    Ok(counter.written)
+}
+
+/// Write the same bytes as `to_writer`, without holding the whole output in
+/// memory.
+///
+/// The value is serialized twice: once to work out the length of every list
+/// and dict, and once more to write it out, so it must serialize the same way
+/// both times. Memory use grows with the number of containers rather than
+/// the size of the output. Writes go through a `BufWriter`.
+pub fn to_writer_streaming<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    let mut counter = LenSerializer::new(io::sink(), Pass::Count { record: true });
+    value.serialize(&mut counter)?;
+    counter.finish()?;
+
+    let mut serializer = LenSerializer::new(io::BufWriter::new(writer), Pass::Write { next: 0 });
+    serializer.lengths = counter.lengths;
+    value.serialize(&mut serializer)?;
+    serializer.finish()?;
+    serializer.writer.flush()?;
+    Ok(())
+}
+
+// The encoding `digest` hashes, streamed like `to_writer_streaming`. Each
+// dict's entries are encoded separately with a canonical `Serializer`, so
+// they can be sorted before they're written; everything outside of dicts
+// goes straight to `writer`.
+#[cfg(feature = "digest")]
+pub(crate) fn to_writer_canonical<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    let mut counter = LenSerializer::new(io::sink(), Pass::Count { record: true });
+    counter.canonical = true;
+    value.serialize(&mut counter)?;
+    counter.finish()?;
+
+    let mut serializer = LenSerializer::new(writer, Pass::Write { next: 0 });
+    serializer.canonical = true;
+    serializer.lengths = counter.lengths;
+    serializer.ends = counter.ends;
+    value.serialize(&mut serializer)?;
+    serializer.finish()
+}
+
+// `ser::to_fmt_writer`, streamed the same way as `to_writer_streaming`.
+// Every string payload is checked to be UTF-8 on the counting pass, so
+// nothing is written if one isn't.
+pub(crate) fn to_fmt_writer<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: ?Sized + fmt::Write,
+    T: ?Sized + Serialize,
+{
+    let mut counter = LenSerializer::new(io::sink(), Pass::Count { record: true });
+    counter.text = true;
+    value.serialize(&mut counter)?;
+    counter.finish()?;
+    if let Some(invalid) = counter.invalid.take() {
+        // the prefixes of the containers it was in weren't counted yet
+        let prefixes: usize = invalid
+            .open
+            .iter()
+            .map(|&slot| prefix_len(counter.lengths[slot]))
+            .sum();
+        return Err(Error::InvalidUtf8 {
+            offset: invalid.offset + prefixes,
+            error: invalid.error,
+        });
+    }
+
+    let mut text = TextWriter {
+        writer,
+        failed: false,
+    };
+    let mut serializer = LenSerializer::new(&mut text, Pass::Write { next: 0 });
+    serializer.lengths = counter.lengths;
+    let result = value
+        .serialize(&mut serializer)
+        .and_then(|()| serializer.finish());
+    match result {
+        Err(_) if text.failed => Err(Error::Fmt),
+        result => result,
+    }
+}
+
+// Passes writes on to a `fmt::Write`. Each one is a whole payload, prefix or
+// tag, so it's UTF-8 by itself once the payloads have been checked.
+struct TextWriter<'a, W: ?Sized> {
+    writer: &'a mut W,
+    failed: bool,
+}
+
+impl<W> io::Write for TextWriter<'_, W>
+where
+    W: ?Sized + fmt::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if self.writer.write_str(s).is_err() {
+            self.failed = true;
+            return Err(io::Error::other("formatter error"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serialize `value` into the front of `buf` and return the part that was
+/// used.
+///
+/// Like `to_writer_streaming` this takes two passes, the first working out
+/// the length of every list and dict; those lengths are the only thing
+/// allocated. If the output doesn't fit nothing is written and the error is
+/// `Error::BufferTooSmall` with the size that's needed.
+pub fn to_slice<'a, T>(value: &T, buf: &'a mut [u8]) -> Result<&'a mut [u8]>
+where
+    T: ?Sized + Serialize,
+{
+    let mut counter = LenSerializer::new(io::sink(), Pass::Count { record: true });
+    value.serialize(&mut counter)?;
+    counter.finish()?;
+    if counter.written > buf.len() {
+        return Err(Error::BufferTooSmall {
+            needed: counter.written,
+            available: buf.len(),
+        });
+    }
+
+    let mut serializer = LenSerializer::new(&mut buf[..], Pass::Write { next: 0 });
+    serializer.lengths = counter.lengths;
+    value.serialize(&mut serializer)?;
+    serializer.finish()?;
+    let written = serializer.written;
+    Ok(&mut buf[..written])
+}
+
+enum Pass {
+    // work out the payload length of every container, keeping them in the
+    // order the containers start when `record` is set
+    Count { record: bool },
+    // write the output, taking the containers' lengths from the count pass
+    Write { next: usize },
+}
+
+// Mirrors `Serializer` with the default config, but writes straight to
+// `writer` instead of building the output in memory. A container's length
+// prefix has to come before its payload, so it takes two passes: counting
+// into `io::sink()` to learn the lengths, then writing with them.
+struct LenSerializer<W> {
+    writer: W,
+    written: usize,
+    pass: Pass,
+    // the count at which each open container's payload started, and its slot
+    // in `lengths`
+    stack: Vec<(usize, usize)>,
+    lengths: Vec<usize>,
+    // look for byte strings that aren't UTF-8, for writing into a
+    // `fmt::Write`, keeping the first in `invalid`
+    text: bool,
+    invalid: Option<Invalid>,
+    // sort dict entries and struct fields, see `to_writer_canonical`
+    canonical: bool,
+    // in canonical mode, the slot after the last container nested in each
+    // one, since those in a dict aren't written by this serializer
+    ends: Vec<usize>,
+    // the encoded entries of the dict being written in canonical mode
+    sorting: Option<Vec<Vec<u8>>>,
+}
+
+// The first payload found that isn't UTF-8, with the counted offset of its
+// first bad byte and the slots of the containers it's in, whose prefixes
+// that offset doesn't include yet.
+struct Invalid {
+    offset: usize,
+    open: Vec<usize>,
+    error: str::Utf8Error,
+}
+
+fn prefix_len(len: usize) -> usize {
+    itoa::Buffer::new().format(len).len() + 1
+}
+
+// counts the bytes of formatted values
+struct Counter(usize);
+
+impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+fn formatted_len<T>(value: &T) -> usize
+where
+    T: ?Sized + Display,
+{
+    let mut counter = Counter(0);
+    // `Counter` never fails
+    let _ = write!(counter, "{}", value);
+    counter.0
+}
+
+impl<W> LenSerializer<W>
+where
+    W: io::Write,
+{
+    fn new(writer: W, pass: Pass) -> Self {
+        LenSerializer {
+            writer,
+            written: 0,
+            pass,
+            stack: Vec::new(),
+            lengths: Vec::new(),
+            text: false,
+            invalid: None,
+            canonical: false,
+            ends: Vec::new(),
+            sorting: None,
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        if !self.stack.is_empty() {
+            return Err(Error::StackProblem);
+        }
+        Ok(())
+    }
+
+    fn add_to_output(&mut self, v: &[u8]) -> Result<()> {
+        self.writer.write_all(v)?;
+        self.written += v.len();
+        Ok(())
+    }
+
+    // `prefix` is how many bytes of the output come before `v`'s own
+    fn check_text(&mut self, v: &[u8], prefix: usize) {
+        if !self.text || self.invalid.is_some() {
+            return;
+        }
+        if let Err(error) = str::from_utf8(v) {
+            self.invalid = Some(Invalid {
+                offset: self.written + prefix + error.valid_up_to(),
+                open: self.stack.iter().map(|&(_, slot)| slot).collect(),
+                error,
+            });
+        }
+    }
+
+    fn add_prefix(&mut self, len: usize) -> Result<()> {
+        self.add_to_output(itoa::Buffer::new().format(len).as_bytes())?;
+        self.add_to_output(b":")
+    }
+
+    fn add_scalar(&mut self, payload: &[u8], tag: u8) -> Result<()> {
+        self.add_prefix(payload.len())?;
+        self.add_to_output(payload)?;
+        self.add_to_output(&[tag])
+    }
+
+    // formats `value` twice, once for its length and once into the writer
+    fn add_display<T>(&mut self, value: &T, tag: u8) -> Result<()>
+    where
+        T: ?Sized + Display,
+    {
+        let len = formatted_len(value);
+        self.add_prefix(len)?;
+        write!(self.writer, "{}", value)?;
+        self.written += len;
+        self.add_to_output(&[tag])
+    }
+
+    fn add_non_finite(&mut self, v: f64) -> Result<()> {
+        let payload: &[u8] = if v.is_nan() {
+            b"nan"
+        } else if v.is_sign_positive() {
+            b"inf"
+        } else {
+            b"-inf"
+        };
+        self.add_scalar(payload, b'^')
+    }
+
+    fn start_container(&mut self) -> Result<()> {
+        let slot = match self.pass {
+            Pass::Count { record } => {
+                let slot = self.lengths.len();
+                if record {
+                    self.lengths.push(0);
+                    if self.canonical {
+                        self.ends.push(0);
+                    }
+                }
+                slot
+            }
+            Pass::Write { ref mut next } => {
+                let slot = *next;
+                *next += 1;
+                let len = *self.lengths.get(slot).ok_or(Error::StackProblem)?;
+                self.add_prefix(len)?;
+                slot
+            }
+        };
+        self.stack.push((self.written, slot));
+        Ok(())
+    }
+
+    fn end_container(&mut self, terminator: u8) -> Result<()> {
+        let (start, slot) = self.stack.pop().ok_or(Error::StackProblem)?;
+        let payload = self.written - start;
+        match self.pass {
+            Pass::Count { record } => {
+                if record {
+                    self.lengths[slot] = payload;
+                    if self.canonical {
+                        self.ends[slot] = self.lengths.len();
+                    }
+                }
+                // the prefix isn't written on this pass, only counted
+                self.written += prefix_len(payload);
+            }
+            Pass::Write { .. } => {
+                if payload != self.lengths[slot] {
+                    return Err(ser::Error::custom(
+                        "value serialized differently on the second pass",
+                    ));
+                }
+            }
+        }
+        self.add_to_output(&[terminator])
+    }
+
+    fn start_dict(&mut self) -> Result<()> {
+        self.start_container()?;
+        if self.canonical {
+            if let Pass::Write { .. } = self.pass {
+                self.sorting = Some(Vec::new());
+            }
+        }
+        Ok(())
+    }
+
+    // encodes a dict entry, or the rest of the last one, on its own
+    fn add_entry<F>(&mut self, new: bool, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Serializer) -> Result<()>,
+    {
+        let entries = self.sorting.as_mut().ok_or(Error::StackProblem)?;
+        let entry = if new {
+            Vec::new()
+        } else {
+            entries.pop().ok_or(Error::StackProblem)?
+        };
+        let config = SerializerConfig::new()
+            .canonical(true)
+            .canonical_structs(true);
+        let mut serializer = Serializer::with_config(entry, config);
+        f(&mut serializer)?;
+        entries.push(serializer.into_inner()?);
+        Ok(())
+    }
+
+    fn end_dict(&mut self) -> Result<()> {
+        if let Some(mut entries) = self.sorting.take() {
+            entries.sort();
+            for entry in &entries {
+                self.add_to_output(entry)?;
+            }
+            // skip the slots of the containers inside the entries
+            let &(_, slot) = self.stack.last().ok_or(Error::StackProblem)?;
+            if let Pass::Write { ref mut next } = self.pass {
+                *next = self.ends[slot];
+            }
+        }
+        self.end_container(b'}')
+    }
+}
+
+impl<W> ser::Serializer for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.add_to_output(if v { b"4:true!" } else { b"5:false!" })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        if !v.is_finite() {
+            return self.add_non_finite(f64::from(v));
+        }
+        self.add_display(&v, b'^')
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        if !v.is_finite() {
+            return self.add_non_finite(v);
+        }
+        self.add_display(&v, b'^')
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.add_scalar(v.as_bytes(), b',')
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.check_text(v, prefix_len(v.len()));
+        self.add_scalar(v, b',')
+    }
+
+    fn collect_str<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Display,
+    {
+        self.add_display(value, b',')
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.add_to_output(b"0:~")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == raw::TOKEN {
+            return value.serialize(raw::Emitter(|bytes: &[u8]| {
+                self.check_text(bytes, 0);
+                self.add_to_output(bytes)
+            }));
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.start_container()?;
+        variant.serialize(&mut *self)?;
+        value.serialize(&mut *self)?;
+        self.end_container(b'}')
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.start_container()?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.start_container()?;
+        variant.serialize(&mut *self)?;
+        self.start_container()?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.start_dict()?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.start_dict()?;
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.start_container()?;
+        variant.serialize(&mut *self)?;
+        self.start_dict()?;
+        Ok(self)
+    }
+}
+
+impl<W> ser::SerializeSeq for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_container(b']')
+    }
+}
+
+impl<W> ser::SerializeTuple for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_container(b']')
+    }
+}
+
+impl<W> ser::SerializeTupleStruct for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_container(b']')
+    }
+}
+
+impl<W> ser::SerializeTupleVariant for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_container(b']')?;
+        self.end_container(b'}')
+    }
+}
+
+impl<W> ser::SerializeMap for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.sorting.is_some() {
+            return self.add_entry(true, |ser| {
+                key.serialize(MapKeySerializer {
+                    ser,
+                    stringify_keys: false,
+                })
+            });
+        }
+        key.serialize(MapKeySerializer {
+            ser: &mut **self,
+            stringify_keys: false,
+        })
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.sorting.is_some() {
+            return self.add_entry(false, |ser| value.serialize(ser));
+        }
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_dict()
+    }
+}
+
+impl<W> ser::SerializeStruct for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.sorting.is_some() {
+            return self.add_entry(true, |ser| {
+                key.serialize(&mut *ser)?;
+                value.serialize(ser)
+            });
+        }
+        key.serialize(&mut **self)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_dict()
+    }
+}
+
+impl<W> ser::SerializeStructVariant for &mut LenSerializer<W>
+where
+    W: io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.sorting.is_some() {
+            return self.add_entry(true, |ser| {
+                key.serialize(&mut *ser)?;
+                value.serialize(ser)
+            });
+        }
+        key.serialize(&mut **self)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_dict()?;
+        self.end_container(b'}')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serialized_len, to_slice, to_writer_streaming};
+    use crate::error::Error;
+    use crate::parse::TNetStringType;
+    use crate::ser::{to_string, to_vec};
+    use maplit::hashmap;
+    use serde::{Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt::Debug;
+    use std::io;
+
+    fn check<T: Serialize + Debug>(value: &T) {
+        assert_eq!(
+            serialized_len(value),
+            to_vec(value).map(|v| v.len()),
+            "{:?}",
+            value
+        );
+        let mut streamed = Vec::new();
+        to_writer_streaming(&mut streamed, value).unwrap();
+        assert_eq!(streamed, to_vec(value).unwrap(), "{:?}", value);
+        let mut buf = vec![0; streamed.len()];
+        assert_eq!(to_slice(value, &mut buf).unwrap(), &streamed[..]);
+    }
+
+    #[derive(Serialize, Debug)]
+    enum E {
+        Unit,
+        Newtype(u32),
+        Tuple(&'static str, i64),
+        Struct { host: String, port: u16 },
+    }
+
+    #[derive(Serialize, Debug)]
+    struct Test {
+        int: u32,
+        seq: Vec<&'static str>,
+        option: Option<f64>,
+        e: Vec<E>,
+        map: BTreeMap<String, Vec<u8>>,
+    }
+
+    #[derive(Serialize, Debug)]
+    struct Unit;
+
+    #[derive(Serialize, Debug)]
+    struct Newtype(String);
+
+    #[derive(Serialize, Debug)]
+    struct TupleStruct(u8, char, bool);
+
+    #[derive(Debug)]
+    struct Displayed(u32);
+
+    impl Serialize for Displayed {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(&format_args!("id-{}", self.0))
+        }
+    }
+
+    #[test]
+    fn test_scalars() {
+        check(&true);
+        check(&false);
+        check(&());
+        check(&Unit);
+        check(&None::<u32>);
+        check(&Some(5));
+        check(&'a');
+        check(&'ü');
+        check(&"");
+        check(&"hello");
+        check(&"x".repeat(9));
+        check(&"x".repeat(10));
+        check(&"x".repeat(1000));
+        check(&serde_bytes::Bytes::new(b"\xff\x00"));
+        check(&Newtype("foo".into()));
+        check(&Displayed(12345));
+    }
+
+    #[test]
+    fn test_numbers() {
+        for &v in &[0, -1, 9, 10, i64::MIN, i64::MAX] {
+            check(&v);
+        }
+        for &v in &[0, u64::MAX] {
+            check(&v);
+        }
+        check(&i8::MIN);
+        check(&u16::MAX);
+        check(&i128::MIN);
+        check(&u128::MAX);
+        for &v in &[
+            0.0,
+            -0.0,
+            1.25,
+            -123.4,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::NAN,
+            -f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ] {
+            check(&v);
+            check(&(v as f32));
+        }
+        check(&0.1f32);
+    }
+
+    #[test]
+    fn test_containers() {
+        check(&Vec::<u32>::new());
+        check(&vec![1, 2, 3]);
+        check(&(1, "two", 3.0));
+        check(&TupleStruct(1, 'c', true));
+        check(&vec![vec![1u8; 9]; 11]);
+        check(&hashmap! {"hello" => "world"});
+        check(&E::Unit);
+        check(&E::Newtype(7));
+        check(&E::Tuple("foo", -1));
+        check(&E::Struct {
+            host: "localhost".into(),
+            port: 80,
+        });
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), vec![1; 100]);
+        map.insert("b".repeat(20), vec![]);
+        check(&Test {
+            int: 1,
+            seq: vec!["a", "bb", "ccc"],
+            option: Some(0.5),
+            e: vec![
+                E::Unit,
+                E::Tuple("x", 1),
+                E::Struct {
+                    host: "example.com".into(),
+                    port: 443,
+                },
+            ],
+            map,
+        });
+
+        #[derive(Serialize, Debug)]
+        struct Node(u32, Vec<Node>);
+        let deep = (0..200).fold(Node(0, vec![]), |acc, i| Node(i, vec![acc]));
+        check(&deep);
+    }
+
+    #[test]
+    fn test_errors() {
+        let test: HashMap<u32, u32> = hashmap! {1 => 1};
+        assert_eq!(
+            serialized_len(&test),
+            Err(Error::NonStringKey(TNetStringType::Int))
+        );
+        check(&hashmap! {'k' => 1});
+    }
+
+    #[test]
+    fn test_to_slice() {
+        let value = (vec!["a", "bb"], Some(E::Tuple("x", -1)));
+        let expected = to_string(&value).unwrap();
+        let mut buf = [0xff; 64];
+        let used = to_slice(&value, &mut buf).unwrap();
+        assert_eq!(used, expected.as_bytes());
+        assert_eq!(buf[expected.len()], 0xff);
+
+        let mut exact = vec![0; expected.len()];
+        assert_eq!(to_slice(&value, &mut exact).unwrap(), expected.as_bytes());
+
+        let mut short = vec![0; expected.len() - 1];
+        assert_eq!(
+            to_slice(&value, &mut short),
+            Err(Error::BufferTooSmall {
+                needed: expected.len(),
+                available: expected.len() - 1,
+            })
+        );
+        assert!(short.iter().all(|&b| b == 0));
+
+        assert_eq!(
+            to_slice(&"", &mut []),
+            Err(Error::BufferTooSmall {
+                needed: 3,
+                available: 0,
+            })
+        );
+        assert_eq!(
+            to_slice(&hashmap! {1 => 1}, &mut buf),
+            Err(Error::NonStringKey(TNetStringType::Int))
+        );
+    }
+
+    // a sequence that is only ever produced one item at a time
+    #[derive(Debug)]
+    struct Lazy(u64);
+
+    impl Serialize for Lazy {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq((0..self.0).map(|i| (i, "item")))
+        }
+    }
+
+    // keeps only the total length and the largest single write
+    #[derive(Default)]
+    struct Recorder {
+        total: usize,
+        largest_write: usize,
+    }
+
+    impl io::Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.total += buf.len();
+            self.largest_write = self.largest_write.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        check(&Lazy(1000));
+
+        let value = Lazy(1_000_000);
+        let mut recorder = Recorder::default();
+        to_writer_streaming(&mut recorder, &value).unwrap();
+        assert_eq!(recorder.total, serialized_len(&value).unwrap());
+        assert!(recorder.total > 10_000_000);
+        assert!(recorder.largest_write <= 8 * 1024);
+    }
+
+    #[test]
+    fn test_streaming_errors() {
+        let test: HashMap<u32, u32> = hashmap! {1 => 1};
+        let mut output = Vec::new();
+        assert_eq!(
+            to_writer_streaming(&mut output, &test),
+            Err(Error::NonStringKey(TNetStringType::Int))
+        );
+        assert!(output.is_empty());
+    }
+}
@@ -1,9 +1,87 @@
+#[cfg(feature = "futures-io")]
+mod async_io;
+#[cfg(feature = "bytes")]
+mod buf;
+mod builder;
+#[cfg(feature = "codec")]
+mod codec;
 mod de;
+mod diff;
+#[cfg(feature = "digest")]
+mod digest;
+#[cfg(feature = "erased")]
+pub mod erased;
 mod error;
+mod explain;
+mod framed;
+mod frames;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+pub mod helpers;
+mod intern;
+#[cfg(feature = "json")]
+mod json;
+mod lazy;
+mod len;
+pub mod map;
+#[cfg(feature = "mongrel2")]
+pub mod mongrel2;
+pub mod netstring;
+mod number;
 mod parse;
+mod patch;
+mod raw;
+mod read;
 mod ser;
+mod spanned;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod ts;
+mod validate;
+mod value;
 
-pub use crate::de::{from_str, Deserializer};
-pub use crate::error::{Error, Result};
-pub use crate::parse::parse;
-pub use crate::ser::{to_string, Serializer};
+#[cfg(feature = "futures-io")]
+pub use crate::async_io::{read_frame, read_frame_with_max, write_frame, write_frame_with_max};
+#[cfg(feature = "bytes")]
+pub use crate::buf::{decode_from, encode_to, from_bytes, shared_bytes, SharedBytes};
+pub use crate::builder::Builder;
+#[cfg(feature = "codec")]
+pub use crate::codec::TNetStringCodec;
+pub use crate::de::{
+    from_slice, from_slice_seed, from_str, from_str_seed, get, Deserializer, StreamDeserializer,
+};
+pub use crate::diff::{diff, Difference};
+#[cfg(feature = "digest")]
+pub use crate::digest::{digest, digest_encoded};
+pub use crate::error::{Category, Error, Result};
+pub use crate::explain::explain;
+pub use crate::framed::{Framed, ReadTimeout};
+#[cfg(feature = "rayon")]
+pub use crate::frames::from_frames_par;
+pub use crate::frames::{frames, FrameAccumulator, Frames};
+pub use crate::intern::{InternedStr, Interner};
+#[cfg(feature = "json")]
+pub use crate::json::{from_json_str, to_json_string};
+pub use crate::lazy::{DictIter, ListIter, TNetStr};
+pub use crate::len::{serialized_len, to_slice, to_writer_streaming};
+pub use crate::map::Map;
+pub use crate::number::Number;
+pub use crate::parse::{
+    frame_len, parse, parse_type, parse_value, FrameStatus, Parsed, Parser, TNetString,
+    TNetStringError, TNetStringType, Token,
+};
+pub use crate::patch::patch;
+pub use crate::raw::RawValue;
+pub use crate::read::{
+    from_reader, from_reader_seed, from_reader_with_limit, read_iter, FrameReader, ReadIter,
+    StringReader,
+};
+pub use crate::ser::{
+    to_fmt_writer, to_string, to_string_compact, to_string_into, to_string_with, to_vec,
+    to_vec_with, to_writer, to_writer_seq, to_writer_seq_separated, NonFiniteFloats, Serializer,
+    SerializerConfig,
+};
+pub use crate::spanned::Spanned;
+pub use crate::validate::{inspect, inspect_with, validate, validate_with, Stats, ValidateConfig};
+pub use crate::value::{from_value, to_value, CanonicalizeOptions, Value, ValueIndex};
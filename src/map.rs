@@ -0,0 +1,601 @@
+//! `Map`, the map in `Value::Dict`, with its entry and iterator types.
+//!
+//! Without the `preserve_order` feature keys are kept sorted, and with it in
+//! the order they were inserted or parsed. The API is the same either way.
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops;
+
+use crate::value::{InPlace, Value};
+
+#[cfg(not(feature = "preserve_order"))]
+use std::collections::{btree_map as imp, BTreeMap};
+
+#[cfg(feature = "preserve_order")]
+use indexmap::map as imp;
+
+#[cfg(not(feature = "preserve_order"))]
+type MapImpl = BTreeMap<String, Value>;
+#[cfg(feature = "preserve_order")]
+type MapImpl = indexmap::IndexMap<String, Value>;
+
+/// A map of strings to values, the contents of a `Value::Dict`.
+///
+/// Two maps with the same entries are equal whatever order they're kept in,
+/// and they're compared and hashed in key order.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Map {
+    map: MapImpl,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map {
+            map: MapImpl::new(),
+        }
+    }
+
+    /// Capacity is only reserved with the `preserve_order` feature.
+    #[cfg(not(feature = "preserve_order"))]
+    pub fn with_capacity(_capacity: usize) -> Map {
+        Map::new()
+    }
+
+    /// Capacity is only reserved with the `preserve_order` feature.
+    #[cfg(feature = "preserve_order")]
+    pub fn with_capacity(capacity: usize) -> Map {
+        Map {
+            map: MapImpl::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        String: Borrow<Q>,
+        Q: ?Sized + Ord + Eq + Hash,
+    {
+        self.map.get(key)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        String: Borrow<Q>,
+        Q: ?Sized + Ord + Eq + Hash,
+    {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        String: Borrow<Q>,
+        Q: ?Sized + Ord + Eq + Hash,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the value that was there before, if any. A key that's already
+    /// present keeps its place in the order.
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        self.map.insert(key, value)
+    }
+
+    /// Removes the key, keeping the order of the ones that remain.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
+    where
+        String: Borrow<Q>,
+        Q: ?Sized + Ord + Eq + Hash,
+    {
+        #[cfg(not(feature = "preserve_order"))]
+        return self.map.remove(key);
+        #[cfg(feature = "preserve_order")]
+        return self.map.shift_remove(key);
+    }
+
+    pub fn entry<S>(&mut self, key: S) -> Entry<'_>
+    where
+        S: Into<String>,
+    {
+        match self.map.entry(key.into()) {
+            imp::Entry::Vacant(vacant) => Entry::Vacant(VacantEntry { vacant }),
+            imp::Entry::Occupied(occupied) => Entry::Occupied(OccupiedEntry { occupied }),
+        }
+    }
+
+    /// Keeps only the entries `f` returns true for, in the same order.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&String, &mut Value) -> bool,
+    {
+        self.map.retain(f);
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            iter: self.map.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            iter: self.map.iter_mut(),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_> {
+        Keys {
+            iter: self.map.keys(),
+        }
+    }
+
+    pub fn values(&self) -> Values<'_> {
+        Values {
+            iter: self.map.values(),
+        }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_> {
+        ValuesMut {
+            iter: self.map.values_mut(),
+        }
+    }
+
+    // sort entries by their encoded keys, the order `SerializerConfig::canonical`
+    // writes them in; a `BTreeMap` is always in key order and is left alone
+    #[cfg(feature = "preserve_order")]
+    pub(crate) fn sort_canonical(&mut self) {
+        self.map
+            .sort_by_cached_key(|key, _| format!("{}:{},", key.len(), key));
+    }
+
+    #[cfg(not(feature = "preserve_order"))]
+    pub(crate) fn sort_canonical(&mut self) {}
+
+    // Replaces the entries with those left in `access`, after a `first` key
+    // that's already been read. Each value is deserialized over the one the
+    // key had before, if any, so its buffers are reused.
+    pub(crate) fn refill<'de, A>(
+        &mut self,
+        first: Option<String>,
+        mut access: A,
+    ) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut old = std::mem::replace(self, Map::with_capacity(self.len()));
+        let mut next = first;
+        while let Some(key) = next {
+            let mut value = old.remove(&key).unwrap_or(Value::Null);
+            access.next_value_seed(InPlace(&mut value))?;
+            self.insert(key, value);
+            next = access.next_key()?;
+        }
+        Ok(())
+    }
+
+    // an `IndexMap` is equal to another with the same entries in any order,
+    // so it's compared and hashed in key order too
+    #[cfg(feature = "preserve_order")]
+    fn sorted(&self) -> Vec<(&String, &Value)> {
+        let mut entries: Vec<_> = self.map.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    #[cfg(not(feature = "preserve_order"))]
+    fn sorted(&self) -> &MapImpl {
+        &self.map
+    }
+}
+
+impl PartialOrd for Map {
+    fn partial_cmp(&self, other: &Map) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Map {
+    fn cmp(&self, other: &Map) -> Ordering {
+        Ord::cmp(&self.sorted(), &other.sorted())
+    }
+}
+
+impl Hash for Map {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted().hash(state);
+    }
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.map, formatter)
+    }
+}
+
+/// Panics if the key isn't present, like the standard maps.
+impl<Q> ops::Index<&Q> for Map
+where
+    String: Borrow<Q>,
+    Q: ?Sized + Ord + Eq + Hash,
+{
+    type Output = Value;
+
+    fn index(&self, key: &Q) -> &Value {
+        &self.map[key]
+    }
+}
+
+impl FromIterator<(String, Value)> for Map {
+    fn from_iter<I>(iter: I) -> Map
+    where
+        I: IntoIterator<Item = (String, Value)>,
+    {
+        Map {
+            map: FromIterator::from_iter(iter),
+        }
+    }
+}
+
+impl Extend<(String, Value)> for Map {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (String, Value)>,
+    {
+        self.map.extend(iter);
+    }
+}
+
+impl Serialize for Map {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Map {
+    fn deserialize<D>(deserializer: D) -> Result<Map, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MapVisitor;
+
+        impl<'de> Visitor<'de> for MapVisitor {
+            type Value = Map;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tnetstring dict")
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<Map, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut map = Map::new();
+                while let Some((key, value)) = access.next_entry()? {
+                    map.insert(key, value);
+                }
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor)
+    }
+
+    /// The value under each key the map already had is deserialized in
+    /// place, reusing its buffers.
+    fn deserialize_in_place<D>(deserializer: D, place: &mut Map) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MapInPlaceVisitor<'a>(&'a mut Map);
+
+        impl<'de> Visitor<'de> for MapInPlaceVisitor<'_> {
+            type Value = ();
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tnetstring dict")
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<(), A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let first = access.next_key()?;
+                self.0.refill(first, access)
+            }
+        }
+
+        deserializer.deserialize_map(MapInPlaceVisitor(place))
+    }
+}
+
+/// A place in a `Map` for a key, from `Map::entry`.
+pub enum Entry<'a> {
+    Vacant(VacantEntry<'a>),
+    Occupied(OccupiedEntry<'a>),
+}
+
+pub struct VacantEntry<'a> {
+    vacant: imp::VacantEntry<'a, String, Value>,
+}
+
+pub struct OccupiedEntry<'a> {
+    occupied: imp::OccupiedEntry<'a, String, Value>,
+}
+
+impl<'a> Entry<'a> {
+    pub fn key(&self) -> &String {
+        match *self {
+            Entry::Vacant(ref e) => e.key(),
+            Entry::Occupied(ref e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: Value) -> &'a mut Value {
+        match self {
+            Entry::Vacant(e) => e.insert(default),
+            Entry::Occupied(e) => e.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut Value
+    where
+        F: FnOnce() -> Value,
+    {
+        match self {
+            Entry::Vacant(e) => e.insert(default()),
+            Entry::Occupied(e) => e.into_mut(),
+        }
+    }
+
+    pub fn and_modify<F>(self, f: F) -> Entry<'a>
+    where
+        F: FnOnce(&mut Value),
+    {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    pub fn key(&self) -> &String {
+        self.vacant.key()
+    }
+
+    pub fn insert(self, value: Value) -> &'a mut Value {
+        self.vacant.insert(value)
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub fn key(&self) -> &String {
+        self.occupied.key()
+    }
+
+    pub fn get(&self) -> &Value {
+        self.occupied.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut Value {
+        self.occupied.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut Value {
+        self.occupied.into_mut()
+    }
+
+    /// Returns the value that was there before.
+    pub fn insert(&mut self, value: Value) -> Value {
+        self.occupied.insert(value)
+    }
+
+    /// Removes the entry, keeping the order of the ones that remain.
+    pub fn remove(self) -> Value {
+        #[cfg(not(feature = "preserve_order"))]
+        return self.occupied.remove();
+        #[cfg(feature = "preserve_order")]
+        return self.occupied.shift_remove();
+    }
+}
+
+macro_rules! delegate_iterator {
+    ($(#[$doc:meta] $name:ident$(<$a:lifetime>)? => $imp:ty, $item:ty;)*) => {
+        $(
+            #[$doc]
+            pub struct $name$(<$a>)? {
+                iter: $imp,
+            }
+
+            impl$(<$a>)? Iterator for $name$(<$a>)? {
+                type Item = $item;
+
+                fn next(&mut self) -> Option<$item> {
+                    self.iter.next()
+                }
+
+                fn size_hint(&self) -> (usize, Option<usize>) {
+                    self.iter.size_hint()
+                }
+            }
+
+            impl$(<$a>)? DoubleEndedIterator for $name$(<$a>)? {
+                fn next_back(&mut self) -> Option<$item> {
+                    self.iter.next_back()
+                }
+            }
+
+            impl$(<$a>)? ExactSizeIterator for $name$(<$a>)? {
+                fn len(&self) -> usize {
+                    self.iter.len()
+                }
+            }
+        )*
+    };
+}
+
+delegate_iterator! {
+    /// The entries of a `Map`, from `Map::iter`.
+    Iter<'a> => imp::Iter<'a, String, Value>, (&'a String, &'a Value);
+    /// The entries of a `Map`, from `Map::iter_mut`.
+    IterMut<'a> => imp::IterMut<'a, String, Value>, (&'a String, &'a mut Value);
+    /// The entries of a `Map`, from `Map::into_iter`.
+    IntoIter => imp::IntoIter<String, Value>, (String, Value);
+    /// The keys of a `Map`, from `Map::keys`.
+    Keys<'a> => imp::Keys<'a, String, Value>, &'a String;
+    /// The values of a `Map`, from `Map::values`.
+    Values<'a> => imp::Values<'a, String, Value>, &'a Value;
+    /// The values of a `Map`, from `Map::values_mut`.
+    ValuesMut<'a> => imp::ValuesMut<'a, String, Value>, &'a mut Value;
+}
+
+impl IntoIterator for Map {
+    type Item = (String, Value);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            iter: self.map.into_iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Map {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Map {
+    type Item = (&'a String, &'a mut Value);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Map};
+    use crate::de::from_str;
+    use crate::value::Value;
+
+    fn map(keys: &[&str]) -> Map {
+        keys.iter()
+            .enumerate()
+            .map(|(i, key)| (key.to_string(), Value::from(i as u64)))
+            .collect()
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut counts = Map::new();
+        for word in "a b a c a b".split(' ') {
+            let count = counts.entry(word).or_insert(Value::from(0u64));
+            *count = Value::from(count.as_u64().unwrap() + 1);
+        }
+        assert_eq!(counts["a"], Value::from(3u64));
+        assert_eq!(counts.get("b"), Some(&Value::from(2u64)));
+
+        counts
+            .entry("c")
+            .and_modify(|v| *v = Value::Null)
+            .or_insert_with(|| unreachable!());
+        assert_eq!(counts["c"], Value::Null);
+        counts.entry("d").and_modify(|_| unreachable!());
+        assert!(!counts.contains_key("d"));
+
+        match counts.entry("a") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), "a");
+                assert_eq!(e.insert(Value::Bool(true)), Value::from(3u64));
+                assert_eq!(e.remove(), Value::Bool(true));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match counts.entry(String::from("e")) {
+            Entry::Vacant(e) => *e.insert(Value::Null) = Value::Bool(false),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(counts.keys().collect::<Vec<_>>(), ["b", "c", "e"]);
+    }
+
+    #[test]
+    fn test_retain_and_mutate() {
+        let mut m = map(&["a", "b", "c", "d"]);
+        m.retain(|_, v| v.as_u64().unwrap() % 2 == 0);
+        assert_eq!(m.keys().collect::<Vec<_>>(), ["a", "c"]);
+
+        for v in m.values_mut() {
+            *v = Value::Null;
+        }
+        m.extend(vec![("z".to_string(), Value::Bool(true))]);
+        let entries: Vec<_> = m.iter().map(|(k, v)| (k.as_str(), v.is_null())).collect();
+        assert_eq!(entries, [("a", true), ("c", true), ("z", false)]);
+        assert_eq!(m.remove("a"), Some(Value::Null));
+        assert_eq!(m.remove("a"), None);
+        assert_eq!(m.len(), 2);
+    }
+
+    #[test]
+    fn test_order() {
+        let m: Map = from_str("24:1:b,1:0#1:a,1:1#1:c,1:2#}").unwrap();
+        let keys: Vec<_> = m.keys().map(String::as_str).collect();
+        if cfg!(feature = "preserve_order") {
+            assert_eq!(keys, ["b", "a", "c"]);
+        } else {
+            assert_eq!(keys, ["a", "b", "c"]);
+        }
+
+        // removing keeps the order of the rest
+        let mut m = map(&["c", "a", "b"]);
+        m.remove("a");
+        m.insert("a".to_string(), Value::Null);
+        m.insert("c".to_string(), Value::Null);
+        let keys: Vec<_> = m.into_iter().map(|(k, _)| k).collect();
+        if cfg!(feature = "preserve_order") {
+            assert_eq!(keys, ["c", "b", "a"]);
+        } else {
+            assert_eq!(keys, ["a", "b", "c"]);
+        }
+
+        // the order doesn't affect equality
+        assert_eq!(map(&["a", "b"]), {
+            let mut m = Map::new();
+            m.insert("b".to_string(), Value::from(1u64));
+            m.insert("a".to_string(), Value::from(0u64));
+            m
+        });
+    }
+}
@@ -0,0 +1,264 @@
+use serde::de::Deserialize;
+
+use crate::de::from_slice;
+use crate::error::{Error, Result};
+use crate::frames::frame_error;
+use crate::parse::{parse, TNetStringType};
+use crate::value::Value;
+
+/// How a request's headers were encoded, which follows the handler's
+/// `protocol` setting in the Mongrel2 config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderFormat {
+    TNetString,
+    Json,
+}
+
+/// A message from Mongrel2 to a handler, laid out as
+/// `SENDER CONN_ID PATH SIZE:HEADERS,SIZE:BODY,`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub sender: String,
+    pub conn_id: String,
+    pub path: String,
+    /// Always a `Value::Dict`, whichever format the headers came in.
+    pub headers: Value,
+    pub header_format: HeaderFormat,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Parse one handler message. The headers can be a tnetstring dict or a
+    /// JSON object wrapped in a netstring; anything after the body is an
+    /// error.
+    pub fn parse(msg: &[u8]) -> Result<Request> {
+        let mut fields = msg.splitn(4, |&byte| byte == b' ');
+        let sender = field(fields.next(), "missing sender")?;
+        let conn_id = field(fields.next(), "missing connection id")?;
+        let path = field(fields.next(), "missing path")?;
+        let rest = fields.next().ok_or(Error::Mongrel2("missing headers"))?;
+
+        let parsed = parse(rest).map_err(frame_error)?;
+        let (headers, header_format) = match parsed.kind {
+            TNetStringType::Dict => {
+                let encoded = &rest[..rest.len() - parsed.rest.len()];
+                (from_slice(encoded)?, HeaderFormat::TNetString)
+            }
+            TNetStringType::Str => {
+                let headers = serde_json::from_slice(parsed.payload)
+                    .map_err(|error| Error::Message(error.to_string()))?;
+                (headers, HeaderFormat::Json)
+            }
+            _ => return Err(Error::Mongrel2("headers aren't a dict or JSON")),
+        };
+        if !matches!(headers, Value::Dict(_)) {
+            return Err(Error::Mongrel2("headers aren't a dict or JSON"));
+        }
+
+        let body = parse(parsed.rest).map_err(frame_error)?;
+        if body.kind != TNetStringType::Str {
+            return Err(Error::Mongrel2("body isn't a netstring"));
+        }
+        if !body.rest.is_empty() {
+            return Err(Error::UnusedParseData);
+        }
+        Ok(Request {
+            sender,
+            conn_id,
+            path,
+            headers,
+            header_format,
+            body: body.payload.to_vec(),
+        })
+    }
+
+    /// Look up a header with a string value, such as `METHOD` or `PATH`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(Value::as_str)
+    }
+
+    /// Deserialize the headers into a `T`, borrowing strings from them where
+    /// it can.
+    pub fn headers_as<'de, T>(&'de self) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        self.headers.deserialize_into()
+    }
+
+    /// Whether this is Mongrel2 reporting that the client hung up, a
+    /// `METHOD` of `JSON` with a `{"type":"disconnect"}` body.
+    pub fn is_disconnect(&self) -> bool {
+        self.header("METHOD") == Some("JSON")
+            && serde_json::from_slice::<Value>(&self.body)
+                .is_ok_and(|body| body.get("type").and_then(Value::as_str) == Some("disconnect"))
+    }
+}
+
+fn field(field: Option<&[u8]>, missing: &'static str) -> Result<String> {
+    match field {
+        Some(field) if !field.is_empty() => Ok(String::from_utf8(field.to_vec())?),
+        _ => Err(Error::Mongrel2(missing)),
+    }
+}
+
+/// Replies from a handler back to Mongrel2.
+pub struct Response;
+
+impl Response {
+    /// Encode a reply to `conn_ids` as `UUID SIZE:ID ID ID, BODY`, where
+    /// `uuid` is the `sender` of the request being answered.
+    ///
+    /// Mongrel2 closes the connections when `body` is empty.
+    pub fn encode(uuid: &str, conn_ids: &[&str], body: &[u8]) -> Vec<u8> {
+        let ids = conn_ids.join(" ");
+        let mut itoa = itoa::Buffer::new();
+        let len = itoa.format(ids.len());
+        let mut reply = Vec::with_capacity(uuid.len() + len.len() + ids.len() + body.len() + 4);
+        reply.extend_from_slice(uuid.as_bytes());
+        reply.push(b' ');
+        reply.extend_from_slice(len.as_bytes());
+        reply.push(b':');
+        reply.extend_from_slice(ids.as_bytes());
+        reply.extend_from_slice(b", ");
+        reply.extend_from_slice(body);
+        reply
+    }
+
+    /// A reply telling Mongrel2 to close `conn_ids`.
+    pub fn close(uuid: &str, conn_ids: &[&str]) -> Vec<u8> {
+        Response::encode(uuid, conn_ids, b"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderFormat, Request, Response};
+    use crate::error::Error;
+    use serde::Deserialize;
+
+    const SENDER: &str = "54c6755b-9628-40a4-9a2d-cc82a816345e";
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Headers<'a> {
+        #[serde(rename = "METHOD")]
+        method: &'a str,
+        #[serde(rename = "URI")]
+        uri: &'a str,
+        host: &'a str,
+    }
+
+    #[test]
+    fn test_get() {
+        let request = Request::parse(include_bytes!("../tests/fixtures/mongrel2_get.msg")).unwrap();
+        assert_eq!(request.sender, SENDER);
+        assert_eq!(request.conn_id, "12");
+        assert_eq!(request.path, "/handlertest");
+        assert_eq!(request.header_format, HeaderFormat::TNetString);
+        assert_eq!(request.header("QUERY"), Some("q=1"));
+        assert_eq!(request.header("missing"), None);
+        assert_eq!(
+            request.headers_as(),
+            Ok(Headers {
+                method: "GET",
+                uri: "/handlertest?q=1",
+                host: "localhost:6767",
+            })
+        );
+        assert!(request.body.is_empty());
+        assert!(!request.is_disconnect());
+    }
+
+    #[test]
+    fn test_post() {
+        let request =
+            Request::parse(include_bytes!("../tests/fixtures/mongrel2_post.msg")).unwrap();
+        assert_eq!(request.conn_id, "3");
+        assert_eq!(request.header("METHOD"), Some("POST"));
+        assert_eq!(request.header("content-length"), Some("11"));
+        assert_eq!(request.body, b"name=Steve!");
+    }
+
+    #[test]
+    fn test_json_headers() {
+        let request =
+            Request::parse(include_bytes!("../tests/fixtures/mongrel2_json.msg")).unwrap();
+        assert_eq!(request.header_format, HeaderFormat::Json);
+        assert_eq!(
+            request.headers,
+            Request::parse(include_bytes!("../tests/fixtures/mongrel2_get.msg"))
+                .unwrap()
+                .headers
+        );
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let request =
+            Request::parse(include_bytes!("../tests/fixtures/mongrel2_disconnect.msg")).unwrap();
+        assert_eq!(request.path, "@*");
+        assert_eq!(request.header_format, HeaderFormat::Json);
+        assert!(request.is_disconnect());
+
+        let msg = format!(
+            "{} 12 @* 16:6:METHOD,4:JSON,}}21:{{\"type\":\"disconnect\"}},",
+            SENDER
+        );
+        let request = Request::parse(msg.as_bytes()).unwrap();
+        assert_eq!(request.header_format, HeaderFormat::TNetString);
+        assert!(request.is_disconnect());
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            Request::parse(b"sender 1"),
+            Err(Error::Mongrel2("missing path"))
+        );
+        assert_eq!(
+            Request::parse(b"sender  / 0:}0:,"),
+            Err(Error::Mongrel2("missing connection id"))
+        );
+        assert_eq!(
+            Request::parse(b"sender 1 /"),
+            Err(Error::Mongrel2("missing headers"))
+        );
+        assert_eq!(
+            Request::parse(b"sender 1 / 0:]0:,"),
+            Err(Error::Mongrel2("headers aren't a dict or JSON"))
+        );
+        assert_eq!(
+            Request::parse(b"sender 1 / 2:[],0:,"),
+            Err(Error::Mongrel2("headers aren't a dict or JSON"))
+        );
+        assert!(matches!(
+            Request::parse(b"sender 1 / 1:{,0:,"),
+            Err(Error::Message(_))
+        ));
+        assert_eq!(
+            Request::parse(b"sender 1 / 0:}0:~"),
+            Err(Error::Mongrel2("body isn't a netstring"))
+        );
+        assert_eq!(
+            Request::parse(b"sender 1 / 0:}0:,x"),
+            Err(Error::UnusedParseData)
+        );
+        assert_eq!(Request::parse(b"sender 1 / 0:}5:ab,"), Err(Error::Eof));
+    }
+
+    #[test]
+    fn test_response() {
+        assert_eq!(
+            Response::encode(SENDER, &["12"], b"HTTP/1.1 200 OK\r\n\r\n"),
+            format!("{} 2:12, HTTP/1.1 200 OK\r\n\r\n", SENDER).into_bytes()
+        );
+        assert_eq!(
+            Response::encode(SENDER, &["1", "22", "333"], b"hi"),
+            format!("{} 8:1 22 333, hi", SENDER).into_bytes()
+        );
+        assert_eq!(
+            Response::close(SENDER, &["7"]),
+            format!("{} 1:7, ", SENDER).into_bytes()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Classic netstrings, `LEN:PAYLOAD,` with no type tag.
+//!
+//! Every netstring reads as a tnetstring string, but not the other way
+//! around. Some transports frame tnetstrings by wrapping each one in a
+//! netstring, see `wrap_tnetstring`.
+
+use crate::error::{Error, Result};
+use crate::frames::frame_error;
+use crate::parse::{parse, split_frame};
+
+/// Split the netstring at the front of `input` into its payload and
+/// whatever follows it.
+pub fn read_netstring(input: &[u8]) -> Result<(&[u8], &[u8])> {
+    match split_frame(input).map_err(frame_error)? {
+        (payload, b',', rest) => Ok((payload, rest)),
+        _ => Err(Error::MissingComma),
+    }
+}
+
+/// Append `payload` to `out` as a netstring.
+pub fn write_netstring(out: &mut Vec<u8>, payload: &[u8]) {
+    out.extend_from_slice(itoa::Buffer::new().format(payload.len()).as_bytes());
+    out.push(b':');
+    out.extend_from_slice(payload);
+    out.push(b',');
+}
+
+/// Iterate over the payloads of back-to-back netstrings in `buf`.
+///
+/// Like `frames`, a malformed netstring yields one `Error::At` with its
+/// offset, then the iterator stops.
+pub fn netstrings(buf: &[u8]) -> Netstrings<'_> {
+    Netstrings {
+        buf,
+        offset: 0,
+        failed: false,
+    }
+}
+
+/// The iterator returned by `netstrings`.
+#[derive(Clone, Debug)]
+pub struct Netstrings<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> Netstrings<'a> {
+    /// Where the next netstring starts.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for Netstrings<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input = &self.buf[self.offset..];
+        if self.failed || input.is_empty() {
+            return None;
+        }
+        match read_netstring(input) {
+            Ok((payload, rest)) => {
+                self.offset = self.buf.len() - rest.len();
+                Some(Ok(payload))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(Error::At {
+                    offset: self.offset,
+                    error: Box::new(error),
+                }))
+            }
+        }
+    }
+}
+
+/// Wrap an encoded tnetstring in an outer netstring. The tnetstring isn't
+/// checked.
+pub fn wrap_tnetstring(encoded: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(encoded.len() + 22);
+    write_netstring(&mut out, encoded);
+    out
+}
+
+/// Undo `wrap_tnetstring`, returning the inner tnetstring and whatever
+/// follows the outer netstring.
+///
+/// Fails if the payload isn't exactly one tnetstring, going by its length
+/// prefix and type tag.
+pub fn unwrap_tnetstring(input: &[u8]) -> Result<(&[u8], &[u8])> {
+    let (encoded, rest) = read_netstring(input)?;
+    if !parse(encoded).map_err(frame_error)?.rest.is_empty() {
+        return Err(Error::UnusedParseData);
+    }
+    Ok((encoded, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{netstrings, read_netstring, unwrap_tnetstring, wrap_tnetstring, write_netstring};
+    use crate::error::Error;
+
+    #[test]
+    fn test_read_write() {
+        assert_eq!(read_netstring(b"0:,"), Ok((&b""[..], &b""[..])));
+        assert_eq!(
+            read_netstring(b"5:hello,rest"),
+            Ok((&b"hello"[..], &b"rest"[..]))
+        );
+
+        let binary = [0u8, 0xff, b',', b':', b'\n'];
+        let mut out = Vec::new();
+        write_netstring(&mut out, &binary);
+        write_netstring(&mut out, b"");
+        assert_eq!(out, b"5:\x00\xff,:\n,0:,");
+        assert_eq!(read_netstring(&out), Ok((&binary[..], &b"0:,"[..])));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(read_netstring(b"5:hello"), Err(Error::Eof));
+        assert_eq!(read_netstring(b"5:hello#"), Err(Error::MissingComma));
+        assert_eq!(read_netstring(b"5:hello!"), Err(Error::MissingComma));
+        assert_eq!(read_netstring(b":,"), Err(Error::LengthNotFound));
+        assert_eq!(read_netstring(b""), Err(Error::Eof));
+    }
+
+    #[test]
+    fn test_iter() {
+        let split: Vec<_> = netstrings(b"5:hello,0:,3:\x00\x01\x02,").collect();
+        assert_eq!(
+            split,
+            vec![Ok(&b"hello"[..]), Ok(&b""[..]), Ok(&b"\x00\x01\x02"[..])]
+        );
+
+        let mut iter = netstrings(b"0:,5:hello~0:,");
+        assert_eq!(iter.next(), Some(Ok(&b""[..])));
+        assert_eq!(iter.offset(), 3);
+        assert_eq!(
+            iter.next(),
+            Some(Err(Error::At {
+                offset: 3,
+                error: Box::new(Error::MissingComma),
+            }))
+        );
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let encoded = crate::to_vec(&vec!["a", "b"]).unwrap();
+        let wrapped = wrap_tnetstring(&encoded);
+        assert_eq!(wrapped, b"11:8:1:a,1:b,],");
+        assert_eq!(unwrap_tnetstring(&wrapped), Ok((&encoded[..], &b""[..])));
+
+        assert_eq!(unwrap_tnetstring(b"3:0:~,x"), Ok((&b"0:~"[..], &b"x"[..])));
+        assert_eq!(unwrap_tnetstring(b"6:0:~0:~,"), Err(Error::UnusedParseData));
+        assert_eq!(unwrap_tnetstring(b"3:0:x,"), Err(Error::UnknownSegmentType));
+        assert_eq!(unwrap_tnetstring(b"0:,"), Err(Error::Eof));
+    }
+}
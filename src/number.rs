@@ -0,0 +1,526 @@
+#[cfg(feature = "arbitrary_precision")]
+use serde::de::value::MapDeserializer;
+#[cfg(feature = "arbitrary_precision")]
+use serde::de::{self, MapAccess};
+use serde::de::{Deserialize, Deserializer, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+
+/// A tnetstring integer or float.
+///
+/// Integers keep their exact value across the whole `i64` and `u64` range,
+/// and are never equal to a float, even one with the same value.
+///
+/// Floats compare with `f64::total_cmp`, so unlike `f64` itself every `NaN`
+/// equals itself and `-0.0` is less than `0.0`, which makes `Number` usable
+/// as a map key. Numbers are ordered by value, with an integer coming just
+/// before a float of the same value; a positive `NaN` is above everything and
+/// a negative one below.
+///
+/// With the `arbitrary_precision` feature, integers too wide for `i64` and
+/// `u64` are kept as their digits, exactly as written, rather than failing
+/// to parse. They're ordered by value like any other number, and aren't
+/// `Copy`, since they hold a string.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "arbitrary_precision"), derive(Copy))]
+pub struct Number {
+    n: N,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "arbitrary_precision"), derive(Copy))]
+enum N {
+    // always zero or more
+    PosInt(u64),
+    // always less than zero
+    NegInt(i64),
+    Float(f64),
+    // the digits of an integer outside the range of both of the above
+    #[cfg(feature = "arbitrary_precision")]
+    Wide(Box<str>),
+}
+
+impl Number {
+    pub fn is_i64(&self) -> bool {
+        self.as_i64().is_some()
+    }
+
+    pub fn is_u64(&self) -> bool {
+        matches!(self.n, N::PosInt(_))
+    }
+
+    /// Whether this was a `^` float rather than a `#` integer.
+    pub fn is_f64(&self) -> bool {
+        matches!(self.n, N::Float(_))
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.n {
+            N::PosInt(v) => i64::try_from(v).ok(),
+            N::NegInt(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.n {
+            N::PosInt(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, which for integers beyond 2^53 loses precision.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.n {
+            N::PosInt(v) => Some(v as f64),
+            N::NegInt(v) => Some(v as f64),
+            N::Float(v) => Some(v),
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(ref digits) => digits.parse().ok(),
+        }
+    }
+
+    // `-0.0` becomes `0.0`, and with `integer_floats` a float with an
+    // integer value that fits an `i64` or `u64` becomes that integer
+    pub(crate) fn canonicalize(&mut self, integer_floats: bool) {
+        if let N::Float(v) = self.n {
+            if v == 0.0 {
+                self.n = N::Float(0.0);
+            }
+            if integer_floats && v.fract() == 0.0 {
+                if v >= 0.0 && v < u64::MAX as f64 {
+                    *self = Number::from(v as u64);
+                } else if v >= i64::MIN as f64 && v < 0.0 {
+                    *self = Number::from(v as i64);
+                }
+            }
+        }
+    }
+
+    /// Read the payload of a `#` integer, of any width: those that fit an
+    /// `i64` or `u64` are stored as one, and `None` if it isn't an integer.
+    #[cfg(feature = "arbitrary_precision")]
+    pub(crate) fn from_digits(digits: &str) -> Option<Number> {
+        if let Ok(v) = digits.parse::<i64>() {
+            return Some(v.into());
+        }
+        if let Ok(v) = digits.parse::<u64>() {
+            return Some(v.into());
+        }
+        let magnitude = digits.strip_prefix('-').unwrap_or(digits);
+        if magnitude.is_empty() || !magnitude.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Number {
+            n: N::Wide(digits.into()),
+        })
+    }
+
+    pub(crate) fn unexpected(&self) -> Unexpected<'static> {
+        match self.n {
+            N::PosInt(v) => Unexpected::Unsigned(v),
+            N::NegInt(v) => Unexpected::Signed(v),
+            N::Float(v) => Unexpected::Float(v),
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(_) => Unexpected::Other("a wide integer"),
+        }
+    }
+
+    pub(crate) fn deserialize_any<'de, V>(&self, visitor: V) -> Result<V::Value, crate::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.n {
+            N::PosInt(v) => visitor.visit_u64(v),
+            N::NegInt(v) => visitor.visit_i64(v),
+            N::Float(v) => visitor.visit_f64(v),
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(ref digits) => visit_wide(digits, visitor),
+        }
+    }
+}
+
+// The key of the single entry map a wide integer's digits are handed to
+// visitors in, the same trick `serde_json` uses. `Number` and `Value` pick
+// it out, and anything else sees a map and fails.
+#[cfg(feature = "arbitrary_precision")]
+pub(crate) const TOKEN: &str = "$tnetstring::private::Number";
+
+#[cfg(feature = "arbitrary_precision")]
+pub(crate) fn visit_wide<'de, V>(digits: &str, visitor: V) -> Result<V::Value, crate::Error>
+where
+    V: Visitor<'de>,
+{
+    let mut map = MapDeserializer::<_, crate::Error>::new(std::iter::once((TOKEN, digits)));
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Number) -> Ordering {
+        match (&self.n, &other.n) {
+            (&N::PosInt(a), &N::PosInt(b)) => a.cmp(&b),
+            (&N::NegInt(a), &N::NegInt(b)) => a.cmp(&b),
+            (&N::PosInt(_), &N::NegInt(_)) => Ordering::Greater,
+            (&N::NegInt(_), &N::PosInt(_)) => Ordering::Less,
+            (&N::PosInt(a), &N::Float(b)) => int_cmp_float(a.into(), b),
+            (&N::NegInt(a), &N::Float(b)) => int_cmp_float(a.into(), b),
+            (&N::Float(a), &N::PosInt(b)) => int_cmp_float(b.into(), a).reverse(),
+            (&N::Float(a), &N::NegInt(b)) => int_cmp_float(b.into(), a).reverse(),
+            (&N::Float(a), &N::Float(b)) => a.total_cmp(&b),
+            #[cfg(feature = "arbitrary_precision")]
+            (N::Wide(a), N::Wide(b)) => wide_cmp(a, b),
+            // past the range of the others, so only the sign matters
+            #[cfg(feature = "arbitrary_precision")]
+            (&N::Wide(ref a), &N::PosInt(_)) | (&N::Wide(ref a), &N::NegInt(_)) => wide_cmp(a, "0"),
+            #[cfg(feature = "arbitrary_precision")]
+            (&N::PosInt(_), &N::Wide(ref b)) | (&N::NegInt(_), &N::Wide(ref b)) => wide_cmp("0", b),
+            #[cfg(feature = "arbitrary_precision")]
+            (N::Wide(a), &N::Float(b)) => wide_cmp_float(a, b),
+            #[cfg(feature = "arbitrary_precision")]
+            (&N::Float(a), N::Wide(b)) => wide_cmp_float(b, a).reverse(),
+        }
+    }
+}
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.n {
+            N::PosInt(v) => (0u8, v).hash(state),
+            N::NegInt(v) => (1u8, v).hash(state),
+            N::Float(v) => (2u8, v.to_bits()).hash(state),
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(ref digits) => (3u8, split_sign(digits)).hash(state),
+        }
+    }
+}
+
+// The sign and the digits without leading zeros.
+#[cfg(feature = "arbitrary_precision")]
+fn split_sign(digits: &str) -> (bool, &str) {
+    let (negative, magnitude) = match digits.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, digits),
+    };
+    (negative, magnitude.trim_start_matches('0'))
+}
+
+// Compares two integers written out in full.
+#[cfg(feature = "arbitrary_precision")]
+fn wide_cmp(a: &str, b: &str) -> Ordering {
+    let (a_negative, a) = split_sign(a);
+    let (b_negative, b) = split_sign(b);
+    let magnitude = a.len().cmp(&b.len()).then_with(|| a.cmp(b));
+    match (a_negative, b_negative) {
+        (false, false) => magnitude,
+        (true, true) => magnitude.reverse(),
+        (false, true) if a.is_empty() && b.is_empty() => Ordering::Equal,
+        (true, false) if a.is_empty() && b.is_empty() => Ordering::Equal,
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+    }
+}
+
+// Like `int_cmp_float`. Any float as wide as `digits` is a whole number, so
+// writing it out in full compares it exactly.
+#[cfg(feature = "arbitrary_precision")]
+fn wide_cmp_float(digits: &str, float: f64) -> Ordering {
+    if float.is_nan() || float.is_infinite() {
+        return int_cmp_float(0, float);
+    }
+    match wide_cmp(digits, &format!("{:.0}", float)) {
+        Ordering::Equal => Ordering::Less,
+        ordering => ordering,
+    }
+}
+
+// Compares exactly, without rounding `int` to a float. An integer sorts
+// before a float of the same value, since the two are never equal.
+fn int_cmp_float(int: i128, float: f64) -> Ordering {
+    if float.is_nan() {
+        return if float.is_sign_negative() {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        };
+    }
+    // every integer fits well within this range
+    if float >= 1e20 {
+        return Ordering::Less;
+    }
+    if float <= -1e20 {
+        return Ordering::Greater;
+    }
+    let whole = float.trunc();
+    match int.cmp(&(whole as i128)) {
+        Ordering::Equal if float > whole => Ordering::Less,
+        Ordering::Equal if float < whole => Ordering::Greater,
+        Ordering::Equal => Ordering::Less,
+        ordering => ordering,
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.n {
+            N::PosInt(v) => Display::fmt(&v, formatter),
+            N::NegInt(v) => Display::fmt(&v, formatter),
+            N::Float(v) => Display::fmt(&v, formatter),
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(ref digits) => formatter.write_str(digits),
+        }
+    }
+}
+
+impl From<u64> for Number {
+    fn from(v: u64) -> Number {
+        Number { n: N::PosInt(v) }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(v: i64) -> Number {
+        let n = match u64::try_from(v) {
+            Ok(v) => N::PosInt(v),
+            Err(_) => N::NegInt(v),
+        };
+        Number { n }
+    }
+}
+
+impl From<f64> for Number {
+    fn from(v: f64) -> Number {
+        Number { n: N::Float(v) }
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.n {
+            N::PosInt(v) => serializer.serialize_u64(v),
+            N::NegInt(v) => serializer.serialize_i64(v),
+            N::Float(v) => serializer.serialize_f64(v),
+            // written out verbatim by this crate's serializers
+            #[cfg(feature = "arbitrary_precision")]
+            N::Wide(ref digits) => {
+                let frame = format!("{}:{}#", digits.len(), digits);
+                serializer.serialize_newtype_struct(
+                    crate::raw::TOKEN,
+                    &crate::raw::Bytes(frame.as_bytes()),
+                )
+            }
+        }
+    }
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer or a float")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    fn visit_map<A>(self, mut map: A) -> Result<Number, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match map.next_key::<String>()? {
+            Some(ref key) if key == TOKEN => {}
+            _ => return Err(de::Error::invalid_type(Unexpected::Map, &self)),
+        }
+        let digits: String = map.next_value()?;
+        Number::from_digits(&digits)
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&digits), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D>(deserializer: D) -> Result<Number, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Number;
+    use crate::de::from_str;
+    use crate::ser::to_string;
+
+    #[test]
+    fn test_boundaries() {
+        let n = Number::from(i64::MAX as u64);
+        assert_eq!(n.as_i64(), Some(i64::MAX));
+        assert_eq!(n.as_u64(), Some(i64::MAX as u64));
+
+        let n = Number::from(i64::MAX as u64 + 1);
+        assert_eq!(n.as_i64(), None);
+        assert_eq!(n.as_u64(), Some(i64::MAX as u64 + 1));
+        assert!(!n.is_i64());
+        assert!(n.is_u64());
+
+        let n = Number::from(-1i64);
+        assert_eq!(n.as_i64(), Some(-1));
+        assert_eq!(n.as_u64(), None);
+
+        assert_eq!(Number::from(0i64), Number::from(0u64));
+        assert!(Number::from(0i64).is_u64());
+    }
+
+    #[test]
+    fn test_floats() {
+        let n = Number::from(2.0);
+        assert!(n.is_f64());
+        assert_eq!(n.as_i64(), None);
+        assert_eq!(n.as_u64(), None);
+        assert_eq!(n.as_f64(), Some(2.0));
+        assert_ne!(n, Number::from(2i64));
+        assert_eq!(Number::from(2u64).as_f64(), Some(2.0));
+        assert_eq!(n.to_string(), "2");
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut numbers: Vec<Number> = vec![
+            f64::NAN.into(),
+            u64::MAX.into(),
+            2.5.into(),
+            (-0.0).into(),
+            0u64.into(),
+            i64::MIN.into(),
+            f64::NEG_INFINITY.into(),
+            (-f64::NAN).into(),
+            1e19.into(),
+            2u64.into(),
+            0.0.into(),
+            (-1i64).into(),
+            (-1.5).into(),
+            (-1.0).into(),
+        ];
+        numbers.sort();
+        let sorted: Vec<String> = numbers
+            .iter()
+            .map(|n| format!("{}{}", n, if n.is_f64() { "^" } else { "#" }))
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                "NaN^",
+                "-inf^",
+                "-9223372036854775808#",
+                "-1.5^",
+                "-1#",
+                "-1^",
+                "0#",
+                "-0^",
+                "0^",
+                "2#",
+                "2.5^",
+                "10000000000000000000^",
+                "18446744073709551615#",
+                "NaN^",
+            ]
+        );
+        assert_eq!(Number::from(f64::NAN), Number::from(f64::NAN));
+        assert_ne!(Number::from(0.0), Number::from(-0.0));
+        assert!(Number::from(u64::MAX) < Number::from(u64::MAX as f64));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in &[
+            "20:18446744073709551615#",
+            "19:9223372036854775807#",
+            "19:9223372036854775808#",
+            "20:-9223372036854775808#",
+            "1:0#",
+            "1:1^",
+            "3:0.5^",
+            "4:-0.5^",
+        ] {
+            let n: Number = from_str(input).unwrap();
+            assert_eq!(to_string(&n).as_deref(), Ok(*input));
+        }
+        assert_eq!(from_str::<Number>("3:1.0^"), Ok(Number::from(1.0)));
+        assert!(from_str::<Number>("1:a,").is_err());
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_arbitrary_precision() {
+        use crate::value::Value;
+
+        let wide = "40:1234567890123456789012345678901234567890#";
+        let value: Value = from_str(wide).unwrap();
+        assert_eq!(value.to_string(), wide);
+        assert_eq!(to_string(&value).as_deref(), Ok(wide));
+        let n: Number = from_str(wide).unwrap();
+        assert_eq!(n.to_string(), &wide[3..43]);
+        assert_eq!(n.as_u64(), None);
+        assert_eq!(n.as_f64(), Some(1.2345678901234568e39));
+        assert!(from_str::<u64>(wide).is_err());
+        assert!(from_str::<i128>(wide).is_err());
+
+        let negative: Number = from_str("21:-18446744073709551616#").unwrap();
+        let mut numbers = vec![
+            n.clone(),
+            negative.clone(),
+            u64::MAX.into(),
+            i64::MIN.into(),
+            1e39.into(),
+            2e39.into(),
+            (-1e30).into(),
+        ];
+        numbers.sort();
+        assert_eq!(
+            numbers,
+            [
+                (-1e30).into(),
+                negative,
+                i64::MIN.into(),
+                u64::MAX.into(),
+                1e39.into(),
+                n,
+                2e39.into(),
+            ]
+        );
+    }
+}
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::str;
 
 #[derive(Debug, PartialEq)]
 pub enum TNetString {
@@ -11,7 +14,8 @@ pub enum TNetString {
     Dict(HashMap<String, TNetString>),
 }
 
-#[derive(Debug, PartialEq)]
+/// The kind of a tnetstring, one for each type tag.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TNetStringType {
     Bool,
     Str,
@@ -22,105 +26,226 @@ pub enum TNetStringType {
     Dict,
 }
 
-#[derive(Debug, PartialEq)]
+impl Display for TNetStringType {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            TNetStringType::Bool => "boolean",
+            TNetStringType::Str => "string",
+            TNetStringType::Int => "integer",
+            TNetStringType::Float => "float",
+            TNetStringType::Null => "null",
+            TNetStringType::List => "list",
+            TNetStringType::Dict => "dict",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TNetStringError {
+    /// There were no bytes at all.
+    EmptyInput,
+    /// The input doesn't start with digits followed by a `:`.
+    MissingLengthPrefix,
+    /// The byte after the payload isn't one of the seven type tags.
     UnknownSegmentType,
     UnableToParseInt,
     UnableToParseFloat,
     NoneZeroLengthNull,
     UnableToTake,
     FoundNonStringKey,
+    /// A dict key that isn't valid UTF-8.
+    NonUtf8Key,
+    /// A dict with a key but no value after it.
+    MissingDictValue,
+    /// A bool that isn't `true` or `false`.
+    InvalidBool,
 }
 
+impl Display for TNetStringError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            TNetStringError::EmptyInput => "empty input",
+            TNetStringError::MissingLengthPrefix => "missing length prefix",
+            TNetStringError::UnknownSegmentType => "unknown type tag",
+            TNetStringError::UnableToParseInt => "error parsing integer",
+            TNetStringError::UnableToParseFloat => "error parsing float",
+            TNetStringError::NoneZeroLengthNull => "null with a non-empty payload",
+            TNetStringError::UnableToTake => "input is shorter than its length prefix",
+            TNetStringError::FoundNonStringKey => "dict key that isn't a string",
+            TNetStringError::NonUtf8Key => "dict key that isn't utf8",
+            TNetStringError::MissingDictValue => "dict key without a value",
+            TNetStringError::InvalidBool => "bool that isn't true or false",
+        })
+    }
+}
+
+impl std::error::Error for TNetStringError {}
+
 fn is_digit(data: u8) -> bool {
-    (b'0' <= data) && (data <= b'9')
+    data.is_ascii_digit()
 }
 
-fn parse_tag(data: &[u8]) -> Result<(&[u8], usize), TNetStringError> {
-    let mut temp_str = String::new();
-    let mut index = 0;
+fn parse_pair(data: &[u8]) -> Result<(&[u8], (String, TNetString)), TNetStringError> {
+    let (remain, parsed_key) = parse_value(data)?;
+    if remain.is_empty() {
+        return Err(TNetStringError::MissingDictValue);
+    }
+    let (remain, parsed_value) = parse_value(remain)?;
 
-    while index < data.len() && is_digit(data[index]) {
-        temp_str.push(data[index] as char);
-        index += 1;
+    match parsed_key {
+        TNetString::Str(key) => Ok((remain, (key, parsed_value))),
+        _ => Err(TNetStringError::FoundNonStringKey),
     }
+}
 
-    let num = match temp_str.parse::<usize>() {
-        Ok(n) => n,
-        Err(_) => return Err(TNetStringError::UnableToParseInt),
-    };
-    index += 1;
+/// The tnetstring at the front of some input, split up by `parse`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parsed<'a> {
+    pub kind: TNetStringType,
+    /// Everything between the `:` and the type tag.
+    pub payload: &'a [u8],
+    /// Whatever follows the type tag.
+    pub rest: &'a [u8],
+}
 
-    Ok((&data[index..], num))
+/// Split the tnetstring at the front of `input` into its type, payload and
+/// whatever follows it.
+///
+/// This is the primitive everything else here is built on, and it's kept
+/// stable. Only the length prefix and type tag are checked; the payload is
+/// returned as is, so a list or dict payload can be fed back into `parse`
+/// to walk its items.
+pub fn parse(input: &[u8]) -> Result<Parsed<'_>, TNetStringError> {
+    let (payload, tag, rest) = split_frame(input)?;
+    Ok(Parsed {
+        kind: tag_type(tag).ok_or(TNetStringError::UnknownSegmentType)?,
+        payload,
+        rest,
+    })
 }
 
-fn take(data: &[u8], n: usize) -> Result<(&[u8], &[u8]), TNetStringError> {
-    if n <= data.len() {
-        Ok((&data[n..], &data[..n]))
-    } else {
-        Err(TNetStringError::UnableToTake)
+// Splits `LEN:PAYLOAD` and the byte after it, whatever it is, off the front
+// of `input`. Plain netstrings share this with `parse`.
+pub(crate) fn split_frame(input: &[u8]) -> Result<(&[u8], u8, &[u8]), TNetStringError> {
+    if input.is_empty() {
+        return Err(TNetStringError::EmptyInput);
+    }
+    let digits = input.iter().take_while(|&&b| is_digit(b)).count();
+    if digits == 0 || input.get(digits) != Some(&b':') {
+        return Err(TNetStringError::MissingLengthPrefix);
     }
+    let start = digits + 1;
+    let tag_pos = start
+        .checked_add(parse_len(&input[..digits])?)
+        .ok_or(TNetStringError::UnableToParseInt)?;
+    let tag = *input.get(tag_pos).ok_or(TNetStringError::UnableToTake)?;
+    Ok((&input[start..tag_pos], tag, &input[tag_pos + 1..]))
 }
 
-fn parse_pair(data: &[u8]) -> Result<(&[u8], (String, TNetString)), TNetStringError> {
-    let (remain, parsed_key) = parse(data)?;
-    let (remain, parsed_value) = parse(remain)?;
-
-    match parsed_key {
-        TNetString::Str(key) => Ok((remain, (key, parsed_value))),
-        _ => Err(TNetStringError::FoundNonStringKey),
+pub(crate) fn tag_type(tag: u8) -> Option<TNetStringType> {
+    match tag {
+        b'!' => Some(TNetStringType::Bool),
+        b',' => Some(TNetStringType::Str),
+        b'#' => Some(TNetStringType::Int),
+        b'^' => Some(TNetStringType::Float),
+        b'~' => Some(TNetStringType::Null),
+        b']' => Some(TNetStringType::List),
+        b'}' => Some(TNetStringType::Dict),
+        _ => None,
     }
 }
 
-// 4:true! --> ("!", "true")
-fn split_data(data: &[u8]) -> Result<(&[u8], &[u8]), TNetStringError> {
-    let (res, num) = parse_tag(data)?;
-    take(res, num)
+/// Look at the length prefix and type tag of the tnetstring at the front of
+/// `data`, without checking its payload.
+pub fn parse_type(data: &[u8]) -> Result<TNetStringType, TNetStringError> {
+    parse(data).map(|parsed| parsed.kind)
 }
 
-pub fn parse_type(data: &[u8]) -> Result<TNetStringType, TNetStringError> {
-    let (type_tag_content, _) = split_data(data)?;
-    match type_tag_content.first() {
-        Some(b'!') => Ok(TNetStringType::Bool),
-        Some(b',') => Ok(TNetStringType::Str),
-        Some(b'#') => Ok(TNetStringType::Int),
-        Some(b'^') => Ok(TNetStringType::Float),
-        Some(b'~') => Ok(TNetStringType::Null),
-        Some(b']') => Ok(TNetStringType::List),
-        Some(b'}') => Ok(TNetStringType::Dict),
-        _ => Err(TNetStringError::UnknownSegmentType),
+/// How much of a tnetstring is at the front of a buffer, see `frame_len`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameStatus {
+    /// A whole frame is there, this many bytes long including its length
+    /// prefix and type tag.
+    Complete(usize),
+    /// The frame is cut short. Once the length prefix has been read this is
+    /// how many more bytes are needed, before then it's unknown.
+    Incomplete(Option<usize>),
+}
+
+/// Check whether `buf` starts with a complete tnetstring, using only its
+/// length prefix and type tag.
+///
+/// This is meant for buffering reads off a socket: the payload isn't
+/// checked, so a `Complete` frame can still fail to parse.
+/// `Incomplete` is what `from_slice`, `validate` and the codec report as
+/// `Error::Incomplete`.
+pub fn frame_len(buf: &[u8]) -> Result<FrameStatus, TNetStringError> {
+    let digits = buf.iter().take_while(|&&b| is_digit(b)).count();
+    let len = match buf.get(digits) {
+        Some(b':') if digits > 0 => parse_len(&buf[..digits])?,
+        Some(_) => return Err(TNetStringError::MissingLengthPrefix),
+        None => {
+            // only check the digits so far fit, so garbage can't buffer forever
+            if digits > 0 {
+                parse_len(buf)?;
+            }
+            return Ok(FrameStatus::Incomplete(None));
+        }
+    };
+    let total = len
+        .checked_add(digits + 2)
+        .ok_or(TNetStringError::UnableToParseInt)?;
+    match buf.get(total - 1) {
+        None => Ok(FrameStatus::Incomplete(Some(total - buf.len()))),
+        Some(&tag) if tag_type(tag).is_some() => Ok(FrameStatus::Complete(total)),
+        Some(_) => Err(TNetStringError::UnknownSegmentType),
     }
 }
 
-pub fn parse(data: &[u8]) -> Result<(&[u8], TNetString), TNetStringError> {
-    let (type_tag_content, content) = split_data(data)?;
+// The spec allows at most 9 digits. Only the streaming readers hold peers
+// to it, so a bogus prefix is rejected before any payload is waited on.
+pub(crate) const MAX_LEN_DIGITS: usize = 9;
 
-    let (remain, type_tag) = take(type_tag_content, 1)?;
-    match type_tag.first() {
-        Some(b'!') => {
+fn parse_len(digits: &[u8]) -> Result<usize, TNetStringError> {
+    digits.iter().try_fold(0usize, |len, &digit| {
+        len.checked_mul(10)
+            .and_then(|len| len.checked_add(usize::from(digit - b'0')))
+            .ok_or(TNetStringError::UnableToParseInt)
+    })
+}
+
+/// Parse a whole tnetstring into a `TNetString` tree.
+pub fn parse_value(data: &[u8]) -> Result<(&[u8], TNetString), TNetStringError> {
+    let Parsed {
+        kind,
+        payload: content,
+        rest: remain,
+    } = parse(data)?;
+    match kind {
+        TNetStringType::Bool => {
             let is_true = content.len() == "true".len();
             Ok((remain, TNetString::Bool(is_true)))
         }
-        Some(b',') => {
+        TNetStringType::Str => {
             let str_content = String::from_utf8_lossy(content);
             Ok((remain, TNetString::Str(str_content.to_string())))
         }
-        Some(b'#') => String::from_utf8_lossy(content)
+        TNetStringType::Int => String::from_utf8_lossy(content)
             .parse::<i64>()
             .map(|num| (remain, TNetString::Int(num)))
             .map_err(|_| TNetStringError::UnableToParseInt),
-        Some(b'^') => String::from_utf8_lossy(content)
+        TNetStringType::Float => String::from_utf8_lossy(content)
             .parse::<f64>()
             .map(|num| (remain, TNetString::Float(num)))
             .map_err(|_| TNetStringError::UnableToParseFloat),
-        Some(b'~') => {
+        TNetStringType::Null => {
             if !content.is_empty() {
                 Err(TNetStringError::NoneZeroLengthNull)
             } else {
                 Ok((remain, TNetString::Null))
             }
         }
-        Some(b']') => {
+        TNetStringType::List => {
             let mut list_content = vec![];
             let mut remain_content = content;
 
@@ -128,7 +253,7 @@ pub fn parse(data: &[u8]) -> Result<(&[u8], TNetString), TNetStringError> {
                 if remain_content.is_empty() {
                     return Ok((remain_content, TNetString::List(list_content)));
                 }
-                match parse(remain_content) {
+                match parse_value(remain_content) {
                     Ok((remain, data)) => {
                         list_content.push(data);
                         remain_content = remain;
@@ -139,7 +264,7 @@ pub fn parse(data: &[u8]) -> Result<(&[u8], TNetString), TNetStringError> {
                 }
             }
         }
-        Some(b'}') => {
+        TNetStringType::Dict => {
             let mut dict = HashMap::new();
             let mut remain_content = content;
 
@@ -158,7 +283,159 @@ pub fn parse(data: &[u8]) -> Result<(&[u8], TNetString), TNetStringError> {
                 }
             }
         }
-        _ => Err(TNetStringError::UnknownSegmentType),
+    }
+}
+
+/// One step of `Parser`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token<'a> {
+    StartList,
+    EndList,
+    StartDict,
+    EndDict,
+    Key(&'a str),
+    Str(&'a [u8]),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Container {
+    is_dict: bool,
+    // position of the container's type tag
+    end: usize,
+    expect_key: bool,
+}
+
+/// A pull parser that walks tnetstrings as a flat series of tokens, without
+/// building anything up.
+///
+/// Each token comes with its byte range in the input: the whole frame for
+/// scalars, keys and `StartList`/`StartDict`, and the type tag for
+/// `EndList`/`EndDict`. Payloads are borrowed from the input. The input can
+/// hold any number of values one after the other. Nesting, dict keys and
+/// scalar payloads are checked as they're reached, and iteration stops
+/// after the first error.
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    stack: Vec<Container>,
+    failed: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Parser {
+            input,
+            pos: 0,
+            stack: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// How many lists and dicts the next token is nested in.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // where the next token starts, and where it has to end by
+    pub(crate) fn position(&self) -> (usize, usize) {
+        let limit = self.stack.last().map_or(self.input.len(), |top| top.end);
+        (self.pos, limit)
+    }
+
+    fn token(&mut self) -> Result<Option<(Token<'a>, Range<usize>)>, TNetStringError> {
+        let start = self.pos;
+        let limit = match self.stack.last_mut() {
+            Some(top) if start == top.end => {
+                if top.is_dict && !top.expect_key {
+                    return Err(TNetStringError::MissingDictValue);
+                }
+                let token = if top.is_dict {
+                    Token::EndDict
+                } else {
+                    Token::EndList
+                };
+                self.stack.pop();
+                self.pos += 1;
+                return Ok(Some((token, start..start + 1)));
+            }
+            Some(top) => top.end,
+            None if start == self.input.len() => return Ok(None),
+            None => self.input.len(),
+        };
+
+        let Parsed {
+            kind,
+            payload,
+            rest,
+        } = parse(&self.input[start..limit])?;
+        let tag_pos = limit - rest.len() - 1;
+        let payload_start = tag_pos - payload.len();
+        let range = start..tag_pos + 1;
+
+        if let Some(top) = self.stack.last_mut().filter(|top| top.is_dict) {
+            top.expect_key = !top.expect_key;
+            if !top.expect_key {
+                if kind != TNetStringType::Str {
+                    return Err(TNetStringError::FoundNonStringKey);
+                }
+                let key = str::from_utf8(payload).map_err(|_| TNetStringError::NonUtf8Key)?;
+                self.pos = range.end;
+                return Ok(Some((Token::Key(key), range)));
+            }
+        }
+
+        self.pos = range.end;
+        let token = match kind {
+            TNetStringType::Str => Token::Str(payload),
+            TNetStringType::Int => str::from_utf8(payload)
+                .ok()
+                .and_then(|payload| payload.parse().ok())
+                .map(Token::Int)
+                .ok_or(TNetStringError::UnableToParseInt)?,
+            TNetStringType::Float => str::from_utf8(payload)
+                .ok()
+                .and_then(|payload| payload.parse().ok())
+                .map(Token::Float)
+                .ok_or(TNetStringError::UnableToParseFloat)?,
+            TNetStringType::Bool if payload == b"true" => Token::Bool(true),
+            TNetStringType::Bool if payload == b"false" => Token::Bool(false),
+            TNetStringType::Bool => return Err(TNetStringError::InvalidBool),
+            TNetStringType::Null if payload.is_empty() => Token::Null,
+            TNetStringType::Null => return Err(TNetStringError::NoneZeroLengthNull),
+            TNetStringType::List | TNetStringType::Dict => {
+                let is_dict = kind == TNetStringType::Dict;
+                self.stack.push(Container {
+                    is_dict,
+                    end: tag_pos,
+                    expect_key: true,
+                });
+                self.pos = payload_start;
+                if is_dict {
+                    Token::StartDict
+                } else {
+                    Token::StartList
+                }
+            }
+        };
+        Ok(Some((token, range)))
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<(Token<'a>, Range<usize>), TNetStringError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.token();
+        self.failed = result.is_err();
+        result.transpose()
     }
 }
 
@@ -178,7 +455,7 @@ mod tests {
         );
         assert_eq!(
             parse_pair(b"5:hello,"),
-            Err(TNetStringError::UnableToParseInt)
+            Err(TNetStringError::MissingDictValue)
         );
     }
 
@@ -189,12 +466,12 @@ mod tests {
         });
 
         assert_eq!(
-            parse(b"16:5:hello,5:world,}"),
+            parse_value(b"16:5:hello,5:world,}"),
             Ok(("".as_bytes(), expected_hashmap))
         );
 
         assert_eq!(
-            parse(b"0:}"),
+            parse_value(b"0:}"),
             Ok(("".as_bytes(), TNetString::Dict(HashMap::new())))
         );
 
@@ -207,7 +484,7 @@ mod tests {
         });
 
         assert_eq!(
-            parse(b"40:5:hello,28:5:hello,16:5:hello,5:world,}}}"),
+            parse_value(b"40:5:hello,28:5:hello,16:5:hello,5:world,}}}"),
             Ok(("".as_bytes(), expected_hashmap))
         );
     }
@@ -215,12 +492,12 @@ mod tests {
     #[test]
     fn it_parses_list_content() {
         assert_eq!(
-            parse(b"4:true!3:bar,"),
+            parse_value(b"4:true!3:bar,"),
             Ok(("3:bar,".as_bytes(), TNetString::Bool(true)))
         );
 
         assert_eq!(
-            parse(b"12:3:foo,3:bar,]"),
+            parse_value(b"12:3:foo,3:bar,]"),
             Ok((
                 "".as_bytes(),
                 TNetString::List(vec![
@@ -229,26 +506,32 @@ mod tests {
                 ])
             ))
         );
-        assert_eq!(parse(b"0:1~"), Err(TNetStringError::UnknownSegmentType));
-        assert_eq!(parse(b"1:a~"), Err(TNetStringError::NoneZeroLengthNull));
+        assert_eq!(
+            parse_value(b"0:1~"),
+            Err(TNetStringError::UnknownSegmentType)
+        );
+        assert_eq!(
+            parse_value(b"1:a~"),
+            Err(TNetStringError::NoneZeroLengthNull)
+        );
     }
 
     #[test]
     fn it_parses_bool_content() {
         assert_eq!(
-            parse(b"4:true!"),
+            parse_value(b"4:true!"),
             Ok(("".as_bytes(), TNetString::Bool(true)))
         );
         assert_eq!(
-            parse(b"4:xyz%!"),
+            parse_value(b"4:xyz%!"),
             Ok(("".as_bytes(), TNetString::Bool(true)))
         );
         assert_eq!(
-            parse(b"5:false!"),
+            parse_value(b"5:false!"),
             Ok(("".as_bytes(), TNetString::Bool(false)))
         );
         assert_eq!(
-            parse(b"5:aaaaa!"),
+            parse_value(b"5:aaaaa!"),
             Ok(("".as_bytes(), TNetString::Bool(false)))
         );
     }
@@ -256,39 +539,39 @@ mod tests {
     #[test]
     fn it_parses_str_content() {
         assert_eq!(
-            parse(b"4:true,"),
+            parse_value(b"4:true,"),
             Ok(("".as_bytes(), TNetString::Str("true".into())))
         );
         assert_eq!(
-            parse(b"4:xyz%,"),
+            parse_value(b"4:xyz%,"),
             Ok(("".as_bytes(), TNetString::Str("xyz%".into())))
         );
         assert_eq!(
-            parse(b"5:false,"),
+            parse_value(b"5:false,"),
             Ok(("".as_bytes(), TNetString::Str("false".into())))
         );
         assert_eq!(
-            parse(b"5:true!,"),
+            parse_value(b"5:true!,"),
             Ok(("".as_bytes(), TNetString::Str("true!".into())))
         );
         assert_eq!(
-            parse(b"5:aaaaa,"),
+            parse_value(b"5:aaaaa,"),
             Ok(("".as_bytes(), TNetString::Str("aaaaa".into())))
         );
         assert_eq!(
-            parse(b"5:,,,,,,"),
+            parse_value(b"5:,,,,,,"),
             Ok(("".as_bytes(), TNetString::Str(",,,,,".into())))
         );
         assert_eq!(
-            parse(b"9:123456789,"),
+            parse_value(b"9:123456789,"),
             Ok(("".as_bytes(), TNetString::Str("123456789".into())))
         );
         assert_eq!(
-            parse(b"10:123456789A,"),
+            parse_value(b"10:123456789A,"),
             Ok(("".as_bytes(), TNetString::Str("123456789A".into())))
         );
         assert_eq!(
-            parse(b"12:3:foo,3:bar,,"),
+            parse_value(b"12:3:foo,3:bar,,"),
             Ok(("".as_bytes(), TNetString::Str("3:foo,3:bar,".into())))
         );
     }
@@ -296,86 +579,365 @@ mod tests {
     #[test]
     fn it_parses_integers_content() {
         assert_eq!(
-            parse(b"4:1000#"),
+            parse_value(b"4:1000#"),
             Ok(("".as_bytes(), TNetString::Int(1000)))
         );
-        assert_eq!(parse(b"5:00000#"), Ok(("".as_bytes(), TNetString::Int(0))));
-        assert_eq!(parse(b"2:-1#"), Ok(("".as_bytes(), TNetString::Int(-1))));
-        assert_eq!(parse(b"5:00001#"), Ok(("".as_bytes(), TNetString::Int(1))));
         assert_eq!(
-            parse(b"5:12340#"),
+            parse_value(b"5:00000#"),
+            Ok(("".as_bytes(), TNetString::Int(0)))
+        );
+        assert_eq!(
+            parse_value(b"2:-1#"),
+            Ok(("".as_bytes(), TNetString::Int(-1)))
+        );
+        assert_eq!(
+            parse_value(b"5:00001#"),
+            Ok(("".as_bytes(), TNetString::Int(1)))
+        );
+        assert_eq!(
+            parse_value(b"5:12340#"),
             Ok(("".as_bytes(), TNetString::Int(12340)))
         );
-        assert_eq!(parse(b"5:,,,,,#"), Err(TNetStringError::UnableToParseInt));
+        assert_eq!(
+            parse_value(b"5:,,,,,#"),
+            Err(TNetStringError::UnableToParseInt)
+        );
     }
 
     #[test]
     fn it_parses_float_content() {
         assert_eq!(
-            parse(b"4:1.00^"),
+            parse_value(b"4:1.00^"),
             Ok(("".as_bytes(), TNetString::Float(1.0000)))
         );
         assert_eq!(
-            parse(b"5:00000^"),
+            parse_value(b"5:00000^"),
             Ok(("".as_bytes(), TNetString::Float(0.0)))
         );
         assert_eq!(
-            parse(b"4:-1.0^"),
+            parse_value(b"4:-1.0^"),
             Ok(("".as_bytes(), TNetString::Float(-1.0)))
         );
         assert_eq!(
-            parse(b"5:00001^"),
+            parse_value(b"5:00001^"),
             Ok(("".as_bytes(), TNetString::Float(1.0)))
         );
         assert_eq!(
-            parse(b"5:123.4^"),
+            parse_value(b"5:123.4^"),
             Ok(("".as_bytes(), TNetString::Float(123.4)))
         );
-        assert_eq!(parse(b"5:,,,,,^"), Err(TNetStringError::UnableToParseFloat));
+        assert_eq!(
+            parse_value(b"5:,,,,,^"),
+            Err(TNetStringError::UnableToParseFloat)
+        );
     }
 
     #[test]
     fn it_parses_null_content() {
-        assert_eq!(parse(b"0:~"), Ok(("".as_bytes(), TNetString::Null)));
-        assert_eq!(parse(b"0:1~"), Err(TNetStringError::UnknownSegmentType));
-        assert_eq!(parse(b"1:a~"), Err(TNetStringError::NoneZeroLengthNull));
+        assert_eq!(parse_value(b"0:~"), Ok(("".as_bytes(), TNetString::Null)));
+        assert_eq!(
+            parse_value(b"0:1~"),
+            Err(TNetStringError::UnknownSegmentType)
+        );
+        assert_eq!(
+            parse_value(b"1:a~"),
+            Err(TNetStringError::NoneZeroLengthNull)
+        );
     }
 
     #[test]
-    fn it_parses_tag() {
-        assert_eq!(parse_tag(b"4:true!"), Ok(("true!".as_bytes(), 4)));
-        assert_eq!(parse_tag(b"10:false!"), Ok(("false!".as_bytes(), 10)));
+    fn it_splits_length_prefixed_data() {
+        assert_eq!(split_frame(b"4:true!"), Ok((&b"true"[..], b'!', &b""[..])));
+        assert_eq!(
+            split_frame(b"10:123456789A!rest"),
+            Ok((&b"123456789A"[..], b'!', &b"rest"[..]))
+        );
+        assert_eq!(
+            split_frame(b"10:false!"),
+            Err(TNetStringError::UnableToTake)
+        );
+        assert_eq!(
+            split_frame(b"5xhello,"),
+            Err(TNetStringError::MissingLengthPrefix)
+        );
+        assert_eq!(
+            split_frame(b"99999999999999999999999:"),
+            Err(TNetStringError::UnableToParseInt)
+        );
     }
 
     #[test]
-    fn it_takes_data() {
+    fn it_checks_length_prefixes() {
+        // the byte after the digits has to be a `:`
         assert_eq!(
-            take(b"4:true!", 2),
-            Ok(("true!".as_bytes(), "4:".as_bytes()))
+            parse_value(b"5xhello,"),
+            Err(TNetStringError::MissingLengthPrefix)
         );
+        assert_eq!(parse_value(b""), Err(TNetStringError::EmptyInput));
         assert_eq!(
-            take(b"4:true!", 7),
-            Ok(("".as_bytes(), "4:true!".as_bytes()))
+            parse_value(b"99999999999999999999999:"),
+            Err(TNetStringError::UnableToParseInt)
         );
-        assert_eq!(take(b"4:true!", 8), Err(TNetStringError::UnableToTake));
     }
 
     #[test]
-    fn it_splits_data() {
-        let (remain, parsed) = split_data(b"4:true!").unwrap();
+    fn it_parses_types() {
+        for &(input, expected) in &[
+            (&b"4:true!"[..], TNetStringType::Bool),
+            (b"5:hello,", TNetStringType::Str),
+            (b"2:42#", TNetStringType::Int),
+            (b"3:1.5^", TNetStringType::Float),
+            (b"0:~", TNetStringType::Null),
+            (b"4:1:1#]", TNetStringType::List),
+            (b"0:}", TNetStringType::Dict),
+            // only the prefix and tag are looked at
+            (b"3:abc#trailing", TNetStringType::Int),
+        ] {
+            assert_eq!(parse_type(input), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn it_reports_type_errors() {
+        assert_eq!(parse_type(b""), Err(TNetStringError::EmptyInput));
+        assert_eq!(
+            parse_type(b"5:hello?"),
+            Err(TNetStringError::UnknownSegmentType)
+        );
+        assert_eq!(parse_type(b"0:x"), Err(TNetStringError::UnknownSegmentType));
+        for input in &[&b":hello,"[..], b"hello,", b"5", b"5hello,", b"-1:x,"] {
+            assert_eq!(
+                parse_type(input),
+                Err(TNetStringError::MissingLengthPrefix),
+                "{:?}",
+                input
+            );
+        }
+        assert_eq!(parse_type(b"5:hell"), Err(TNetStringError::UnableToTake));
+        assert_eq!(parse_type(b"5:hello"), Err(TNetStringError::UnableToTake));
         assert_eq!(
-            ((
-                String::from_utf8(remain.to_vec()).unwrap(),
-                String::from_utf8(parsed.to_vec()).unwrap()
-            )),
-            (("!".into(), "true".into()))
+            parse_type(b"99999999999999999999999:"),
+            Err(TNetStringError::UnableToParseInt)
         );
+    }
 
-        assert_eq!(split_data(b"10:false!"), Err(TNetStringError::UnableToTake));
+    #[test]
+    fn it_measures_complete_frames() {
+        assert_eq!(frame_len(b"5:hello,"), Ok(FrameStatus::Complete(8)));
+        assert_eq!(frame_len(b"0:~"), Ok(FrameStatus::Complete(3)));
+        assert_eq!(frame_len(b"4:true!5:hello,"), Ok(FrameStatus::Complete(7)));
         assert_eq!(
-            split_data(b"10:123456789A!"),
-            Ok(("!".as_bytes(), "123456789A".as_bytes()))
+            frame_len(b"12:3:foo,3:bar,]0:~"),
+            Ok(FrameStatus::Complete(16))
         );
     }
 
+    #[test]
+    fn it_measures_incomplete_frames() {
+        let frame = b"12:3:foo,3:bar,]";
+        // split in the prefix
+        assert_eq!(frame_len(b""), Ok(FrameStatus::Incomplete(None)));
+        assert_eq!(frame_len(b"1"), Ok(FrameStatus::Incomplete(None)));
+        assert_eq!(frame_len(b"12"), Ok(FrameStatus::Incomplete(None)));
+        // split in the payload or before the tag
+        for split in 3..frame.len() {
+            assert_eq!(
+                frame_len(&frame[..split]),
+                Ok(FrameStatus::Incomplete(Some(frame.len() - split)))
+            );
+        }
+        assert_eq!(frame_len(b"123:"), Ok(FrameStatus::Incomplete(Some(124))));
+    }
+
+    #[test]
+    fn it_rejects_garbage_frames() {
+        for input in &[&b"x"[..], b":", b"12x", b"-1:", b" 5:hello,"] {
+            assert_eq!(
+                frame_len(input),
+                Err(TNetStringError::MissingLengthPrefix),
+                "{:?}",
+                input
+            );
+        }
+        assert_eq!(
+            frame_len(b"5:hello?"),
+            Err(TNetStringError::UnknownSegmentType)
+        );
+        assert_eq!(
+            frame_len(b"99999999999999999999999"),
+            Err(TNetStringError::UnableToParseInt)
+        );
+        assert_eq!(
+            frame_len(b"18446744073709551615:"),
+            Err(TNetStringError::UnableToParseInt)
+        );
+    }
+
+    // writes the tokens back out, taking container lengths from their
+    // nested contents
+    fn rebuild(input: &[u8]) -> Vec<u8> {
+        let mut stack = vec![Vec::new()];
+        for token in Parser::new(input) {
+            let (token, range) = token.unwrap();
+            let mut scalar = |payload: &[u8], tag: u8| {
+                let output = stack.last_mut().unwrap();
+                output.extend(payload.len().to_string().into_bytes());
+                output.push(b':');
+                output.extend(payload);
+                output.push(tag);
+            };
+            match token {
+                Token::StartList | Token::StartDict => stack.push(Vec::new()),
+                Token::EndList | Token::EndDict => {
+                    let payload = stack.pop().unwrap();
+                    let output = stack.last_mut().unwrap();
+                    output.extend(payload.len().to_string().into_bytes());
+                    output.push(b':');
+                    output.extend(payload);
+                    output.push(input[range.start]);
+                }
+                Token::Key(key) => scalar(key.as_bytes(), b','),
+                Token::Str(payload) => scalar(payload, b','),
+                Token::Int(n) => scalar(n.to_string().as_bytes(), b'#'),
+                Token::Float(n) => scalar(n.to_string().as_bytes(), b'^'),
+                Token::Bool(b) => scalar(b.to_string().as_bytes(), b'!'),
+                Token::Null => scalar(b"", b'~'),
+            }
+        }
+        stack.pop().unwrap()
+    }
+
+    #[test]
+    fn it_rebuilds_tokens_losslessly() {
+        for input in &[
+            &b"5:hello,"[..],
+            b"0:,",
+            b"4:1000#",
+            b"2:-1#",
+            b"3:1.5^",
+            b"4:true!",
+            b"5:false!",
+            b"0:~",
+            b"0:]",
+            b"0:}",
+            b"12:3:foo,3:bar,]",
+            b"16:5:hello,5:world,}",
+            b"40:5:hello,28:5:hello,16:5:hello,5:world,}}}",
+            b"42:2:id,1:7#4:body,22:1:a,1:1#1:b,7:1:c,0:~]}}",
+            b"22:1:a,14:1:1#4:true!0:~]}",
+            b"4:\xff\x00\x01\x02,",
+            b"1:1#0:~5:hello,",
+        ] {
+            assert_eq!(rebuild(input), *input);
+        }
+    }
+
+    #[test]
+    fn it_emits_tokens_with_ranges() {
+        let input = b"28:1:a,14:1:1#4:true!0:~]0:,0:}}";
+        let tokens: Vec<_> = Parser::new(input).map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::StartDict, 0..32),
+                (Token::Key("a"), 3..7),
+                (Token::StartList, 7..25),
+                (Token::Int(1), 10..14),
+                (Token::Bool(true), 14..21),
+                (Token::Null, 21..24),
+                (Token::EndList, 24..25),
+                (Token::Key(""), 25..28),
+                (Token::StartDict, 28..31),
+                (Token::EndDict, 30..31),
+                (Token::EndDict, 31..32),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_stops_at_token_errors() {
+        let errors = |input: &[u8]| {
+            let tokens: Vec<_> = Parser::new(input).collect();
+            let last = tokens.last().cloned();
+            assert!(tokens[..tokens.len() - 1].iter().all(Result::is_ok));
+            last.unwrap().unwrap_err()
+        };
+        assert_eq!(errors(b"8:1:1#1:a,}"), TNetStringError::FoundNonStringKey);
+        assert_eq!(errors(b"4:1:a,}"), TNetStringError::MissingDictValue);
+        assert_eq!(
+            errors(b"10:4:\xff\xfe\xfd\xfc,0:~}"),
+            TNetStringError::NonUtf8Key
+        );
+        assert_eq!(errors(b"8:6:hello,]"), TNetStringError::UnableToTake);
+        assert_eq!(
+            errors(b"9:5:hello,x]"),
+            TNetStringError::MissingLengthPrefix
+        );
+        assert_eq!(errors(b"3:yes!"), TNetStringError::InvalidBool);
+        assert_eq!(errors(b"1:x~"), TNetStringError::NoneZeroLengthNull);
+        assert_eq!(errors(b"2:4x#"), TNetStringError::UnableToParseInt);
+        assert_eq!(errors(b"3:1.x^"), TNetStringError::UnableToParseFloat);
+        assert_eq!(errors(b"5:hello?"), TNetStringError::UnknownSegmentType);
+        assert_eq!(errors(b"0:~junk"), TNetStringError::MissingLengthPrefix);
+    }
+
+    #[test]
+    fn it_splits_frames() {
+        for &(input, kind, payload) in &[
+            (&b"4:true!"[..], TNetStringType::Bool, &b"true"[..]),
+            (b"5:hello,", TNetStringType::Str, b"hello"),
+            (b"2:42#", TNetStringType::Int, b"42"),
+            (b"3:1.5^", TNetStringType::Float, b"1.5"),
+            (b"0:~", TNetStringType::Null, b""),
+            (b"0:]", TNetStringType::List, b""),
+            (b"0:}", TNetStringType::Dict, b""),
+        ] {
+            let mut with_rest = input.to_vec();
+            with_rest.extend(b"0:~");
+            assert_eq!(
+                parse(&with_rest),
+                Ok(Parsed {
+                    kind,
+                    payload,
+                    rest: b"0:~",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn it_splits_nested_frames() {
+        let dict = parse(b"29:5:hello,17:1:1#4:true!3:abc,]}").unwrap();
+        assert_eq!(dict.kind, TNetStringType::Dict);
+        assert_eq!(dict.rest, b"");
+        let key = parse(dict.payload).unwrap();
+        assert_eq!(
+            (key.kind, key.payload),
+            (TNetStringType::Str, &b"hello"[..])
+        );
+        let list = parse(key.rest).unwrap();
+        assert_eq!(list.kind, TNetStringType::List);
+        assert_eq!(list.rest, b"");
+
+        let mut items = Vec::new();
+        let mut payload = list.payload;
+        while !payload.is_empty() {
+            let item = parse(payload).unwrap();
+            items.push((item.kind, item.payload));
+            payload = item.rest;
+        }
+        assert_eq!(
+            items,
+            [
+                (TNetStringType::Int, &b"1"[..]),
+                (TNetStringType::Bool, b"true"),
+                (TNetStringType::Str, b"abc"),
+            ]
+        );
+
+        assert_eq!(parse(b""), Err(TNetStringError::EmptyInput));
+        assert_eq!(parse(b"x:,"), Err(TNetStringError::MissingLengthPrefix));
+        assert_eq!(parse(b"5:hello"), Err(TNetStringError::UnableToTake));
+        assert_eq!(parse(b"5:hello?"), Err(TNetStringError::UnknownSegmentType));
+    }
 }
@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::ops::Range;
+
+use crate::error::{Error, Result};
+use crate::frames::frame_error;
+use crate::parse::{parse, TNetStringType};
+use crate::ser::to_vec;
+
+/// Set the value at `path` in the encoded dict `input`, without decoding
+/// the rest of it.
+///
+/// Each segment of `path` is a key in a dict nested in the one before. The
+/// value found there is swapped for `new_value`, or if the last key is
+/// missing it's added to the end of its dict. Only the dicts along the path
+/// are walked, by hopping over length prefixes, and their prefixes are
+/// rewritten to fit; everything else is copied across untouched. A path
+/// through something other than a dict fails with `Error::UnexpectedType`,
+/// and a missing key before the last with `Error::MissingKey`, both wrapped
+/// in `Error::At` with the offset of the dict or value.
+pub fn patch<T>(input: &[u8], path: &[&str], new_value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut replacement = to_vec(new_value)?;
+    let (_, _, tag_pos) = frame(input, 0, input.len())?;
+    if tag_pos + 1 != input.len() {
+        return Err(at(tag_pos + 1, Error::UnusedParseData));
+    }
+
+    let mut dicts = Vec::new();
+    let mut target = 0..input.len();
+    for (i, key) in path.iter().enumerate() {
+        let (kind, payload_start, tag_pos) = frame(input, target.start, target.end)?;
+        if kind != TNetStringType::Dict {
+            return Err(at(
+                target.start,
+                Error::UnexpectedType {
+                    expected: TNetStringType::Dict,
+                    found: kind,
+                },
+            ));
+        }
+        dicts.push(Dict {
+            start: target.start,
+            payload_start,
+            tag_pos,
+        });
+        target = match find(input, payload_start, tag_pos, key)? {
+            Some(value) => value,
+            None if i + 1 == path.len() => {
+                let mut entry = to_vec(key)?;
+                entry.append(&mut replacement);
+                replacement = entry;
+                tag_pos..tag_pos
+            }
+            None => return Err(at(target.start, Error::MissingKey(key.to_string()))),
+        };
+    }
+
+    // work outwards, since a prefix that changes width changes the length
+    // of every dict around it too
+    let mut growth = replacement.len() as isize - target.len() as isize;
+    let mut prefixes = Vec::with_capacity(dicts.len());
+    for dict in dicts.iter().rev() {
+        let len = (dict.tag_pos - dict.payload_start) as isize + growth;
+        let prefix = format!("{}:", len);
+        growth += prefix.len() as isize - (dict.payload_start - dict.start) as isize;
+        prefixes.push(prefix);
+    }
+
+    let mut output = Vec::with_capacity((input.len() as isize + growth) as usize);
+    let mut copied = 0;
+    for (dict, prefix) in dicts.iter().zip(prefixes.iter().rev()) {
+        output.extend_from_slice(&input[copied..dict.start]);
+        output.extend_from_slice(prefix.as_bytes());
+        copied = dict.payload_start;
+    }
+    output.extend_from_slice(&input[copied..target.start]);
+    output.extend_from_slice(&replacement);
+    output.extend_from_slice(&input[target.end..]);
+    Ok(output)
+}
+
+// A dict along the path, by offsets into the input.
+struct Dict {
+    start: usize,
+    payload_start: usize,
+    tag_pos: usize,
+}
+
+fn at(offset: usize, error: Error) -> Error {
+    Error::At {
+        offset,
+        error: Box::new(error),
+    }
+}
+
+// The type, payload start and type tag position of the frame at `start`,
+// which has to end by `limit`.
+fn frame(input: &[u8], start: usize, limit: usize) -> Result<(TNetStringType, usize, usize)> {
+    let parsed = parse(&input[start..limit]).map_err(|e| at(start, frame_error(e)))?;
+    let tag_pos = limit - parsed.rest.len() - 1;
+    Ok((parsed.kind, tag_pos - parsed.payload.len(), tag_pos))
+}
+
+// Where the value for `key` is in the dict payload between `start` and
+// `end`, if it's there.
+fn find(input: &[u8], start: usize, end: usize, key: &str) -> Result<Option<Range<usize>>> {
+    let mut pos = start;
+    while pos < end {
+        let (kind, key_start, key_tag) = frame(input, pos, end)?;
+        if kind != TNetStringType::Str {
+            return Err(at(pos, Error::NonStringKey(kind)));
+        }
+        let value_start = key_tag + 1;
+        if value_start == end {
+            return Err(at(value_start, Error::ParsingMap));
+        }
+        let (_, _, value_tag) = frame(input, value_start, end)?;
+        if &input[key_start..key_tag] == key.as_bytes() {
+            return Ok(Some(value_start..value_tag + 1));
+        }
+        pos = value_tag + 1;
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::patch;
+    use crate::error::Error;
+    use crate::parse::TNetStringType;
+
+    const HEADERS: &[u8] = b"26:4:host,5:a.com,4:path,1:/,}";
+    const NESTED: &[u8] = b"50:1:a,31:1:b,16:1:c,1:1#1:d,1:2#}1:e,0:~}1:z,4:true!}";
+
+    fn at(offset: usize, error: Error) -> Error {
+        Error::At {
+            offset,
+            error: Box::new(error),
+        }
+    }
+
+    #[test]
+    fn test_same_length() {
+        assert_eq!(
+            patch(HEADERS, &["host"], &"b.com").unwrap(),
+            b"26:4:host,5:b.com,4:path,1:/,}"
+        );
+    }
+
+    #[test]
+    fn test_prefix_width_changes() {
+        let x = "x".repeat(72);
+        let input = format!("99:4:hdrs,9:1:h,2:ab,}}1:b,72:{},}}", x);
+        let longer = patch(input.as_bytes(), &["hdrs", "h"], &"abc").unwrap();
+        assert_eq!(
+            String::from_utf8(longer.clone()).unwrap(),
+            format!("101:4:hdrs,10:1:h,3:abc,}}1:b,72:{},}}", x)
+        );
+        let shorter = patch(&longer, &["hdrs", "h"], &"").unwrap();
+        assert_eq!(
+            String::from_utf8(shorter).unwrap(),
+            format!("97:4:hdrs,7:1:h,0:,}}1:b,72:{},}}", x)
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        assert_eq!(
+            patch(NESTED, &["a", "b", "c"], &42).unwrap(),
+            &b"51:1:a,32:1:b,17:1:c,2:42#1:d,1:2#}1:e,0:~}1:z,4:true!}"[..]
+        );
+        assert_eq!(
+            patch(NESTED, &["a", "b", "new"], &[1]).unwrap(),
+            &b"63:1:a,44:1:b,29:1:c,1:1#1:d,1:2#3:new,4:1:1#]}1:e,0:~}1:z,4:true!}"[..]
+        );
+        assert_eq!(
+            patch(HEADERS, &["via"], &"proxy").unwrap(),
+            &b"40:4:host,5:a.com,4:path,1:/,3:via,5:proxy,}"[..]
+        );
+        assert_eq!(patch(HEADERS, &[], &()).unwrap(), b"0:~");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            patch(HEADERS, &["host", "name"], &1),
+            Err(at(
+                10,
+                Error::UnexpectedType {
+                    expected: TNetStringType::Dict,
+                    found: TNetStringType::Str,
+                }
+            ))
+        );
+        assert_eq!(
+            patch(NESTED, &["a", "x", "c"], &1),
+            Err(at(7, Error::MissingKey("x".into())))
+        );
+        assert_eq!(
+            patch(b"26:4:host,5:a.com,4:path,1:/,}0:~", &["host"], &1),
+            Err(at(30, Error::UnusedParseData))
+        );
+        assert_eq!(
+            patch(b"8:1:1#1:a,}", &["a"], &1),
+            Err(at(2, Error::NonStringKey(TNetStringType::Int)))
+        );
+        assert_eq!(
+            patch(b"26:4:host,5:a.com", &["host"], &1),
+            Err(at(0, Error::Eof))
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize, Serializer};
+use std::fmt::{self, Debug, Display};
+
+use crate::de::skip_value;
+use crate::error::{Error, Result};
+
+// The newtype struct name that tells this crate's serializers and
+// deserializer to pass the bytes through untouched.
+pub(crate) const TOKEN: &str = "$tnetstring::private::RawValue";
+
+/// An encoded tnetstring that's carried through serialization as is.
+///
+/// Deserializing a `&RawValue` or `Box<RawValue>` checks that the next value
+/// is well formed and captures its bytes without decoding it, and serializing
+/// one writes those bytes back out verbatim. This only works with this
+/// crate's serializers and deserializer.
+#[repr(transparent)]
+pub struct RawValue {
+    bytes: [u8],
+}
+
+impl RawValue {
+    fn from_borrowed(bytes: &[u8]) -> &RawValue {
+        // `RawValue` is a `repr(transparent)` wrapper around `[u8]`
+        unsafe { &*(bytes as *const [u8] as *const RawValue) }
+    }
+
+    fn from_owned(bytes: Box<[u8]>) -> Box<RawValue> {
+        unsafe { Box::from_raw(Box::into_raw(bytes) as *mut RawValue) }
+    }
+
+    /// Wrap an already encoded tnetstring, checking that it's a single well
+    /// formed value.
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Box<RawValue>> {
+        if !skip_value(&bytes)?.is_empty() {
+            return Err(Error::UnusedParseData);
+        }
+        Ok(RawValue::from_owned(bytes.into_boxed_slice()))
+    }
+
+    /// The encoded bytes, including the length prefix and type tag.
+    pub fn get(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Clone for Box<RawValue> {
+    fn clone(&self) -> Self {
+        RawValue::from_owned(self.bytes.into())
+    }
+}
+
+impl Debug for RawValue {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_tuple("RawValue")
+            .field(&String::from_utf8_lossy(&self.bytes))
+            .finish()
+    }
+}
+
+impl Serialize for RawValue {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(TOKEN, &Bytes(&self.bytes))
+    }
+}
+
+pub(crate) struct Bytes<'a>(pub(crate) &'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct RawVisitor;
+
+impl<'de> Visitor<'de> for RawVisitor {
+    type Value = &'de RawValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a raw tnetstring")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> std::result::Result<&'de RawValue, E> {
+        Ok(RawValue::from_borrowed(v))
+    }
+}
+
+struct BoxedRawVisitor;
+
+impl<'de> Visitor<'de> for BoxedRawVisitor {
+    type Value = Box<RawValue>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a raw tnetstring")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Box<RawValue>, E> {
+        Ok(RawValue::from_owned(v.into()))
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a RawValue {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(TOKEN, RawVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Box<RawValue> {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(TOKEN, BoxedRawVisitor)
+    }
+}
+
+// Hands the bytes inside a `RawValue` to `F`, which is how the serializers
+// get at them without copying.
+pub(crate) struct Emitter<F>(pub(crate) F);
+
+fn not_raw<T>() -> Result<T> {
+    Err(ser::Error::custom("expected the bytes of a RawValue"))
+}
+
+impl<F, O> Serializer for Emitter<F>
+where
+    F: FnOnce(&[u8]) -> Result<O>,
+{
+    type Ok = O;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<O, Error>;
+    type SerializeTuple = Impossible<O, Error>;
+    type SerializeTupleStruct = Impossible<O, Error>;
+    type SerializeTupleVariant = Impossible<O, Error>;
+    type SerializeMap = Impossible<O, Error>;
+    type SerializeStruct = Impossible<O, Error>;
+    type SerializeStructVariant = Impossible<O, Error>;
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<O> {
+        (self.0)(v)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_char(self, _v: char) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<O> {
+        not_raw()
+    }
+
+    fn collect_str<T>(self, _value: &T) -> Result<O>
+    where
+        T: ?Sized + Display,
+    {
+        not_raw()
+    }
+
+    fn serialize_none(self) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<O>
+    where
+        T: ?Sized + Serialize,
+    {
+        not_raw()
+    }
+
+    fn serialize_unit(self) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<O> {
+        not_raw()
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<O>
+    where
+        T: ?Sized + Serialize,
+    {
+        not_raw()
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<O>
+    where
+        T: ?Sized + Serialize,
+    {
+        not_raw()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        not_raw()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        not_raw()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        not_raw()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        not_raw()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        not_raw()
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        not_raw()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        not_raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RawValue;
+    use crate::de::from_str;
+    use crate::error::Error;
+    use crate::len::{serialized_len, to_writer_streaming};
+    use crate::ser::to_string;
+    use crate::value::{to_value, Value};
+    use maplit::btreemap;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Envelope {
+        id: u32,
+        body: Box<RawValue>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct BorrowedEnvelope<'a> {
+        id: u32,
+        #[serde(borrow)]
+        body: &'a RawValue,
+    }
+
+    const INPUT: &str = "42:2:id,1:7#4:body,22:1:a,1:1#1:b,7:1:c,0:~]}}";
+    const BODY: &str = "22:1:a,1:1#1:b,7:1:c,0:~]}";
+
+    #[test]
+    fn test_round_trip() {
+        let envelope: Envelope = from_str(INPUT).unwrap();
+        assert_eq!(envelope.id, 7);
+        assert_eq!(envelope.body.get(), BODY.as_bytes());
+        assert_eq!(to_string(&envelope).as_deref(), Ok(INPUT));
+
+        let borrowed: BorrowedEnvelope = from_str(INPUT).unwrap();
+        assert_eq!(borrowed.id, 7);
+        assert_eq!(borrowed.body.get(), BODY.as_bytes());
+        assert_eq!(
+            borrowed.body.get().as_ptr(),
+            INPUT[INPUT.len() - BODY.len() - 1..].as_ptr()
+        );
+
+        assert_eq!(serialized_len(&envelope), Ok(INPUT.len()));
+        let mut streamed = Vec::new();
+        to_writer_streaming(&mut streamed, &envelope).unwrap();
+        assert_eq!(streamed, INPUT.as_bytes());
+        assert_eq!(
+            to_value(&envelope).unwrap()["body"]["b"][0],
+            Value::from("c")
+        );
+    }
+
+    #[test]
+    fn test_map_values() {
+        let input = "23:1:x,4:1:1#]1:y,5:hello,}";
+        let map: BTreeMap<String, Box<RawValue>> = from_str(input).unwrap();
+        assert_eq!(map["x"].get(), b"4:1:1#]");
+        assert_eq!(map["y"].get(), b"5:hello,");
+        assert_eq!(to_string(&map).as_deref(), Ok(input));
+
+        let spliced = btreemap! {"raw" => RawValue::from_vec(b"2:42#".to_vec()).unwrap()};
+        assert_eq!(to_string(&spliced).as_deref(), Ok("11:3:raw,2:42#}"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for input in &[
+            "22:2:id,1:7#4:body,3:1:a}}",
+            "27:2:id,1:7#4:body,8:1:1#1:1#}}",
+            "22:2:id,1:7#4:body,3:abc!}",
+            "22:2:id,1:7#4:body,3:abc?}",
+            "23:2:id,1:7#4:body,4:2:1#]}",
+            "20:2:id,1:7#4:body,1:x~}",
+        ] {
+            assert!(from_str::<Envelope>(input).is_err(), "{}", input);
+        }
+        assert_eq!(
+            RawValue::from_vec(b"1:1#1:2#".to_vec()).map(|_| ()),
+            Err(Error::UnusedParseData)
+        );
+        assert!(RawValue::from_vec(b"5:1:1#]".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,682 @@
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read};
+use std::marker::PhantomData;
+
+use serde::de::{DeserializeOwned, DeserializeSeed};
+
+use crate::de::{from_slice, from_slice_seed};
+use crate::error::{Error, Result};
+use crate::parse::{tag_type, MAX_LEN_DIGITS};
+
+/// Read one tnetstring from `reader` and deserialize it.
+///
+/// Exactly one frame is read, anything after it is left in the reader. The
+/// length prefix is read a byte at a time, so wrap unbuffered readers like
+/// files and sockets in a `BufReader`.
+///
+/// The prefix isn't trusted for allocation: the payload buffer grows as
+/// bytes arrive, and prefixes longer than the 9 digits the spec allows fail
+/// with `Error::ParsingLength`. To also cap how much a frame may claim, use
+/// `from_reader_with_limit`.
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    from_reader_seed(PhantomData, reader)
+}
+
+/// Like `from_reader`, but fails with `Error::InputLimitExceeded` if the
+/// frame is longer than `max_len` bytes. That's checked as soon as the
+/// length prefix has been read, before any of the payload. `0` means no
+/// limit.
+pub fn from_reader_with_limit<R, T>(reader: R, max_len: usize) -> Result<T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    read_one(PhantomData, reader, max_len)
+}
+
+/// Like `from_reader`, but driven by a `DeserializeSeed`. The seed can't
+/// borrow from the input, which only lives as long as the call.
+pub fn from_reader_seed<R, S, T>(seed: S, reader: R) -> Result<T>
+where
+    R: Read,
+    S: for<'de> DeserializeSeed<'de, Value = T>,
+{
+    read_one(seed, reader, 0)
+}
+
+fn read_one<R, S, T>(seed: S, mut reader: R, max_len: usize) -> Result<T>
+where
+    R: Read,
+    S: for<'de> DeserializeSeed<'de, Value = T>,
+{
+    let mut frame = Vec::new();
+    if !read_frame(&mut reader, max_len, &mut frame)? {
+        return Err(Error::Eof);
+    }
+    from_slice_seed(seed, &frame)
+}
+
+/// Iterate over back-to-back tnetstrings read from `reader`, deserializing
+/// each into a `T`.
+///
+/// Only one frame is held in memory at a time. The iterator ends when
+/// `reader` runs out between frames; running out in the middle of one
+/// yields `Error::Eof`. After any error the iterator stops.
+pub fn read_iter<R, T>(reader: R) -> ReadIter<R, T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    ReadIter {
+        reader: BufReader::new(reader),
+        frame: Vec::new(),
+        max_frame_len: 0,
+        separators: Vec::new(),
+        done: false,
+        output: PhantomData,
+    }
+}
+
+/// The iterator returned by `read_iter`.
+pub struct ReadIter<R, T> {
+    reader: BufReader<R>,
+    frame: Vec<u8>,
+    max_frame_len: usize,
+    separators: Vec<u8>,
+    done: bool,
+    output: PhantomData<fn() -> T>,
+}
+
+impl<R, T> ReadIter<R, T> {
+    /// Fail with `Error::InputLimitExceeded` on any frame longer than this
+    /// many bytes, as soon as its length prefix has been read. `0`, the
+    /// default, means no limit.
+    pub fn max_frame_len(mut self, limit: usize) -> Self {
+        self.max_frame_len = limit;
+        self
+    }
+
+    /// Skip any of these bytes between frames, such as `b"\r\n"` for one
+    /// frame per line. None are skipped by default.
+    pub fn separators(mut self, separators: &[u8]) -> Self {
+        self.separators = separators.to_vec();
+        self
+    }
+}
+
+impl<R, T> Iterator for ReadIter<R, T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = match skip_separators(&mut self.reader, &self.separators)
+            .and_then(|()| read_frame(&mut self.reader, self.max_frame_len, &mut self.frame))
+        {
+            Ok(true) => from_slice(&self.frame),
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(error) => Err(error),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+/// Reads the values of a stream of tnetstrings one at a time, stepping into
+/// lists and dicts, so that a large string can be streamed out of its frame
+/// rather than held in memory.
+///
+/// Since a tnetstring's type tag comes after its payload, stepping into a
+/// list or dict, or reading a string, only checks the tag once everything
+/// in it has been read. Errors are `Error::At` with the offset into the
+/// stream.
+pub struct FrameReader<R> {
+    reader: BufReader<R>,
+    // bytes read so far
+    position: u64,
+    // for each list and dict stepped into, where its payload ends and the
+    // tag it should have
+    open: Vec<(u64, u8)>,
+    // the bytes left of a string being streamed, before its tag
+    string_left: Option<u64>,
+    frame: Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        FrameReader {
+            reader: BufReader::new(reader),
+            position: 0,
+            open: Vec::new(),
+            string_left: None,
+            frame: Vec::new(),
+        }
+    }
+
+    /// How many bytes of the stream have been read.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Whether there's another value in the list or dict stepped into last,
+    /// or at the top level, anything left in the stream.
+    pub fn has_next(&mut self) -> Result<bool> {
+        self.finish_string()?;
+        match self.open.last() {
+            Some(&(end, _)) => Ok(self.position < end),
+            None => Ok(!self.reader.fill_buf()?.is_empty()),
+        }
+    }
+
+    /// Step into the list that's next.
+    pub fn enter_list(&mut self) -> Result<()> {
+        self.enter(b']')
+    }
+
+    /// Step into the dict that's next. Its keys and values come one after
+    /// the other.
+    pub fn enter_dict(&mut self) -> Result<()> {
+        self.enter(b'}')
+    }
+
+    /// Step out of the list or dict stepped into last, once all of its
+    /// values have been read, and check its tag.
+    pub fn leave(&mut self) -> Result<()> {
+        self.finish_string()?;
+        let (end, tag) = match self.open.last() {
+            Some(&open) => open,
+            None => return Err(Error::Nesting("leave without enter_list or enter_dict")),
+        };
+        if self.position < end {
+            return Err(self.at(Error::Nesting("values left before leave")));
+        }
+        self.open.pop();
+        self.expect_tag(tag)
+    }
+
+    /// Read the next value whole and deserialize it.
+    pub fn next_value<T>(&mut self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let start = self.position;
+        let len = self.read_prefix()?;
+        self.frame.push(b':');
+        let rest = len as u64 + 1;
+        if (&mut self.reader).take(rest).read_to_end(&mut self.frame)? as u64 != rest {
+            return Err(self.at(Error::Eof));
+        }
+        self.position += rest;
+        from_slice(&self.frame).map_err(|error| Error::At {
+            offset: start as usize,
+            error: Box::new(error),
+        })
+    }
+
+    /// The payload of the string that's next, as a reader.
+    ///
+    /// Its tag is checked once the payload has been read to the end, which
+    /// fails the read that got there. If the reader is dropped sooner, the
+    /// rest of the payload is skipped on the next call to the `FrameReader`.
+    pub fn next_string_reader(&mut self) -> Result<StringReader<'_, R>> {
+        let len = self.read_prefix()?;
+        self.string_left = Some(len as u64);
+        Ok(StringReader { frames: self })
+    }
+
+    fn enter(&mut self, tag: u8) -> Result<()> {
+        let len = self.read_prefix()?;
+        self.open.push((self.position + len as u64, tag));
+        Ok(())
+    }
+
+    // Reads the next value's length prefix into `frame` and checks that the
+    // value fits in the list or dict it's in.
+    fn read_prefix(&mut self) -> Result<usize> {
+        self.finish_string()?;
+        let start = self.position;
+        let at = |error| Error::At {
+            offset: start as usize,
+            error: Box::new(error),
+        };
+        let len = read_prefix(&mut self.reader, &mut self.frame)
+            .map_err(at)?
+            .ok_or_else(|| at(Error::Eof))?;
+        self.position += self.frame.len() as u64 + 1;
+        if let Some(&(end, _)) = self.open.last() {
+            if self.position + len as u64 + 1 > end {
+                return Err(at(Error::Eof));
+            }
+        }
+        Ok(len)
+    }
+
+    // Skips whatever is left of a string being streamed and checks its tag.
+    fn finish_string(&mut self) -> Result<()> {
+        let left = match self.string_left {
+            Some(left) => left,
+            None => return Ok(()),
+        };
+        let skipped = io::copy(&mut (&mut self.reader).take(left), &mut io::sink())?;
+        self.position += skipped;
+        if skipped != left {
+            self.string_left = Some(left - skipped);
+            return Err(self.at(Error::Eof));
+        }
+        self.string_left = None;
+        self.expect_tag(b',')
+    }
+
+    fn expect_tag(&mut self, tag: u8) -> Result<()> {
+        let found = match read_byte(&mut self.reader)? {
+            Some(found) => found,
+            None => return Err(self.at(Error::Eof)),
+        };
+        if found != tag {
+            let error = match (tag_type(tag), tag_type(found)) {
+                (Some(expected), Some(found)) => Error::UnexpectedType { expected, found },
+                _ => Error::UnknownSegmentType,
+            };
+            return Err(self.at(error));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn at(&self, error: Error) -> Error {
+        Error::At {
+            offset: self.position as usize,
+            error: Box::new(error),
+        }
+    }
+}
+
+/// The payload of a string, from `FrameReader::next_string_reader`.
+pub struct StringReader<'a, R> {
+    frames: &'a mut FrameReader<R>,
+}
+
+impl<R> StringReader<'_, R> {
+    /// How many bytes of the payload are left to read.
+    pub fn remaining(&self) -> u64 {
+        self.frames.string_left.unwrap_or(0)
+    }
+}
+
+impl<R: Read> Read for StringReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = match self.frames.string_left {
+            Some(left) => left,
+            None => return Ok(0),
+        };
+        let max = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let n = self.frames.reader.read(&mut buf[..max])?;
+        if n == 0 && max != 0 {
+            return Err(self.frames.at(Error::Eof).into());
+        }
+        self.frames.position += n as u64;
+        self.frames.string_left = Some(left - n as u64);
+        if left == n as u64 {
+            self.frames.finish_string()?;
+        }
+        Ok(n)
+    }
+}
+
+// Consumes any run of `separators` bytes between frames.
+fn skip_separators<R: BufRead>(reader: &mut R, separators: &[u8]) -> Result<()> {
+    loop {
+        let buf = reader.fill_buf()?;
+        let count = buf
+            .iter()
+            .take_while(|byte| separators.contains(byte))
+            .count();
+        if count == 0 {
+            return Ok(());
+        }
+        reader.consume(count);
+    }
+}
+
+// Reads the next frame into `frame`, or returns `false` if `reader` was
+// already at its end. `max_len` of `0` means no limit.
+fn read_frame<R: Read>(reader: &mut R, max_len: usize, frame: &mut Vec<u8>) -> Result<bool> {
+    let len = match read_prefix(reader, frame)? {
+        Some(len) => len,
+        None => return Ok(false),
+    };
+    let total = frame.len() + len + 2;
+    if max_len != 0 && total > max_len {
+        return Err(Error::InputLimitExceeded {
+            limit: max_len,
+            size: total,
+        });
+    }
+    frame.push(b':');
+    // grows as the bytes arrive rather than trusting the prefix up front
+    let rest = len as u64 + 1;
+    if reader.take(rest).read_to_end(frame)? as u64 != rest {
+        return Err(Error::Eof);
+    }
+    Ok(true)
+}
+
+// Reads a length prefix and its `:`, leaving its digits in `digits`, or
+// returns `None` if `reader` was already at its end.
+fn read_prefix<R: Read>(reader: &mut R, digits: &mut Vec<u8>) -> Result<Option<usize>> {
+    digits.clear();
+    loop {
+        match read_byte(reader)? {
+            None if digits.is_empty() => return Ok(None),
+            None => return Err(Error::Eof),
+            Some(b':') if !digits.is_empty() => break,
+            Some(digit @ b'0'..=b'9') if digits.len() < MAX_LEN_DIGITS => digits.push(digit),
+            Some(b'0'..=b'9') => return Err(Error::ParsingLength),
+            Some(_) => return Err(Error::LengthNotFound),
+        }
+    }
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .map(Some)
+        .ok_or(Error::ParsingLength)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_reader, from_reader_seed, from_reader_with_limit, read_frame, read_iter};
+    use crate::error::{Error, Result};
+    use serde::Deserialize;
+    use std::io::{Cursor, Read};
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        name: String,
+    }
+
+    const RECORDS: &[u8] =
+        b"22:2:id,1:1#4:name,3:ann,}22:2:id,1:2#4:name,3:bob,}22:2:id,1:3#4:name,3:cat,}";
+
+    fn record(id: u32, name: &str) -> Record {
+        Record {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read_iter() {
+        let records: Vec<Result<Record>> = read_iter(Cursor::new(RECORDS)).collect();
+        assert_eq!(
+            records,
+            vec![
+                Ok(record(1, "ann")),
+                Ok(record(2, "bob")),
+                Ok(record(3, "cat"))
+            ]
+        );
+        assert_eq!(read_iter::<_, Record>(Cursor::new(b"")).next(), None);
+    }
+
+    #[test]
+    fn test_read_iter_separators() {
+        let input = b"3:one,\r\n\r\n4:a\r\nb,\r\n3:two,";
+        let records: Vec<Result<String>> = read_iter(Cursor::new(&input[..]))
+            .separators(b"\r\n")
+            .collect();
+        assert_eq!(
+            records,
+            vec![Ok("one".into()), Ok("a\r\nb".into()), Ok("two".into())]
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let truncated = &RECORDS[..RECORDS.len() - 5];
+        let records: Vec<Result<Record>> = read_iter(Cursor::new(truncated)).collect();
+        assert_eq!(
+            records,
+            vec![Ok(record(1, "ann")), Ok(record(2, "bob")), Err(Error::Eof)]
+        );
+
+        // cut off in the length prefix
+        let records: Vec<Result<String>> = read_iter(Cursor::new(b"0:,12")).collect();
+        assert_eq!(records, vec![Ok(String::new()), Err(Error::Eof)]);
+    }
+
+    #[test]
+    fn test_errors_stop_iteration() {
+        let records: Vec<Result<u32>> = read_iter(Cursor::new(b"1:1#x:1#1:2#")).collect();
+        assert_eq!(records, vec![Ok(1), Err(Error::LengthNotFound)]);
+
+        let mut iter = read_iter::<_, String>(Cursor::new(b"5:hello,999999:")).max_frame_len(64);
+        assert_eq!(iter.next(), Some(Ok("hello".to_string())));
+        assert_eq!(
+            iter.next(),
+            Some(Err(Error::InputLimitExceeded {
+                limit: 64,
+                size: 1_000_007,
+            }))
+        );
+        assert_eq!(iter.next(), None);
+    }
+
+    // claims a huge frame, then dries up after a few bytes
+    struct Liar {
+        sent: usize,
+    }
+
+    impl Read for Liar {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let data = b"999999999:abc";
+            let n = buf.len().min(data.len() - self.sent);
+            buf[..n].copy_from_slice(&data[self.sent..self.sent + n]);
+            self.sent += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_huge_prefix() {
+        let mut frame = Vec::new();
+        assert_eq!(
+            read_frame(&mut Liar { sent: 0 }, 0, &mut frame),
+            Err(Error::Eof)
+        );
+        assert!(frame.capacity() < 1024, "{}", frame.capacity());
+
+        assert_eq!(from_reader::<_, String>(Liar { sent: 0 }), Err(Error::Eof));
+        assert_eq!(
+            from_reader_with_limit::<_, String>(Liar { sent: 0 }, 1024),
+            Err(Error::InputLimitExceeded {
+                limit: 1024,
+                size: 999_999_999 + 11,
+            })
+        );
+        assert_eq!(
+            from_reader_with_limit::<_, String>(Cursor::new(b"5:hello,"), 8),
+            Ok("hello".to_string())
+        );
+        let mut iter = read_iter::<_, String>(Liar { sent: 0 }).max_frame_len(1024);
+        assert_eq!(
+            iter.next(),
+            Some(Err(Error::InputLimitExceeded {
+                limit: 1024,
+                size: 999_999_999 + 11,
+            }))
+        );
+
+        assert_eq!(
+            from_reader::<_, String>(Cursor::new(b"0000000005:hello,")),
+            Err(Error::ParsingLength)
+        );
+        assert_eq!(
+            from_reader::<_, String>(Cursor::new(b"000000005:hello,")),
+            Ok("hello".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let mut reader = Cursor::new(RECORDS);
+        assert_eq!(from_reader(&mut reader), Ok(record(1, "ann")));
+        assert_eq!(from_reader(&mut reader), Ok(record(2, "bob")));
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &RECORDS[52..]);
+
+        assert_eq!(from_reader::<_, u32>(Cursor::new(b"")), Err(Error::Eof));
+    }
+
+    #[test]
+    fn test_seed() {
+        use serde::de::{DeserializeSeed, Deserializer};
+
+        // renumbers records read from a shard
+        struct Shard(u32);
+
+        impl<'de> DeserializeSeed<'de> for Shard {
+            type Value = Record;
+
+            fn deserialize<D>(self, deserializer: D) -> std::result::Result<Record, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let read = Record::deserialize(deserializer)?;
+                Ok(record(read.id + self.0, &read.name))
+            }
+        }
+
+        let mut reader = Cursor::new(RECORDS);
+        assert_eq!(
+            from_reader_seed(Shard(100), &mut reader),
+            Ok(record(101, "ann"))
+        );
+        assert_eq!(
+            from_reader_seed(Shard(200), &mut reader),
+            Ok(record(202, "bob"))
+        );
+    }
+
+    #[test]
+    fn test_string_reader() -> Result<()> {
+        use super::FrameReader;
+        use serde::Serialize;
+
+        #[derive(Serialize)]
+        struct Upload {
+            name: &'static str,
+            body: String,
+            size: usize,
+        }
+
+        let body: String = (0..3_000_000u32)
+            .map(|i| char::from(b'a' + (i * 7 % 26) as u8))
+            .collect();
+        let mut input = crate::ser::to_vec(&Upload {
+            name: "file.txt",
+            body: body.clone(),
+            size: body.len(),
+        })?;
+        input.extend_from_slice(b"1:1#");
+
+        let mut frames = FrameReader::new(Cursor::new(&input));
+        frames.enter_dict()?;
+        assert_eq!(frames.next_value::<String>()?, "name");
+        assert_eq!(frames.next_value::<String>()?, "file.txt");
+        assert_eq!(frames.next_value::<String>()?, "body");
+        let mut reader = frames.next_string_reader()?;
+        assert_eq!(reader.remaining(), body.len() as u64);
+        let mut copied = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            let n = reader.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            copied.extend_from_slice(&chunk[..n]);
+        }
+        assert!(copied == body.as_bytes());
+        assert_eq!(frames.next_value::<String>()?, "size");
+        assert_eq!(frames.next_value::<usize>()?, body.len());
+        assert!(!frames.has_next()?);
+        frames.leave()?;
+        assert!(frames.has_next()?);
+        assert_eq!(frames.next_value::<u32>()?, 1);
+        assert!(!frames.has_next()?);
+        assert_eq!(frames.position(), input.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_reader_errors() -> Result<()> {
+        use super::FrameReader;
+        use crate::parse::TNetStringType;
+
+        // the tag is only checked at the end
+        let mut frames = FrameReader::new(&b"5:hello#"[..]);
+        let mut reader = frames.next_string_reader()?;
+        let mut chunk = [0; 3];
+        assert_eq!(reader.read(&mut chunk)?, 3);
+        let error = reader.read(&mut chunk).unwrap_err();
+        assert_eq!(
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<Error>()
+                .map(|e| *e)
+                .ok(),
+            Some(Error::At {
+                offset: 7,
+                error: Box::new(Error::UnexpectedType {
+                    expected: TNetStringType::Str,
+                    found: TNetStringType::Int,
+                }),
+            })
+        );
+
+        // a reader dropped early is skipped past
+        let mut frames = FrameReader::new(&b"15:5:hello,4:true!]"[..]);
+        frames.enter_list()?;
+        frames.next_string_reader()?.read_exact(&mut chunk)?;
+        assert!(frames.next_value::<bool>()?);
+        frames.leave()?;
+
+        // a value that claims more than its list holds
+        let mut frames = FrameReader::new(&b"8:6:hello,]"[..]);
+        frames.enter_list()?;
+        assert_eq!(
+            frames.next_string_reader().err(),
+            Some(Error::At {
+                offset: 2,
+                error: Box::new(Error::Eof),
+            })
+        );
+        Ok(())
+    }
+}
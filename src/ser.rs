@@ -24,51 +24,527 @@
 // IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use serde;
-use serde::{ser, Serialize};
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::{self, Display, LowerExp};
+use std::io;
+use std::mem;
+use std::str;
 
 use crate::error::{Error, Result};
+use crate::parse::TNetStringType;
+use crate::raw;
 
+#[derive(Default)]
 pub struct Serializer {
-    output: Vec<String>,
+    output: Vec<u8>,
+    // length of `output` before anything was serialized into it
+    base: usize,
+    // offsets into `output` where the payloads of the containers that are
+    // still being written start
+    stack: Vec<usize>,
+    // in canonical mode, the offsets where the entries of the open maps start
+    // and, for each open map, where its entries start in `entries`
+    entries: Vec<usize>,
+    maps: Vec<usize>,
+    // where the last `None` was written, to tell it apart from other nulls
+    // when skipping `None` fields
+    none_at: Option<usize>,
+    config: SerializerConfig,
 }
 
-// TODO(sbdchd): add a to_bytes func
-pub fn to_string<T>(value: &T) -> Result<String>
+/// Options for `to_string_with`, `to_vec_with` and `Serializer::with_config`.
+///
+/// The default configuration produces the same output as `to_string`.
+#[derive(Clone, Debug)]
+pub struct SerializerConfig {
+    capacity: usize,
+    compact: bool,
+    stringify_keys: bool,
+    non_finite_floats: NonFiniteFloats,
+    canonical: bool,
+    canonical_structs: bool,
+    python_compat: bool,
+    human_readable: bool,
+    skip_none_fields: bool,
+    unit_variants_as_dicts: bool,
+    max_output_bytes: usize,
+}
+
+impl Default for SerializerConfig {
+    fn default() -> Self {
+        SerializerConfig {
+            capacity: 0,
+            compact: false,
+            stringify_keys: false,
+            non_finite_floats: NonFiniteFloats::default(),
+            canonical: false,
+            canonical_structs: false,
+            python_compat: false,
+            human_readable: true,
+            skip_none_fields: false,
+            unit_variants_as_dicts: false,
+            max_output_bytes: 0,
+        }
+    }
+}
+
+/// How NaN and infinite floats are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NonFiniteFloats {
+    /// Fail with `Error::NonFiniteFloat`.
+    Error,
+    /// Write them as null, `0:~`.
+    Null,
+    /// Write them as the floats `3:nan^`, `3:inf^` and `4:-inf^`, which
+    /// `from_str` reads back. This is the default.
+    #[default]
+    Literal,
+}
+
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer {
-        output: vec![String::new()],
-    };
+    to_vec_with(value, &SerializerConfig::default())
+}
+
+pub fn to_vec_with<T>(value: &T, config: &SerializerConfig) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::with_config(Vec::new(), config.clone());
     value.serialize(&mut serializer)?;
-    serializer
-        .output
-        .last()
-        .ok_or(Error::StackProblem)
-        .map(String::from)
+    serializer.into_inner()
+}
+
+pub fn to_writer<W, T>(mut writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    writer.write_all(&to_vec(value)?)?;
+    Ok(())
+}
+
+/// Write the serialized value to a `fmt::Write`, such as a `String` or a
+/// `fmt::Formatter`.
+///
+/// Like `to_writer_streaming` the output isn't held in memory; the value is
+/// serialized twice, the first time to work out container lengths. Fails
+/// before writing anything if the output would contain byte strings that
+/// aren't valid UTF-8, with `Error::InvalidUtf8` giving the offset of the
+/// first bad byte in the output, and with `Error::Fmt` if the writer fails.
+pub fn to_fmt_writer<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: ?Sized + fmt::Write,
+    T: Serialize,
+{
+    crate::len::to_fmt_writer(writer, value)
+}
+
+/// Write each value of `iter` as its own top-level tnetstring, one after the
+/// other, and return how many were written.
+///
+/// `Deserializer::into_iter` reads them back. If a value fails, the values
+/// before it have already been written and the error is wrapped in
+/// `Error::Item` with the value's index.
+pub fn to_writer_seq<W, I>(writer: W, iter: I) -> Result<u64>
+where
+    W: io::Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    to_writer_seq_separated(writer, iter, b"")
+}
+
+/// Like `to_writer_seq`, but write `separator` after each value, such as
+/// `b"\n"` for one tnetstring per line. `StreamDeserializer::separators`
+/// skips it again when reading.
+pub fn to_writer_seq_separated<W, I>(mut writer: W, iter: I, separator: &[u8]) -> Result<u64>
+where
+    W: io::Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut buffer = Vec::new();
+    let mut count = 0;
+    for value in iter {
+        let at_index = move |error| Error::Item {
+            index: count,
+            error: Box::new(error),
+        };
+        buffer.clear();
+        let mut serializer = Serializer::new(buffer);
+        value.serialize(&mut serializer).map_err(at_index)?;
+        buffer = serializer.into_inner().map_err(at_index)?;
+        buffer.extend_from_slice(separator);
+        writer
+            .write_all(&buffer)
+            .map_err(|error| at_index(error.into()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: Serialize,
+{
+    Ok(String::from_utf8(to_vec(value)?)?)
+}
+
+pub fn to_string_with<T>(value: &T, config: &SerializerConfig) -> Result<String>
+where
+    T: Serialize,
+{
+    Ok(String::from_utf8(to_vec_with(value, config)?)?)
+}
+
+/// Append the serialized value to `output`, reusing its allocation.
+///
+/// On error `output` is left as it was. Byte strings that aren't valid UTF-8
+/// fail with `Error::InvalidUtf8`, its offset counted from the start of the
+/// appended output.
+pub fn to_string_into<T>(value: &T, output: &mut String) -> Result<()>
+where
+    T: Serialize,
+{
+    let start = output.len();
+    let mut serializer = Serializer::new(mem::take(output).into_bytes());
+    let mut result = value.serialize(&mut serializer);
+    if result.is_ok() && !serializer.stack.is_empty() {
+        result = Err(Error::StackProblem);
+    }
+    let mut bytes = serializer.output;
+    if result.is_ok() {
+        if let Err(error) = str::from_utf8(&bytes[start..]) {
+            result = Err(Error::InvalidUtf8 {
+                offset: error.valid_up_to(),
+                error,
+            });
+        }
+    }
+    if result.is_err() {
+        bytes.truncate(start);
+    }
+    *output = String::from_utf8(bytes)?;
+    result
+}
+
+/// Serialize structs as lists of their field values in declaration order
+/// rather than as dicts keyed by field name.
+///
+/// `from_str` accepts either form, so the output round-trips.
+pub fn to_string_compact<T>(value: &T) -> Result<String>
+where
+    T: Serialize,
+{
+    to_string_with(value, &SerializerConfig::new().compact(true))
+}
+
+impl SerializerConfig {
+    pub fn new() -> Self {
+        SerializerConfig::default()
+    }
+
+    /// Reserve room for this many bytes in the output before serializing.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Write structs as lists of their field values, see `to_string_compact`.
+    pub fn compact(mut self, enabled: bool) -> Self {
+        self.compact = enabled;
+        self
+    }
+
+    /// Write integer, bool and char map keys as strings.
+    ///
+    /// Dict keys have to be strings, so by default a map with any other kind
+    /// of key fails with `Error::NonStringKey`. Struct fields are always
+    /// strings and aren't affected.
+    pub fn stringify_keys(mut self, enabled: bool) -> Self {
+        self.stringify_keys = enabled;
+        self
+    }
+
+    /// Choose how NaN and infinite floats are written.
+    pub fn non_finite_floats(mut self, policy: NonFiniteFloats) -> Self {
+        self.non_finite_floats = policy;
+        self
+    }
+
+    /// Write the entries of every map sorted bytewise by their encoded keys,
+    /// so equal values always serialize to the same bytes regardless of the
+    /// map's iteration order.
+    ///
+    /// Struct fields keep their declaration order.
+    pub fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    // sort struct fields the way `canonical` sorts map entries, as they'd be
+    // once read back into a `Value`; used by `digest`
+    pub(crate) fn canonical_structs(mut self, enabled: bool) -> Self {
+        self.canonical_structs = enabled;
+        self
+    }
+
+    /// Match the output of the reference Python `tnetstring` package.
+    ///
+    /// The only difference is how finite floats are written. Python uses
+    /// `repr`, which always includes a decimal point (`3:1.0^` rather than
+    /// `1:1^`) and switches to scientific notation with a signed, two digit
+    /// exponent outside of `1e-4 <= |x| < 1e16` (`5:1e+16^`, `5:1e-05^`).
+    /// NaN and infinities are already written as Python writes them, and
+    /// dict keys are always strings in both.
+    pub fn python_compat(mut self, enabled: bool) -> Self {
+        self.python_compat = enabled;
+        self
+    }
+
+    /// Set what `is_human_readable` reports to `Serialize` impls, `true` by
+    /// default.
+    ///
+    /// Types like `IpAddr` and `Uuid` use a more compact encoding when it's
+    /// `false`. The deserializer has to be configured the same way with
+    /// `Deserializer::human_readable` to read it back.
+    pub fn human_readable(mut self, enabled: bool) -> Self {
+        self.human_readable = enabled;
+        self
+    }
+
+    /// Leave out struct fields whose value is `None`.
+    ///
+    /// Map entries and list elements are always written, and so are the
+    /// fields of compact structs since those are matched up by position.
+    pub fn skip_none_fields(mut self, enabled: bool) -> Self {
+        self.skip_none_fields = enabled;
+        self
+    }
+
+    /// Write unit variants as `{name: null}` dicts, the same shape as the
+    /// other variants, rather than as bare strings.
+    ///
+    /// The deserializer reads either form. Unit variants used as map keys
+    /// are still written as strings.
+    pub fn unit_variants_as_dicts(mut self, enabled: bool) -> Self {
+        self.unit_variants_as_dicts = enabled;
+        self
+    }
+
+    /// Fail with `Error::OutputLimitExceeded` as soon as the output would
+    /// grow past this many bytes. `0`, the default, means no limit.
+    pub fn max_output_bytes(mut self, limit: usize) -> Self {
+        self.max_output_bytes = limit;
+        self
+    }
 }
 
 // due to the structure of serde serializers being broken into multiple steps we
-// use a stack since we are required to know the length of a sequences and dicts
-// before we can serialize them.
+// don't know the length of a sequence or dict until all of its elements have
+// been written, so we remember where each container's payload starts and
+// insert the length prefix in front of it once the container is done.
 impl Serializer {
-    fn add_to_output(&mut self, v: &str) {
-        if let Some(val) = self.output.last_mut() {
-            val.push_str(v);
+    /// Create a serializer that appends to `output`.
+    ///
+    /// The buffer can be taken back with `into_inner`, cleared and handed to
+    /// a new serializer to avoid allocating for every value.
+    pub fn new(output: Vec<u8>) -> Self {
+        Serializer::with_config(output, SerializerConfig::default())
+    }
+
+    pub fn with_config(mut output: Vec<u8>, config: SerializerConfig) -> Self {
+        output.reserve(config.capacity);
+        Serializer {
+            base: output.len(),
+            output,
+            config,
+            ..Serializer::default()
+        }
+    }
+
+    /// Return the output buffer.
+    ///
+    /// Fails with `Error::StackProblem` if a container was started but never
+    /// ended, since the output would be missing its length prefix.
+    pub fn into_inner(self) -> Result<Vec<u8>> {
+        if !self.stack.is_empty() {
+            return Err(Error::StackProblem);
+        }
+        Ok(self.output)
+    }
+
+    // checked before the output grows so a value that is too large fails
+    // before it is written
+    fn check_limit(&self, additional: usize) -> Result<()> {
+        let limit = self.config.max_output_bytes;
+        let attempted = self.output.len() - self.base + additional;
+        if limit != 0 && attempted > limit {
+            return Err(Error::OutputLimitExceeded { limit, attempted });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_to_output(&mut self, v: &[u8]) -> Result<()> {
+        self.check_limit(v.len())?;
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    // writes `payload` with its length prefix and type tag
+    fn add_scalar(&mut self, payload: &[u8], tag: u8) -> Result<()> {
+        let mut len = itoa::Buffer::new();
+        let prefix = len.format(payload.len()).as_bytes();
+        self.check_limit(prefix.len() + payload.len() + 2)?;
+        self.output.extend_from_slice(prefix);
+        self.output.push(b':');
+        self.output.extend_from_slice(payload);
+        self.output.push(tag);
+        Ok(())
+    }
+
+    fn add_float<T>(&mut self, v: T) -> Result<()>
+    where
+        T: Display + LowerExp,
+    {
+        if self.config.python_compat {
+            self.add_scalar(python_float(v).as_bytes(), b'^')
+        } else {
+            self.add_scalar(v.to_string().as_bytes(), b'^')
+        }
+    }
+
+    fn add_non_finite(&mut self, v: f64) -> Result<()> {
+        let payload: &[u8] = if v.is_nan() {
+            b"nan"
+        } else if v.is_sign_positive() {
+            b"inf"
+        } else {
+            b"-inf"
+        };
+        match self.config.non_finite_floats {
+            NonFiniteFloats::Error => Err(Error::NonFiniteFloat),
+            NonFiniteFloats::Null => self.add_to_output(b"0:~"),
+            NonFiniteFloats::Literal => self.add_scalar(payload, b'^'),
+        }
+    }
+
+    fn add_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let start = self.output.len();
+        if self.config.canonical_structs {
+            self.entries.push(start);
+        }
+        key.serialize(&mut *self)?;
+        let value_start = self.output.len();
+        self.none_at = None;
+        value.serialize(&mut *self)?;
+        // a `None` at the start of a list or dict also starts at `value_start`
+        let is_none = self.none_at == Some(value_start) && self.output.len() == value_start + 3;
+        if self.config.skip_none_fields && is_none {
+            self.output.truncate(start);
+            if self.config.canonical_structs {
+                self.entries.pop();
+            }
         }
+        Ok(())
+    }
+
+    pub(crate) fn start_container(&mut self) {
+        self.stack.push(self.output.len());
+    }
+
+    // encoded keys never are a prefix of one another, so comparing whole
+    // entries orders them by key
+    fn sort_entries(&mut self) -> Result<()> {
+        let first = self.maps.pop().ok_or(Error::StackProblem)?;
+        let mut bounds: Vec<_> = self.entries.drain(first..).collect();
+        bounds.push(self.output.len());
+        let start = bounds[0];
+        let mut ranges: Vec<_> = bounds.windows(2).map(|w| w[0]..w[1]).collect();
+        ranges.sort_by(|a, b| self.output[a.clone()].cmp(&self.output[b.clone()]));
+        let sorted: Vec<u8> = ranges
+            .into_iter()
+            .flat_map(|range| self.output[range].iter().cloned())
+            .collect();
+        self.output[start..].copy_from_slice(&sorted);
+        Ok(())
+    }
+
+    pub(crate) fn end_container(&mut self, terminator: u8) -> Result<()> {
+        let start = self.stack.pop().ok_or(Error::StackProblem)?;
+        self.add_prefix(start, terminator)
+    }
+
+    // puts the length of everything written since `start` in front of it
+    // and ends it with `terminator`
+    fn add_prefix(&mut self, start: usize, terminator: u8) -> Result<()> {
+        let mut len = itoa::Buffer::new();
+        let prefix = len.format(self.output.len() - start).as_bytes();
+        self.check_limit(prefix.len() + 2)?;
+        self.output
+            .splice(start..start, prefix.iter().cloned().chain(Some(b':')));
+        self.output.push(terminator);
+        Ok(())
     }
+}
+
+// Formats into a serializer's output, checking each piece against the output
+// limit before it's copied in.
+struct LimitedWriter<'a> {
+    ser: &'a mut Serializer,
+    error: Option<Error>,
+}
 
-    fn add_string_to_stack(&mut self) {
-        self.output.push(String::new())
+impl fmt::Write for LimitedWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.ser.add_to_output(s.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
     }
+}
+
+// Python's `repr` of a finite float: the shortest digits that read back as the
+// same value, positional with at least one decimal for exponents from -4 to
+// 15 and scientific with a signed two digit exponent otherwise.
+fn python_float<T>(v: T) -> String
+where
+    T: LowerExp,
+{
+    // `{:e}` gives the same shortest digits as `Display`, e.g. `-1.234e2`
+    let scientific = format!("{:e}", v);
+    let (mantissa, exp) = scientific.split_at(scientific.find('e').unwrap_or(0));
+    let exp: i32 = exp[1..].parse().unwrap_or(0);
+    let (sign, unsigned) = match mantissa.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", mantissa),
+    };
+    let digits = unsigned.replace('.', "");
 
-    fn pop_string(&mut self) -> Option<String> {
-        self.output.pop()
+    if !(-4..16).contains(&exp) {
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", mantissa, exp_sign, exp.abs());
+    }
+    if exp < 0 {
+        let zeros = "0".repeat((-exp - 1) as usize);
+        return format!("{}0.{}{}", sign, zeros, digits);
+    }
+    let int_len = exp as usize + 1;
+    if digits.len() > int_len {
+        format!("{}{}.{}", sign, &digits[..int_len], &digits[int_len..])
+    } else {
+        let zeros = "0".repeat(int_len - digits.len());
+        format!("{}{}{}.0", sign, digits, zeros)
     }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl ser::Serializer for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -81,9 +557,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
+    }
+
     fn serialize_bool(self, v: bool) -> Result<()> {
-        self.add_to_output(if v { "4:true!" } else { "5:false!" });
-        Ok(())
+        self.add_to_output(if v { b"4:true!" } else { b"5:false!" })
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
@@ -99,9 +578,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        let num = &v.to_string();
-        self.add_to_output(&format!("{}:{}#", num.len(), num));
-        Ok(())
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
@@ -117,36 +594,64 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        let num = &v.to_string();
-        self.add_to_output(&format!("{}:{}#", num.len(), num));
-        Ok(())
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.add_scalar(itoa::Buffer::new().format(v).as_bytes(), b'#')
     }
 
+    // formatting the f32 itself rather than widening it to an f64 gives the
+    // shortest representation that reads back as the same value
     fn serialize_f32(self, v: f32) -> Result<()> {
-        self.serialize_f64(f64::from(v))
+        if !v.is_finite() {
+            return self.add_non_finite(f64::from(v));
+        }
+        self.add_float(v)
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
-        let num = &v.to_string();
-        self.add_to_output(&format!("{}:{}^", num.len(), num));
-        Ok(())
+        if !v.is_finite() {
+            return self.add_non_finite(v);
+        }
+        self.add_float(v)
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
-        self.serialize_str(&v.to_string())
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.add_to_output(&format!("{}:{},", v.len(), v));
-        Ok(())
+        self.serialize_bytes(v.as_bytes())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        self.add_to_output(&format!("{}:{},", v.len(), String::from_utf8(v.to_vec())?));
-        Ok(())
+        self.add_scalar(v, b',')
+    }
+
+    // format straight into the output and put the length in front afterwards,
+    // remembering where it started, instead of going through a temporary string
+    fn collect_str<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Display,
+    {
+        let start = self.output.len();
+        let mut writer = LimitedWriter {
+            ser: self,
+            error: None,
+        };
+        if fmt::Write::write_fmt(&mut writer, format_args!("{}", value)).is_err() {
+            return Err(writer.error.unwrap_or(Error::Fmt));
+        }
+        self.add_prefix(start, b',')
     }
 
     fn serialize_none(self) -> Result<()> {
+        self.none_at = Some(self.output.len());
         self.serialize_unit()
     }
 
@@ -158,8 +663,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_unit(self) -> Result<()> {
-        self.add_to_output("0:~");
-        Ok(())
+        self.add_to_output(b"0:~")
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
@@ -172,13 +676,23 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<()> {
-        self.serialize_str(variant)
+        if self.config.unit_variants_as_dicts {
+            self.start_container();
+            self.serialize_str(variant)?;
+            self.add_to_output(b"0:~")?;
+            self.end_container(b'}')
+        } else {
+            self.serialize_str(variant)
+        }
     }
 
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        if name == raw::TOKEN {
+            return value.serialize(raw::Emitter(|bytes: &[u8]| self.add_to_output(bytes)));
+        }
         value.serialize(self)
     }
 
@@ -192,18 +706,15 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
+        self.start_container();
         variant.serialize(&mut *self)?;
         value.serialize(&mut *self)?;
-
-        if let Some(val) = self.output.last_mut() {
-            *val = format!("{}:{}}}", val.len(), val);
-        }
-        Ok(())
+        self.end_container(b'}')
     }
 
     // `len` is the number of elements
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.add_string_to_stack();
+        self.start_container();
         Ok(self)
     }
 
@@ -226,18 +737,26 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
+        self.start_container();
         variant.serialize(&mut *self)?;
-        self.add_string_to_stack();
+        self.start_container();
         Ok(self)
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.add_string_to_stack();
+        if self.config.canonical {
+            self.maps.push(self.entries.len());
+        }
+        self.start_container();
         Ok(self)
     }
 
-    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.serialize_map(Some(len))
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        if self.config.canonical_structs && !self.config.compact {
+            self.maps.push(self.entries.len());
+        }
+        self.start_container();
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -247,12 +766,17 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
+        self.start_container();
         variant.serialize(&mut *self)?;
+        if self.config.canonical_structs {
+            self.maps.push(self.entries.len());
+        }
+        self.start_container();
         Ok(self)
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -264,18 +788,11 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     }
 
     fn end(self) -> Result<Self::Ok> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}]",
-                most_recent_string.len(),
-                most_recent_string
-            ));
-        }
-        Ok(())
+        self.end_container(b']')
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -287,18 +804,11 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}]",
-                most_recent_string.len(),
-                most_recent_string
-            ));
-        }
-        Ok(())
+        self.end_container(b']')
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl ser::SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -310,18 +820,11 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}]",
-                most_recent_string.len(),
-                most_recent_string
-            ));
-        }
-        Ok(())
+        self.end_container(b']')
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -333,21 +836,12 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}]",
-                most_recent_string.len(),
-                most_recent_string
-            ));
-        }
-        if let Some(val) = self.output.last_mut() {
-            *val = format!("{}:{}}}", val.len(), val);
-        }
-        Ok(())
+        self.end_container(b']')?;
+        self.end_container(b'}')
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl ser::SerializeMap for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -355,7 +849,13 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)
+        if self.config.canonical {
+            self.entries.push(self.output.len());
+        }
+        key.serialize(MapKeySerializer {
+            stringify_keys: self.config.stringify_keys,
+            ser: &mut **self,
+        })
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
@@ -366,18 +866,14 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}}}",
-                most_recent_string.len(),
-                most_recent_string
-            ));
+        if self.config.canonical {
+            self.sort_entries()?;
         }
-        Ok(())
+        self.end_container(b'}')
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -385,23 +881,22 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)
+        if self.config.compact {
+            value.serialize(&mut **self)
+        } else {
+            self.add_field(key, value)
+        }
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}}}",
-                most_recent_string.len(),
-                most_recent_string
-            ));
+        if self.config.canonical_structs && !self.config.compact {
+            self.sort_entries()?;
         }
-        Ok(())
+        self.end_container(if self.config.compact { b']' } else { b'}' })
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl ser::SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -409,89 +904,381 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        self.add_string_to_stack();
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)
+        self.add_field(key, value)
     }
 
     fn end(self) -> Result<()> {
-        if let Some(most_recent_string) = self.pop_string() {
-            self.add_to_output(&format!(
-                "{}:{}}}",
-                most_recent_string.len(),
-                most_recent_string
-            ));
+        if self.config.canonical_structs {
+            self.sort_entries()?;
         }
-        if let Some(val) = self.output.last_mut() {
-            *val = format!("{}:{}}}", val.len(), val);
-        }
-        Ok(())
+        self.end_container(b'}')?;
+        self.end_container(b'}')
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::to_string;
-    use maplit::hashmap;
-    use serde::Serialize;
-
-    #[test]
-    fn test_bool() {
-        let test = true;
-        let expected = "4:true!";
-        assert_eq!(to_string(&test).unwrap(), expected);
+// Only lets through keys that are written as tnetstring strings, or that can
+// be turned into one when `stringify_keys` is enabled, and hands them on to
+// `ser`.
+pub(crate) struct MapKeySerializer<S> {
+    pub(crate) ser: S,
+    pub(crate) stringify_keys: bool,
+}
 
-        let test = false;
-        let expected = "5:false!";
-        assert_eq!(to_string(&test).unwrap(), expected);
+impl<S> MapKeySerializer<S>
+where
+    S: ser::Serializer<Ok = (), Error = Error>,
+{
+    fn stringify(self, v: &str, found: TNetStringType) -> Result<()> {
+        if self.stringify_keys {
+            self.ser.serialize_str(v)
+        } else {
+            Err(Error::NonStringKey(found))
+        }
     }
+}
 
-    #[test]
-    fn test_str() {
-        let test = "true";
-        let expected = "4:true,";
-        assert_eq!(to_string(&test).unwrap(), expected);
+impl<S> ser::Serializer for MapKeySerializer<S>
+where
+    S: ser::Serializer<Ok = (), Error = Error>,
+{
+    type Ok = ();
+    type Error = Error;
 
-        let test = "false";
-        let expected = "5:false,";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
 
-        let test = "3:foo,3:bar,";
-        let expected = "12:3:foo,3:bar,,";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn is_human_readable(&self) -> bool {
+        self.ser.is_human_readable()
     }
 
-    #[test]
-    fn test_ints() {
-        let test = -1;
-        let expected = "2:-1#";
-        assert_eq!(to_string(&test).unwrap(), expected);
-
-        let test = 12340;
-        let expected = "5:12340#";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.stringify(if v { "true" } else { "false" }, TNetStringType::Bool)
+    }
 
-        let test = 0;
-        let expected = "1:0#";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i64(i64::from(v))
     }
 
-    #[test]
-    fn test_floats() {
-        let test = 1.00;
-        let expected = "1:1^";
-        assert_eq!(to_string(&test).unwrap(), expected);
-        let test = -1.0;
-        let expected = "2:-1^";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
 
-        let test = 1.25;
-        let expected = "4:1.25^";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
 
-        let test = 123.4;
-        let expected = "5:123.4^";
-        assert_eq!(to_string(&test).unwrap(), expected);
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.stringify(itoa::Buffer::new().format(v), TNetStringType::Int)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.stringify(itoa::Buffer::new().format(v), TNetStringType::Int)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.stringify(itoa::Buffer::new().format(v), TNetStringType::Int)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.stringify(itoa::Buffer::new().format(v), TNetStringType::Int)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(Error::NonStringKey(TNetStringType::Float))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(Error::NonStringKey(TNetStringType::Float))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.ser.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.ser.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.ser.serialize_bytes(v)
+    }
+
+    fn collect_str<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Display,
+    {
+        self.ser.collect_str(value)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Err(Error::NonStringKey(TNetStringType::Null))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(Error::NonStringKey(TNetStringType::Null))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.ser.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(Error::NonStringKey(TNetStringType::Dict))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::NonStringKey(TNetStringType::List))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::NonStringKey(TNetStringType::List))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::NonStringKey(TNetStringType::List))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::NonStringKey(TNetStringType::Dict))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::NonStringKey(TNetStringType::Dict))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::NonStringKey(TNetStringType::Dict))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::NonStringKey(TNetStringType::Dict))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        to_fmt_writer, to_string, to_string_compact, to_string_into, to_string_with, to_vec,
+        to_vec_with, to_writer, to_writer_seq, to_writer_seq_separated, NonFiniteFloats,
+        Serializer, SerializerConfig,
+    };
+    use crate::error::{Error, Result};
+    use maplit::hashmap;
+    use serde::Serialize;
+    use std::fmt::{self, Display};
+    use std::io;
+
+    #[test]
+    fn test_bool() {
+        let test = true;
+        let expected = "4:true!";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = false;
+        let expected = "5:false!";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_str() {
+        let test = "true";
+        let expected = "4:true,";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = "false";
+        let expected = "5:false,";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = "3:foo,3:bar,";
+        let expected = "12:3:foo,3:bar,,";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_ints() {
+        let test = -1;
+        let expected = "2:-1#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = 12340;
+        let expected = "5:12340#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = 0;
+        let expected = "1:0#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_128_bit_ints() {
+        let test = u128::MAX;
+        let expected = "39:340282366920938463463374607431768211455#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = i128::MIN;
+        let expected = "40:-170141183460469231731687303715884105728#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = 0u128;
+        let expected = "1:0#";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_floats() {
+        let test = 1.00;
+        let expected = "1:1^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+        let test = -1.0;
+        let expected = "2:-1^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = 1.25;
+        let expected = "4:1.25^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = 123.4;
+        let expected = "5:123.4^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        fn with_policy<T: Serialize>(value: &T, policy: NonFiniteFloats) -> Result<String> {
+            to_string_with(value, &SerializerConfig::new().non_finite_floats(policy))
+        }
+
+        assert_eq!(to_string(&f64::NAN), Ok("3:nan^".into()));
+        assert_eq!(to_string(&f64::INFINITY), Ok("3:inf^".into()));
+        assert_eq!(to_string(&f64::NEG_INFINITY), Ok("4:-inf^".into()));
+        assert_eq!(to_string(&f32::NAN), Ok("3:nan^".into()));
+        assert_eq!(to_string(&f32::NEG_INFINITY), Ok("4:-inf^".into()));
+
+        for &(value, literal) in &[
+            (f64::NAN, "3:nan^"),
+            (f64::INFINITY, "3:inf^"),
+            (f64::NEG_INFINITY, "4:-inf^"),
+        ] {
+            assert_eq!(
+                with_policy(&value, NonFiniteFloats::Error),
+                Err(Error::NonFiniteFloat)
+            );
+            assert_eq!(
+                with_policy(&(value as f32), NonFiniteFloats::Error),
+                Err(Error::NonFiniteFloat)
+            );
+            assert_eq!(with_policy(&value, NonFiniteFloats::Null), Ok("0:~".into()));
+            assert_eq!(
+                with_policy(&value, NonFiniteFloats::Literal),
+                Ok(literal.into())
+            );
+        }
+
+        assert_eq!(
+            with_policy(&vec![1.5, f64::NAN], NonFiniteFloats::Null),
+            Ok("9:3:1.5^0:~]".into())
+        );
+        assert_eq!(
+            with_policy(&1.5, NonFiniteFloats::Error),
+            Ok("3:1.5^".into())
+        );
+    }
+
+    #[test]
+    fn test_f32() {
+        let test = 1.1f32;
+        let expected = "3:1.1^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = -0.5f32;
+        let expected = "4:-0.5^";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = f32::MIN_POSITIVE;
+        let expected = format!("{}:{}^", test.to_string().len(), test);
+        assert_eq!(to_string(&test).unwrap(), expected);
     }
 
     #[test]
@@ -499,14 +1286,17 @@ mod tests {
         let test = vec!["foo", "bar"];
         let expected = "12:3:foo,3:bar,]";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
 
         let test = vec![10, 10];
         let expected = "10:2:10#2:10#]";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
 
         let test = vec![vec![10, 10]];
         let expected = "14:10:2:10#2:10#]]";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -514,6 +1304,7 @@ mod tests {
         let expected = "12:3:foo,3:bar,]";
         let test = ("foo", "bar");
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -523,6 +1314,7 @@ mod tests {
         let test = Test("foo", "bar");
         let expected = "12:3:foo,3:bar,]";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -534,6 +1326,12 @@ mod tests {
         let test = E::T("foo", "bar");
         let expected = "20:1:T,12:3:foo,3:bar,]}";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = vec![E::T("a", "b"), E::T("c", "d")];
+        let expected = "38:15:1:T,8:1:a,1:b,]}15:1:T,8:1:c,1:d,]}]";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -544,6 +1342,7 @@ mod tests {
         let test = Test("foo");
         let expected = "3:foo,";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -554,8 +1353,36 @@ mod tests {
         }
 
         let test = Test::T("foo");
-        let expected = "10:1:T,3:foo,}".into();
-        assert_eq!(to_string(&test), Ok(expected));
+        let expected: String = "10:1:T,3:foo,}".into();
+        assert_eq!(to_string(&test), Ok(expected.clone()));
+        assert_eq!(to_vec(&test), Ok(expected.into_bytes()));
+    }
+
+    #[test]
+    fn test_newtype_variant_nested() {
+        #[derive(Serialize)]
+        enum E {
+            T(u32),
+        }
+
+        #[derive(Serialize)]
+        struct S {
+            first: u32,
+            e: E,
+        }
+
+        let test = S {
+            first: 1,
+            e: E::T(2),
+        };
+        let expected = "27:5:first,1:1#1:e,8:1:T,1:2#}}";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        let test = vec![E::T(1), E::T(2)];
+        let expected = "22:8:1:T,1:1#}8:1:T,1:2#}]";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -563,6 +1390,7 @@ mod tests {
         let test: Option<()> = None;
         let expected = "0:~";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -573,6 +1401,125 @@ mod tests {
         };
         let expected = "16:5:hello,5:world,}";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_canonical() {
+        use std::collections::HashMap;
+
+        fn canonical<T: Serialize>(value: &T) -> String {
+            to_string_with(value, &SerializerConfig::new().canonical(true)).unwrap()
+        }
+
+        fn make_map() -> HashMap<String, u32> {
+            (0..16).map(|i| (format!("key{}", i), i)).collect()
+        }
+
+        let expected = canonical(&make_map());
+        for _ in 0..50 {
+            assert_eq!(canonical(&make_map()), expected);
+        }
+
+        // ordinary mode follows the map's iteration order
+        struct Entries(Vec<(&'static str, u32)>);
+
+        impl Serialize for Entries {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().cloned())
+            }
+        }
+
+        let forward = Entries(vec![("a", 1), ("b", 2)]);
+        let backward = Entries(vec![("b", 2), ("a", 1)]);
+        assert_eq!(to_string(&backward).unwrap(), "16:1:b,1:2#1:a,1:1#}");
+        assert_ne!(to_string(&forward).unwrap(), to_string(&backward).unwrap());
+        assert_eq!(canonical(&forward), canonical(&backward));
+
+        let test: HashMap<&str, u32> = hashmap! {"b" => 2, "a" => 1, "ab" => 3};
+        assert_eq!(canonical(&test), "25:1:a,1:1#1:b,1:2#2:ab,1:3#}");
+
+        #[derive(Serialize)]
+        struct Test {
+            z: HashMap<&'static str, HashMap<&'static str, u32>>,
+            a: Vec<HashMap<&'static str, u32>>,
+        }
+
+        let make_test = || Test {
+            z: hashmap! {
+                "y" => hashmap! {"d" => 4, "c" => 3},
+                "x" => hashmap! {"b" => 2, "a" => 1},
+            },
+            a: vec![hashmap! {"f" => 6, "e" => 5}, hashmap! {"h" => 8, "g" => 7}],
+        };
+        let expected = "104:1:z,48:1:x,16:1:a,1:1#1:b,1:2#}1:y,16:1:c,1:3#1:d,1:4#}}\
+                        1:a,40:16:1:e,1:5#1:f,1:6#}16:1:g,1:7#1:h,1:8#}]}";
+        for _ in 0..20 {
+            assert_eq!(canonical(&make_test()), expected);
+        }
+    }
+
+    #[test]
+    fn test_non_string_keys() {
+        use crate::parse::TNetStringType;
+        use std::collections::BTreeMap;
+
+        fn stringified<T: Serialize>(value: &T) -> Result<String> {
+            to_string_with(value, &SerializerConfig::new().stringify_keys(true))
+        }
+
+        let mut ints = BTreeMap::new();
+        ints.insert(1u32, "one");
+        ints.insert(20, "twenty");
+        assert_eq!(
+            to_string(&ints),
+            Err(Error::NonStringKey(TNetStringType::Int))
+        );
+        assert_eq!(
+            stringified(&ints),
+            Ok("24:1:1,3:one,2:20,6:twenty,}".into())
+        );
+
+        let mut neg = BTreeMap::new();
+        neg.insert(-5i64, 0);
+        assert_eq!(stringified(&neg), Ok("9:2:-5,1:0#}".into()));
+
+        let mut bools = BTreeMap::new();
+        bools.insert(false, 0);
+        bools.insert(true, 1);
+        assert_eq!(
+            to_string(&bools),
+            Err(Error::NonStringKey(TNetStringType::Bool))
+        );
+        assert_eq!(
+            stringified(&bools),
+            Ok("23:5:false,1:0#4:true,1:1#}".into())
+        );
+
+        let mut chars = BTreeMap::new();
+        chars.insert('a', 1);
+        assert_eq!(to_string(&chars), Ok("8:1:a,1:1#}".into()));
+        assert_eq!(stringified(&chars), Ok("8:1:a,1:1#}".into()));
+
+        let mut tuples = BTreeMap::new();
+        tuples.insert((1, 2), "x");
+        assert_eq!(
+            to_string(&tuples),
+            Err(Error::NonStringKey(TNetStringType::List))
+        );
+        assert_eq!(
+            stringified(&tuples),
+            Err(Error::NonStringKey(TNetStringType::List))
+        );
+
+        #[derive(Serialize)]
+        struct Test {
+            a: u32,
+        }
+        assert_eq!(stringified(&Test { a: 1 }), Ok("8:1:a,1:1#}".into()));
     }
 
     #[test]
@@ -585,6 +1532,7 @@ mod tests {
         let test = T { int: 10 };
         let expected = "11:3:int,2:10#}";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
 
         #[derive(Serialize)]
         struct Test {
@@ -598,6 +1546,607 @@ mod tests {
         };
         let expected = "27:3:int,1:1#3:seq,8:1:a,1:b,]}";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_struct_compact() {
+        #[derive(Serialize)]
+        struct Inner {
+            a: u32,
+            b: Option<&'static str>,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            inner: Inner,
+            seq: Vec<&'static str>,
+        }
+
+        let test = Inner { a: 1, b: None };
+        let expected = "7:1:1#0:~]";
+        assert_eq!(to_string_compact(&test).unwrap(), expected);
+
+        let test = Outer {
+            inner: Inner { a: 1, b: Some("x") },
+            seq: vec!["a", "b"],
+        };
+        let expected = "22:8:1:1#1:x,]8:1:a,1:b,]]";
+        assert_eq!(to_string_compact(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bytes() {
+        #[derive(Serialize)]
+        struct Test<'a> {
+            #[serde(with = "serde_bytes")]
+            data: &'a [u8],
+        }
+
+        let test = Test {
+            data: &[0xff, 0x00],
+        };
+        let expected = b"12:4:data,2:\xff\x00,}";
+        assert_eq!(to_vec(&test).unwrap(), expected.to_vec());
+        assert!(matches!(
+            to_string(&test),
+            Err(Error::InvalidUtf8 { offset: 12, .. })
+        ));
+
+        let test = serde_bytes::Bytes::new(&[0xff, 0x00]);
+        assert_eq!(to_vec(&test).unwrap(), b"2:\xff\x00,".to_vec());
+
+        let test = Test { data: b"abc" };
+        let expected = "13:4:data,3:abc,}";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_string_into() {
+        #[derive(Serialize)]
+        struct Test {
+            id: u32,
+            tags: Vec<&'static str>,
+        }
+
+        let values = vec![
+            Test {
+                id: 1,
+                tags: vec!["a", "b"],
+            },
+            Test {
+                id: 22,
+                tags: vec![],
+            },
+            Test {
+                id: 333,
+                tags: vec!["longer tag"],
+            },
+        ];
+
+        let mut output = String::with_capacity(64);
+        let capacity = output.capacity();
+        let ptr = output.as_ptr();
+        for value in &values {
+            output.clear();
+            to_string_into(value, &mut output).unwrap();
+            assert_eq!(output, to_string(value).unwrap());
+            assert_eq!(output.capacity(), capacity);
+            assert_eq!(output.as_ptr(), ptr);
+        }
+
+        let mut output = String::from("3:foo,");
+        to_string_into(&1, &mut output).unwrap();
+        assert_eq!(output, "3:foo,1:1#");
+        let invalid: std::collections::HashMap<u32, u32> = hashmap! {1 => 1};
+        assert_eq!(
+            to_string_into(&invalid, &mut output),
+            Err(Error::NonStringKey(crate::parse::TNetStringType::Int))
+        );
+        assert_eq!(output, "3:foo,1:1#");
+        // the offset is into what was being appended
+        assert!(matches!(
+            to_string_into(&serde_bytes::Bytes::new(b"\xff"), &mut output),
+            Err(Error::InvalidUtf8 { offset: 2, .. })
+        ));
+        assert_eq!(output, "3:foo,1:1#");
+
+        let mut buffer = Vec::with_capacity(64);
+        for value in &values {
+            buffer.clear();
+            let mut serializer = Serializer::new(buffer);
+            value.serialize(&mut serializer).unwrap();
+            buffer = serializer.into_inner().unwrap();
+            assert_eq!(buffer, to_vec(value).unwrap());
+            assert_eq!(buffer.capacity(), 64);
+        }
+    }
+
+    #[test]
+    fn test_collect_str() {
+        use serde::Serializer as _;
+        use std::fmt;
+
+        #[derive(PartialEq, Eq, Hash)]
+        struct Id(u32, &'static str);
+
+        impl fmt::Display for Id {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}-{:04}", self.1, self.0)
+            }
+        }
+
+        impl Serialize for Id {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        let test = Id(42, "user");
+        let expected = "9:user-0042,";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_string(&test.to_string()).unwrap(), expected);
+
+        let test = vec![Id(1, "a"), Id(12345, "ü")];
+        let expected = "20:6:a-0001,8:ü-12345,]";
+        assert_eq!(to_string(&test).unwrap(), expected);
+
+        let test: std::collections::HashMap<_, _> = hashmap! {Id(7, "k") => 1};
+        assert_eq!(to_string(&test).unwrap(), "13:6:k-0007,1:1#}");
+
+        let mut serializer = Serializer::new(Vec::new());
+        serializer.collect_str("").unwrap();
+        assert_eq!(serializer.into_inner().unwrap(), b"0:,");
+    }
+
+    #[test]
+    fn test_stack_problem() {
+        use serde::ser::{SerializeMap, SerializeSeq, SerializeTupleVariant};
+        use serde::Serializer as _;
+
+        // a container that is never ended
+        let mut serializer = Serializer::new(Vec::new());
+        let mut seq = serializer.serialize_seq(None).unwrap();
+        seq.serialize_element(&1).unwrap();
+        assert_eq!(serializer.into_inner(), Err(Error::StackProblem));
+
+        let mut serializer = Serializer::new(Vec::new());
+        let mut map = serializer.serialize_map(None).unwrap();
+        map.serialize_entry("a", &1).unwrap();
+        let mut seq = map.serialize_seq(None).unwrap();
+        seq.serialize_element(&2).unwrap();
+        SerializeSeq::end(seq).unwrap();
+        assert_eq!(serializer.into_inner(), Err(Error::StackProblem));
+
+        // ending more containers than were started
+        let mut serializer = Serializer::new(Vec::new());
+        let seq = serializer.serialize_seq(None).unwrap();
+        SerializeSeq::end(seq).unwrap();
+        assert_eq!(SerializeSeq::end(&mut serializer), Err(Error::StackProblem));
+
+        let mut serializer = Serializer::new(Vec::new());
+        let mut variant = serializer.serialize_tuple_variant("E", 0, "T", 1).unwrap();
+        variant.serialize_field(&1).unwrap();
+        SerializeSeq::end(&mut *variant).unwrap();
+        assert_eq!(
+            SerializeTupleVariant::end(variant),
+            Err(Error::StackProblem)
+        );
+
+        // a balanced sequence driven by hand is fine
+        let mut serializer = Serializer::new(Vec::new());
+        let mut variant = serializer.serialize_tuple_variant("E", 0, "T", 1).unwrap();
+        variant.serialize_field(&1).unwrap();
+        SerializeTupleVariant::end(variant).unwrap();
+        assert_eq!(serializer.into_inner(), Ok(b"11:1:T,4:1:1#]}".to_vec()));
+    }
+
+    #[test]
+    fn test_config() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+            seq: Vec<&'static str>,
+            float: f64,
+        }
+
+        let test = Test {
+            int: 1,
+            seq: vec!["a", "b"],
+            float: f64::INFINITY,
+        };
+        let default = SerializerConfig::default();
+        assert_eq!(to_string_with(&test, &default), to_string(&test));
+        assert_eq!(to_vec_with(&test, &default), to_vec(&test));
+
+        let config = SerializerConfig::new().capacity(1024);
+        let output = to_vec_with(&test, &config).unwrap();
+        assert_eq!(output, to_vec(&test).unwrap());
+        assert!(output.capacity() >= 1024);
+
+        let config = SerializerConfig::new().compact(true);
+        assert_eq!(to_string_with(&test, &config), to_string_compact(&test));
+        assert_eq!(
+            to_string_with(&test, &config),
+            Ok("21:1:1#8:1:a,1:b,]3:inf^]".into())
+        );
+
+        let config = config.non_finite_floats(NonFiniteFloats::Null);
+        assert_eq!(
+            to_string_with(&test, &config),
+            Ok("18:1:1#8:1:a,1:b,]0:~]".into())
+        );
+
+        let mut serializer = Serializer::with_config(Vec::new(), config);
+        test.serialize(&mut serializer).unwrap();
+        assert_eq!(
+            serializer.into_inner(),
+            Ok(b"18:1:1#8:1:a,1:b,]0:~]".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_python_compat() {
+        let config = SerializerConfig::new().python_compat(true);
+
+        // the fixtures are what Python's `tnetstring.dumps` writes for these
+        let floats = vec![
+            1.0,
+            -1.5,
+            0.1,
+            123.4,
+            1e16,
+            1e15,
+            1e-5,
+            0.0001,
+            1.5e300,
+            2.5e-7,
+            -0.0,
+            0.0,
+            1e22,
+            12345678.9,
+            5e-324,
+            f64::MAX,
+            1.0 / 3.0,
+        ];
+        let expected = include_bytes!("../tests/fixtures/python_floats.tnet");
+        assert_eq!(to_vec_with(&floats, &config).unwrap(), &expected[..]);
+
+        #[derive(Serialize)]
+        struct Sensor {
+            name: &'static str,
+            ratio: f64,
+            count: u32,
+            ok: bool,
+            missing: Option<u32>,
+            readings: Vec<f64>,
+        }
+
+        let test = Sensor {
+            name: "sensor",
+            ratio: 0.5,
+            count: 3,
+            ok: true,
+            missing: None,
+            readings: vec![1.0, 2.25, -40.0, 1e-7],
+        };
+        let expected = include_bytes!("../tests/fixtures/python_dict.tnet");
+        assert_eq!(to_vec_with(&test, &config).unwrap(), &expected[..]);
+
+        assert_eq!(to_string_with(&1.0f32, &config), Ok("3:1.0^".into()));
+        assert_eq!(to_string_with(&0.1f32, &config), Ok("3:0.1^".into()));
+        assert_eq!(to_string_with(&1e20f32, &config), Ok("5:1e+20^".into()));
+        assert_eq!(to_string_with(&f64::NAN, &config), Ok("3:nan^".into()));
+        assert_eq!(
+            to_string_with(&f64::NEG_INFINITY, &config),
+            Ok("4:-inf^".into())
+        );
+        assert_eq!(to_string(&1.0), Ok("1:1^".into()));
+    }
+
+    #[test]
+    fn test_skip_none_fields() {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        struct Inner {
+            a: Option<u32>,
+        }
+
+        #[derive(Serialize)]
+        struct Test {
+            first: Option<u32>,
+            second: Option<&'static str>,
+            unit: (),
+            list: Vec<Option<u32>>,
+            map: BTreeMap<&'static str, Option<u32>>,
+            inner: Inner,
+            last: Option<u32>,
+        }
+
+        #[derive(Serialize)]
+        enum E {
+            S { a: Option<u32>, b: Option<u32> },
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert("k", None);
+        let test = Test {
+            first: None,
+            second: Some("x"),
+            unit: (),
+            list: vec![None, Some(1)],
+            map,
+            inner: Inner { a: None },
+            last: None,
+        };
+        let config = SerializerConfig::new().skip_none_fields(true);
+
+        assert_eq!(
+            to_string(&test),
+            Ok(
+                "95:5:first,0:~6:second,1:x,4:unit,0:~4:list,7:0:~1:1#]3:map,\
+                7:1:k,0:~}5:inner,7:1:a,0:~}4:last,0:~}"
+                    .into()
+            )
+        );
+        assert_eq!(
+            to_string_with(&test, &config),
+            Ok("67:6:second,1:x,4:unit,0:~4:list,7:0:~1:1#]3:map,7:1:k,0:~}5:inner,0:}}".into())
+        );
+
+        let test = E::S {
+            a: None,
+            b: Some(2),
+        };
+        assert_eq!(
+            to_string_with(&test, &config),
+            Ok("15:1:S,8:1:b,1:2#}}".into())
+        );
+
+        let compact = config.compact(true);
+        assert_eq!(
+            to_string_with(&Inner { a: None }, &compact),
+            Ok("3:0:~]".into())
+        );
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let config = SerializerConfig::new().max_output_bytes(1024);
+        let large = vec![12345u64; 1_000_000];
+        let mut serializer = Serializer::with_config(Vec::new(), config.clone());
+        match large.serialize(&mut serializer) {
+            Err(Error::OutputLimitExceeded { limit, attempted }) => {
+                assert_eq!(limit, 1024);
+                assert!(attempted > 1024 && attempted < 1100);
+            }
+            other => panic!("expected OutputLimitExceeded, got {:?}", other),
+        }
+        assert!(serializer.output.capacity() < 4096);
+
+        // the length prefix counts too
+        let value = vec!["x".repeat(1012)];
+        assert_eq!(to_vec(&value).unwrap().len(), 1024);
+        assert_eq!(to_vec_with(&value, &config), to_vec(&value));
+        let value = vec!["x".repeat(1013)];
+        assert_eq!(
+            to_vec_with(&value, &config),
+            Err(Error::OutputLimitExceeded {
+                limit: 1024,
+                attempted: 1025
+            })
+        );
+
+        // only what this value adds to the buffer counts
+        let mut output = "x".repeat(2000);
+        let config = SerializerConfig::new().max_output_bytes(10);
+        let mut serializer = Serializer::with_config(output.into_bytes(), config);
+        "abc".serialize(&mut serializer).unwrap();
+        output = String::from_utf8(serializer.into_inner().unwrap()).unwrap();
+        assert!(output.ends_with("3:abc,"));
+
+        assert_eq!(
+            to_vec_with(&large, &SerializerConfig::new()),
+            to_vec(&large)
+        );
+
+        // `collect_str` stops formatting as soon as it's over
+        struct Huge(std::cell::Cell<usize>);
+
+        impl Display for Huge {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for _ in 0..10_000 {
+                    self.0.set(self.0.get() + 1);
+                    f.write_str(&"x".repeat(1000))?;
+                }
+                Ok(())
+            }
+        }
+
+        let huge = Huge(std::cell::Cell::new(0));
+        let config = SerializerConfig::new().max_output_bytes(16);
+        let mut serializer = Serializer::with_config(Vec::new(), config);
+        assert_eq!(
+            serde::Serializer::collect_str(&mut serializer, &huge),
+            Err(Error::OutputLimitExceeded {
+                limit: 16,
+                attempted: 1000
+            })
+        );
+        assert_eq!(huge.0.get(), 1);
+        assert!(serializer.output.capacity() < 1000);
+    }
+
+    #[test]
+    fn test_fmt_writer() {
+        use std::fmt;
+
+        let mut output = String::from("frame: ");
+        to_fmt_writer(&mut output, &vec!["a", "b"]).unwrap();
+        assert_eq!(output, "frame: 8:1:a,1:b,]");
+
+        struct Wrapper<T>(T);
+
+        impl<T: Serialize> fmt::Display for Wrapper<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                to_fmt_writer(f, &self.0).map_err(|_| fmt::Error)
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+        }
+
+        let formatted = format!("<{}>", Wrapper(Test { int: 1 }));
+        assert_eq!(formatted, "<10:3:int,1:1#}>");
+
+        let bytes = serde_bytes::Bytes::new(b"\xff");
+        let mut output = String::new();
+        assert!(matches!(
+            to_fmt_writer(&mut output, &bytes),
+            Err(Error::InvalidUtf8 { offset: 2, .. })
+        ));
+        assert_eq!(output, "");
+        // nothing is written when the bad bytes come part way through, and
+        // the offset counts the prefixes of the containers around them
+        let mut output = String::new();
+        let nested = vec![("ok", bytes)];
+        assert_eq!(to_vec(&nested).unwrap(), b"12:9:2:ok,1:\xff,]]");
+        assert!(matches!(
+            to_fmt_writer(&mut output, &nested),
+            Err(Error::InvalidUtf8 { offset: 12, .. })
+        ));
+        assert_eq!(output, "");
+
+        // the output reaches the writer a piece at a time, not as one copy
+        struct Pieces(Vec<String>);
+
+        impl fmt::Write for Pieces {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.0.push(s.to_owned());
+                Ok(())
+            }
+        }
+
+        let mut pieces = Pieces(Vec::new());
+        to_fmt_writer(&mut pieces, &vec!["a", "b"]).unwrap();
+        assert_eq!(pieces.0.concat(), "8:1:a,1:b,]");
+        assert!(pieces.0.len() > 1);
+
+        struct FailingWriter;
+
+        impl fmt::Write for FailingWriter {
+            fn write_str(&mut self, _s: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        assert_eq!(to_fmt_writer(&mut FailingWriter, &1), Err(Error::Fmt));
+    }
+
+    #[test]
+    fn test_writer_seq() {
+        use crate::de::Deserializer;
+        use crate::parse::TNetStringType;
+        use serde::Deserialize;
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Record {
+            id: u32,
+            name: String,
+        }
+
+        let records = vec![
+            Record {
+                id: 1,
+                name: "one".into(),
+            },
+            Record {
+                id: 2,
+                name: "two".into(),
+            },
+            Record {
+                id: 3,
+                name: "three".into(),
+            },
+        ];
+        let mut output = Vec::new();
+        assert_eq!(to_writer_seq(&mut output, &records), Ok(3));
+        let read: Vec<Record> = Deserializer::from_slice(&output)
+            .into_iter()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, records);
+
+        let mut output = Vec::new();
+        assert_eq!(to_writer_seq(&mut output, (0..5).map(|i| i * 10)), Ok(5));
+        assert_eq!(output, b"1:0#2:10#2:20#2:30#2:40#");
+
+        let mut output = Vec::new();
+        assert_eq!(to_writer_seq_separated(&mut output, &records, b"\n"), Ok(3));
+        assert_eq!(output.iter().filter(|&&byte| byte == b'\n').count(), 3);
+        assert!(output.ends_with(b"5:three,}\n"));
+        let read: Vec<Record> = Deserializer::from_slice(&output)
+            .into_iter()
+            .separators(b"\n")
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, records);
+
+        // an empty map has no keys to reject, the second one fails
+        let mut invalid = BTreeMap::new();
+        invalid.insert(1u32, 1u32);
+        let maps = vec![BTreeMap::new(), invalid];
+        let mut output = Vec::new();
+        assert_eq!(
+            to_writer_seq(&mut output, &maps),
+            Err(Error::Item {
+                index: 1,
+                error: Box::new(Error::NonStringKey(TNetStringType::Int)),
+            })
+        );
+        assert_eq!(output, b"0:}");
+    }
+
+    #[test]
+    fn test_writer() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+            seq: Vec<&'static str>,
+        }
+
+        let test = Test {
+            int: 1,
+            seq: vec!["a", "b"],
+        };
+        let mut output = Vec::new();
+        to_writer(&mut output, &test).unwrap();
+        assert_eq!(output, to_string(&test).unwrap().as_bytes());
+
+        struct FailingWriter;
+
+        impl io::Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert_eq!(
+            to_writer(FailingWriter, &test),
+            Err(Error::Io(io::ErrorKind::BrokenPipe.into()))
+        );
     }
 
     #[test]
@@ -610,6 +2159,20 @@ mod tests {
         let test = Test::A { b: 10 };
         let expected = "16:1:A,9:1:b,2:10#}}";
         assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
+
+        #[derive(Serialize)]
+        enum Config {
+            Server { host: &'static str, port: u16 },
+        }
+
+        let test = Config::Server {
+            host: "localhost",
+            port: 80,
+        };
+        let expected = "44:6:Server,31:4:host,9:localhost,4:port,2:80#}}";
+        assert_eq!(to_string(&test).unwrap(), expected);
+        assert_eq!(to_vec(&test).unwrap(), expected.as_bytes());
     }
 
     #[test]
@@ -623,19 +2186,23 @@ mod tests {
         }
 
         let u = E::Unit;
-        let expected = "4:Unit,".into();
-        assert_eq!(to_string(&u), Ok(expected));
+        let expected: String = "4:Unit,".into();
+        assert_eq!(to_string(&u), Ok(expected.clone()));
+        assert_eq!(to_vec(&u), Ok(expected.into_bytes()));
 
         let n = E::Newtype(1);
-        let expected = "14:7:Newtype,1:1#}".into();
-        assert_eq!(to_string(&n), Ok(expected));
+        let expected: String = "14:7:Newtype,1:1#}".into();
+        assert_eq!(to_string(&n), Ok(expected.clone()));
+        assert_eq!(to_vec(&n), Ok(expected.into_bytes()));
 
         let t = E::Tuple(1, 2);
-        let expected = "19:5:Tuple,8:1:1#1:2#]}".into();
-        assert_eq!(to_string(&t), Ok(expected));
+        let expected: String = "19:5:Tuple,8:1:1#1:2#]}".into();
+        assert_eq!(to_string(&t), Ok(expected.clone()));
+        assert_eq!(to_vec(&t), Ok(expected.into_bytes()));
 
         let s = E::Struct { a: 1 };
-        let expected = "20:6:Struct,8:1:a,1:1#}}".into();
-        assert_eq!(to_string(&s), Ok(expected));
+        let expected: String = "20:6:Struct,8:1:a,1:1#}}".into();
+        assert_eq!(to_string(&s), Ok(expected.clone()));
+        assert_eq!(to_vec(&s), Ok(expected.into_bytes()));
     }
 }
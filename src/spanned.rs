@@ -0,0 +1,169 @@
+use serde::de::{self, Deserialize, Deserializer, Expected, MapAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+// The struct name and fields that tell this crate's deserializer to hand
+// over the span of the next value along with it.
+pub(crate) const NAME: &str = "$tnetstring::private::Spanned";
+pub(crate) const START: &str = "$tnetstring::private::Spanned::start";
+pub(crate) const END: &str = "$tnetstring::private::Spanned::end";
+pub(crate) const VALUE: &str = "$tnetstring::private::Spanned::value";
+pub(crate) const FIELDS: &[&str] = &[START, END, VALUE];
+
+/// A deserialized value along with the byte range of the input it was read
+/// from, for pointing at the exact bytes in error messages.
+///
+/// The span covers the whole tnetstring, from the length prefix to the type
+/// tag. This only works with this crate's deserializer, and serializing a
+/// `Spanned<T>` writes just the `T`. Spanned values compare by their values
+/// alone.
+#[derive(Clone, Debug)]
+pub struct Spanned<T> {
+    start: usize,
+    end: usize,
+    value: T,
+}
+
+impl<T> Spanned<T> {
+    /// The byte offsets of the value in the input.
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Spanned<T>) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Spanned<T> {}
+
+impl<T: Serialize> Serialize for Spanned<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Spanned<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Spanned<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SpannedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for SpannedVisitor<T> {
+            type Value = Spanned<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a spanned value")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Spanned<T>, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let start = next_field(&mut map, START, &self)?;
+                let end = next_field(&mut map, END, &self)?;
+                let value = next_field(&mut map, VALUE, &self)?;
+                Ok(Spanned { start, end, value })
+            }
+        }
+
+        // anything but this crate's deserializer gives some other map
+        fn next_field<'de, A, V>(
+            map: &mut A,
+            name: &str,
+            expected: &dyn Expected,
+        ) -> Result<V, A::Error>
+        where
+            A: MapAccess<'de>,
+            V: Deserialize<'de>,
+        {
+            match map.next_key::<String>()? {
+                Some(ref key) if key == name => map.next_value(),
+                _ => Err(de::Error::invalid_type(Unexpected::Map, expected)),
+            }
+        }
+
+        deserializer.deserialize_struct(NAME, FIELDS, SpannedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Spanned;
+    use crate::de::from_str;
+    use crate::ser::to_string;
+    use crate::value::{from_value, Value};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        name: Spanned<String>,
+        port: Spanned<u16>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Config {
+        id: u32,
+        server: Spanned<Server>,
+        tags: Vec<Spanned<String>>,
+    }
+
+    const INPUT: &str =
+        "72:2:id,1:7#6:server,27:4:name,5:hello,4:port,2:80#}4:tags,12:1:a,5:hello,]}";
+
+    #[test]
+    fn test_span() {
+        let input = "27:4:name,5:hello,4:port,2:80#}";
+        let server: Server = from_str(input).unwrap();
+        assert_eq!(&input[server.name.span()], "5:hello,");
+        assert_eq!(server.name.get_ref(), "hello");
+        assert_eq!(server.port.span(), 25..30);
+        assert_eq!(server.port.into_inner(), 80);
+
+        // serialized as just the value
+        let server: Server = from_str(input).unwrap();
+        assert_eq!(to_string(&server).unwrap(), input);
+    }
+
+    #[test]
+    fn test_nested() {
+        let config: Config = from_str(INPUT).unwrap();
+        assert_eq!(config.id, 7);
+        let server = config.server.get_ref();
+        assert_eq!(config.server.span(), 21..52);
+        assert_eq!(
+            &INPUT[config.server.span()],
+            "27:4:name,5:hello,4:port,2:80#}"
+        );
+        assert_eq!(&INPUT[server.name.span()], "5:hello,");
+        assert_eq!(server.name.span(), 31..39);
+        assert_eq!(&INPUT[server.port.span()], "2:80#");
+        let spans: Vec<_> = config.tags.iter().map(Spanned::span).collect();
+        assert_eq!(spans, [62..66, 66..74]);
+        assert_eq!(&INPUT[spans[1].clone()], "5:hello,");
+
+        // other deserializers don't know where values came from
+        let value: Value = from_str(INPUT).unwrap();
+        assert!(from_value::<Config>(value).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Assertions for tests of types that go through tnetstrings.
+//!
+//! Each panics with the encoding involved, escaped, along with the error or
+//! the values that didn't match, and reports the caller's line.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+use crate::de::from_slice;
+use crate::ser::to_vec;
+
+/// Assert that `value` serializes, and deserializes back into an equal `T`.
+#[track_caller]
+pub fn assert_roundtrip<T>(value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let encoded = match to_vec(value) {
+        Ok(encoded) => encoded,
+        Err(error) => panic!("failed to serialize {:?}: {}", value, error),
+    };
+    match from_slice::<T>(&encoded) {
+        Ok(ref decoded) if decoded == value => {}
+        Ok(decoded) => panic!(
+            "round trip changed {:?}\n  into {:?}\n  encoded as `{}`",
+            value,
+            decoded,
+            encoded.escape_ascii()
+        ),
+        Err(error) => panic!(
+            "failed to deserialize {:?} from `{}`: {}",
+            value,
+            encoded.escape_ascii(),
+            error
+        ),
+    }
+}
+
+/// Assert that `value` serializes to exactly `expected`.
+#[track_caller]
+pub fn assert_encodes_to<T, E>(value: &T, expected: E)
+where
+    T: Serialize + Debug,
+    E: AsRef<[u8]>,
+{
+    let expected = expected.as_ref();
+    match to_vec(value) {
+        Ok(ref encoded) if encoded == expected => {}
+        Ok(encoded) => panic!(
+            "{:?} encoded as\n  `{}`\n  expected\n  `{}`",
+            value,
+            encoded.escape_ascii(),
+            expected.escape_ascii()
+        ),
+        Err(error) => panic!("failed to serialize {:?}: {}", value, error),
+    }
+}
+
+/// Assert that `input` deserializes into a `T` equal to `expected`.
+#[track_caller]
+pub fn assert_decodes_from<T, I>(input: I, expected: &T)
+where
+    T: DeserializeOwned + PartialEq + Debug,
+    I: AsRef<[u8]>,
+{
+    let input = input.as_ref();
+    match from_slice::<T>(input) {
+        Ok(ref decoded) if decoded == expected => {}
+        Ok(decoded) => panic!(
+            "`{}` decoded as\n  {:?}\n  expected\n  {:?}",
+            input.escape_ascii(),
+            decoded,
+            expected
+        ),
+        Err(error) => panic!(
+            "failed to deserialize `{}`: {}",
+            input.escape_ascii(),
+            error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_decodes_from, assert_encodes_to, assert_roundtrip};
+    use serde::{Deserialize, Serialize};
+    use std::panic::catch_unwind;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    // serializes `0.5` as `0`, so it never survives a round trip
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Lossy(#[serde(serialize_with = "truncate")] f64);
+
+    fn truncate<S: serde::Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.trunc())
+    }
+
+    fn panic_message<F: FnOnce() + std::panic::UnwindSafe>(f: F) -> String {
+        let payload = catch_unwind(f).unwrap_err();
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => (*payload.downcast::<&str>().unwrap()).to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_passing() {
+        assert_roundtrip(&Point { x: 1, y: -2 });
+        assert_encodes_to(&Point { x: 1, y: -2 }, "17:1:x,1:1#1:y,2:-2#}");
+        assert_encodes_to(&serde_bytes::Bytes::new(b"\xff"), b"1:\xff,");
+        assert_decodes_from("17:1:x,1:1#1:y,2:-2#}", &Point { x: 1, y: -2 });
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(
+            panic_message(|| assert_roundtrip(&Lossy(0.5))),
+            "round trip changed Lossy(0.5)\n  into Lossy(0.0)\n  encoded as `1:0^`"
+        );
+        assert_eq!(
+            panic_message(|| assert_encodes_to(&Point { x: 1, y: 2 }, "17:1:x,1:1#1:y,2:-2#}")),
+            "Point { x: 1, y: 2 } encoded as\n  `16:1:x,1:1#1:y,1:2#}`\n  \
+             expected\n  `17:1:x,1:1#1:y,2:-2#}`"
+        );
+        assert_eq!(
+            panic_message(|| assert_decodes_from(b"3:\xffab,", &Point { x: 1, y: 2 })),
+            "failed to deserialize `3:\\xffab,`: expected struct `Point` (dict), found string `,` at byte 0 \
+             in `3:\\xffab,`"
+        );
+    }
+}
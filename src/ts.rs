@@ -0,0 +1,257 @@
+//! Helpers for timestamp fields, for use with `#[serde(with = "...")]`.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Event {
+//!     #[serde(with = "tnetstring::ts::unix_millis")]
+//!     at: chrono::DateTime<chrono::Utc>,
+//! }
+//! ```
+//!
+//! Each module works with any `Timestamp`: chrono's `DateTime<Utc>` with
+//! the `chrono` feature, and time's `OffsetDateTime` with the `time`
+//! feature. Timestamps that don't fit, or strings that don't parse, fail
+//! with an error rather than panicking.
+
+#[cfg(feature = "chrono")]
+use std::convert::TryFrom;
+
+/// A point in time the `ts` helpers can read and write.
+pub trait Timestamp: Sized {
+    /// Nanoseconds since the Unix epoch.
+    fn unix_nanos(&self) -> i128;
+    /// `None` if `nanos` is out of range.
+    fn from_unix_nanos(nanos: i128) -> Option<Self>;
+    /// `None` if the year can't be written in RFC 3339.
+    fn to_rfc3339(&self) -> Option<String>;
+    fn from_rfc3339(s: &str) -> Option<Self>;
+}
+
+#[cfg(feature = "chrono")]
+impl Timestamp for chrono::DateTime<chrono::Utc> {
+    fn unix_nanos(&self) -> i128 {
+        i128::from(self.timestamp()) * 1_000_000_000 + i128::from(self.timestamp_subsec_nanos())
+    }
+
+    fn from_unix_nanos(nanos: i128) -> Option<Self> {
+        let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+        let subsec = nanos.rem_euclid(1_000_000_000) as u32;
+        chrono::DateTime::from_timestamp(secs, subsec)
+    }
+
+    fn to_rfc3339(&self) -> Option<String> {
+        Some(self.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+    }
+
+    fn from_rfc3339(s: &str) -> Option<Self> {
+        chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|datetime| datetime.with_timezone(&chrono::Utc))
+    }
+}
+
+#[cfg(feature = "time")]
+impl Timestamp for time::OffsetDateTime {
+    fn unix_nanos(&self) -> i128 {
+        self.unix_timestamp_nanos()
+    }
+
+    fn from_unix_nanos(nanos: i128) -> Option<Self> {
+        time::OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()
+    }
+
+    fn to_rfc3339(&self) -> Option<String> {
+        self.format(&time::format_description::well_known::Rfc3339)
+            .ok()
+    }
+
+    fn from_rfc3339(s: &str) -> Option<Self> {
+        time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339).ok()
+    }
+}
+
+macro_rules! unix_module {
+    ($name:ident, $unit:expr, $doc:expr) => {
+        #[doc = $doc]
+        pub mod $name {
+            use serde::de::{self, Deserialize, Deserializer, Unexpected};
+            use serde::ser::{self, Serializer};
+            use std::convert::TryFrom;
+
+            use super::Timestamp;
+
+            const NANOS: i128 = $unit;
+
+            pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                T: Timestamp,
+                S: Serializer,
+            {
+                let n = i64::try_from(value.unix_nanos().div_euclid(NANOS))
+                    .map_err(|_| ser::Error::custom("timestamp out of range"))?;
+                serializer.serialize_i64(n)
+            }
+
+            pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+            where
+                T: Timestamp,
+                D: Deserializer<'de>,
+            {
+                let n = i64::deserialize(deserializer)?;
+                T::from_unix_nanos(i128::from(n) * NANOS).ok_or_else(|| {
+                    de::Error::invalid_value(Unexpected::Signed(n), &"a timestamp in range")
+                })
+            }
+        }
+    };
+}
+
+unix_module!(
+    unix_seconds,
+    1_000_000_000,
+    "Whole seconds since the Unix epoch, as an integer. Anything below a \
+     second is dropped, rounding towards the past."
+);
+unix_module!(
+    unix_millis,
+    1_000_000,
+    "Milliseconds since the Unix epoch, as an integer. Anything below a \
+     millisecond is dropped, rounding towards the past."
+);
+
+/// An RFC 3339 string in UTC, like `2001-02-03T04:05:06.789Z`.
+pub mod rfc3339 {
+    use serde::de::{self, Deserialize, Deserializer, Unexpected};
+    use serde::ser::{self, Serializer};
+    use std::borrow::Cow;
+
+    use super::Timestamp;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Timestamp,
+        S: Serializer,
+    {
+        let s = value
+            .to_rfc3339()
+            .ok_or_else(|| ser::Error::custom("timestamp out of range for RFC 3339"))?;
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Timestamp,
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        T::from_rfc3339(&s)
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&s), &"an RFC 3339 timestamp"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::de::from_str;
+    use crate::ser::to_string;
+    use serde::{Deserialize, Serialize};
+
+    macro_rules! event {
+        ($name:ident, $ty:ty) => {
+            #[derive(Serialize, Deserialize, Debug, PartialEq)]
+            struct $name {
+                #[serde(with = "super::unix_seconds")]
+                secs: $ty,
+                #[serde(with = "super::unix_millis")]
+                millis: $ty,
+                #[serde(with = "super::rfc3339")]
+                text: $ty,
+            }
+        };
+    }
+
+    const ENCODED: &str =
+        "79:4:secs,9:981173106#6:millis,12:981173106789#4:text,24:2001-02-03T04:05:06.789Z,}";
+
+    fn assert_malformed<T: serde::de::DeserializeOwned + std::fmt::Debug>() {
+        for input in &[
+            // past the range of either library
+            "74:4:secs,19:9223372036854775807#6:millis,1:0#4:text,20:2001-02-03T04:05:06Z,}",
+            "55:4:secs,1:0#6:millis,1:0#4:text,20:2001-02-30T04:05:06Z,}",
+            "45:4:secs,1:0#6:millis,1:0#4:text,10:not a date,}",
+            "36:4:secs,1:0#6:millis,1:0#4:text,2:12#}",
+        ] {
+            let result = from_str::<T>(input);
+            assert!(
+                matches!(result, Err(ref error) if error.is_data()),
+                "{}: {:?}",
+                input,
+                result
+            );
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{DateTime, TimeZone, Utc};
+        event!(Event, DateTime<Utc>);
+
+        let at = Utc.with_ymd_and_hms(2001, 2, 3, 4, 5, 6).unwrap()
+            + chrono::Duration::milliseconds(789);
+        let event = Event {
+            secs: at,
+            millis: at,
+            text: at,
+        };
+        let encoded = to_string(&event).unwrap();
+        assert_eq!(encoded, ENCODED);
+        let decoded: Event = from_str(&encoded).unwrap();
+        assert_eq!(decoded.secs, at - chrono::Duration::milliseconds(789));
+        assert_eq!(decoded.millis, at);
+        assert_eq!(decoded.text, at);
+
+        // before the epoch rounds down, not towards zero
+        let before = Utc.timestamp_opt(-1, 500_000_000).unwrap();
+        assert_eq!(
+            to_string(&Event {
+                secs: before,
+                millis: before,
+                text: before,
+            })
+            .unwrap(),
+            "63:4:secs,2:-1#6:millis,4:-500#4:text,24:1969-12-31T23:59:59.500Z,}"
+        );
+        assert_malformed::<Event>();
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time() {
+        use time::macros::datetime;
+        use time::OffsetDateTime;
+        event!(Event, OffsetDateTime);
+
+        let at = datetime!(2001-02-03 04:05:06.789 UTC);
+        let event = Event {
+            secs: at,
+            millis: at,
+            text: at,
+        };
+        let encoded = to_string(&event).unwrap();
+        assert_eq!(encoded, ENCODED);
+        let decoded: Event = from_str(&encoded).unwrap();
+        assert_eq!(decoded.secs, datetime!(2001-02-03 04:05:06 UTC));
+        assert_eq!(decoded.millis, at);
+        assert_eq!(decoded.text, at);
+
+        // RFC 3339 has no years before 0
+        let far = datetime!(-0001-01-01 0:00 UTC);
+        assert!(to_string(&Event {
+            secs: far,
+            millis: far,
+            text: far,
+        })
+        .is_err());
+        assert_malformed::<Event>();
+    }
+}
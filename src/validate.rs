@@ -0,0 +1,376 @@
+use std::str;
+
+use crate::error::{Error, Result};
+use crate::frames::incomplete;
+use crate::parse::{split_frame, TNetStringError, TNetStringType};
+
+/// Limits for `validate_with` and `inspect_with`.
+#[derive(Clone, Debug)]
+pub struct ValidateConfig {
+    max_depth: usize,
+    max_size: usize,
+}
+
+impl Default for ValidateConfig {
+    fn default() -> Self {
+        ValidateConfig {
+            max_depth: 128,
+            max_size: 0,
+        }
+    }
+}
+
+impl ValidateConfig {
+    pub fn new() -> Self {
+        ValidateConfig::default()
+    }
+
+    /// Fail with `Error::DepthLimitExceeded` on lists and dicts nested
+    /// deeper than this. Defaults to 128, `0` means no limit.
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = limit;
+        self
+    }
+
+    /// Fail with `Error::InputLimitExceeded` on input longer than this many
+    /// bytes. `0`, the default, means no limit.
+    pub fn max_size(mut self, limit: usize) -> Self {
+        self.max_size = limit;
+        self
+    }
+}
+
+/// Check that `input` is exactly one well formed tnetstring, without
+/// decoding it.
+///
+/// Every length prefix and type tag is checked, along with the payloads of
+/// integers, floats, bools and nulls, and that dict keys are strings.
+/// Failures are wrapped in `Error::At` with the offset of the offending
+/// byte.
+pub fn validate(input: &[u8]) -> Result<()> {
+    validate_with(input, &ValidateConfig::default())
+}
+
+pub fn validate_with(input: &[u8], config: &ValidateConfig) -> Result<()> {
+    walk(input, config).map(|_| ())
+}
+
+/// Size and shape of an encoded message, see `inspect`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// How deeply lists and dicts are nested, `0` for a lone scalar.
+    pub max_depth: usize,
+    /// Every value, counting containers, their items, and dict keys.
+    pub elements: usize,
+    /// Key and value pairs across all dicts.
+    pub dict_entries: usize,
+    /// The longest string payload, keys included.
+    pub largest_str: usize,
+    pub total_bytes: usize,
+}
+
+/// Gather `Stats` about `input` in the same pass that `validate` makes over
+/// it, with the same checks and default limits.
+pub fn inspect(input: &[u8]) -> Result<Stats> {
+    inspect_with(input, &ValidateConfig::default())
+}
+
+pub fn inspect_with(input: &[u8], config: &ValidateConfig) -> Result<Stats> {
+    walk(input, config)
+}
+
+fn walk(input: &[u8], config: &ValidateConfig) -> Result<Stats> {
+    if config.max_size != 0 && input.len() > config.max_size {
+        return Err(Error::InputLimitExceeded {
+            limit: config.max_size,
+            size: input.len(),
+        });
+    }
+    let mut validator = Validator {
+        input,
+        max_depth: config.max_depth,
+        stats: Stats {
+            total_bytes: input.len(),
+            ..Stats::default()
+        },
+    };
+    let (_, end) = validator
+        .value(0, input.len(), 0)
+        .map_err(|error| incomplete(input).map_or(error, |error| at(0, error)))?;
+    if end != input.len() {
+        return Err(at(end, Error::UnusedParseData));
+    }
+    Ok(validator.stats)
+}
+
+fn at(offset: usize, error: Error) -> Error {
+    Error::At {
+        offset,
+        error: Box::new(error),
+    }
+}
+
+struct Validator<'a> {
+    input: &'a [u8],
+    max_depth: usize,
+    stats: Stats,
+}
+
+impl Validator<'_> {
+    // Checks the value starting at `start`, which has to end by `limit`, and
+    // returns its type and where it ends.
+    fn value(
+        &mut self,
+        start: usize,
+        limit: usize,
+        depth: usize,
+    ) -> Result<(TNetStringType, usize)> {
+        let (payload, tag, rest) = split_frame(&self.input[start..limit]).map_err(|error| {
+            at(
+                start,
+                match error {
+                    TNetStringError::UnableToParseInt => Error::ParsingLength,
+                    TNetStringError::UnableToTake => Error::Eof,
+                    _ => Error::LengthNotFound,
+                },
+            )
+        })?;
+        let len = payload.len();
+        let tag_pos = limit - rest.len() - 1;
+        let payload_start = tag_pos - len;
+        self.stats.elements += 1;
+        let kind = match tag {
+            b',' => {
+                self.stats.largest_str = self.stats.largest_str.max(len);
+                TNetStringType::Str
+            }
+            b'#' if is_int(payload) => TNetStringType::Int,
+            b'#' => return Err(at(payload_start, Error::ParsingInt)),
+            b'^' if is_float(payload) => TNetStringType::Float,
+            b'^' => return Err(at(payload_start, Error::ParsingFloat)),
+            b'!' if payload == b"true" || payload == b"false" => TNetStringType::Bool,
+            b'!' => return Err(at(payload_start, Error::ParsingBool)),
+            b'~' if payload.is_empty() => TNetStringType::Null,
+            b'~' => return Err(at(payload_start, Error::ParsingUnit)),
+            b']' => {
+                self.enter(start, depth)?;
+                let mut pos = payload_start;
+                while pos < tag_pos {
+                    pos = self.value(pos, tag_pos, depth + 1)?.1;
+                }
+                TNetStringType::List
+            }
+            b'}' => {
+                self.enter(start, depth)?;
+                let mut pos = payload_start;
+                while pos < tag_pos {
+                    let (key, key_end) = self.value(pos, tag_pos, depth + 1)?;
+                    if key != TNetStringType::Str {
+                        return Err(at(pos, Error::NonStringKey(key)));
+                    }
+                    if key_end == tag_pos {
+                        return Err(at(key_end, Error::ParsingMap));
+                    }
+                    pos = self.value(key_end, tag_pos, depth + 1)?.1;
+                    self.stats.dict_entries += 1;
+                }
+                TNetStringType::Dict
+            }
+            _ => return Err(at(tag_pos, Error::UnknownSegmentType)),
+        };
+        Ok((kind, tag_pos + 1))
+    }
+
+    fn enter(&mut self, start: usize, depth: usize) -> Result<()> {
+        if self.max_depth != 0 && depth >= self.max_depth {
+            return Err(at(start, Error::DepthLimitExceeded(self.max_depth)));
+        }
+        self.stats.max_depth = self.stats.max_depth.max(depth + 1);
+        Ok(())
+    }
+}
+
+fn is_int(payload: &[u8]) -> bool {
+    let digits = payload.strip_prefix(b"-").unwrap_or(payload);
+    !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+}
+
+// anything `from_str` reads back, including `nan` and `inf`
+fn is_float(payload: &[u8]) -> bool {
+    str::from_utf8(payload).is_ok_and(|payload| payload.parse::<f64>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inspect, inspect_with, validate, validate_with, Stats, ValidateConfig};
+    use crate::error::Error;
+    use crate::parse::TNetStringType;
+
+    fn error_at(input: &str) -> (usize, Error) {
+        match validate(input.as_bytes()) {
+            Err(Error::At { offset, error }) => (offset, *error),
+            other => panic!("{:?}: {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn test_valid() {
+        for input in &[
+            "5:hello,",
+            "0:,",
+            "2:42#",
+            "2:-7#",
+            "3:1.5^",
+            "3:nan^",
+            "4:true!",
+            "5:false!",
+            "0:~",
+            "0:]",
+            "0:}",
+            "22:1:a,14:1:1#4:true!0:~]}",
+        ] {
+            assert_eq!(validate(input.as_bytes()), Ok(()), "{}", input);
+        }
+        assert_eq!(validate(b"5:\xff\x00\x01\x02\x03,"), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(error_at("5:hello,x"), (8, Error::UnusedParseData));
+        assert_eq!(error_at("5:hello,0:~"), (8, Error::UnusedParseData));
+        assert_eq!(error_at(""), (0, Error::Incomplete { needed: None }));
+        assert_eq!(error_at("x:,"), (0, Error::LengthNotFound));
+        assert_eq!(error_at("5xhello,"), (0, Error::LengthNotFound));
+        assert_eq!(error_at("8:1:a,5xa,]"), (6, Error::LengthNotFound));
+        assert_eq!(
+            error_at("5:hello"),
+            (0, Error::Incomplete { needed: Some(1) })
+        );
+        assert_eq!(error_at("5:hello?"), (7, Error::UnknownSegmentType));
+        assert_eq!(error_at("2:4x#"), (2, Error::ParsingInt));
+        assert_eq!(error_at("1:-#"), (2, Error::ParsingInt));
+        assert_eq!(error_at("0:#"), (2, Error::ParsingInt));
+        assert_eq!(error_at("3:1.x^"), (2, Error::ParsingFloat));
+        assert_eq!(error_at("3:yes!"), (2, Error::ParsingBool));
+        assert_eq!(error_at("1:x~"), (2, Error::ParsingUnit));
+        assert_eq!(
+            error_at("99999999999999999999999:"),
+            (0, Error::ParsingLength)
+        );
+    }
+
+    #[test]
+    fn test_bad_nested_lengths() {
+        // the inner string claims more bytes than the list holds
+        assert_eq!(error_at("8:6:hello,]"), (2, Error::Eof));
+        // and here it leaves a stray byte behind in the list
+        assert_eq!(error_at("9:5:hello,x]"), (10, Error::LengthNotFound));
+        assert_eq!(error_at("7:4:1:a,}]"), (8, Error::ParsingMap));
+        assert_eq!(error_at("5:1:a,x]"), (6, Error::LengthNotFound));
+    }
+
+    #[test]
+    fn test_dict_keys() {
+        assert_eq!(
+            error_at("8:1:1#1:a,}"),
+            (2, Error::NonStringKey(TNetStringType::Int))
+        );
+        assert_eq!(
+            error_at("19:1:a,1:b,0:]5:false,}"),
+            (11, Error::NonStringKey(TNetStringType::List))
+        );
+        assert_eq!(error_at("4:1:a,}"), (6, Error::ParsingMap));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut deep = "0:]".to_string();
+        for _ in 0..200 {
+            deep = format!("{}:{}]", deep.len(), deep);
+        }
+        assert_eq!(
+            validate(deep.as_bytes()),
+            Err(Error::At {
+                offset: 512,
+                error: Box::new(Error::DepthLimitExceeded(128)),
+            })
+        );
+        let config = ValidateConfig::new().max_depth(0);
+        assert_eq!(validate_with(deep.as_bytes(), &config), Ok(()));
+        let config = ValidateConfig::new().max_depth(2);
+        assert_eq!(validate_with(b"3:0:]]", &config), Ok(()));
+        assert!(validate_with(b"6:3:0:]]]", &config).is_err());
+
+        let config = ValidateConfig::new().max_size(8);
+        assert_eq!(validate_with(b"5:hello,", &config), Ok(()));
+        assert_eq!(
+            validate_with(b"6:hello!,", &config),
+            Err(Error::InputLimitExceeded { limit: 8, size: 9 })
+        );
+    }
+
+    #[test]
+    fn test_inspect() {
+        let input =
+            b"76:2:id,2:42#4:user,31:4:name,5:alice,5:admin,5:false!}4:tags,13:1:a,3:1.5^0:~]}";
+        assert_eq!(
+            inspect(input),
+            Ok(Stats {
+                max_depth: 2,
+                elements: 14,
+                dict_entries: 5,
+                largest_str: 5,
+                total_bytes: 80,
+            })
+        );
+        assert_eq!(
+            inspect(b"0:~"),
+            Ok(Stats {
+                max_depth: 0,
+                elements: 1,
+                dict_entries: 0,
+                largest_str: 0,
+                total_bytes: 3,
+            })
+        );
+        assert_eq!(inspect(b"0:]").map(|stats| stats.max_depth), Ok(1));
+    }
+
+    #[test]
+    fn test_inspect_limits() {
+        let mut deep = "0:]".to_string();
+        for _ in 0..200 {
+            deep = format!("{}:{}]", deep.len(), deep);
+        }
+        assert!(inspect(deep.as_bytes()).is_err());
+        let config = ValidateConfig::new().max_depth(0);
+        assert_eq!(
+            inspect_with(deep.as_bytes(), &config).map(|stats| stats.max_depth),
+            Ok(201)
+        );
+
+        let config = ValidateConfig::new().max_size(4);
+        assert_eq!(
+            inspect_with(b"5:hello,", &config),
+            Err(Error::InputLimitExceeded { limit: 4, size: 8 })
+        );
+    }
+
+    #[test]
+    fn test_inspect_malformed() {
+        assert_eq!(
+            inspect(b"19:1:a,1:b,0:]5:false,}"),
+            Err(Error::At {
+                offset: 11,
+                error: Box::new(Error::NonStringKey(TNetStringType::List)),
+            })
+        );
+        assert_eq!(
+            inspect(b"5:hello,x"),
+            Err(Error::At {
+                offset: 8,
+                error: Box::new(Error::UnusedParseData),
+            })
+        );
+    }
+}
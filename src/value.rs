@@ -0,0 +1,2428 @@
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess,
+    IntoDeserializer, MapAccess, SeqAccess, Unexpected, VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde::ser::{self, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Write};
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ops;
+use std::str::FromStr;
+
+use crate::diff::{self, Difference};
+use crate::error::{Error, Result};
+use crate::map::Map;
+use crate::number::Number;
+use crate::parse::TNetStringType;
+use crate::raw;
+
+/// Convert `value` into a `Value` tree, laid out the same way `to_string`
+/// would write it, without going through text.
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ValueSerializer)
+}
+
+/// Deserialize a `T` out of a `Value` tree, without going through text.
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+/// Any tnetstring, for when there's no type to deserialize into.
+///
+/// Integers and floats are told apart by their `#` and `^` tags, so `1:1^`
+/// is a float `Number` that isn't equal to the integer in `1:1#`. A `,`
+/// string is a `Str` when it's valid UTF-8 and `Bytes` otherwise; both are
+/// written back out as the same raw bytes.
+///
+/// Values have a total order, so they can be map keys and sorted: `Null` <
+/// `Bool` < `Number` < `Str` and `Bytes` < `List` < `Dict`. Numbers are
+/// ordered as described on `Number`, strings and bytes together by their
+/// bytes, with a `Str` just before `Bytes` holding the same ones, lists item
+/// by item, and dicts by their entries in key order, whichever order the
+/// `Map` keeps them in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(Number),
+    Str(String),
+    /// A string payload that isn't valid UTF-8.
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(Map),
+}
+
+impl Value {
+    pub(crate) fn kind(&self) -> TNetStringType {
+        match *self {
+            Value::Null => TNetStringType::Null,
+            Value::Bool(_) => TNetStringType::Bool,
+            Value::Number(ref n) if n.is_f64() => TNetStringType::Float,
+            Value::Number(_) => TNetStringType::Int,
+            Value::Str(_) | Value::Bytes(_) => TNetStringType::Str,
+            Value::List(_) => TNetStringType::List,
+            Value::Dict(_) => TNetStringType::Dict,
+        }
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        match *self {
+            Value::Null => Unexpected::Unit,
+            Value::Bool(v) => Unexpected::Bool(v),
+            Value::Number(ref n) => n.unexpected(),
+            Value::Str(ref v) => Unexpected::Str(v),
+            Value::Bytes(ref v) => Unexpected::Bytes(v),
+            Value::List(_) => Unexpected::Seq,
+            Value::Dict(_) => Unexpected::Map,
+        }
+    }
+
+    /// Serialize to a tnetstring, writing dict entries in the `Map`'s order.
+    ///
+    /// `Bytes` can't go in a `String` as they are, so they're written as
+    /// strings with `U+FFFD` in place of what isn't UTF-8, framed with their
+    /// new lengths. The output always parses, but back into `Str`s; use
+    /// `to_vec` for values that might hold `Bytes`.
+    pub fn to_tnetstring(&self) -> String {
+        // keys are always strings and non-finite floats are written as
+        // literals, so there's nothing that can fail
+        let output = crate::ser::to_vec(&LossyText(self)).expect("a Value always serializes");
+        String::from_utf8(output).expect("every payload is UTF-8")
+    }
+
+    /// Render the value as an indented tree with each value's type, for
+    /// reading rather than parsing.
+    pub fn dump_pretty(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0);
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, depth: usize) {
+        // `String` never fails
+        let _ = match *self {
+            Value::Null => write!(output, "null"),
+            Value::Bool(v) => write!(output, "bool {}", v),
+            Value::Number(ref n) if n.is_f64() => write!(output, "float {}", n),
+            Value::Number(ref n) => write!(output, "int {}", n),
+            Value::Str(ref v) => write!(output, "str {:?}", v),
+            Value::Bytes(ref v) => write!(output, "bytes \"{}\"", v.escape_ascii()),
+            Value::List(ref list) if list.is_empty() => write!(output, "list []"),
+            Value::Dict(ref dict) if dict.is_empty() => write!(output, "dict {{}}"),
+            Value::List(ref list) => {
+                output.push_str("list [\n");
+                for value in list {
+                    indent(output, depth + 1);
+                    value.write_pretty(output, depth + 1);
+                    output.push_str(",\n");
+                }
+                indent(output, depth);
+                write!(output, "]")
+            }
+            Value::Dict(ref dict) => {
+                output.push_str("dict {\n");
+                for (key, value) in dict {
+                    indent(output, depth + 1);
+                    let _ = write!(output, "{:?}: ", key);
+                    value.write_pretty(output, depth + 1);
+                    output.push_str(",\n");
+                }
+                indent(output, depth);
+                write!(output, "}}")
+            }
+        };
+    }
+
+    /// Deserialize a `T` out of this value, borrowing strings from it where
+    /// `T` allows.
+    pub fn deserialize_into<'de, T>(&'de self) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        T::deserialize(self)
+    }
+
+    /// Look up a dict entry by key or a list item by position, or `None` if
+    /// there isn't one.
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&Value> {
+        index.index_into(self)
+    }
+
+    pub fn get_mut<I: ValueIndex>(&mut self, index: I) -> Option<&mut Value> {
+        index.index_into_mut(self)
+    }
+
+    /// Look up a nested value by a JSON Pointer (RFC 6901) style path such
+    /// as `/headers/host` or `/items/0/id`.
+    ///
+    /// Segments index lists when they're a position and dicts otherwise,
+    /// with `~1` and `~0` standing for `/` and `~` in keys. The empty path is
+    /// the value itself.
+    pub fn pointer(&self, path: &str) -> Option<&Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        if !path.starts_with('/') {
+            return None;
+        }
+        path.split('/')
+            .skip(1)
+            .map(unescape_token)
+            .try_fold(self, |target, token| match *target {
+                Value::Dict(ref dict) => dict.get(&token),
+                Value::List(ref list) => parse_position(&token).and_then(|i| list.get(i)),
+                _ => None,
+            })
+    }
+
+    pub fn pointer_mut(&mut self, path: &str) -> Option<&mut Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        if !path.starts_with('/') {
+            return None;
+        }
+        path.split('/')
+            .skip(1)
+            .map(unescape_token)
+            .try_fold(self, |target, token| match *target {
+                Value::Dict(ref mut dict) => dict.get_mut(&token),
+                Value::List(ref mut list) => {
+                    parse_position(&token).and_then(move |i| list.get_mut(i))
+                }
+                _ => None,
+            })
+    }
+
+    /// Deep merge `other` into this value, the way JSON Merge Patch
+    /// (RFC 7396) does.
+    ///
+    /// Dicts are merged key by key, recursively, with `other` winning. A
+    /// `Null` in an `other` dict removes the key. Anything else, including
+    /// lists, replaces the value wholesale.
+    pub fn merge(&mut self, other: Value) {
+        let other = match other {
+            Value::Dict(other) => other,
+            other => {
+                *self = other;
+                return;
+            }
+        };
+        if !matches!(*self, Value::Dict(_)) {
+            *self = Value::Dict(Map::new());
+        }
+        if let Value::Dict(ref mut dict) = *self {
+            for (key, value) in other {
+                if value.is_null() {
+                    dict.remove(&key);
+                } else {
+                    dict.entry(key).or_insert(Value::Null).merge(value);
+                }
+            }
+        }
+    }
+
+    /// `merge` for when the merged value is all that's needed.
+    pub fn merged(mut self, other: Value) -> Value {
+        self.merge(other);
+        self
+    }
+
+    /// Compare against `other`, listing every place they differ. Dict keys
+    /// are visited in sorted order and list items by position.
+    pub fn diff(&self, other: &Value) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff::walk(&mut String::new(), self, other, &mut differences);
+        differences
+    }
+
+    /// Normalize the value in place, so equal data always encodes the same.
+    ///
+    /// Dicts are sorted by their encoded keys, the order
+    /// `SerializerConfig::canonical` writes them in, and `-0.0` becomes
+    /// `0.0`. Without the `preserve_order` feature dicts are always in key
+    /// order instead, so write them canonically to get the same bytes.
+    pub fn canonicalize(&mut self) {
+        self.canonicalize_with(&CanonicalizeOptions::new());
+    }
+
+    /// `canonicalize` with other rules for floats.
+    pub fn canonicalize_with(&mut self, options: &CanonicalizeOptions) {
+        match *self {
+            Value::Number(ref mut n) => n.canonicalize(options.integer_floats),
+            Value::List(ref mut list) => {
+                for value in list {
+                    value.canonicalize_with(options);
+                }
+            }
+            Value::Dict(ref mut dict) => {
+                dict.sort_canonical();
+                for value in dict.values_mut() {
+                    value.canonicalize_with(options);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `canonicalize` for when the normalized value is all that's needed.
+    pub fn canonicalized(mut self) -> Value {
+        self.canonicalize();
+        self
+    }
+
+    /// Move the value out, leaving `Null` in its place.
+    pub fn take(&mut self) -> Value {
+        mem::replace(self, Value::Null)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(*self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<&Number> {
+        match *self {
+            Value::Number(ref n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_number().and_then(Number::as_i64)
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_number().and_then(Number::as_u64)
+    }
+
+    /// Any number as a float, see `Number::as_f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_number().and_then(Number::as_f64)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The payload of a `Str` or `Bytes`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Str(ref v) => Some(v.as_bytes()),
+            Value::Bytes(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::List(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&Map> {
+        match *self {
+            Value::Dict(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict_mut(&mut self) -> Option<&mut Map> {
+        match *self {
+            Value::Dict(ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// How `Value::canonicalize_with` normalizes floats.
+#[derive(Clone, Debug, Default)]
+pub struct CanonicalizeOptions {
+    integer_floats: bool,
+}
+
+impl CanonicalizeOptions {
+    pub fn new() -> Self {
+        CanonicalizeOptions::default()
+    }
+
+    /// Turn floats with integer values, like `2.0`, into integers when they
+    /// fit an `i64` or `u64`. Off by default.
+    pub fn integer_floats(mut self, enabled: bool) -> Self {
+        self.integer_floats = enabled;
+        self
+    }
+}
+
+fn unescape_token(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+// list positions are plain digits, without a sign or leading zeros
+fn parse_position(token: &str) -> Option<usize> {
+    if token.starts_with('+') || (token.starts_with('0') && token.len() > 1) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Something a `Value` can be indexed with: a `usize` for lists, or a
+/// string for dicts.
+pub trait ValueIndex: private::Sealed {
+    #[doc(hidden)]
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value>;
+
+    #[doc(hidden)]
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value>;
+}
+
+impl ValueIndex for usize {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        match *value {
+            Value::List(ref list) => list.get(*self),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        match *value {
+            Value::List(ref mut list) => list.get_mut(*self),
+            _ => None,
+        }
+    }
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        match *value {
+            Value::Dict(ref dict) => dict.get(self),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        match *value {
+            Value::Dict(ref mut dict) => dict.get_mut(self),
+            _ => None,
+        }
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        self.as_str().index_into_mut(value)
+    }
+}
+
+impl<T> ValueIndex for &T
+where
+    T: ?Sized + ValueIndex,
+{
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        (**self).index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        (**self).index_into_mut(value)
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for usize {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl<T: ?Sized + Sealed> Sealed for &T {}
+}
+
+static NULL: Value = Value::Null;
+
+// missing keys and out of range positions give `Null` rather than panicking
+impl<I> ops::Index<I> for Value
+where
+    I: ValueIndex,
+{
+    type Output = Value;
+
+    fn index(&self, index: I) -> &Value {
+        index.index_into(self).unwrap_or(&NULL)
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => a.cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Str(a), Value::Bytes(b)) => a.as_bytes().cmp(b).then(Ordering::Less),
+            (Value::Bytes(a), Value::Str(b)) => a[..].cmp(b.as_bytes()).then(Ordering::Greater),
+            (Value::List(a), Value::List(b)) => a.cmp(b),
+            (Value::Dict(a), Value::Dict(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match *self {
+            Value::Null => {}
+            Value::Bool(v) => v.hash(state),
+            Value::Number(ref v) => v.hash(state),
+            Value::Str(ref v) => v.hash(state),
+            Value::Bytes(ref v) => v.hash(state),
+            Value::List(ref v) => v.hash(state),
+            Value::Dict(ref v) => v.hash(state),
+        }
+    }
+}
+
+impl Value {
+    // where each kind of value comes in the order
+    fn rank(&self) -> u8 {
+        match *self {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::Str(_) | Value::Bytes(_) => 3,
+            Value::List(_) => 4,
+            Value::Dict(_) => 5,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Value {
+        Value::Bool(v)
+    }
+}
+
+macro_rules! from_integer {
+    ($($signed:ty)*; $($unsigned:ty)*) => {
+        $(
+            impl From<$signed> for Value {
+                fn from(v: $signed) -> Value {
+                    Value::Number(i64::from(v).into())
+                }
+            }
+        )*
+        $(
+            impl From<$unsigned> for Value {
+                fn from(v: $unsigned) -> Value {
+                    Value::Number(u64::from(v).into())
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i8 i16 i32 i64; u8 u16 u32 u64);
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Value {
+        Value::Number(f64::from(v).into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Value {
+        Value::Number(v.into())
+    }
+}
+
+impl From<Number> for Value {
+    fn from(v: Number) -> Value {
+        Value::Number(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Value {
+        Value::Str(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::Str(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Value {
+        Value::List(v)
+    }
+}
+
+fn indent(output: &mut String, depth: usize) {
+    for _ in 0..depth {
+        output.push_str("    ");
+    }
+}
+
+// the tnetstring encoding, the same as `to_tnetstring`
+impl Display for Value {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.to_tnetstring())
+    }
+}
+
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Value> {
+        crate::de::from_str(s)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(v) => serializer.serialize_bool(v),
+            Value::Number(ref v) => v.serialize(serializer),
+            Value::Str(ref v) => serializer.serialize_str(v),
+            Value::Bytes(ref v) => serializer.serialize_bytes(v),
+            Value::List(ref v) => serializer.collect_seq(v),
+            Value::Dict(ref v) => serializer.collect_map(v),
+        }
+    }
+}
+
+// Serializes a value with its `Bytes` as lossily decoded strings.
+struct LossyText<'a>(&'a Value);
+
+impl Serialize for LossyText<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self.0 {
+            Value::Bytes(ref v) => serializer.serialize_str(&String::from_utf8_lossy(v)),
+            Value::List(ref v) => serializer.collect_seq(v.iter().map(LossyText)),
+            Value::Dict(ref v) => serializer.collect_map(v.iter().map(|(k, v)| (k, LossyText(v)))),
+            ref value => value.serialize(serializer),
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any tnetstring value")
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> std::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Value, E> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> std::result::Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v.to_owned()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut dict = Map::new();
+        let first: String = match map.next_key()? {
+            Some(key) => key,
+            None => return Ok(Value::Dict(dict)),
+        };
+        #[cfg(feature = "arbitrary_precision")]
+        {
+            if first == crate::number::TOKEN {
+                return wide_number(map.next_value()?);
+            }
+        }
+        dict.insert(first, map.next_value()?);
+        while let Some((key, value)) = map.next_entry()? {
+            dict.insert(key, value);
+        }
+        Ok(Value::Dict(dict))
+    }
+}
+
+#[cfg(feature = "arbitrary_precision")]
+fn wide_number<E: de::Error>(digits: String) -> std::result::Result<Value, E> {
+    Number::from_digits(&digits)
+        .map(Value::Number)
+        .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&digits), &"an integer"))
+}
+
+// An integer too wide for 64 bits, which only `arbitrary_precision` can hold.
+#[cfg(feature = "arbitrary_precision")]
+fn wide_integer<T: Display>(v: T) -> Result<Value> {
+    let digits = v.to_string();
+    Number::from_digits(&digits)
+        .map(Value::Number)
+        .ok_or_else(|| ser::Error::custom(format!("{} isn't an integer", digits)))
+}
+
+#[cfg(not(feature = "arbitrary_precision"))]
+fn wide_integer<T: Display>(v: T) -> Result<Value> {
+    Err(ser::Error::custom(format!("{} doesn't fit in a Number", v)))
+}
+
+// Overwrites a value, reusing the buffers of its strings, lists and dicts
+// where the new value has the same type.
+struct ValueInPlaceVisitor<'a>(&'a mut Value);
+
+impl<'de> Visitor<'de> for ValueInPlaceVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any tnetstring value")
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<(), E> {
+        *self.0 = Value::Null;
+        Ok(())
+    }
+
+    fn visit_none<E>(self) -> std::result::Result<(), E> {
+        *self.0 = Value::Null;
+        Ok(())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> std::result::Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize_in_place(deserializer, self.0)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<(), E> {
+        *self.0 = Value::Bool(v);
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<(), E> {
+        *self.0 = v.into();
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<(), E> {
+        *self.0 = v.into();
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<(), E> {
+        *self.0 = v.into();
+        Ok(())
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<(), E> {
+        match *self.0 {
+            Value::Str(ref mut s) => {
+                s.clear();
+                s.push_str(v);
+            }
+            _ => *self.0 = Value::Str(v.to_owned()),
+        }
+        Ok(())
+    }
+
+    fn visit_string<E>(self, v: String) -> std::result::Result<(), E> {
+        *self.0 = Value::Str(v);
+        Ok(())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<(), E> {
+        match *self.0 {
+            Value::Bytes(ref mut bytes) => {
+                bytes.clear();
+                bytes.extend_from_slice(v);
+            }
+            _ => *self.0 = Value::Bytes(v.to_owned()),
+        }
+        Ok(())
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<(), E> {
+        *self.0 = Value::Bytes(v);
+        Ok(())
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        if !matches!(*self.0, Value::List(_)) {
+            *self.0 = Value::List(Vec::new());
+        }
+        if let Value::List(ref mut list) = *self.0 {
+            for filled in 0..list.len() {
+                if seq.next_element_seed(InPlace(&mut list[filled]))?.is_none() {
+                    list.truncate(filled);
+                    return Ok(());
+                }
+            }
+            while let Some(value) = seq.next_element()? {
+                list.push(value);
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let first: Option<String> = map.next_key()?;
+        #[cfg(feature = "arbitrary_precision")]
+        {
+            if first.as_deref() == Some(crate::number::TOKEN) {
+                *self.0 = wide_number(map.next_value()?)?;
+                return Ok(());
+            }
+        }
+        if !matches!(*self.0, Value::Dict(_)) {
+            *self.0 = Value::Dict(Map::new());
+        }
+        match *self.0 {
+            Value::Dict(ref mut dict) => dict.refill(first, map),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Deserializes over an existing value, for the items of lists and dicts.
+pub(crate) struct InPlace<'a>(pub(crate) &'a mut Value);
+
+impl<'de> DeserializeSeed<'de> for InPlace<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize_in_place(deserializer, self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+
+    /// Strings, bytes and lists keep their buffers when the new value has
+    /// the same type, as do the values under keys a dict had before.
+    fn deserialize_in_place<D>(
+        deserializer: D,
+        place: &mut Value,
+    ) -> std::result::Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueInPlaceVisitor(place))
+    }
+}
+
+fn dict_key(key: Value) -> Result<String> {
+    match key {
+        Value::Str(key) => Ok(key),
+        Value::Bytes(_) => Err(Error::NonUtf8Str),
+        other => Err(Error::NonStringKey(other.kind())),
+    }
+}
+
+fn variant_dict(variant: &'static str, value: Value) -> Value {
+    let mut dict = Map::new();
+    dict.insert(variant.to_owned(), value);
+    Value::Dict(dict)
+}
+
+// Builds a `Value` the same shape as the tnetstring `Serializer` writes with
+// the default config.
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeDict;
+    type SerializeStruct = SerializeDict;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        Ok(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value> {
+        if let Ok(v) = u64::try_from(v) {
+            return Ok(v.into());
+        }
+        match i64::try_from(v) {
+            Ok(v) => Ok(v.into()),
+            Err(_) => wide_integer(v),
+        }
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value> {
+        match u64::try_from(v) {
+            Ok(v) => Ok(v.into()),
+            Err(_) => wide_integer(v),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(v.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(String::from_utf8(v.to_vec()).map_or_else(|e| Value::Bytes(e.into_bytes()), Value::Str))
+    }
+
+    fn collect_str<T>(self, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Display,
+    {
+        Ok(Value::Str(value.to_string()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        Ok(Value::Str(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == raw::TOKEN {
+            return value.serialize(raw::Emitter(|bytes: &[u8]| crate::de::from_slice(bytes)));
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(variant_dict(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeList(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            variant,
+            list: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(SerializeDict {
+            dict: Map::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            variant,
+            dict: Map::new(),
+        })
+    }
+}
+
+struct SerializeList(Vec<Value>);
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::List(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeTupleVariant {
+    variant: &'static str,
+    list: Vec<Value>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.list.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(variant_dict(self.variant, Value::List(self.list)))
+    }
+}
+
+struct SerializeDict {
+    dict: Map,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeDict {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = Some(dict_key(to_value(key)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self.key.take().ok_or(Error::StackProblem)?;
+        self.dict.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Dict(self.dict))
+    }
+}
+
+impl ser::SerializeStruct for SerializeDict {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.dict.insert(key.to_owned(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Dict(self.dict))
+    }
+}
+
+struct SerializeStructVariant {
+    variant: &'static str,
+    dict: Map,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.dict.insert(key.to_owned(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(variant_dict(self.variant, Value::Dict(self.dict)))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+// Reads a `Value` the way the tnetstring `Deserializer` reads the text it
+// came from.
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Number(n) => n.deserialize_any(visitor),
+            Value::Str(v) => visitor.visit_string(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::List(v) => {
+                let mut seq = SeqDeserializer::new(v.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Dict(v) => {
+                let mut map = MapDeserializer::new(v.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Str(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Dict(dict) if dict.len() == 1 => {
+                let (variant, value) = dict.into_iter().next().ok_or(Error::ParsingEnum)?;
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            }
+            other => Err(de::Error::invalid_type(
+                other.unexpected(),
+                &"a string or a dict with a single key",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Value)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(Value::Str(self.variant))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Value {
+    type Error = Error;
+
+    // `{name: null}`, as written by `unit_variants_as_dicts`
+    fn unit_variant(self) -> Result<()> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de Value {
+    type Deserializer = &'de Value;
+
+    fn into_deserializer(self) -> &'de Value {
+        self
+    }
+}
+
+// Same as the impl for `Value`, but borrows strings out of the tree instead
+// of taking it apart.
+impl<'de> Deserializer<'de> for &'de Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match *self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Number(ref n) => n.deserialize_any(visitor),
+            Value::Str(ref v) => visitor.visit_borrowed_str(v),
+            Value::Bytes(ref v) => visitor.visit_borrowed_bytes(v),
+            Value::List(ref v) => {
+                let mut seq = SeqDeserializer::new(v.iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Dict(ref v) => {
+                let mut map = MapDeserializer::new(v.iter().map(|(k, v)| (k.as_str(), v)));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match *self {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match *self {
+            Value::Str(ref variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            Value::Dict(ref dict) if dict.len() == 1 => {
+                let (variant, value) = dict.iter().next().ok_or(Error::ParsingEnum)?;
+                visitor.visit_enum(EnumRefDeserializer { variant, value })
+            }
+            _ => Err(de::Error::invalid_type(
+                self.unexpected(),
+                &"a string or a dict with a single key",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct EnumRefDeserializer<'de> {
+    variant: &'de str,
+    value: &'de Value,
+}
+
+impl<'de> EnumAccess<'de> for EnumRefDeserializer<'de> {
+    type Error = Error;
+    type Variant = &'de Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, &'de Value)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &'de Value {
+    type Error = Error;
+
+    // `{name: null}`, as written by `unit_variants_as_dicts`
+    fn unit_variant(self) -> Result<()> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+}
+
+/// Build a `Value` from a JSON-like literal.
+///
+/// Keys are string expressions, values can be `null`, `true`, `false`,
+/// nested lists and dicts, or any expression whose type implements
+/// `Serialize`.
+///
+/// ```
+/// use tnetstring::tnet;
+///
+/// let port = 8080;
+/// let value = tnet!({"host": "localhost", "port": port, "tags": [1, true, null]});
+/// assert_eq!(value["port"].as_u64(), Some(8080));
+/// ```
+#[macro_export]
+macro_rules! tnet {
+    ($($tt:tt)+) => {
+        $crate::tnet_internal!($($tt)+)
+    };
+}
+
+// A token muncher, the same shape as `serde_json::json!`. Lists are collected
+// as `@list [elements] rest`, dicts as `@dict map (key tokens) (rest)`.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! tnet_internal {
+    (@list [$($elems:expr,)*]) => {
+        vec![$($elems,)*]
+    };
+    (@list [$($elems:expr),*]) => {
+        vec![$($elems),*]
+    };
+    (@list [$($elems:expr,)*] null $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!(null)] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] true $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!(true)] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] false $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!(false)] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] [$($list:tt)*] $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!([$($list)*])] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] {$($dict:tt)*} $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!({$($dict)*})] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] $next:expr, $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!($next),] $($rest)*)
+    };
+    (@list [$($elems:expr,)*] $last:expr) => {
+        $crate::tnet_internal!(@list [$($elems,)* $crate::tnet_internal!($last)])
+    };
+    (@list [$($elems:expr),*] , $($rest:tt)*) => {
+        $crate::tnet_internal!(@list [$($elems,)*] $($rest)*)
+    };
+
+    (@dict $dict:ident () ()) => {};
+    (@dict $dict:ident [$($key:tt)+] ($value:expr) , $($rest:tt)*) => {
+        let _ = $dict.insert(($($key)+).into(), $value);
+        $crate::tnet_internal!(@dict $dict () ($($rest)*));
+    };
+    (@dict $dict:ident [$($key:tt)+] ($value:expr)) => {
+        let _ = $dict.insert(($($key)+).into(), $value);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: null $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!(null)) $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: true $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!(true)) $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: false $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!(false)) $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: [$($list:tt)*] $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!([$($list)*])) $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: {$($inner:tt)*} $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!({$($inner)*})) $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: $value:expr , $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!($value)) , $($rest)*);
+    };
+    (@dict $dict:ident ($($key:tt)+) (: $value:expr)) => {
+        $crate::tnet_internal!(@dict $dict [$($key)+] ($crate::tnet_internal!($value)));
+    };
+    // a key in parentheses is taken whole
+    (@dict $dict:ident () (($key:expr) : $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict ($key) (: $($rest)*));
+    };
+    // otherwise the key is every token up to the colon
+    (@dict $dict:ident ($($key:tt)*) ($tt:tt $($rest:tt)*)) => {
+        $crate::tnet_internal!(@dict $dict ($($key)* $tt) ($($rest)*));
+    };
+
+    (null) => {
+        $crate::Value::Null
+    };
+    (true) => {
+        $crate::Value::Bool(true)
+    };
+    (false) => {
+        $crate::Value::Bool(false)
+    };
+    ([]) => {
+        $crate::Value::List(vec![])
+    };
+    ([ $($tt:tt)+ ]) => {
+        $crate::Value::List($crate::tnet_internal!(@list [] $($tt)+))
+    };
+    ({}) => {
+        $crate::Value::Dict($crate::Map::new())
+    };
+    ({ $($tt:tt)+ }) => {
+        $crate::Value::Dict({
+            let mut dict = $crate::Map::new();
+            $crate::tnet_internal!(@dict dict () ($($tt)+));
+            dict
+        })
+    };
+    // anything `Serialize`, this has to be the last rule
+    ($other:expr) => {
+        $crate::to_value(&$other).expect("tnet! values must serialize to a Value")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_value, to_value, CanonicalizeOptions, Value};
+    use crate::de::from_str;
+    use crate::error::Error;
+    use crate::parse::TNetStringType;
+    use crate::ser::{to_string, to_vec_with, SerializerConfig};
+    use maplit::{btreemap, hashmap};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    fn dict(entries: BTreeMap<String, Value>) -> Value {
+        Value::Dict(entries.into_iter().collect())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum E {
+        Unit,
+        Newtype(u32),
+        Tuple(u32, u32),
+        Struct { a: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Message {
+        host: String,
+        port: u16,
+        ratio: f64,
+        tags: Vec<String>,
+        parent: Option<Box<Message>>,
+        kind: E,
+    }
+
+    fn round_trip(value: Value) {
+        let encoded = value.to_tnetstring();
+        assert_eq!(encoded.parse::<Value>(), Ok(value), "{}", encoded);
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!("0:~".parse(), Ok(Value::Null));
+        assert_eq!("4:true!".parse(), Ok(Value::Bool(true)));
+        assert_eq!("2:42#".parse(), Ok(Value::from(42)));
+        assert_eq!("2:42^".parse(), Ok(Value::from(42.0)));
+        assert_eq!("3:1.0^".parse(), Ok(Value::from(1.0)));
+        assert_eq!("5:hello,".parse(), Ok(Value::Str("hello".into())));
+
+        assert_eq!(Value::from(42).to_tnetstring(), "2:42#");
+        assert_eq!(Value::from(42.0).to_tnetstring(), "2:42^");
+        assert_eq!(Value::Null.to_tnetstring(), "0:~");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let input = b"22:3:bin,4:\xff\x00ab,1:s,2:ok,}";
+        let value: Value = crate::de::from_slice(input).unwrap();
+        assert_eq!(value["bin"], Value::Bytes(b"\xff\x00ab".to_vec()));
+        assert_eq!(value["s"], Value::Str("ok".into()));
+        assert_eq!(value["bin"].as_bytes(), Some(&b"\xff\x00ab"[..]));
+        assert_eq!(value["s"].as_bytes(), Some(&b"ok"[..]));
+        assert_eq!(value["bin"].as_str(), None);
+        assert_eq!(crate::ser::to_vec(&value).unwrap(), &input[..]);
+        assert_eq!(value["bin"].dump_pretty(), "bytes \"\\xff\\x00ab\"");
+
+        let bytes = serde_bytes::ByteBuf::from(vec![0xff, 0xfe]);
+        assert_eq!(to_value(&bytes), Ok(Value::Bytes(vec![0xff, 0xfe])));
+        assert_eq!(
+            to_value(serde_bytes::Bytes::new(b"ok")),
+            Ok(Value::Str("ok".into()))
+        );
+        let decoded: serde_bytes::ByteBuf = from_value(Value::Bytes(vec![0xff])).unwrap();
+        assert_eq!(decoded.into_vec(), [0xff]);
+        let decoded: serde_bytes::ByteBuf = value["bin"].deserialize_into().unwrap();
+        assert_eq!(decoded.into_vec(), b"\xff\x00ab");
+        assert!(from_value::<String>(Value::Bytes(vec![0xff])).is_err());
+    }
+
+    #[test]
+    fn test_str_bytes_ordering() {
+        let str_a = Value::Str("a".into());
+        let bytes_a = Value::Bytes(b"a".to_vec());
+        assert_ne!(str_a, bytes_a);
+        assert!(str_a < bytes_a);
+        assert!(bytes_a < Value::Str("b".into()));
+        assert!(Value::Bytes(vec![0xff]) > Value::Str("z".into()));
+        assert!(Value::from(1) < bytes_a && bytes_a < tnet!([]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(Value::List(vec![]));
+        round_trip(dict(btreemap! {}));
+        round_trip(Value::List(vec![
+            Value::Null,
+            Value::Bool(false),
+            Value::from(i64::MIN),
+            Value::from(-0.5),
+            Value::Str("".into()),
+            Value::List(vec![Value::List(vec![Value::from(1)]), Value::from(2)]),
+        ]));
+        round_trip(dict(btreemap! {
+            "a".into() => dict(btreemap! {
+                "b".into() => Value::List(vec![Value::from(1e20), Value::Null]),
+                "c".into() => dict(btreemap! {}),
+            }),
+            "d".into() => Value::Str("e".into()),
+        }));
+    }
+
+    #[cfg(not(feature = "preserve_order"))]
+    #[test]
+    fn test_dict_order() {
+        let value = dict(btreemap! {
+            "b".into() => Value::from(2),
+            "a".into() => Value::from(1),
+        });
+        assert_eq!(value.to_tnetstring(), "16:1:a,1:1#1:b,1:2#}");
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_preserve_order() {
+        let input = "61:5:zebra,1:1#5:apple,22:1:y,0:~1:x,8:1:b,1:a,]}5:mango,4:true!}";
+        let value: Value = input.parse().unwrap();
+        let keys: Vec<&str> = value.as_dict().unwrap().keys().map(|k| &k[..]).collect();
+        assert_eq!(keys, ["zebra", "apple", "mango"]);
+        assert_eq!(value.to_tnetstring(), input);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut values = vec![
+            tnet!({"b": 1, "a": 2}),
+            tnet!([1, 2]),
+            tnet!("b"),
+            Value::from(1.5),
+            tnet!({"a": 2}),
+            tnet!(true),
+            Value::from(1),
+            tnet!(null),
+            tnet!([1]),
+            tnet!(""),
+            tnet!(false),
+            Value::from(f64::NAN),
+            Value::from(-3),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                tnet!(null),
+                tnet!(false),
+                tnet!(true),
+                Value::from(-3),
+                Value::from(1),
+                Value::from(1.5),
+                Value::from(f64::NAN),
+                tnet!(""),
+                tnet!("b"),
+                tnet!([1]),
+                tnet!([1, 2]),
+                tnet!({"a": 2}),
+                tnet!({"a": 2, "b": 1}),
+            ]
+        );
+        let mut reversed = values.clone();
+        reversed.reverse();
+        reversed.sort();
+        assert_eq!(reversed, values);
+    }
+
+    #[test]
+    fn test_hash_and_dict_order() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::{BTreeSet, HashSet};
+        use std::hash::{Hash, Hasher};
+
+        let hash = |value: &Value| {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+        let forwards: Value = "16:1:a,1:1#1:b,1:2#}".parse().unwrap();
+        let backwards: Value = "16:1:b,1:2#1:a,1:1#}".parse().unwrap();
+        assert_eq!(forwards, backwards);
+        assert_eq!(forwards.cmp(&backwards), std::cmp::Ordering::Equal);
+        assert_eq!(hash(&forwards), hash(&backwards));
+        assert_eq!(hash(&Value::from(f64::NAN)), hash(&Value::from(f64::NAN)));
+
+        let unique: HashSet<Value> = vec![
+            forwards.clone(),
+            backwards.clone(),
+            Value::from(1),
+            Value::from(1.0),
+            Value::from(1u8),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(unique.len(), 3);
+        let sorted: BTreeSet<Value> = vec![backwards, Value::from(1.0), forwards, Value::from(1)]
+            .into_iter()
+            .collect();
+        assert_eq!(sorted.len(), 3);
+        assert_eq!(sorted.iter().next(), Some(&Value::from(1)));
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let input = include_str!("../tests/fixtures/python_dict.tnet");
+        assert_eq!(
+            input.parse(),
+            Ok(dict(btreemap! {
+                "name".into() => Value::Str("sensor".into()),
+                "ratio".into() => Value::from(0.5),
+                "count".into() => Value::from(3),
+                "ok".into() => Value::Bool(true),
+                "missing".into() => Value::Null,
+                "readings".into() => Value::List(vec![
+                    Value::from(1.0),
+                    Value::from(2.25),
+                    Value::from(-40.0),
+                    Value::from(1e-7),
+                ]),
+            }))
+        );
+
+        let input = include_str!("../tests/fixtures/python_floats.tnet");
+        match input.parse() {
+            Ok(Value::List(items)) => {
+                assert_eq!(items.len(), 17);
+                assert!(items
+                    .iter()
+                    .all(|v| matches!(v, Value::Number(n) if n.is_f64())));
+                round_trip(Value::List(items));
+            }
+            other => panic!("expected a list, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("".parse::<Value>(), Err(Error::Incomplete { needed: None }));
+        assert_eq!("1:1#1:2#".parse::<Value>(), Err(Error::UnusedParseData));
+        assert_eq!(
+            "8:1:1#1:2#}".parse::<Value>(),
+            Err(Error::Expected {
+                expected: TNetStringType::Str,
+                target: None,
+                found: Some(b'#'),
+                offset: 2,
+                snippet: "8:1:1#1:2#}".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_from_value() {
+        let message = Message {
+            host: "localhost".into(),
+            port: 80,
+            ratio: 0.5,
+            tags: vec!["a".into()],
+            parent: Some(Box::new(Message {
+                host: "example.com".into(),
+                port: 443,
+                ratio: 1.0,
+                tags: vec![],
+                parent: None,
+                kind: E::Unit,
+            })),
+            kind: E::Struct { a: 1 },
+        };
+        let value = to_value(&message).unwrap();
+        assert_eq!(from_str(&to_string(&message).unwrap()), Ok(value.clone()));
+        assert_eq!(from_value(value.clone()), Ok(message));
+
+        let mut value = value;
+        if let Value::Dict(ref mut dict) = value {
+            dict.insert("port".into(), Value::from(8080));
+        }
+        let patched: Message = from_value(value).unwrap();
+        assert_eq!(patched.port, 8080);
+    }
+
+    #[test]
+    fn test_enums() {
+        assert_eq!(to_value(&E::Unit), Ok(Value::Str("Unit".into())));
+        assert_eq!(
+            to_value(&E::Newtype(1)),
+            Ok(dict(btreemap! {"Newtype".into() => Value::from(1)}))
+        );
+        assert_eq!(
+            to_value(&E::Tuple(1, 2)),
+            Ok(dict(btreemap! {
+                "Tuple".into() => Value::List(vec![Value::from(1), Value::from(2)]),
+            }))
+        );
+        assert_eq!(
+            to_value(&E::Struct { a: 1 }),
+            Ok(dict(btreemap! {
+                "Struct".into() => dict(btreemap! {"a".into() => Value::from(1)}),
+            }))
+        );
+
+        for e in [E::Unit, E::Newtype(1), E::Tuple(1, 2), E::Struct { a: 1 }] {
+            let value = to_value(&e).unwrap();
+            assert_eq!(
+                from_str::<Value>(&to_string(&e).unwrap()),
+                Ok(value.clone())
+            );
+            assert_eq!(from_value::<E>(value), Ok(e));
+        }
+    }
+
+    #[test]
+    fn test_value_errors() {
+        let value = dict(btreemap! {
+            "host".into() => Value::Str("localhost".into()),
+            "port".into() => Value::Str("eighty".into()),
+        });
+        assert_eq!(
+            from_value::<Message>(value),
+            Err(Error::InvalidType {
+                unexpected: "string \"eighty\"".into(),
+                expected: "u16".into(),
+            })
+        );
+        assert_eq!(
+            from_value::<E>(Value::from(1)),
+            Err(Error::InvalidType {
+                unexpected: "integer `1`".into(),
+                expected: "a string or a dict with a single key".into(),
+            })
+        );
+        assert_eq!(
+            to_value(&hashmap! {1 => 2}),
+            Err(Error::NonStringKey(TNetStringType::Int))
+        );
+        #[cfg(not(feature = "arbitrary_precision"))]
+        {
+            assert!(to_value(&u128::MAX).is_err());
+            assert!(to_value(&i128::MIN).is_err());
+        }
+        #[cfg(feature = "arbitrary_precision")]
+        {
+            let wide = |digits: &str| Value::Number(crate::Number::from_digits(digits).unwrap());
+            assert_eq!(to_value(&u128::MAX), Ok(wide(&u128::MAX.to_string())));
+            assert_eq!(to_value(&i128::MIN), Ok(wide(&i128::MIN.to_string())));
+            assert_eq!(
+                crate::to_vec(&to_value(&i128::MIN).unwrap()),
+                crate::to_vec(&i128::MIN)
+            );
+            assert_eq!(to_value(&u128::from(u64::MAX)), Ok(Value::from(u64::MAX)));
+        }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut value: Value = "49:4:name,3:foo,5:ports,11:2:80#3:443#]3:tls,4:true!}"
+            .parse()
+            .unwrap();
+        assert_eq!(value["name"].as_str(), Some("foo"));
+        assert_eq!(value["ports"][1].as_i64(), Some(443));
+        assert_eq!(value.get("tls").and_then(Value::as_bool), Some(true));
+        assert_eq!(value["ports"].as_list().map(Vec::len), Some(2));
+        assert_eq!(value.as_dict().map(|d| d.len()), Some(3));
+        assert_eq!(value["name"].as_i64(), None);
+        assert_eq!(Value::from(0.5).as_f64(), Some(0.5));
+
+        *value.get_mut("name").unwrap() = "bar".into();
+        assert_eq!(value["name"], Value::from("bar"));
+        let ports = value.get_mut("ports").unwrap().take();
+        assert!(value["ports"].is_null());
+        assert_eq!(ports, Value::from(vec![Value::from(80), Value::from(443)]));
+        assert_eq!(value.get(String::from("tls")), Some(&Value::from(true)));
+    }
+
+    #[test]
+    fn test_missing() {
+        let value = Value::from(vec![Value::from("a")]);
+        assert!(value[1].is_null());
+        assert!(value["a"].is_null());
+        assert!(value[0]["a"][5].is_null());
+        assert_eq!(value.get(1), None);
+        assert_eq!(Value::Null.get("a"), None);
+    }
+
+    #[test]
+    fn test_numbers() {
+        let max = "20:18446744073709551615#".parse::<Value>().unwrap();
+        assert_eq!(max.as_u64(), Some(u64::MAX));
+        assert_eq!(max.as_i64(), None);
+        assert_eq!(max.to_tnetstring(), "20:18446744073709551615#");
+        assert_eq!(to_value(&u64::MAX), Ok(max));
+
+        let min = "20:-9223372036854775808#".parse::<Value>().unwrap();
+        assert_eq!(min.as_i64(), Some(i64::MIN));
+        assert_eq!(min.as_u64(), None);
+        assert_eq!(min.to_tnetstring(), "20:-9223372036854775808#");
+
+        assert_eq!(Value::from(5u64), Value::from(5i64));
+        assert_eq!(from_value::<u64>(Value::from(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(from_value::<i8>(Value::from(-5)), Ok(-5));
+        assert!(from_value::<i64>(Value::from(u64::MAX)).is_err());
+
+        let float = "1:1^".parse::<Value>().unwrap();
+        assert_ne!(float, Value::from(1));
+        assert_eq!(float.as_i64(), None);
+        assert_eq!(float.as_f64(), Some(1.0));
+        assert_eq!(float.to_tnetstring(), "1:1^");
+        assert_eq!("3:1.0^".parse::<Value>(), Ok(float));
+    }
+
+    #[test]
+    fn test_deserialize_in_place() {
+        use crate::de::Deserializer;
+        use crate::map::Map;
+
+        fn pointers(value: &Value) -> (*const u8, *const Value, *const u8) {
+            let tags = value["tags"].as_list().unwrap();
+            (
+                value["name"].as_str().unwrap().as_ptr(),
+                tags.as_ptr(),
+                tags[0].as_str().unwrap().as_ptr(),
+            )
+        }
+
+        let input: &[u8] = b"65:4:name,12:first record,4:tags,23:5:alpha,4:beta,5:gamma,]1:n,1:1#}\
+            49:4:name,6:second,4:tags,14:5:delta,3:eps,]1:n,1:2#}\
+            61:4:tags,12:1:x,1:y,1:z,]4:name,9:third one,1:n,1:3#5:extra,0:~}";
+        let frames: Vec<&[u8]> = crate::frames::frames(input)
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        let mut de = Deserializer::from_slice(input);
+        let mut value = Value::Null;
+        Value::deserialize_in_place(&mut de, &mut value).unwrap();
+        let first = pointers(&value);
+        for frame in &frames[1..] {
+            Value::deserialize_in_place(&mut de, &mut value).unwrap();
+            assert_eq!(value, crate::de::from_slice::<Value>(frame).unwrap());
+            assert_eq!(pointers(&value), first);
+        }
+
+        // a `Map` on its own does the same, and values of a new type replace
+        // the old ones
+        let mut de = Deserializer::from_slice(input);
+        let mut map = Map::new();
+        Map::deserialize_in_place(&mut de, &mut map).unwrap();
+        let name = map["name"].as_str().unwrap().as_ptr();
+        Map::deserialize_in_place(&mut de, &mut map).unwrap();
+        assert_eq!(map["name"].as_str().unwrap().as_ptr(), name);
+        let mut value = Value::from(vec![Value::from("a")]);
+        Value::deserialize_in_place(&mut Deserializer::from_slice(frames[2]), &mut value).unwrap();
+        assert_eq!(value, crate::de::from_slice::<Value>(frames[2]).unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_ref() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Envelope<'a> {
+            kind: &'a str,
+            payload: Value,
+        }
+
+        let input = "64:4:kind,7:message,7:payload,33:4:host,9:localhost,4:kind,4:Unit,}}";
+        let value: Value = input.parse().unwrap();
+        let envelope: Envelope = value.deserialize_into().unwrap();
+        assert_eq!(envelope.kind, "message");
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Payload {
+            host: String,
+            kind: E,
+        }
+        assert_eq!(
+            Payload::deserialize(&envelope.payload),
+            Ok(Payload {
+                host: "localhost".into(),
+                kind: E::Unit,
+            })
+        );
+
+        for e in [E::Unit, E::Newtype(1), E::Tuple(1, 2), E::Struct { a: 1 }] {
+            let value = to_value(&e).unwrap();
+            assert_eq!(value.deserialize_into::<E>(), Ok(e));
+        }
+        assert_eq!(Value::from("Unit").deserialize_into::<E>(), Ok(E::Unit));
+        assert_eq!(Value::Null.deserialize_into::<Option<u8>>(), Ok(None::<u8>));
+    }
+
+    #[test]
+    fn test_deserialize_ref_errors() {
+        let value = Value::from(vec![Value::from("a")]);
+        assert_eq!(
+            value.deserialize_into::<Vec<u32>>(),
+            Err(Error::InvalidType {
+                unexpected: "string \"a\"".into(),
+                expected: "u32".into(),
+            })
+        );
+        assert_eq!(
+            value.deserialize_into::<E>(),
+            Err(Error::InvalidType {
+                unexpected: "sequence".into(),
+                expected: "a string or a dict with a single key".into(),
+            })
+        );
+        assert_eq!(
+            value.deserialize_into::<(u32, u32)>(),
+            Err(Error::InvalidType {
+                unexpected: "string \"a\"".into(),
+                expected: "u32".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_pointer() {
+        let mut value = dict(btreemap! {
+            "headers".into() => dict(btreemap! {
+                "host".into() => Value::from("example.com"),
+                "a/b".into() => Value::from(1),
+                "m~n".into() => Value::from(2),
+                "".into() => Value::from(3),
+            }),
+            "items".into() => Value::from(vec![
+                dict(btreemap! {"id".into() => Value::from(10)}),
+                dict(btreemap! {"id".into() => Value::from(11)}),
+            ]),
+        });
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(
+            value.pointer("/headers/host"),
+            Some(&Value::from("example.com"))
+        );
+        assert_eq!(value.pointer("/items/1/id"), Some(&Value::from(11)));
+        assert_eq!(value.pointer("/headers/a~1b"), Some(&Value::from(1)));
+        assert_eq!(value.pointer("/headers/m~0n"), Some(&Value::from(2)));
+        assert_eq!(value.pointer("/headers/"), Some(&Value::from(3)));
+
+        assert_eq!(value.pointer("headers"), None);
+        assert_eq!(value.pointer("/missing"), None);
+        assert_eq!(value.pointer("/headers/host/more"), None);
+        assert_eq!(value.pointer("/items/2"), None);
+        assert_eq!(value.pointer("/items/01"), None);
+        assert_eq!(value.pointer("/items/+1"), None);
+        assert_eq!(value.pointer("/items/-1"), None);
+        assert_eq!(value.pointer("/items/id"), None);
+
+        *value.pointer_mut("/items/0/id").unwrap() = Value::from(20);
+        assert_eq!(value["items"][0]["id"], Value::from(20));
+        assert_eq!(value.pointer_mut("/items/5"), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let defaults = dict(btreemap! {
+            "timeout".into() => Value::from(30),
+            "retry".into() => dict(btreemap! {
+                "count".into() => Value::from(3),
+                "backoff".into() => Value::from(0.5),
+            }),
+            "hosts".into() => Value::from(vec![Value::from("a"), Value::from("b")]),
+            "debug".into() => Value::from(false),
+        });
+        let options = dict(btreemap! {
+            "retry".into() => dict(btreemap! {
+                "count".into() => Value::from(5),
+                "jitter".into() => Value::from(true),
+            }),
+            "hosts".into() => Value::from(vec![Value::from("c")]),
+            "debug".into() => Value::Null,
+            "user".into() => Value::from("me"),
+        });
+        assert_eq!(
+            defaults.merged(options),
+            dict(btreemap! {
+                "timeout".into() => Value::from(30),
+                "retry".into() => dict(btreemap! {
+                    "count".into() => Value::from(5),
+                    "backoff".into() => Value::from(0.5),
+                    "jitter".into() => Value::from(true),
+                }),
+                "hosts".into() => Value::from(vec![Value::from("c")]),
+                "user".into() => Value::from("me"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_replaces() {
+        let mut value = Value::from(1);
+        value.merge(Value::from("a"));
+        assert_eq!(value, Value::from("a"));
+
+        let mut value = dict(btreemap! {"a".into() => Value::from(1)});
+        value.merge(Value::from(vec![]));
+        assert_eq!(value, Value::from(vec![]));
+
+        // a dict merged over anything else starts from an empty dict, so its
+        // nulls are dropped rather than kept
+        let mut value = Value::from(1);
+        value.merge(dict(btreemap! {
+            "a".into() => Value::Null,
+            "b".into() => dict(btreemap! {"c".into() => Value::Null}),
+        }));
+        assert_eq!(value, dict(btreemap! {"b".into() => dict(btreemap! {})}));
+
+        let mut value = dict(btreemap! {"a".into() => Value::from(1)});
+        value.merge(Value::Null);
+        assert!(value.is_null());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        fn build(order: &[&str], zero: f64) -> Value {
+            let mut dict = crate::map::Map::new();
+            for &key in order {
+                let mut inner = crate::map::Map::new();
+                inner.insert(key.to_owned(), Value::from(zero));
+                inner.insert("x".to_owned(), Value::from(1.5));
+                dict.insert(key.to_owned(), Value::Dict(inner));
+            }
+            dict.insert("list".to_owned(), Value::from(vec![Value::from(zero)]));
+            Value::Dict(dict)
+        }
+
+        let a = build(&["b", "ab", "long key"], -0.0);
+        let b = build(&["long key", "b", "ab"], 0.0);
+        assert_ne!(a, b);
+        let a = a.canonicalized();
+        assert_eq!(a, b.clone().canonicalized());
+
+        // the same bytes as the canonical serializer, and the same again
+        // after another round
+        let config = SerializerConfig::new().canonical(true);
+        let expected = to_vec_with(&b, &config).unwrap();
+        assert_eq!(to_vec_with(&a, &config).unwrap(), expected);
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(crate::ser::to_vec(&a).unwrap(), expected);
+        assert_eq!(a.to_tnetstring(), a.clone().canonicalized().to_tnetstring());
+
+        let mut value = Value::from(vec![
+            Value::from(2.0),
+            Value::from(-3.0),
+            Value::from(0.5),
+            Value::from(1e20),
+        ]);
+        value.canonicalize();
+        assert_eq!(
+            value.to_tnetstring(),
+            "40:1:2^2:-3^3:0.5^21:100000000000000000000^]"
+        );
+        value.canonicalize_with(&CanonicalizeOptions::new().integer_floats(true));
+        assert_eq!(
+            value.to_tnetstring(),
+            "40:1:2#2:-3#3:0.5^21:100000000000000000000^]"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let input =
+            "74:3:int,1:1#3:seq,8:1:a,1:b,]4:meta,36:2:ok,4:true!4:none,0:~5:ratio,3:0.5^}}";
+        let value: Value = input.parse().unwrap();
+        assert_eq!(format!("{}", value), value.to_tnetstring());
+        assert_eq!(value.to_string().parse(), Ok(value.clone()));
+        assert_eq!(Value::from(vec![]).to_string(), "0:]");
+
+        // `Bytes` come back as the `Str` they'd decode to, in a frame that
+        // still parses
+        let value: Value = crate::from_slice(b"11:4:\xff\x00ab,1:x,]").unwrap();
+        assert_eq!(value[0], Value::Bytes(b"\xff\x00ab".to_vec()));
+        assert_eq!(value.to_string(), "13:6:\u{fffd}\0ab,1:x,]");
+        assert_eq!(
+            value.to_string().parse(),
+            Ok(Value::from(vec![
+                Value::from("\u{fffd}\0ab"),
+                Value::from("x")
+            ]))
+        );
+    }
+
+    #[cfg(not(feature = "preserve_order"))]
+    #[test]
+    fn test_dump_pretty() {
+        let input =
+            "74:3:int,1:1#3:seq,8:1:a,1:b,]4:meta,36:2:ok,4:true!4:none,0:~5:ratio,3:0.5^}}";
+        let mut value: Value = input.parse().unwrap();
+        if let Value::Dict(ref mut dict) = value {
+            dict.insert("empty".into(), Value::from(vec![]));
+            dict.insert("quote".into(), Value::from("say \"hi\""));
+        }
+        assert_eq!(
+            value.dump_pretty(),
+            r#"dict {
+    "empty": list [],
+    "int": int 1,
+    "meta": dict {
+        "none": null,
+        "ok": bool true,
+        "ratio": float 0.5,
+    },
+    "quote": str "say \"hi\"",
+    "seq": list [
+        str "a",
+        str "b",
+    ],
+}"#
+        );
+        assert_eq!(Value::from(1.0).dump_pretty(), "float 1");
+        assert_eq!(dict(btreemap! {}).dump_pretty(), "dict {}");
+    }
+
+    #[test]
+    fn test_tnet_macro() {
+        assert_eq!(tnet!(null), Value::Null);
+        assert_eq!(tnet!([]), Value::from(vec![]));
+        assert_eq!(tnet!({}), dict(btreemap! {}));
+        assert_eq!(tnet!(1.5), Value::from(1.5));
+        assert_eq!(tnet!("a"), Value::from("a"));
+
+        let value = tnet!({"key": [1, true, null, {"nested": "x"}]});
+        assert_eq!(
+            value,
+            dict(btreemap! {
+                "key".into() => Value::from(vec![
+                    Value::from(1),
+                    Value::from(true),
+                    Value::Null,
+                    dict(btreemap! {"nested".into() => Value::from("x")}),
+                ]),
+            })
+        );
+        assert_eq!(
+            value.to_tnetstring(),
+            "41:3:key,31:1:1#4:true!0:~13:6:nested,1:x,}]}"
+        );
+
+        // trailing commas
+        assert_eq!(tnet!([1, 2,]), tnet!([1, 2]));
+        assert_eq!(
+            tnet!({"a": 1, "b": [false,],}),
+            tnet!({"a": 1, "b": [false]})
+        );
+    }
+
+    #[test]
+    fn test_tnet_macro_interpolation() {
+        let host = "localhost".to_string();
+        let ports = vec![80u16, 443];
+        let key = "dynamic";
+        let value = tnet!({
+            "host": host,
+            "ports": ports,
+            "next": ports[0] + 1,
+            key: E::Newtype(1),
+            (format!("{}-{}", key, 2)): [ports.len(), -1],
+        });
+        assert_eq!(value["host"], Value::from("localhost"));
+        assert_eq!(value["ports"][1], Value::from(443));
+        assert_eq!(value["next"], Value::from(81));
+        assert_eq!(value["dynamic"]["Newtype"], Value::from(1));
+        assert_eq!(value["dynamic-2"], tnet!([2, -1]));
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn tns(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tns"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn test_decode() {
+    let output = tns(&["decode"], b"27:2:id,2:42#4:tags,7:1:a,0:~]}");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "{\"id\":42,\"tags\":[\"a\",null]}\n");
+
+    let output = tns(&["decode", "tests/fixtures/python_dict.tnet"], b"");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with('{'));
+}
+
+#[test]
+fn test_decode_malformed() {
+    let output = tns(&["decode"], b"9:1:a,2:4x#}");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tns: at byte 8: error parsing integer\n");
+
+    let output = tns(&["decode"], b"5:hello,0:~");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tns: at byte 8: unused parse data\n");
+}
+
+#[test]
+fn test_decode_stream() {
+    let output = tns(&["decode", "--stream"], b"5:hello,2:42#0:]");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "\"hello\"\n42\n[]\n");
+
+    // frames before the bad one still come out
+    let output = tns(&["decode", "--stream"], b"5:hello,8:1:a,1:x~]");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "\"hello\"\n");
+    assert_eq!(stderr(&output), "tns: at byte 16: error parsing unit\n");
+
+    let output = tns(&["decode", "--stream"], b"0:~0:~5:hel");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "null\nnull\n");
+    assert_eq!(
+        stderr(&output),
+        "tns: at byte 6: incomplete input, 3 more bytes needed\n"
+    );
+}
+
+#[test]
+fn test_encode() {
+    let output = tns(&["encode"], b"{\"id\": 42, \"tags\": [\"a\", null]}\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "27:2:id,2:42#4:tags,7:1:a,0:~]}");
+
+    let output = tns(&["encode"], b"{\"id\": 42,\n \"tags\": [\"a\" null]}");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tns: at byte 25: expected `,` or `]`\n");
+}
+
+#[test]
+fn test_encode_stream() {
+    let output = tns(&["encode", "--stream"], b"\"hello\"\n\n42\n[]\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "5:hello,2:42#0:]");
+
+    let output = tns(&["encode", "--stream"], b"1\n2\n{\"a\" 1}\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "1:1#1:2#");
+    assert_eq!(stderr(&output), "tns: at byte 9: expected `:`\n");
+}
+
+#[test]
+fn test_round_trip() {
+    let encoded = tns(&["encode"], b"{\"a\": [1, 2.5, true, \"x\"], \"b\": {}}");
+    let decoded = tns(&["decode"], &encoded.stdout);
+    assert_eq!(stdout(&decoded), "{\"a\":[1,2.5,true,\"x\"],\"b\":{}}\n");
+}
+
+#[test]
+fn test_usage() {
+    let output = tns(&[], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("tns: missing command\n"));
+
+    let output = tns(&["decode", "--nope"], b"");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = tns(&["--help"], b"");
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("usage: tns"));
+
+    let output = tns(&["decode", "does-not-exist"], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("tns: does-not-exist: "));
+}
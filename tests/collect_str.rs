@@ -0,0 +1,59 @@
+use serde::Serializer as _;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fmt;
+use tnetstring::Serializer;
+
+struct Counting;
+
+// counted per thread, so tests running alongside don't add to each other's
+// counts
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+// only `Display`, like a timestamp or an id type
+struct Id(u64);
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "id-{:08}", self.0)
+    }
+}
+
+#[test]
+fn test_collect_str_allocations() {
+    let mut serializer = Serializer::new(Vec::with_capacity(64));
+    let (result, count) = allocations(|| (&mut serializer).collect_str(&Id(42)));
+    result.unwrap();
+    assert_eq!(count, 0);
+    let output = serializer.into_inner().unwrap();
+    assert_eq!(output, b"11:id-00000042,");
+
+    // the same output as formatting into a `String` first, which allocates
+    let mut naive = Serializer::new(Vec::with_capacity(64));
+    let (result, count) = allocations(|| (&mut naive).serialize_str(&Id(42).to_string()));
+    result.unwrap();
+    assert!(count > 0);
+    assert_eq!(naive.into_inner().unwrap(), output);
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tnetstring::Framed;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Request {
+    Add(i64, i64),
+    Echo(String),
+    Quit,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Response {
+    id: u32,
+    result: String,
+}
+
+fn serve(stream: TcpStream) {
+    let mut framed = Framed::new(stream).max_frame_len(1024);
+    for id in 0.. {
+        let result = match framed.recv::<Request>().unwrap() {
+            Request::Add(a, b) => (a + b).to_string(),
+            Request::Echo(message) => message,
+            Request::Quit => return,
+        };
+        framed.send(&Response { id, result }).unwrap();
+    }
+}
+
+#[test]
+fn test_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || serve(listener.accept().unwrap().0));
+
+    let mut client = Framed::new(TcpStream::connect(address).unwrap());
+    client.send(&Request::Add(2, 3)).unwrap();
+    client.send(&Request::Echo("hi".to_owned())).unwrap();
+    assert_eq!(
+        client.recv::<Response>().unwrap(),
+        Response {
+            id: 0,
+            result: "5".to_owned()
+        }
+    );
+    assert_eq!(
+        client.recv::<Response>().unwrap(),
+        Response {
+            id: 1,
+            result: "hi".to_owned()
+        }
+    );
+
+    // nothing more is coming until the next request
+    let timeout = Duration::from_millis(50);
+    assert_eq!(client.recv_timeout::<Response>(timeout).unwrap(), None);
+    assert_eq!(client.get_ref().read_timeout().unwrap(), None);
+    client.send(&Request::Echo("again".to_owned())).unwrap();
+    assert_eq!(
+        client
+            .recv_timeout::<Response>(Duration::from_secs(10))
+            .unwrap(),
+        Some(Response {
+            id: 2,
+            result: "again".to_owned()
+        })
+    );
+
+    client.send(&Request::Quit).unwrap();
+    server.join().unwrap();
+    assert_eq!(client.recv::<Response>(), Err(tnetstring::Error::Eof));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_partial_frame() {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let (a, mut b) = UnixStream::pair().unwrap();
+    let mut framed = Framed::new(a);
+    b.write_all(b"12:5:hello,1:1#").unwrap();
+    let timeout = Duration::from_millis(20);
+    assert_eq!(framed.recv_timeout::<(String, u8)>(timeout).unwrap(), None);
+    assert_eq!(framed.buffered(), 15);
+    b.write_all(b"]").unwrap();
+    assert_eq!(
+        framed.recv_timeout::<(String, u8)>(timeout).unwrap(),
+        Some(("hello".to_owned(), 1))
+    );
+    framed.send(&[1u8, 2]).unwrap();
+    let mut b = Framed::new(b);
+    assert_eq!(b.recv::<Vec<u8>>().unwrap(), [1, 2]);
+}
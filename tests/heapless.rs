@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counting;
+
+// counted per thread, so tests running alongside don't add to each other's
+// counts
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Reading {
+    sensor: heapless::String<8>,
+    #[serde(with = "tnetstring::helpers::heapless_bytes")]
+    raw: heapless::Vec<u8, 4>,
+    samples: heapless::Vec<i32, 4>,
+    ok: bool,
+}
+
+const INPUT: &[u8] =
+    b"69:6:sensor,5:probe,3:raw,4:\x00\x01\xfe\xff,7:samples,13:1:1#2:-2#1:3#]2:ok,4:true!}";
+
+fn allocations<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_no_allocations() {
+    let mut buf = [0u8; 128];
+    let (reading, count) = allocations(|| {
+        let mut de = tnetstring::Deserializer::from_slice(INPUT);
+        let reading = Reading::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        reading
+    });
+    assert_eq!(count, 0);
+    // while a std `String` would allocate
+    let (_, count) = allocations(|| tnetstring::from_slice::<String>(b"5:probe,").unwrap());
+    assert_eq!(count, 1);
+    assert_eq!(reading.sensor, "probe");
+    assert_eq!(reading.raw, [0x00, 0x01, 0xfe, 0xff]);
+    assert_eq!(reading.samples, [1, -2, 3]);
+    assert!(reading.ok);
+
+    let encoded = tnetstring::to_slice(&reading, &mut buf).unwrap();
+    assert_eq!(encoded, INPUT);
+}
+
+#[test]
+fn test_capacity_exceeded() {
+    for input in &[
+        &b"73:6:sensor,9:too long!,3:raw,4:\x00\x01\xfe\xff,7:samples,13:1:1#2:-2#1:3#]2:ok,4:true!}"[..],
+        b"70:6:sensor,5:probe,3:raw,5:\x00\x01\xfe\xff\x00,7:samples,13:1:1#2:-2#1:3#]2:ok,4:true!}",
+        b"77:6:sensor,5:probe,3:raw,4:\x00\x01\xfe\xff,7:samples,21:1:1#2:-2#1:3#1:4#1:5#]2:ok,4:true!}",
+    ] {
+        let error = tnetstring::from_slice::<Reading>(input).unwrap_err();
+        assert!(error.is_data(), "{}", error);
+    }
+}
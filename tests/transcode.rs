@@ -0,0 +1,79 @@
+use serde_json::json;
+
+fn to_json(input: &[u8]) -> serde_json::Value {
+    let mut out = Vec::new();
+    let mut de = tnetstring::Deserializer::from_slice(input);
+    let mut ser = serde_json::Serializer::new(&mut out);
+    serde_transcode::transcode(&mut de, &mut ser).unwrap();
+    de.end().unwrap();
+    serde_json::from_slice(&out).unwrap()
+}
+
+fn to_tnetstring(input: &str) -> Vec<u8> {
+    let mut de = serde_json::Deserializer::from_str(input);
+    let mut ser = tnetstring::Serializer::new(Vec::new());
+    serde_transcode::transcode(&mut de, &mut ser).unwrap();
+    ser.into_inner().unwrap()
+}
+
+#[test]
+fn test_dict_fixture() {
+    let input = include_bytes!("fixtures/python_dict.tnet");
+    let json = to_json(input);
+    assert_eq!(
+        json,
+        json!({
+            "name": "sensor",
+            "ratio": 0.5,
+            "count": 3,
+            "ok": true,
+            "missing": null,
+            "readings": [1.0, 2.25, -40.0, 1e-7],
+        })
+    );
+    let back = to_tnetstring(&json.to_string());
+    assert_eq!(
+        tnetstring::from_slice::<tnetstring::Value>(&back),
+        tnetstring::from_slice::<tnetstring::Value>(input)
+    );
+}
+
+#[test]
+fn test_floats_fixture() {
+    let input = include_bytes!("fixtures/python_floats.tnet");
+    let json = to_json(input);
+    let floats: Vec<f64> = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(floats.len(), 17);
+    assert_eq!(floats[0], 1.0);
+    assert_eq!(floats[16], 1.0 / 3.0);
+    assert_eq!(
+        tnetstring::from_slice::<Vec<f64>>(&to_tnetstring(&json.to_string())),
+        Ok(floats)
+    );
+}
+
+#[test]
+fn test_nested() {
+    let json = json!({
+        "a": [[], {}, [[1, -2], {"b": {"c": [null]}}]],
+        "big": u64::MAX,
+        "min": i64::MIN,
+        "empty": "",
+        "text": "caf\u{e9} \"quoted\"",
+        "f": false,
+    });
+    let encoded = to_tnetstring(&json.to_string());
+    assert_eq!(to_json(&encoded), json);
+}
+
+#[test]
+fn test_scalars() {
+    for input in &["null", "true", "0", "-1", "1.5", "\"x\"", "[]", "{}"] {
+        let encoded = to_tnetstring(input);
+        assert_eq!(
+            to_json(&encoded),
+            serde_json::from_str::<serde_json::Value>(input).unwrap()
+        );
+    }
+    assert_eq!(to_tnetstring("[1.0,2]"), b"8:1:1^1:2#]");
+}